@@ -12,6 +12,15 @@
 //!    scope_size   u64        Number of bytes of child scope
 //! ```
 //!
+//! Scopes with key-value tags (see [`crate::profile_scope_tags`]) start with `'{'` instead,
+//! and have the tags between the data and the scope size:
+//!
+//! ```ignore
+//!    num_tags     LEB128     Number of tags
+//!    key          str        Tag key (key and value are repeated num_tags times)
+//!    value        str        Tag value
+//! ```
+//!
 //! This is followed by `scope_size` number of bytes of data
 //! containing any child scopes. The scope is then closed by:
 //!
@@ -20,9 +29,48 @@
 //!    time_ns      i64        Time stamp of when scope finished
 //! ```
 //!
-//! Integers are encoded in little endian.
-//! Strings are encoded as a single u8 length + that many bytes of UTF8.
-//! At the moment strings may be at most 127 bytes long.
+//! Between and inside scopes there can be counter samples, consisting of:
+//!
+//! ```ignore
+//!    '#'          byte       Sentinel
+//!    counter id   u32        Identifier of the counter, registered like a scope
+//!    time_ns      i64        Time stamp of the sample
+//!    value        f64        The value of the counter
+//! ```
+//!
+//! Likewise there can be task events, recorded when an async task (see [`crate::profile_task`])
+//! starts or stops running on this thread:
+//!
+//! ```ignore
+//!    '[' or ']'   byte       Sentinel: '[' when the task starts running, ']' when it stops
+//!    task name id u32        Identifier of the task name, registered like a scope
+//!    task id      u64        Unique identifier of the task
+//!    time_ns      i64        Time stamp of the event
+//! ```
+//!
+//! And flow points, which connect scopes on different threads (see [`crate::profile_flow`]):
+//!
+//! ```ignore
+//!    '>'          byte       Sentinel
+//!    flow id      u64        Identifier shared by all points of the same flow
+//!    time_ns      i64        Time stamp of the flow point
+//!    depth        u32        Number of scopes open when the point was recorded
+//! ```
+//!
+//! And markers of instantaneous events (see [`crate::profile_marker`]):
+//!
+//! ```ignore
+//!    '!'          byte       Sentinel
+//!    marker id    u32        Identifier of the marker name, registered like a scope
+//!    time_ns      i64        Time stamp of the event
+//! ```
+//!
+//! Integers and floats are encoded in little endian.
+//! Strings are encoded as a LEB128 length + that many bytes of UTF8.
+//! Strings longer than [`MAX_STRING_LENGTH`] bytes are truncated.
+//!
+//! Before `PFD9` frames, strings were at most 127 bytes with a single u8 length,
+//! which is the same as the LEB128 encoding of such short lengths.
 
 use super::*;
 use anyhow::Context;
@@ -30,7 +78,16 @@ use byteorder::{LittleEndian as LE, ReadBytesExt, WriteBytesExt};
 use std::mem::size_of;
 
 const SCOPE_BEGIN: u8 = b'(';
+const SCOPE_BEGIN_TAGGED: u8 = b'{';
 const SCOPE_END: u8 = b')';
+const COUNTER: u8 = b'#';
+const TASK_ENTER: u8 = b'[';
+const TASK_EXIT: u8 = b']';
+const FLOW: u8 = b'>';
+const MARKER: u8 = b'!';
+
+/// Scope data longer than this many bytes is truncated when recorded.
+const MAX_STRING_LENGTH: usize = 64 * 1024;
 
 /// Used when parsing a Stream.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -44,6 +101,40 @@ pub struct ScopeRecord<'s> {
     /// e.g. function argument, like a mesh name. Optional.
     /// Example: "image.png".
     pub data: &'s str,
+
+    /// Key-value tags, see [`crate::profile_scope_tags`]. Usually empty.
+    pub tags: ScopeTags<'s>,
+}
+
+/// The key-value tags of a scope, see [`crate::profile_scope_tags`].
+///
+/// These are parsed from the stream on the fly by [`Self::iter`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScopeTags<'s> {
+    num_tags: usize,
+    /// The encoded keys and values.
+    bytes: &'s [u8],
+}
+
+impl<'s> ScopeTags<'s> {
+    /// Does the scope have no tags?
+    pub fn is_empty(&self) -> bool {
+        self.num_tags == 0
+    }
+
+    /// The number of tags.
+    pub fn len(&self) -> usize {
+        self.num_tags
+    }
+
+    /// The keys and values, in the order they were recorded.
+    pub fn iter(&self) -> impl Iterator<Item = (&'s str, &'s str)> {
+        let mut reader = Reader(std::io::Cursor::new(self.bytes));
+        (0..self.num_tags).map_while(move |_| {
+            // Already validated when the scope was parsed:
+            Some((reader.parse_string().ok()?, reader.parse_string().ok()?))
+        })
+    }
 }
 
 impl<'s> ScopeRecord<'s> {
@@ -54,6 +145,74 @@ impl<'s> ScopeRecord<'s> {
     }
 }
 
+/// One sample of a counter, e.g. memory usage, recorded with [`crate::profile_counter`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CounterRecord {
+    /// Identifies the counter.
+    /// Its name can be looked up in [`crate::ScopeCollection`] like that of a scope.
+    pub id: ScopeId,
+
+    /// When the sample was recorded.
+    pub time_ns: NanoSecond,
+
+    /// The value of the counter at [`Self::time_ns`].
+    pub value: f64,
+}
+
+/// Whether an async task started or stopped running on a thread, see [`TaskRecord`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskEvent {
+    /// The task started being polled.
+    Enter,
+
+    /// The task stopped being polled, either because it is waiting on something or because it finished.
+    Exit,
+}
+
+/// A task starting or stopping to run on a thread, recorded with [`crate::profile_task`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TaskRecord {
+    /// Identifies the name of the task.
+    /// It can be looked up in [`crate::ScopeCollection`] like that of a scope.
+    pub id: ScopeId,
+
+    /// Unique for each profiled task, and the same on all threads the task runs on.
+    pub task_id: u64,
+
+    /// When the event happened.
+    pub time_ns: NanoSecond,
+
+    /// Whether the task started or stopped running.
+    pub event: TaskEvent,
+}
+
+/// A point in a flow between scopes, e.g. from where work is submitted on one thread
+/// to where it is executed on another. Recorded with [`crate::profile_flow`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlowRecord {
+    /// All points with the same id belong to the same flow.
+    pub flow_id: u64,
+
+    /// When the point was recorded.
+    pub time_ns: NanoSecond,
+
+    /// The number of scopes that were open when the point was recorded,
+    /// so `depth - 1` is the depth of the scope the point belongs to.
+    /// `0` if it was recorded outside of any scope.
+    pub depth: usize,
+}
+
+/// An instantaneous event, e.g. "level loaded", recorded with [`crate::profile_marker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MarkerRecord {
+    /// Identifies the name of the marker.
+    /// It can be looked up in [`crate::ScopeCollection`] like that of a scope.
+    pub id: ScopeId,
+
+    /// When the event happened.
+    pub time_ns: NanoSecond,
+}
+
 /// Used when parsing a Stream.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Scope<'s> {
@@ -96,6 +255,11 @@ impl Stream {
         self.0.clear();
     }
 
+    /// Removes everything after the first `len` bytes, e.g. to discard a scope.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.0.truncate(len);
+    }
+
     /// Extends the stream with the given bytes.
     fn extend(&mut self, bytes: &[u8]) {
         self.0.extend(bytes);
@@ -118,7 +282,23 @@ impl Stream {
         scope_id: ScopeId,
         data: &str,
     ) -> (usize, NanoSecond) {
-        self.0.push(SCOPE_BEGIN);
+        self.begin_scope_with_tags(now_ns, scope_id, data, &[])
+    }
+
+    /// Like [`Self::begin_scope`], but with key-value tags, see [`crate::profile_scope_tags`].
+    #[inline]
+    pub fn begin_scope_with_tags<F: Fn() -> i64>(
+        &mut self,
+        now_ns: F,
+        scope_id: ScopeId,
+        data: &str,
+        tags: &[(&str, &str)],
+    ) -> (usize, NanoSecond) {
+        self.0.push(if tags.is_empty() {
+            SCOPE_BEGIN
+        } else {
+            SCOPE_BEGIN_TAGGED
+        });
 
         self.write_scope_id(scope_id);
         let time_stamp_offset = self.0.len();
@@ -127,6 +307,13 @@ impl Stream {
             .expect("can't fail");
 
         self.write_str(data);
+        if !tags.is_empty() {
+            self.write_leb128(tags.len() as u64);
+            for (key, value) in tags {
+                self.write_str(key);
+                self.write_str(value);
+            }
+        }
         // Put place-holder value for total scope size.
         let offset = self.0.len();
         self.write_scope_size(ScopeSize::unfinished());
@@ -158,6 +345,56 @@ impl Stream {
         self.write_nanos(stop_ns);
     }
 
+    /// Records a sample of a counter.
+    #[inline]
+    pub fn record_counter(&mut self, counter_id: ScopeId, time_ns: NanoSecond, value: f64) {
+        self.0.push(COUNTER);
+        self.write_scope_id(counter_id);
+        self.write_nanos(time_ns);
+        self.0.write_f64::<LE>(value).expect("can't fail");
+    }
+
+    /// Records an async task starting or stopping to run on this thread.
+    #[inline]
+    pub fn record_task_event(&mut self, record: TaskRecord) {
+        self.0.push(match record.event {
+            TaskEvent::Enter => TASK_ENTER,
+            TaskEvent::Exit => TASK_EXIT,
+        });
+        self.write_scope_id(record.id);
+        self.0.write_u64::<LE>(record.task_id).expect("can't fail");
+        self.write_nanos(record.time_ns);
+    }
+
+    /// Records a point of a flow between scopes.
+    #[inline]
+    pub fn record_flow(&mut self, record: FlowRecord) {
+        self.0.push(FLOW);
+        self.0.write_u64::<LE>(record.flow_id).expect("can't fail");
+        self.write_nanos(record.time_ns);
+        self.0
+            .write_u32::<LE>(record.depth.try_into().unwrap_or(u32::MAX))
+            .expect("can't fail");
+    }
+
+    /// Records an instantaneous event.
+    #[inline]
+    pub fn record_marker(&mut self, record: MarkerRecord) {
+        self.0.push(MARKER);
+        self.write_scope_id(record.id);
+        self.write_nanos(record.time_ns);
+    }
+
+    /// Replace the ids of all scopes, counters, tasks and markers in the stream,
+    /// e.g. to match the [`crate::ScopeCollection`] of another recording.
+    pub(crate) fn remap_ids(&mut self, remap: impl Fn(ScopeId) -> ScopeId) -> Result<()> {
+        for (position, id) in Reader::from_start(self).read_ids()? {
+            let mut dest = &mut self.0[position..position + size_of::<u32>()];
+            dest.write_u32::<LE>(remap(id).0.get()).expect("can't fail");
+        }
+        Ok(())
+    }
+
     #[inline]
     fn write_nanos(&mut self, nanos: NanoSecond) {
         self.0.write_i64::<LE>(nanos).expect("can't fail");
@@ -178,11 +415,22 @@ impl Stream {
 
     #[inline]
     fn write_str(&mut self, s: &str) {
-        // Future-proof: we may want to use VLQs later.
-        const MAX_STRING_LENGTH: usize = 127;
         let len = s.len().min(MAX_STRING_LENGTH);
-        self.0.write_u8(len as u8).expect("can't fail");
-        self.0.extend(s[0..len].as_bytes()); // This may split a character in two. The parser should handle that.
+        self.write_leb128(len as u64);
+        self.0.extend(&s.as_bytes()[0..len]); // This may split a character in two. The parser should handle that.
+    }
+
+    #[inline]
+    fn write_leb128(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.0.push(byte);
+                return;
+            }
+            self.0.push(byte | 0x80);
+        }
     }
 }
 
@@ -223,24 +471,57 @@ impl StreamInfo {
     /// Try to avoid calling this, and instead keep score while collecting a [`StreamInfo`].
     pub fn parse(stream: Stream) -> Result<StreamInfo> {
         let top_scopes = Reader::from_start(&stream).read_top_scopes()?;
-        if top_scopes.is_empty() {
-            Ok(StreamInfo {
-                stream,
-                num_scopes: 0,
-                depth: 0,
-                range_ns: (NanoSecond::MAX, NanoSecond::MIN),
-            })
+        let Records {
+            counters,
+            tasks,
+            flows,
+            markers,
+        } = Reader::from_start(&stream).read_records()?;
+
+        let mut range_ns = (NanoSecond::MAX, NanoSecond::MIN);
+        if let (Some(first), Some(last)) = (top_scopes.first(), top_scopes.last()) {
+            range_ns = (first.record.start_ns, last.record.stop_ns());
+        }
+        let record_times = counters
+            .iter()
+            .map(|counter| counter.time_ns)
+            .chain(tasks.iter().map(|task| task.time_ns))
+            .chain(flows.iter().map(|flow| flow.time_ns))
+            .chain(markers.iter().map(|marker| marker.time_ns));
+        for time_ns in record_times {
+            range_ns.0 = range_ns.0.min(time_ns);
+            range_ns.1 = range_ns.1.max(time_ns);
+        }
+
+        let (num_scopes, depth) = if top_scopes.is_empty() {
+            (0, 0)
         } else {
-            let (num_scopes, depth) = Reader::count_scope_and_depth(&stream)?;
-            let min_ns = top_scopes.first().unwrap().record.start_ns;
-            let max_ns = top_scopes.last().unwrap().record.stop_ns();
+            Reader::count_scope_and_depth(&stream)?
+        };
 
-            Ok(StreamInfo {
-                stream,
-                num_scopes,
-                depth,
-                range_ns: (min_ns, max_ns),
-            })
+        Ok(StreamInfo {
+            stream,
+            num_scopes,
+            depth,
+            range_ns,
+        })
+    }
+
+    /// A stream with a single scope with the given times, e.g. measured on the GPU.
+    pub(crate) fn from_scope(
+        scope_id: ScopeId,
+        start_ns: NanoSecond,
+        stop_ns: NanoSecond,
+        data: &str,
+    ) -> Self {
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| start_ns, scope_id, data);
+        stream.end_scope(offset, stop_ns);
+        StreamInfo {
+            stream,
+            num_scopes: 1,
+            depth: 1,
+            range_ns: (start_ns, stop_ns),
         }
     }
 
@@ -308,6 +589,15 @@ impl ScopeSize {
     }
 }
 
+/// All the records of a stream that are not scopes, see [`Reader::read_records`].
+#[derive(Default)]
+struct Records {
+    counters: Vec<CounterRecord>,
+    tasks: Vec<TaskRecord>,
+    flows: Vec<FlowRecord>,
+    markers: Vec<MarkerRecord>,
+}
+
 /// Errors that can happen when parsing a [`Stream`] of profile data.
 #[derive(Debug)]
 pub enum Error {
@@ -328,6 +618,15 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Parses a [`Stream`] of profiler data.
 pub struct Reader<'s>(std::io::Cursor<&'s [u8]>);
 
+/// The start of a scope, as parsed by [`Reader::parse_scope_begin`].
+struct ScopeBegin<'s> {
+    scope_id: ScopeId,
+    start_ns: NanoSecond,
+    data: &'s str,
+    tags: ScopeTags<'s>,
+    scope_size: ScopeSize,
+}
+
 impl<'s> Reader<'s> {
     /// Returns a reader that starts reading from the start of the stream.
     pub fn from_start(stream: &'s Stream) -> Self {
@@ -348,18 +647,36 @@ impl<'s> Reader<'s> {
     /// Parse the next scope in the stream, if any,
     /// and advance to the next sibling scope (if any).
     fn parse_scope(&mut self) -> Result<Option<Scope<'s>>> {
-        match self.peek_u8() {
-            Some(SCOPE_BEGIN) => {
-                self.parse_u8()
-                    .expect("swallowing already peeked SCOPE_BEGIN");
+        loop {
+            match self.peek_u8() {
+                Some(SCOPE_BEGIN | SCOPE_BEGIN_TAGGED) => break,
+                Some(COUNTER) => {
+                    // Counters are read with `read_counters`.
+                    self.parse_counter()?;
+                }
+                Some(TASK_ENTER | TASK_EXIT) => {
+                    // Task events are read with `read_task_records`.
+                    self.parse_task_record()?;
+                }
+                Some(FLOW) => {
+                    // Flow points are read with `read_flow_records`.
+                    self.parse_flow_record()?;
+                }
+                Some(MARKER) => {
+                    // Markers are read with `read_markers`.
+                    self.parse_marker()?;
+                }
+                Some(_) | None => return Ok(None),
             }
-            Some(_) | None => return Ok(None),
         }
 
-        let scope_id = self.parse_scope_id()?;
-        let start_ns = self.parse_nanos()?;
-        let data = self.parse_string()?;
-        let scope_size = self.parse_scope_size()?;
+        let ScopeBegin {
+            scope_id,
+            start_ns,
+            data,
+            tags,
+            scope_size,
+        } = self.parse_scope_begin()?;
         if scope_size == ScopeSize::unfinished() {
             return Err(Error::ScopeNeverEnded);
         }
@@ -381,6 +698,7 @@ impl<'s> Reader<'s> {
                 start_ns,
                 duration_ns: stop_ns - start_ns,
                 data,
+                tags,
             },
             child_begin_position,
             child_end_position,
@@ -397,6 +715,182 @@ impl<'s> Reader<'s> {
         Ok(scopes)
     }
 
+    /// Read all counter samples from here until the end of the stream,
+    /// including those inside of scopes, in the order they were recorded.
+    pub fn read_counters(self) -> Result<Vec<CounterRecord>> {
+        Ok(self.read_records()?.counters)
+    }
+
+    /// Read all task events from here until the end of the stream,
+    /// including those inside of scopes, in the order they were recorded.
+    pub fn read_task_records(self) -> Result<Vec<TaskRecord>> {
+        Ok(self.read_records()?.tasks)
+    }
+
+    /// Read all flow points from here until the end of the stream,
+    /// including those inside of scopes, in the order they were recorded.
+    pub fn read_flow_records(self) -> Result<Vec<FlowRecord>> {
+        Ok(self.read_records()?.flows)
+    }
+
+    /// Read all markers from here until the end of the stream,
+    /// including those inside of scopes, in the order they were recorded.
+    pub fn read_markers(self) -> Result<Vec<MarkerRecord>> {
+        Ok(self.read_records()?.markers)
+    }
+
+    /// Read all records that are not scopes, skipping over the scopes.
+    fn read_records(mut self) -> Result<Records> {
+        let mut records = Records::default();
+        while let Some(sentinel) = self.peek_u8() {
+            match sentinel {
+                SCOPE_BEGIN | SCOPE_BEGIN_TAGGED => {
+                    self.parse_scope_begin()?;
+                }
+                SCOPE_END => {
+                    self.parse_u8()?;
+                    self.parse_nanos()?;
+                }
+                COUNTER => records.counters.push(self.parse_counter()?),
+                TASK_ENTER | TASK_EXIT => records.tasks.push(self.parse_task_record()?),
+                FLOW => records.flows.push(self.parse_flow_record()?),
+                MARKER => records.markers.push(self.parse_marker()?),
+                _ => return Err(Error::InvalidStream),
+            }
+        }
+        Ok(records)
+    }
+
+    /// Read the ids of all scopes and records from here until the end of the stream,
+    /// together with their positions in the stream.
+    fn read_ids(mut self) -> Result<Vec<(usize, ScopeId)>> {
+        let mut ids = vec![];
+        while let Some(sentinel) = self.peek_u8() {
+            // The id always comes right after the sentinel:
+            let position = self.0.position() as usize + 1;
+            match sentinel {
+                SCOPE_BEGIN | SCOPE_BEGIN_TAGGED => {
+                    ids.push((position, self.parse_scope_begin()?.scope_id));
+                }
+                SCOPE_END => {
+                    self.parse_u8()?;
+                    self.parse_nanos()?;
+                }
+                COUNTER => ids.push((position, self.parse_counter()?.id)),
+                TASK_ENTER | TASK_EXIT => ids.push((position, self.parse_task_record()?.id)),
+                FLOW => {
+                    // Flows are identified by their own ids, not scope ids:
+                    self.parse_flow_record()?;
+                }
+                MARKER => ids.push((position, self.parse_marker()?.id)),
+                _ => return Err(Error::InvalidStream),
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Parse the start of a scope, up to and including its size.
+    fn parse_scope_begin(&mut self) -> Result<ScopeBegin<'s>> {
+        let tagged = match self.parse_u8()? {
+            SCOPE_BEGIN => false,
+            SCOPE_BEGIN_TAGGED => true,
+            _ => return Err(Error::InvalidStream),
+        };
+        let scope_id = self.parse_scope_id()?;
+        let start_ns = self.parse_nanos()?;
+        let data = self.parse_string()?;
+        let tags = if tagged {
+            self.parse_tags()?
+        } else {
+            ScopeTags::default()
+        };
+        let scope_size = self.parse_scope_size()?;
+        Ok(ScopeBegin {
+            scope_id,
+            start_ns,
+            data,
+            tags,
+            scope_size,
+        })
+    }
+
+    fn parse_tags(&mut self) -> Result<ScopeTags<'s>> {
+        let num_tags =
+            usize::try_from(self.parse_leb128()?).map_err(|_err| Error::InvalidStream)?;
+        let begin = self.0.position() as usize;
+        for _ in 0..num_tags {
+            self.parse_string()?;
+            self.parse_string()?;
+        }
+        let end = self.0.position() as usize;
+        Ok(ScopeTags {
+            num_tags,
+            bytes: &self.0.get_ref()[begin..end],
+        })
+    }
+
+    fn parse_counter(&mut self) -> Result<CounterRecord> {
+        if self.parse_u8()? != COUNTER {
+            return Err(Error::InvalidStream);
+        }
+        let id = self.parse_scope_id()?;
+        let time_ns = self.parse_nanos()?;
+        let value = self
+            .0
+            .read_f64::<LE>()
+            .map_err(|_err| Error::PrematureEnd)?;
+        Ok(CounterRecord { id, time_ns, value })
+    }
+
+    fn parse_task_record(&mut self) -> Result<TaskRecord> {
+        let event = match self.parse_u8()? {
+            TASK_ENTER => TaskEvent::Enter,
+            TASK_EXIT => TaskEvent::Exit,
+            _ => return Err(Error::InvalidStream),
+        };
+        let id = self.parse_scope_id()?;
+        let task_id = self
+            .0
+            .read_u64::<LE>()
+            .map_err(|_err| Error::PrematureEnd)?;
+        let time_ns = self.parse_nanos()?;
+        Ok(TaskRecord {
+            id,
+            task_id,
+            time_ns,
+            event,
+        })
+    }
+
+    fn parse_flow_record(&mut self) -> Result<FlowRecord> {
+        if self.parse_u8()? != FLOW {
+            return Err(Error::InvalidStream);
+        }
+        let flow_id = self
+            .0
+            .read_u64::<LE>()
+            .map_err(|_err| Error::PrematureEnd)?;
+        let time_ns = self.parse_nanos()?;
+        let depth = self
+            .0
+            .read_u32::<LE>()
+            .map_err(|_err| Error::PrematureEnd)?;
+        Ok(FlowRecord {
+            flow_id,
+            time_ns,
+            depth: depth as usize,
+        })
+    }
+
+    fn parse_marker(&mut self) -> Result<MarkerRecord> {
+        if self.parse_u8()? != MARKER {
+            return Err(Error::InvalidStream);
+        }
+        let id = self.parse_scope_id()?;
+        let time_ns = self.parse_nanos()?;
+        Ok(MarkerRecord { id, time_ns })
+    }
+
     /// [`None`] if at end of stream
     fn peek_u8(&mut self) -> Option<u8> {
         let position = self.0.position();
@@ -429,11 +923,26 @@ impl<'s> Reader<'s> {
             .map(ScopeSize)
     }
 
+    fn parse_leb128(&mut self) -> Result<u64> {
+        let mut value = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.parse_u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::InvalidStream)
+    }
+
     fn parse_string(&mut self) -> Result<&'s str> {
-        let len = self.parse_u8().map_err(|_err| Error::PrematureEnd)? as usize;
+        let len = self.parse_leb128()?;
         let data = self.0.get_ref();
         let begin = self.0.position() as usize;
-        let end = begin + len;
+        let end = usize::try_from(len)
+            .ok()
+            .and_then(|len| begin.checked_add(len))
+            .ok_or(Error::PrematureEnd)?;
         if end <= data.len() {
             let s = longest_valid_utf8_prefix(&data[begin..end]);
             self.0.set_position(end as u64);
@@ -477,7 +986,7 @@ fn longest_valid_utf8_prefix(data: &[u8]) -> &str {
     match std::str::from_utf8(data) {
         Ok(s) => s,
         Err(error) => {
-            // The string may be been truncated to fit a max length of 255.
+            // The string may be been truncated to fit `MAX_STRING_LENGTH`.
             // This truncation may have happened in the middle of a unicode character.
             std::str::from_utf8(&data[..error.valid_up_to()]).expect("We can trust valid_up_to")
         }
@@ -505,7 +1014,8 @@ fn write_scope() {
         ScopeRecord {
             start_ns: 100,
             duration_ns: 200,
-            data: "data"
+            data: "data",
+            tags: ScopeTags::default(),
         }
     );
 }
@@ -530,7 +1040,8 @@ fn test_profile_data() {
         ScopeRecord {
             start_ns: 100,
             duration_ns: 300,
-            data: "data_top"
+            data: "data_top",
+            tags: ScopeTags::default(),
         }
     );
 
@@ -546,7 +1057,8 @@ fn test_profile_data() {
         ScopeRecord {
             start_ns: 200,
             duration_ns: 100,
-            data: "data_middle_0"
+            data: "data_middle_0",
+            tags: ScopeTags::default(),
         }
     );
     assert_eq!(
@@ -554,7 +1066,236 @@ fn test_profile_data() {
         ScopeRecord {
             start_ns: 300,
             duration_ns: 100,
-            data: "data_middle_1"
+            data: "data_middle_1",
+            tags: ScopeTags::default(),
         }
     );
 }
+
+#[test]
+fn test_scope_tags() {
+    let mut stream = Stream::default();
+    let (top, _) = stream.begin_scope_with_tags(
+        || 100,
+        ScopeId::new(1),
+        "data",
+        &[("mesh", "cube.obj"), ("lod", "2")],
+    );
+    let (child, _) = stream.begin_scope(|| 150, ScopeId::new(2), "");
+    stream.end_scope(child, 200);
+    stream.end_scope(top, 300);
+
+    let top_scopes = Reader::from_start(&stream).read_top_scopes().unwrap();
+    assert_eq!(top_scopes.len(), 1);
+    let tags = top_scopes[0].record.tags;
+    assert_eq!(tags.len(), 2);
+    assert_eq!(
+        tags.iter().collect::<Vec<_>>(),
+        [("mesh", "cube.obj"), ("lod", "2")]
+    );
+    assert_eq!(top_scopes[0].record.data, "data");
+
+    // Untagged scopes are encoded as before:
+    let children = Reader::with_offset(&stream, top_scopes[0].child_begin_position)
+        .unwrap()
+        .read_top_scopes()
+        .unwrap();
+    assert_eq!(children.len(), 1);
+    assert!(children[0].record.tags.is_empty());
+    assert_eq!(
+        stream.bytes()[top_scopes[0].child_begin_position as usize],
+        SCOPE_BEGIN
+    );
+
+    assert_eq!(
+        Reader::from_start(&stream)
+            .read_ids()
+            .unwrap()
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect::<Vec<_>>(),
+        [ScopeId::new(1), ScopeId::new(2)]
+    );
+}
+
+#[test]
+fn counters_between_scopes() {
+    let mut stream = Stream::default();
+    stream.record_counter(ScopeId::new(10), 50, 1.0);
+    let (top, _) = stream.begin_scope(|| 100, ScopeId::new(1), "");
+    stream.record_counter(ScopeId::new(10), 150, 2.0);
+    let (child, _) = stream.begin_scope(|| 200, ScopeId::new(2), "");
+    stream.end_scope(child, 300);
+    stream.record_counter(ScopeId::new(11), 350, -3.5);
+    stream.end_scope(top, 400);
+    stream.record_counter(ScopeId::new(10), 450, 4.0);
+
+    let top_scopes = Reader::from_start(&stream).read_top_scopes().unwrap();
+    assert_eq!(top_scopes.len(), 1);
+    let children = Reader::with_offset(&stream, top_scopes[0].child_begin_position)
+        .unwrap()
+        .read_top_scopes()
+        .unwrap();
+    assert_eq!(children.len(), 1);
+    assert_eq!(Reader::count_scope_and_depth(&stream).unwrap(), (2, 2));
+
+    let counters = Reader::from_start(&stream).read_counters().unwrap();
+    let samples: Vec<_> = counters
+        .iter()
+        .map(|counter| (counter.id, counter.time_ns, counter.value))
+        .collect();
+    assert_eq!(
+        samples,
+        [
+            (ScopeId::new(10), 50, 1.0),
+            (ScopeId::new(10), 150, 2.0),
+            (ScopeId::new(11), 350, -3.5),
+            (ScopeId::new(10), 450, 4.0),
+        ]
+    );
+}
+
+#[test]
+fn task_records_between_scopes() {
+    let task = |task_id, time_ns, event| TaskRecord {
+        id: ScopeId::new(20),
+        task_id,
+        time_ns,
+        event,
+    };
+    let mut stream = Stream::default();
+    stream.record_task_event(task(1, 50, TaskEvent::Enter));
+    let (top, _) = stream.begin_scope(|| 100, ScopeId::new(1), "");
+    stream.record_task_event(task(2, 150, TaskEvent::Enter));
+    stream.record_counter(ScopeId::new(10), 160, 1.0);
+    stream.record_task_event(task(2, 200, TaskEvent::Exit));
+    stream.end_scope(top, 300);
+    stream.record_task_event(task(1, 350, TaskEvent::Exit));
+
+    assert_eq!(
+        Reader::from_start(&stream).read_top_scopes().unwrap().len(),
+        1
+    );
+    assert_eq!(
+        Reader::from_start(&stream).read_counters().unwrap().len(),
+        1
+    );
+    assert_eq!(
+        Reader::from_start(&stream).read_task_records().unwrap(),
+        [
+            task(1, 50, TaskEvent::Enter),
+            task(2, 150, TaskEvent::Enter),
+            task(2, 200, TaskEvent::Exit),
+            task(1, 350, TaskEvent::Exit),
+        ]
+    );
+    assert_eq!(StreamInfo::parse(stream).unwrap().range_ns, (50, 350));
+}
+
+#[test]
+fn long_scope_data() {
+    let data_of_len = |len: usize| -> String { "abcdefg".chars().cycle().take(len).collect() };
+    let read_data = |stream: &Stream| -> String {
+        let scopes = Reader::from_start(stream).read_top_scopes().unwrap();
+        assert_eq!(scopes.len(), 1);
+        scopes[0].record.data.to_owned()
+    };
+
+    for len in [0, 127, 128, 300, 5_000, MAX_STRING_LENGTH] {
+        let data = data_of_len(len);
+        let mut stream = Stream::default();
+        let (start, _) = stream.begin_scope(|| 100, ScopeId::new(1), &data);
+        stream.end_scope(start, 200);
+        assert_eq!(read_data(&stream), data);
+    }
+
+    // Too long data is truncated, without leaving half a character behind:
+    let data = format!("{}ö", data_of_len(MAX_STRING_LENGTH - 1));
+    let mut stream = Stream::default();
+    let (start, _) = stream.begin_scope(|| 100, ScopeId::new(1), &data);
+    stream.end_scope(start, 200);
+    assert_eq!(read_data(&stream), data_of_len(MAX_STRING_LENGTH - 1));
+
+    // Streams from before PFD9, with a single byte length, still parse:
+    let mut bytes = vec![SCOPE_BEGIN];
+    bytes.extend(1_u32.to_le_bytes());
+    bytes.extend(100_i64.to_le_bytes());
+    bytes.push(4);
+    bytes.extend(b"data");
+    bytes.extend(0_u64.to_le_bytes());
+    bytes.push(SCOPE_END);
+    bytes.extend(200_i64.to_le_bytes());
+    assert_eq!(read_data(&Stream::from(bytes)), "data");
+}
+
+#[test]
+fn flow_records_between_scopes() {
+    let flow = |flow_id, time_ns, depth| FlowRecord {
+        flow_id,
+        time_ns,
+        depth,
+    };
+    let mut stream = Stream::default();
+    stream.record_flow(flow(7, 50, 0));
+    let (top, _) = stream.begin_scope(|| 100, ScopeId::new(1), "");
+    let (child, _) = stream.begin_scope(|| 110, ScopeId::new(2), "");
+    stream.record_flow(flow(8, 150, 2));
+    stream.record_task_event(TaskRecord {
+        id: ScopeId::new(20),
+        task_id: 1,
+        time_ns: 160,
+        event: TaskEvent::Enter,
+    });
+    stream.end_scope(child, 200);
+    stream.end_scope(top, 300);
+    stream.record_flow(flow(7, 350, 0));
+
+    assert_eq!(Reader::count_scope_and_depth(&stream).unwrap(), (2, 2));
+    assert_eq!(
+        Reader::from_start(&stream)
+            .read_task_records()
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        Reader::from_start(&stream).read_flow_records().unwrap(),
+        [flow(7, 50, 0), flow(8, 150, 2), flow(7, 350, 0)]
+    );
+    assert_eq!(StreamInfo::parse(stream).unwrap().range_ns, (50, 350));
+}
+
+#[test]
+fn markers_between_scopes() {
+    let marker = |id, time_ns| MarkerRecord {
+        id: ScopeId::new(id),
+        time_ns,
+    };
+    let mut stream = Stream::default();
+    stream.record_marker(marker(10, 50));
+    let (top, _) = stream.begin_scope(|| 100, ScopeId::new(1), "");
+    let (child, _) = stream.begin_scope(|| 110, ScopeId::new(2), "");
+    stream.record_marker(marker(11, 150));
+    stream.end_scope(child, 200);
+    stream.end_scope(top, 300);
+    stream.record_marker(marker(10, 350));
+
+    assert_eq!(Reader::count_scope_and_depth(&stream).unwrap(), (2, 2));
+    assert_eq!(
+        Reader::from_start(&stream).read_top_scopes().unwrap().len(),
+        1
+    );
+    assert_eq!(
+        Reader::from_start(&stream).read_markers().unwrap(),
+        [marker(10, 50), marker(11, 150), marker(10, 350)]
+    );
+
+    stream
+        .remap_ids(|id| ScopeId::new(id.0.get() + 100))
+        .unwrap();
+    assert_eq!(
+        Reader::from_start(&stream).read_markers().unwrap(),
+        [marker(110, 50), marker(111, 150), marker(110, 350)]
+    );
+    assert_eq!(StreamInfo::parse(stream).unwrap().range_ns, (50, 350));
+}
@@ -0,0 +1,476 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    sync::Arc,
+};
+
+use anyhow::Context as _;
+use bincode::Options as _;
+use byteorder::{ReadBytesExt as _, LE};
+
+use crate::{FrameData, FrameIndex, NanoSecond, ScopeCollection, ScopeDetails};
+
+const INDEX_MAGIC: &[u8; 4] = b"PFIX";
+
+/// Size of a [`FileIndexEntry`] on disk.
+const ENTRY_SIZE: u64 = 3 * 8;
+
+/// Size of what comes after the index: its offset, and then [`INDEX_MAGIC`] again.
+const TRAILER_SIZE: u64 = 8 + 4;
+
+/// Where a frame is stored in a `.puffin` file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileIndexEntry {
+    /// [`FrameData::frame_index`] of the frame.
+    pub frame_index: FrameIndex,
+
+    /// Byte offset of the frame from the start of the `.puffin` data.
+    pub offset: u64,
+
+    /// [`FrameData::duration_ns`] of the frame.
+    pub duration_ns: NanoSecond,
+}
+
+/// An index of the frames in a `.puffin` file, to read frames on demand.
+///
+/// This lets you seek to specific frames without reading the whole file first,
+/// so you can open recordings that are too large to load with [`crate::FrameView::read`].
+///
+/// [`crate::FrameView::write`] stores the index at the end of the file.
+/// Readers that don't know about the index stop reading before it.
+/// Files without a valid index, e.g. written before the index was added or cut short while recording,
+/// are indexed by skipping through them instead.
+///
+/// The layout, after the last frame, is:
+/// * `0u32`, the end-of-stream marker.
+/// * `PFIX`, the number of entries (`u32`), and then for each entry:
+///   the frame index (`u64`), byte offset (`u64`) and duration in nanoseconds (`i64`).
+/// * The length (`u32`) of all scope details, and the scope details themselves.
+/// * The byte offset (`u64`) of the `PFIX` above, and then `PFIX` again.
+///
+/// All integers are little endian.
+#[derive(Clone, Default)]
+pub struct FileIndex {
+    entries: Vec<FileIndexEntry>,
+    scope_collection: ScopeCollection,
+}
+
+impl FileIndex {
+    /// The frames in the file, in the order they are stored.
+    pub fn entries(&self) -> &[FileIndexEntry] {
+        &self.entries
+    }
+
+    /// All the scopes used by the frames in the file.
+    ///
+    /// The frames themselves only carry the scopes that were new to them,
+    /// so you need these when you read frames out of order.
+    pub fn scope_collection(&self) -> &ScopeCollection {
+        &self.scope_collection
+    }
+
+    /// Index the frames of a `.puffin` file.
+    ///
+    /// Uses the index at the end of the file if there is one.
+    /// Otherwise, or if the index doesn't make sense (e.g. because the file was truncated),
+    /// this skips through the whole file, reading only the small frame headers.
+    ///
+    /// Afterwards, the read position is unspecified.
+    pub fn read(read: &mut (impl Read + Seek)) -> anyhow::Result<Self> {
+        if let Ok(Some(index)) = Self::read_footer(read) {
+            Ok(index)
+        } else {
+            Self::scan(read)
+        }
+    }
+
+    /// Read the index at the end of the file, or `None` if it is missing or invalid.
+    fn read_footer(read: &mut (impl Read + Seek)) -> anyhow::Result<Option<Self>> {
+        let file_len = read.seek(SeekFrom::End(0))?;
+        // `PUF0`, end-of-stream marker, an empty index and the trailer:
+        if file_len < 4 + 4 + 4 + 4 + 4 + TRAILER_SIZE {
+            return Ok(None);
+        }
+
+        read.seek(SeekFrom::End(-(TRAILER_SIZE as i64)))?;
+        let index_offset = read.read_u64::<LE>()?;
+        if !read_magic(read)? || file_len - TRAILER_SIZE < index_offset || index_offset < 8 {
+            return Ok(None);
+        }
+
+        read.seek(SeekFrom::Start(index_offset - 4))?;
+        if read.read_u32::<LE>()? != 0 || !read_magic(read)? {
+            return Ok(None);
+        }
+
+        let num_entries = read.read_u32::<LE>()? as u64;
+        let entries_end = index_offset + 4 + 4 + num_entries * ENTRY_SIZE;
+        if file_len - TRAILER_SIZE < entries_end + 4 {
+            return Ok(None);
+        }
+
+        let mut entries = Vec::with_capacity(num_entries as usize);
+        let mut min_offset = 4; // after `PUF0`
+        for _ in 0..num_entries {
+            let entry = FileIndexEntry {
+                frame_index: read.read_u64::<LE>()?,
+                offset: read.read_u64::<LE>()?,
+                duration_ns: read.read_i64::<LE>()?,
+            };
+            if entry.offset < min_offset || index_offset - 4 <= entry.offset {
+                return Ok(None);
+            }
+            min_offset = entry.offset + 1;
+            entries.push(entry);
+        }
+
+        let scopes_len = read.read_u32::<LE>()? as u64;
+        if entries_end + 4 + scopes_len != file_len - TRAILER_SIZE {
+            return Ok(None);
+        }
+        let mut serialized_scopes = vec![0; scopes_len as usize];
+        read.read_exact(&mut serialized_scopes)?;
+        let scopes: Vec<ScopeDetails> = bincode::options()
+            .deserialize(&serialized_scopes)
+            .context("Can not deserialize scope details")?;
+
+        let mut scope_collection = ScopeCollection::default();
+        for scope in scopes {
+            if scope.scope_id.is_none() {
+                return Ok(None);
+            }
+            scope_collection.insert(Arc::new(scope));
+        }
+
+        Ok(Some(Self {
+            entries,
+            scope_collection,
+        }))
+    }
+
+    /// Skip through a `.puffin` file from its start, noting where each frame is.
+    ///
+    /// Frames in formats from before 2021-11 (`PFD1` and older) lack a separate header,
+    /// so those are read in whole.
+    fn scan(read: &mut (impl Read + Seek)) -> anyhow::Result<Self> {
+        let file_len = read.seek(SeekFrom::End(0))?;
+        read.rewind()?;
+
+        let mut magic = [0_u8; 4];
+        read.read_exact(&mut magic)?;
+        if &magic != b"PUF0" {
+            anyhow::bail!("Expected .puffin magic header of 'PUF0', found {:?}", magic);
+        }
+
+        let mut index = Self::default();
+        let mut offset = 4;
+        while offset < file_len {
+            let mut header = [0_u8; 4];
+            read.read_exact(&mut header)?;
+            if header == [0_u8; 4] {
+                break; // end-of-stream sentinel, possibly followed by the index.
+            }
+
+            let (meta, scope_delta) = match &header {
+                b"PFD2" | b"PFD3" | b"PFD4" | b"PFD5" | b"PFD6" | b"PFD7" | b"PFD8" | b"PFD9"
+                | b"PFDA" | b"PFDB" | b"PFDC" | b"PFDD" => {
+                    let meta_length = read.read_u32::<LE>()? as usize;
+                    let mut meta = vec![0_u8; meta_length];
+                    read.read_exact(&mut meta)?;
+                    let meta = crate::frame_data::deserialize_meta(&header, &meta)?;
+
+                    let streams_compressed_length = read.read_u32::<LE>()?;
+                    let compression_kind_length = if &header == b"PFD2" { 0 } else { 1 };
+                    read.seek(SeekFrom::Current(
+                        compression_kind_length + i64::from(streams_compressed_length),
+                    ))?;
+
+                    let scope_delta = if &header == b"PFD2" || &header == b"PFD3" {
+                        vec![]
+                    } else {
+                        let serialized_scope_len = read.read_u32::<LE>()? as usize;
+                        let mut serialized_scopes = vec![0; serialized_scope_len];
+                        read.read_exact(&mut serialized_scopes)?;
+                        crate::frame_data::deserialize_scope_delta(&header, &serialized_scopes)?
+                    };
+                    (meta, scope_delta)
+                }
+                _ => {
+                    // Older or newer formats: let `FrameData::read_next` deal with it.
+                    read.seek(SeekFrom::Start(offset))?;
+                    let frame = FrameData::read_next(read)?
+                        .with_context(|| format!("Expected a frame at byte {offset}"))?;
+                    (*frame.meta(), vec![])
+                }
+            };
+
+            let next_offset = read.stream_position()?;
+            anyhow::ensure!(
+                next_offset <= file_len,
+                "Frame {} at byte {offset} is cut short",
+                meta.frame_index
+            );
+
+            for scope in scope_delta {
+                anyhow::ensure!(
+                    scope.scope_id.is_some(),
+                    "Frame {} has a scope without an id",
+                    meta.frame_index
+                );
+                index.scope_collection.insert(Arc::new(scope));
+            }
+            index.entries.push(FileIndexEntry {
+                frame_index: meta.frame_index,
+                offset,
+                duration_ns: meta.range_ns.1 - meta.range_ns.0,
+            });
+            offset = next_offset;
+        }
+
+        Ok(index)
+    }
+
+    /// Read one of the frames in [`Self::entries`] from the file.
+    ///
+    /// The thread streams stay packed until you call [`FrameData::unpacked`].
+    /// Use [`Self::scope_collection`] to look up its scopes.
+    pub fn read_frame(
+        &self,
+        read: &mut (impl Read + Seek),
+        entry: &FileIndexEntry,
+    ) -> anyhow::Result<FrameData> {
+        read.seek(SeekFrom::Start(entry.offset))?;
+        let frame = FrameData::read_next(read)?
+            .with_context(|| format!("Expected a frame at byte {}", entry.offset))?;
+        anyhow::ensure!(
+            frame.frame_index() == entry.frame_index,
+            "Expected frame {}, found frame {}",
+            entry.frame_index,
+            frame.frame_index()
+        );
+        Ok(frame)
+    }
+
+    /// Keep track of a frame that is about to be written at `offset`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn add_frame(&mut self, frame: &FrameData, offset: u64) {
+        for scope in &frame.scope_delta {
+            self.scope_collection.insert(scope.clone());
+        }
+        self.entries.push(FileIndexEntry {
+            frame_index: frame.frame_index(),
+            offset,
+            duration_ns: frame.duration_ns(),
+        });
+    }
+
+    /// Write the end-of-stream marker and the index, after the last frame.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn write(
+        &self,
+        write: &mut CountingWriter<impl std::io::Write>,
+    ) -> anyhow::Result<()> {
+        use byteorder::WriteBytesExt as _;
+        use std::io::Write as _;
+
+        write.write_u32::<LE>(0)?;
+
+        let index_offset = write.num_bytes;
+        write.write_all(INDEX_MAGIC)?;
+        write.write_u32::<LE>(self.entries.len() as u32)?;
+        for entry in &self.entries {
+            write.write_u64::<LE>(entry.frame_index)?;
+            write.write_u64::<LE>(entry.offset)?;
+            write.write_i64::<LE>(entry.duration_ns)?;
+        }
+
+        let scopes: Vec<_> = self.scope_collection.scopes_by_id().values().collect();
+        let serialized_scopes = bincode::options().serialize(&scopes)?;
+        write.write_u32::<LE>(serialized_scopes.len() as u32)?;
+        write.write_all(&serialized_scopes)?;
+
+        write.write_u64::<LE>(index_offset)?;
+        write.write_all(INDEX_MAGIC)?;
+        Ok(())
+    }
+}
+
+fn read_magic(read: &mut impl Read) -> std::io::Result<bool> {
+    let mut magic = [0_u8; 4];
+    read.read_exact(&mut magic)?;
+    Ok(&magic == INDEX_MAGIC)
+}
+
+/// Keeps track of how many bytes have been written, for the offsets in the [`FileIndex`].
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct CountingWriter<W> {
+    pub inner: W,
+    pub num_bytes: u64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<W> CountingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            num_bytes: 0,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<W: std::io::Write> std::io::Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.num_bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{collections::BTreeMap, io::Cursor, sync::Arc};
+
+    use crate::{FrameData, FrameView, ScopeDetails, ScopeId, Stream};
+
+    use super::FileIndex;
+
+    /// Three frames, with the scope details in the first one.
+    fn frame_view() -> FrameView {
+        frame_view_with_durations(&[100, 200, 300])
+    }
+
+    /// One frame per duration, with the scope details in the first one.
+    fn frame_view_with_durations(durations: &[i64]) -> FrameView {
+        // Don't use `GlobalProfiler` to register scopes, as that would change the scope ids in other tests.
+        let scope_id = ScopeId::new(1);
+        let mut frame_view = FrameView::default();
+        for (i, &duration_ns) in (0..).zip(durations) {
+            let mut stream = Stream::default();
+            let (offset, _) = stream.begin_scope(|| i * 1000, scope_id, "");
+            stream.end_scope(offset, i * 1000 + duration_ns);
+            let thread_streams = BTreeMap::from([crate::test_thread_stream("main", stream)]);
+            let scope_delta = if i == 0 {
+                vec![Arc::new(
+                    ScopeDetails::from_scope_id(scope_id).with_function_name("work"),
+                )]
+            } else {
+                vec![]
+            };
+            let frame = FrameData::new(i as u64, thread_streams, scope_delta, false).unwrap();
+            frame_view.add_frame(Arc::new(frame));
+        }
+        frame_view
+    }
+
+    #[test]
+    fn index_roundtrip() {
+        let scope_id = ScopeId::new(1);
+        let mut bytes = vec![];
+        frame_view().write(&mut bytes).unwrap();
+
+        // Readers that don't know of the index still work:
+        let linear = FrameView::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(linear.all_uniq().count(), 3);
+
+        let mut file = Cursor::new(bytes);
+        let index = FileIndex::read_footer(&mut file).unwrap().unwrap();
+        assert_eq!(index.entries().len(), 3);
+        assert!(index.scope_collection().fetch_by_id(&scope_id).is_some());
+
+        let last = index.entries()[2];
+        assert_eq!(last.duration_ns, 300);
+        let frame = index.read_frame(&mut file, &last).unwrap();
+        assert_eq!(frame.frame_index(), last.frame_index);
+
+        // A truncated index is not used, but the frames are still found:
+        let mut bytes = file.into_inner();
+        bytes.truncate(bytes.len() - 1);
+        let mut file = Cursor::new(bytes);
+        assert!(FileIndex::read_footer(&mut file).unwrap().is_none());
+        assert_eq!(
+            FileIndex::read(&mut file).unwrap().entries(),
+            index.entries()
+        );
+    }
+
+    #[test]
+    fn frame_view_from_index() {
+        let scope_id = ScopeId::new(1);
+        let mut bytes = vec![];
+        frame_view_with_durations(&[300, 100, 200])
+            .write(&mut bytes)
+            .unwrap();
+        let mut file = Cursor::new(bytes);
+        let index = FileIndex::read(&mut file).unwrap();
+
+        // Only the latest frame, and the slowest frame:
+        let mut frame_view = FrameView::default();
+        frame_view.set_max_recent(1);
+        frame_view.set_max_slow(1);
+        let mut num_read = 0;
+        frame_view
+            .add_frames_from_index(&mut file, &index, |num_frames| {
+                num_read = num_frames;
+                true
+            })
+            .unwrap();
+        assert_eq!(num_read, 2);
+        let frame_indices: Vec<_> = frame_view
+            .all_uniq()
+            .map(|frame| frame.frame_index())
+            .collect();
+        assert_eq!(frame_indices, [0, 2]);
+
+        // The scopes come from the index, even if the frame declaring them is skipped:
+        assert!(frame_view
+            .scope_collection()
+            .fetch_by_id(&scope_id)
+            .is_some());
+    }
+
+    #[test]
+    fn index_without_footer() {
+        let scope_id = ScopeId::new(1);
+        let frame_view = frame_view();
+
+        // Without the index at the end, like a file that was cut off:
+        let mut bytes = b"PUF0".to_vec();
+        for (i, frame) in frame_view.all_uniq().enumerate() {
+            frame
+                .write_into(frame_view.scope_collection(), i == 0, &mut bytes)
+                .unwrap();
+        }
+
+        let mut file = Cursor::new(bytes);
+        assert!(FileIndex::read_footer(&mut file).unwrap().is_none());
+        let index = FileIndex::read(&mut file).unwrap();
+        let frame_indices: Vec<_> = index
+            .entries()
+            .iter()
+            .map(|entry| entry.frame_index)
+            .collect();
+        assert_eq!(frame_indices, [0, 1, 2]);
+        assert_eq!(index.entries()[2].duration_ns, 300);
+        assert!(index.scope_collection().fetch_by_id(&scope_id).is_some());
+
+        let last = index.entries()[2];
+        let frame = index.read_frame(&mut file, &last).unwrap();
+        assert_eq!(frame.frame_index(), 2);
+        assert_eq!(frame.unpacked().unwrap().meta.num_scopes, 1);
+
+        // Scanning skips the index at the end of written files:
+        let mut bytes = vec![];
+        frame_view.write(&mut bytes).unwrap();
+        let scanned = FileIndex::scan(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(scanned.entries(), index.entries());
+
+        // A frame cut short is an error:
+        let mut bytes = file.into_inner();
+        bytes.truncate(bytes.len() - 1);
+        assert!(FileIndex::read(&mut Cursor::new(bytes)).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    io::Write as _,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use crate::{file_index::CountingWriter, FileIndex, FrameData, FrameSinkId};
+
+/// How often the recorded frames are flushed to disk.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Automatically connects to [`crate::GlobalProfiler`] and appends each new frame to a `.puffin` file.
+///
+/// Frames are written as they are produced (on a background thread) and flushed about once a second,
+/// so if the application crashes, the file still contains the frames up to shortly before the crash.
+///
+/// When the [`FileRecorder`] is dropped, a [`crate::FileIndex`] is added and the file is closed.
+pub struct FileRecorder {
+    sink_id: FrameSinkId,
+    writer: Option<std::thread::JoinHandle<()>>,
+}
+
+impl FileRecorder {
+    /// Create (or truncate) the file at `path` and start recording to it.
+    pub fn create(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let mut file = CountingWriter::new(std::io::BufWriter::new(std::fs::File::create(path)?));
+        file.write_all(b"PUF0")?;
+        file.flush()?;
+
+        let (tx, rx) = mpsc::channel();
+
+        let writer = std::thread::Builder::new()
+            .name("puffin-file-recorder".to_owned())
+            .spawn(move || write_frames(file, &rx))?;
+
+        let mut profiler = crate::GlobalProfiler::lock();
+        let sink_id = profiler.add_sink(Box::new(move |frame| {
+            tx.send(frame).ok();
+        }));
+        // The scopes registered before we were created must be part of the file too.
+        profiler.emit_scope_snapshot();
+
+        Ok(Self {
+            sink_id,
+            writer: Some(writer),
+        })
+    }
+
+    /// Sink ID
+    pub fn sink_id(&self) -> FrameSinkId {
+        self.sink_id
+    }
+}
+
+impl Drop for FileRecorder {
+    fn drop(&mut self) {
+        // Removing the sink drops the sender, which makes the writer thread finish up.
+        crate::GlobalProfiler::lock().remove_sink(self.sink_id);
+        if let Some(writer) = self.writer.take() {
+            writer.join().ok();
+        }
+    }
+}
+
+fn write_frames(
+    mut file: CountingWriter<std::io::BufWriter<std::fs::File>>,
+    rx: &mpsc::Receiver<Arc<FrameData>>,
+) {
+    let mut last_flush = std::time::Instant::now();
+    let mut index = FileIndex::default();
+
+    loop {
+        match rx.recv_timeout(FLUSH_INTERVAL) {
+            Ok(frame) => {
+                index.add_frame(&frame, file.num_bytes);
+                // Each frame only carries the scopes that are new to it,
+                // so after a failed write the rest of the file would be unreadable.
+                if let Err(err) = frame.write_into(&Default::default(), false, &mut file) {
+                    eprintln!("puffin ERROR: Failed to record frame to file: {err:#}");
+                    return;
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                if let Err(err) = index.write(&mut file) {
+                    eprintln!("puffin ERROR: Failed to write index to file: {err:#}");
+                }
+                file.flush().ok();
+                return;
+            }
+        }
+
+        if FLUSH_INTERVAL <= last_flush.elapsed() {
+            file.flush().ok();
+            last_flush = std::time::Instant::now();
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use crate::ScopeDetails;
-use crate::{Error, FrameIndex, NanoSecond, Result, StreamInfo, ThreadInfo};
+use crate::{
+    CounterRecord, Error, FlowRecord, FrameIndex, MarkerRecord, NanoSecond, Reader, Result, Scope,
+    ScopeId, ScopeRecord, Stream, StreamInfo, ThreadInfo,
+};
 #[cfg(feature = "packing")]
 use parking_lot::RwLock;
 
@@ -25,6 +28,31 @@ pub struct FrameMeta {
 
     /// Total number of scopes.
     pub num_scopes: usize,
+
+    /// Unix time in nanoseconds at the start of the frame (`range_ns.0`),
+    /// for lining the frame up with e.g. logs from other machines.
+    ///
+    /// Only recorded if turned on with [`crate::Profiler::set_record_wall_clock`],
+    /// and missing in data from older versions of puffin.
+    pub wall_clock_ns: Option<NanoSecond>,
+}
+
+impl FrameMeta {
+    /// The Unix time in nanoseconds of `time_ns`, a time stamp within this frame,
+    /// if [`Self::wall_clock_ns`] was recorded.
+    pub fn wall_clock_ns_at(&self, time_ns: NanoSecond) -> Option<NanoSecond> {
+        self.wall_clock_ns
+            .map(|wall_clock_ns| wall_clock_ns + (time_ns - self.range_ns.0))
+    }
+
+    /// Roughly how many nanoseconds the profiler itself added to this frame,
+    /// assuming each scope costs `per_scope_ns`.
+    ///
+    /// Micro-benchmarks put the cost of a scope at around 50 ns,
+    /// but real applications tend to see several times that.
+    pub fn estimated_overhead_ns(&self, per_scope_ns: f64) -> f64 {
+        self.num_scopes as f64 * per_scope_ns
+    }
 }
 
 /// One frame worth of profile data, collected from many sources.
@@ -43,11 +71,16 @@ impl UnpackedFrameData {
         frame_index: FrameIndex,
         thread_streams: BTreeMap<ThreadInfo, StreamInfo>,
     ) -> Result<Self> {
-        let thread_streams: BTreeMap<_, _> = thread_streams
-            .into_iter()
-            .map(|(info, stream_info)| (info, Arc::new(stream_info)))
-            .collect();
+        Self::from_thread_streams(
+            frame_index,
+            thread_streams
+                .into_iter()
+                .map(|(info, stream_info)| (info, Arc::new(stream_info)))
+                .collect(),
+        )
+    }
 
+    fn from_thread_streams(frame_index: FrameIndex, thread_streams: ThreadStreams) -> Result<Self> {
         let mut num_bytes = 0;
         let mut num_scopes = 0;
 
@@ -67,6 +100,7 @@ impl UnpackedFrameData {
                     range_ns: (min_ns, max_ns),
                     num_bytes,
                     num_scopes,
+                    wall_clock_ns: None,
                 },
                 thread_streams,
             })
@@ -90,6 +124,250 @@ impl UnpackedFrameData {
         let (min, max) = self.meta.range_ns;
         max - min
     }
+
+    /// The `n` longest scopes in this frame, over all threads, slowest first.
+    ///
+    /// Scopes are ranked by their full duration, including time spent in child scopes.
+    /// See [`Self::slowest_scopes_by`] to rank by self time instead.
+    ///
+    /// Useful for regression tests, e.g. asserting that no scope took more than X ms.
+    pub fn slowest_scopes(&self, n: usize) -> Result<Vec<(ScopeId, ScopeRecord<'_>)>> {
+        self.slowest_scopes_by(n, ScopeTime::Total)
+    }
+
+    /// The `n` slowest scopes in this frame, over all threads, slowest first.
+    ///
+    /// The returned [`ScopeRecord`]s are always the raw records from the streams,
+    /// i.e. `duration_ns` is the full duration even when ranking by [`ScopeTime::SelfTime`].
+    pub fn slowest_scopes_by(
+        &self,
+        n: usize,
+        time: ScopeTime,
+    ) -> Result<Vec<(ScopeId, ScopeRecord<'_>)>> {
+        if n == 0 {
+            return Ok(vec![]);
+        }
+
+        let mut slowest = std::collections::BinaryHeap::new();
+
+        for stream_info in self.thread_streams.values() {
+            for scope in Reader::from_start(&stream_info.stream) {
+                collect_slowest(&mut slowest, n, time, &stream_info.stream, &scope?)?;
+            }
+        }
+
+        Ok(slowest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|std::cmp::Reverse(candidate)| (candidate.id, candidate.record))
+            .collect())
+    }
+
+    /// The `n` scopes with the most total time in this frame, summed over all their calls
+    /// on all threads, worst first.
+    ///
+    /// Unlike [`Self::slowest_scopes`], which ranks single calls, this finds the scopes that
+    /// are cheap but called often. Time spent in recursive calls of a scope is only counted once.
+    pub fn top_scopes_by_duration(&self, n: usize) -> Result<Vec<(ScopeId, NanoSecond)>> {
+        let mut totals = BTreeMap::new();
+        for stream_info in self.thread_streams.values() {
+            for scope in Reader::from_start(&stream_info.stream) {
+                collect_totals(&mut totals, &mut vec![], &stream_info.stream, &scope?)?;
+            }
+        }
+
+        let mut totals: Vec<_> = totals.into_iter().collect();
+        totals.sort_by_key(|&(id, total_ns)| (std::cmp::Reverse(total_ns), id));
+        totals.truncate(n);
+        Ok(totals)
+    }
+
+    /// All counter samples in this frame, over all threads, ordered by time.
+    ///
+    /// See [`crate::profile_counter`].
+    pub fn counters(&self) -> Result<Vec<CounterRecord>> {
+        let mut counters = vec![];
+        for stream_info in self.thread_streams.values() {
+            counters.extend(Reader::from_start(&stream_info.stream).read_counters()?);
+        }
+        counters.sort_by_key(|counter| counter.time_ns);
+        Ok(counters)
+    }
+
+    /// All flow points in this frame, with the thread they were recorded on, ordered by time.
+    ///
+    /// See [`crate::profile_flow`].
+    pub fn flows(&self) -> Result<Vec<(ThreadInfo, FlowRecord)>> {
+        let mut flows = vec![];
+        for (thread_info, stream_info) in &self.thread_streams {
+            for flow in Reader::from_start(&stream_info.stream).read_flow_records()? {
+                flows.push((thread_info.clone(), flow));
+            }
+        }
+        flows.sort_by_key(|(_, flow)| flow.time_ns);
+        Ok(flows)
+    }
+
+    /// All markers in this frame, from all threads, ordered by time.
+    ///
+    /// See [`crate::profile_marker`].
+    pub fn markers(&self) -> Result<Vec<MarkerRecord>> {
+        let mut markers = vec![];
+        for stream_info in self.thread_streams.values() {
+            markers.extend(Reader::from_start(&stream_info.stream).read_markers()?);
+        }
+        markers.sort_by_key(|marker| marker.time_ns);
+        Ok(markers)
+    }
+
+    /// All scopes of this frame as a tree, per thread.
+    ///
+    /// Each thread maps to its top-level scopes, in the order they were recorded.
+    pub fn scopes_tree(&self) -> Result<BTreeMap<ThreadInfo, Vec<ScopeNode>>> {
+        self.thread_streams
+            .iter()
+            .map(|(thread_info, stream_info)| {
+                Ok((
+                    thread_info.clone(),
+                    ScopeNode::read_siblings(&stream_info.stream, 0)?,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// A scope and all its child scopes, see [`UnpackedFrameData::scopes_tree`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScopeNode {
+    /// Identifies the scope, see [`crate::ScopeCollection`].
+    pub scope_id: ScopeId,
+
+    /// The start of this scope in nanoseconds.
+    pub start_ns: NanoSecond,
+
+    /// The duration of this scope in nanoseconds.
+    pub duration_ns: NanoSecond,
+
+    /// e.g. function argument, like a mesh name. Optional.
+    pub data: String,
+
+    /// The scopes directly inside of this one, in the order they were recorded.
+    pub children: Vec<ScopeNode>,
+}
+
+impl ScopeNode {
+    /// The end of this scope in nanoseconds.
+    #[inline]
+    pub fn stop_ns(&self) -> NanoSecond {
+        self.start_ns + self.duration_ns
+    }
+
+    /// The number of levels of scopes in this tree, i.e. `1` if there are no children.
+    pub fn depth(&self) -> usize {
+        1 + self.children.iter().map(Self::depth).max().unwrap_or(0)
+    }
+
+    fn read_siblings(stream: &Stream, offset: u64) -> Result<Vec<Self>> {
+        Reader::with_offset(stream, offset)?
+            .map(|scope| {
+                let scope = scope?;
+                Ok(Self {
+                    scope_id: scope.id,
+                    start_ns: scope.record.start_ns,
+                    duration_ns: scope.record.duration_ns,
+                    data: scope.record.data.to_owned(),
+                    children: Self::read_siblings(stream, scope.child_begin_position)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// What duration to rank scopes by in [`UnpackedFrameData::slowest_scopes_by`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScopeTime {
+    /// The full duration of the scope, including its children.
+    #[default]
+    Total,
+
+    /// The duration of the scope minus the time covered by its child scopes.
+    SelfTime,
+}
+
+/// Temporary structure while selecting the slowest scopes.
+#[derive(PartialEq, Eq)]
+struct SlowScope<'s> {
+    rank_ns: NanoSecond,
+    id: ScopeId,
+    record: ScopeRecord<'s>,
+}
+
+impl<'s> Ord for SlowScope<'s> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.rank_ns
+            .cmp(&other.rank_ns)
+            .then_with(|| other.record.start_ns.cmp(&self.record.start_ns))
+            .then_with(|| self.id.cmp(&other.id))
+    }
+}
+
+impl<'s> PartialOrd for SlowScope<'s> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Recursively visit `scope` and its children, keeping the `n` slowest in a min-heap.
+fn collect_slowest<'s>(
+    slowest: &mut std::collections::BinaryHeap<std::cmp::Reverse<SlowScope<'s>>>,
+    n: usize,
+    time: ScopeTime,
+    stream: &'s Stream,
+    scope: &Scope<'s>,
+) -> Result<()> {
+    let mut ns_used_by_children = 0;
+    for child_scope in Reader::with_offset(stream, scope.child_begin_position)? {
+        let child_scope = child_scope?;
+        collect_slowest(slowest, n, time, stream, &child_scope)?;
+        ns_used_by_children += child_scope.record.duration_ns;
+    }
+
+    let rank_ns = match time {
+        ScopeTime::Total => scope.record.duration_ns,
+        ScopeTime::SelfTime => scope.record.duration_ns.saturating_sub(ns_used_by_children),
+    };
+
+    slowest.push(std::cmp::Reverse(SlowScope {
+        rank_ns,
+        id: scope.id,
+        record: scope.record,
+    }));
+    if slowest.len() > n {
+        slowest.pop();
+    }
+
+    Ok(())
+}
+
+/// Recursively add the duration of `scope` and its children to `totals`,
+/// skipping scopes already in `stack` (recursive calls).
+fn collect_totals(
+    totals: &mut BTreeMap<ScopeId, NanoSecond>,
+    stack: &mut Vec<ScopeId>,
+    stream: &Stream,
+    scope: &Scope<'_>,
+) -> Result<()> {
+    if !stack.contains(&scope.id) {
+        *totals.entry(scope.id).or_default() += scope.record.duration_ns;
+    }
+
+    stack.push(scope.id);
+    for child_scope in Reader::with_offset(stream, scope.child_begin_position)? {
+        collect_totals(totals, stack, stream, &child_scope?)?;
+    }
+    stack.pop();
+
+    Ok(())
 }
 
 // ----------------------------------------------------------------------------
@@ -109,6 +387,7 @@ pub struct FrameData {
 }
 
 #[cfg(not(feature = "packing"))]
+#[derive(Debug)]
 pub enum Never {}
 
 #[cfg(not(feature = "packing"))]
@@ -127,7 +406,7 @@ impl FrameData {
         ))
     }
 
-    fn from_unpacked(
+    pub(crate) fn from_unpacked(
         unpacked_frame: Arc<UnpackedFrameData>,
         scope_delta: Vec<Arc<ScopeDetails>>,
         full_delta: bool,
@@ -184,6 +463,9 @@ impl FrameData {
 
     /// Does nothing because this [`FrameData`] is unpacked by default.
     pub fn pack(&self) {}
+
+    /// Does nothing because this [`FrameData`] is unpacked by default.
+    pub fn pack_with_config(&self, _config: &CompressionConfig) {}
 }
 
 #[cfg(all(feature = "serialization", not(feature = "packing")))]
@@ -193,6 +475,61 @@ compile_error!(
 
 // ----------------------------------------------------------------------------
 
+/// Which algorithm to compress frames with when they are packed.
+///
+/// See <https://github.com/EmbarkStudios/puffin/pull/130> for pros-and-cons of different compression algorithms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    /// Don't compress, only serialize.
+    Uncompressed,
+
+    /// Very fast, and lightweight dependency
+    #[cfg(feature = "lz4")]
+    Lz4,
+
+    /// Big dependency, slow compression, but compresses better than lz4.
+    /// Not available on wasm, where zstd can only be decompressed.
+    #[cfg(all(feature = "zstd", not(target_arch = "wasm32")))]
+    Zstd,
+}
+
+impl Default for CompressionAlgorithm {
+    /// `Lz4` if the `lz4` feature is enabled, else `Zstd` if the `zstd` feature is enabled.
+    fn default() -> Self {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "lz4")] {
+                Self::Lz4
+            } else if #[cfg(all(feature = "zstd", not(target_arch = "wasm32")))] {
+                Self::Zstd
+            } else {
+                Self::Uncompressed
+            }
+        }
+    }
+}
+
+/// How to compress frames when they are packed.
+///
+/// The algorithm is stored along with each packed frame,
+/// so frames packed with different configs can be read back the same way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Which algorithm to compress with.
+    pub algorithm: CompressionAlgorithm,
+
+    /// Only used by zstd, where it goes from 1 (fastest) to 22 (smallest).
+    pub level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: CompressionAlgorithm::default(),
+            level: 3,
+        }
+    }
+}
+
 /// See <https://github.com/EmbarkStudios/puffin/pull/130> for pros-and-cons of different compression algorithms.
 #[cfg(feature = "packing")]
 #[repr(u8)]
@@ -227,6 +564,29 @@ impl CompressionKind {
 struct PackedStreams {
     compression_kind: CompressionKind,
     bytes: Vec<u8>,
+
+    /// Read from data from before [`ThreadInfo::id`] was added (before `PFDC`).
+    legacy_thread_info: bool,
+}
+
+/// A [`ThreadInfo`] from before [`ThreadInfo::id`] was added.
+#[cfg(feature = "packing")]
+#[derive(serde::Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+struct LegacyThreadInfo {
+    start_time_ns: Option<NanoSecond>,
+    name: String,
+}
+
+/// [`ThreadStreams`] from before [`ThreadInfo::id`] was added.
+#[cfg(feature = "packing")]
+type LegacyThreadStreams = BTreeMap<LegacyThreadInfo, Arc<StreamInfo>>;
+
+#[cfg(feature = "packing")]
+fn from_legacy_thread_streams(streams: LegacyThreadStreams) -> ThreadStreams {
+    streams
+        .into_iter()
+        .map(|(info, stream_info)| (ThreadInfo::new(info.start_time_ns, info.name), stream_info))
+        .collect()
 }
 
 #[cfg(feature = "packing")]
@@ -235,35 +595,42 @@ impl PackedStreams {
         Self {
             compression_kind,
             bytes,
+            legacy_thread_info: false,
         }
     }
 
-    pub fn pack(streams: &ThreadStreams) -> Self {
+    /// Like [`Self::new`], for streams from before [`ThreadInfo::id`] was added.
+    #[cfg(feature = "serialization")]
+    fn new_legacy(compression_kind: CompressionKind, bytes: Vec<u8>) -> Self {
+        Self {
+            legacy_thread_info: true,
+            ..Self::new(compression_kind, bytes)
+        }
+    }
+
+    pub fn pack(streams: &ThreadStreams, config: &CompressionConfig) -> Self {
         use bincode::Options as _;
 
         let serialized = bincode::options()
             .serialize(streams)
             .expect("bincode failed to encode");
 
-        cfg_if::cfg_if! {
-            if #[cfg(feature = "lz4")] {
-                Self {
-                    compression_kind: CompressionKind::Lz4,
-                    bytes: lz4_flex::compress_prepend_size(&serialized),
-                }
-            } else if #[cfg(feature = "zstd")] {
-                let level = 3;
-                let bytes = zstd::encode_all(std::io::Cursor::new(&serialized), level)
+        match config.algorithm {
+            CompressionAlgorithm::Uncompressed => {
+                Self::new(CompressionKind::Uncompressed, serialized)
+            }
+
+            #[cfg(feature = "lz4")]
+            CompressionAlgorithm::Lz4 => Self::new(
+                CompressionKind::Lz4,
+                lz4_flex::compress_prepend_size(&serialized),
+            ),
+
+            #[cfg(all(feature = "zstd", not(target_arch = "wasm32")))]
+            CompressionAlgorithm::Zstd => {
+                let bytes = zstd::encode_all(std::io::Cursor::new(&serialized), config.level)
                     .expect("zstd failed to compress");
-                Self {
-                    compression_kind: CompressionKind::Zstd,
-                    bytes,
-                }
-            } else {
-                Self {
-                    compression_kind: CompressionKind::Uncompressed,
-                    bytes: serialized,
-                }
+                Self::new(CompressionKind::Zstd, bytes)
             }
         }
     }
@@ -278,12 +645,18 @@ impl PackedStreams {
         use anyhow::Context as _;
         use bincode::Options as _;
 
-        fn deserialize(bytes: &[u8]) -> anyhow::Result<ThreadStreams> {
+        let deserialize = |bytes: &[u8]| -> anyhow::Result<ThreadStreams> {
             crate::profile_scope!("bincode deserialize");
+            if self.legacy_thread_info {
+                let streams: LegacyThreadStreams = bincode::options()
+                    .deserialize(bytes)
+                    .context("bincode deserialize")?;
+                return Ok(from_legacy_thread_streams(streams));
+            }
             bincode::options()
                 .deserialize(bytes)
                 .context("bincode deserialize")
-        }
+        };
 
         match self.compression_kind {
             CompressionKind::Uncompressed => deserialize(&self.bytes),
@@ -302,10 +675,10 @@ impl PackedStreams {
 
             CompressionKind::Zstd => {
                 cfg_if::cfg_if! {
-                    if #[cfg(feature = "zstd")] {
+                    if #[cfg(feature = "ruzstd")] {
                         deserialize(&decode_zstd(&self.bytes)?)
                     } else {
-                        anyhow::bail!("Data compressed with zstd, but the zstd feature is not enabled")
+                        anyhow::bail!("Data compressed with zstd, but neither the zstd nor the ruzstd feature is enabled")
                     }
                 }
             }
@@ -404,7 +777,7 @@ impl FrameDataState {
         }
     }
 
-    #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
+    #[cfg(all(feature = "serialization", not(target_arch = "wasm32")))] // compression not supported on wasm
     fn packed(&self) -> Option<&PackedStreams> {
         match self {
             FrameDataState::Unpacked(_) => None,
@@ -412,19 +785,19 @@ impl FrameDataState {
         }
     }
 
-    fn pack_and_remove(&mut self) {
+    fn pack_and_remove(&mut self, config: &CompressionConfig) {
         if let FrameDataState::Unpacked(ref unpacked) | FrameDataState::Both(ref unpacked, _) =
             *self
         {
-            let packed = PackedStreams::pack(&unpacked.thread_streams);
+            let packed = PackedStreams::pack(&unpacked.thread_streams, config);
             *self = Self::Packed(packed);
         }
     }
 
-    #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
-    fn pack_and_keep(&mut self) {
+    #[cfg(all(feature = "serialization", not(target_arch = "wasm32")))] // compression not supported on wasm
+    fn pack_and_keep(&mut self, config: &CompressionConfig) {
         if let FrameDataState::Unpacked(ref unpacked) = *self {
-            let packed = PackedStreams::pack(&unpacked.thread_streams);
+            let packed = PackedStreams::pack(&unpacked.thread_streams, config);
             *self = Self::Packed(packed);
         }
     }
@@ -465,7 +838,7 @@ impl FrameData {
         ))
     }
 
-    fn from_unpacked(
+    pub(crate) fn from_unpacked(
         unpacked_frame: Arc<UnpackedFrameData>,
         scope_delta: Vec<Arc<ScopeDetails>>,
         full_delta: bool,
@@ -484,6 +857,39 @@ impl FrameData {
         &self.meta
     }
 
+    /// A copy of this frame with only the threads for which `keep` returns `true`.
+    ///
+    /// The [`FrameMeta`] is recomputed from the remaining threads,
+    /// so e.g. [`FrameMeta::range_ns`] and [`FrameMeta::num_scopes`] only cover those.
+    /// The scope delta is kept as is, even if some of its scopes are only used by removed threads.
+    ///
+    /// Returns `None` if no thread with any data is left.
+    pub fn retain_threads(
+        &self,
+        keep: impl Fn(&ThreadInfo) -> bool,
+    ) -> anyhow::Result<Option<Self>> {
+        let unpacked = self.unpacked()?;
+        let thread_streams = unpacked
+            .thread_streams
+            .iter()
+            .filter(|(info, _)| keep(info))
+            .map(|(info, stream_info)| (info.clone(), stream_info.clone()))
+            .collect();
+
+        let Ok(mut retained) =
+            UnpackedFrameData::from_thread_streams(self.meta.frame_index, thread_streams)
+        else {
+            return Ok(None); // No data left
+        };
+        retained.meta.wall_clock_ns = self.meta.wall_clock_ns_at(retained.meta.range_ns.0);
+
+        Ok(Some(Self::from_unpacked(
+            Arc::new(retained),
+            self.scope_delta.clone(),
+            self.full_delta,
+        )))
+    }
+
     /// Number of bytes used by the packed data, if packed.
     pub fn packed_size(&self) -> Option<usize> {
         self.data.read().packed_size()
@@ -547,13 +953,19 @@ impl FrameData {
     /// Make the [`FrameData`] use up less memory.
     /// Idempotent.
     pub fn pack(&self) {
-        self.data.write().pack_and_remove();
+        self.pack_with_config(&CompressionConfig::default());
+    }
+
+    /// Make the [`FrameData`] use up less memory, compressing it as configured.
+    /// Idempotent: does nothing if the frame is already packed.
+    pub fn pack_with_config(&self, config: &CompressionConfig) {
+        self.data.write().pack_and_remove(config);
     }
 
     /// Create a packed storage without freeing the unpacked storage.
-    #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
-    fn create_packed(&self) {
-        self.data.write().pack_and_keep();
+    #[cfg(all(feature = "serialization", not(target_arch = "wasm32")))] // compression not supported on wasm
+    pub(crate) fn create_packed(&self, config: &CompressionConfig) {
+        self.data.write().pack_and_keep(config);
     }
 
     /// Writes one [`FrameData`] into a stream, prefixed by its length ([`u32`] le).
@@ -570,11 +982,21 @@ impl FrameData {
 
         let meta_serialized = bincode::options().serialize(&self.meta)?;
 
-        write.write_all(b"PFD4")?;
+        write.write_all(b"PFDD")?;
         write.write_all(&(meta_serialized.len() as u32).to_le_bytes())?;
         write.write_all(&meta_serialized)?;
 
-        self.create_packed();
+        let legacy_packed = self
+            .data
+            .read()
+            .packed()
+            .map_or(false, |packed| packed.legacy_thread_info);
+        if legacy_packed {
+            // Read from an older file: pack it again in the current format.
+            let unpacked = self.unpacked()?;
+            *self.data.write() = FrameDataState::Unpacked(unpacked);
+        }
+        self.create_packed(&CompressionConfig::default());
         let packed_streams_lock = self.data.read();
         let packed_streams = packed_streams_lock.packed().unwrap(); // We just called create_packed
 
@@ -601,7 +1023,6 @@ impl FrameData {
     #[cfg(feature = "serialization")]
     pub fn read_next(read: &mut impl std::io::Read) -> anyhow::Result<Option<Self>> {
         use anyhow::Context as _;
-        use bincode::Options as _;
         use byteorder::{ReadBytesExt, LE};
 
         let mut header = [0_u8; 4];
@@ -613,10 +1034,10 @@ impl FrameData {
             }
         }
 
-        #[derive(Clone, serde::Deserialize, serde::Serialize)]
+        #[derive(serde::Deserialize)]
         pub struct LegacyFrameData {
             pub frame_index: FrameIndex,
-            pub thread_streams: ThreadStreams,
+            pub thread_streams: LegacyThreadStreams,
             pub range_ns: (NanoSecond, NanoSecond),
             pub num_bytes: usize,
             pub num_scopes: usize,
@@ -637,8 +1058,9 @@ impl FrameData {
                         range_ns,
                         num_bytes,
                         num_scopes,
+                        wall_clock_ns: None,
                     },
-                    thread_streams,
+                    thread_streams: from_legacy_thread_streams(thread_streams),
                 }
             }
 
@@ -659,8 +1081,10 @@ impl FrameData {
                 // We stopped supporting this in 2021-11-16 in order to remove `lz4_flex` dependency.
                 anyhow::bail!("Found legacy puffin data, which we can no longer decode")
             } else if &header == b"PFD1" {
-                #[cfg(feature = "zstd")]
+                #[cfg(feature = "ruzstd")]
                 {
+                    use bincode::Options as _;
+
                     // Added 2021-09
                     let mut compressed_length = [0_u8; 4];
                     read.read_exact(&mut compressed_length)?;
@@ -675,9 +1099,11 @@ impl FrameData {
                         .context("bincode deserialize")?;
                     Ok(Some(legacy.into_frame_data()))
                 }
-                #[cfg(not(feature = "zstd"))]
+                #[cfg(not(feature = "ruzstd"))]
                 {
-                    anyhow::bail!("Cannot decode old puffin data without the `zstd` feature")
+                    anyhow::bail!(
+                        "Cannot decode old puffin data without the `zstd` or `ruzstd` feature"
+                    )
                 }
             } else if &header == b"PFD2" {
                 // Added 2021-11-15
@@ -687,9 +1113,7 @@ impl FrameData {
                 let mut meta = vec![0_u8; meta_length];
                 read.read_exact(&mut meta)?;
 
-                let meta: FrameMeta = bincode::options()
-                    .deserialize(&meta)
-                    .context("bincode deserialize")?;
+                let meta = deserialize_meta(&header, &meta)?;
 
                 let mut streams_compressed_length = [0_u8; 4];
                 read.read_exact(&mut streams_compressed_length)?;
@@ -699,7 +1123,8 @@ impl FrameData {
                 let mut streams_compressed = vec![0_u8; streams_compressed_length];
                 read.read_exact(&mut streams_compressed)?;
 
-                let packed_streams = PackedStreams::new(compression_kind, streams_compressed);
+                let packed_streams =
+                    PackedStreams::new_legacy(compression_kind, streams_compressed);
 
                 // Don't unpack now - do it if/when needed!
 
@@ -717,9 +1142,7 @@ impl FrameData {
                 let mut meta = vec![0_u8; meta_length];
                 read.read_exact(&mut meta)?;
 
-                let meta: FrameMeta = bincode::options()
-                    .deserialize(&meta)
-                    .context("bincode deserialize")?;
+                let meta = deserialize_meta(&header, &meta)?;
 
                 let mut streams_compressed_length = [0_u8; 4];
                 read.read_exact(&mut streams_compressed_length)?;
@@ -730,7 +1153,8 @@ impl FrameData {
                 let mut streams_compressed = vec![0_u8; streams_compressed_length];
                 read.read_exact(&mut streams_compressed)?;
 
-                let packed_streams = PackedStreams::new(compression_kind, streams_compressed);
+                let packed_streams =
+                    PackedStreams::new_legacy(compression_kind, streams_compressed);
 
                 // Don't unpack now - do it if/when needed!
 
@@ -740,15 +1164,32 @@ impl FrameData {
                     scope_delta: Default::default(),
                     full_delta: false,
                 }))
-            } else if &header == b"PFD4" {
-                // Added 2024-01-08: Split up stream scope details from the record stream.
+            } else if &header == b"PFD4"
+                || &header == b"PFD5"
+                || &header == b"PFD6"
+                || &header == b"PFD7"
+                || &header == b"PFD8"
+                || &header == b"PFD9"
+                || &header == b"PFDA"
+                || &header == b"PFDB"
+                || &header == b"PFDC"
+                || &header == b"PFDD"
+            {
+                // PFD4 added 2024-01-08: Split up stream scope details from the record stream.
+                // PFD5: Added `ScopeDetails::category`.
+                // PFD6: Streams may contain counter samples, which older readers can't skip.
+                // PFD7: Streams may contain async task events.
+                // PFD8: Added `FrameMeta::wall_clock_ns`.
+                // PFD9: Strings in streams have a LEB128 length, so they may be longer than 127 bytes.
+                // PFDA: Streams may contain flow points.
+                // PFDB: Scopes may have key-value tags.
+                // PFDC: Added `ThreadInfo::id`.
+                // PFDD: Streams may contain markers.
                 let meta_length = read.read_u32::<LE>()? as usize;
                 let meta = {
                     let mut meta = vec![0_u8; meta_length];
                     read.read_exact(&mut meta)?;
-                    bincode::options()
-                        .deserialize(&meta)
-                        .context("bincode deserialize")?
+                    deserialize_meta(&header, &meta)?
                 };
 
                 let streams_compressed_length = read.read_u32::<LE>()? as usize;
@@ -756,16 +1197,18 @@ impl FrameData {
                 let streams_compressed = {
                     let mut streams_compressed = vec![0_u8; streams_compressed_length];
                     read.read_exact(&mut streams_compressed)?;
-                    PackedStreams::new(compression_kind, streams_compressed)
+                    if &header == b"PFDC" || &header == b"PFDD" {
+                        PackedStreams::new(compression_kind, streams_compressed)
+                    } else {
+                        PackedStreams::new_legacy(compression_kind, streams_compressed)
+                    }
                 };
 
                 let serialized_scope_len = read.read_u32::<LE>()?;
-                let deserialized_scopes: Vec<crate::ScopeDetails> = {
+                let deserialized_scopes = {
                     let mut serialized_scopes = vec![0; serialized_scope_len as usize];
                     read.read_exact(&mut serialized_scopes)?;
-                    bincode::options()
-                        .deserialize_from(serialized_scopes.as_slice())
-                        .context("Can not deserialize scope details")?
+                    deserialize_scope_delta(&header, &serialized_scopes)?
                 };
 
                 let new_scopes: Vec<_> = deserialized_scopes
@@ -794,10 +1237,127 @@ impl FrameData {
             Ok(Some(legacy.into_frame_data()))
         }
     }
+
+    /// Encode this frame into a buffer, e.g. to send it over your own transport.
+    ///
+    /// The same encoding as [`Self::write_into`]. Decode it again with [`Self::from_bytes`].
+    #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
+    #[cfg(feature = "serialization")]
+    pub fn to_bytes(
+        &self,
+        scope_collection: &crate::ScopeCollection,
+        send_all_scopes: bool,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        self.write_into(scope_collection, send_all_scopes, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decode a frame encoded with [`Self::to_bytes`].
+    ///
+    /// Like [`Self::read_next`], [`None`] is returned if `bytes` is empty.
+    /// Fails if there is anything left after the frame.
+    #[cfg(feature = "serialization")]
+    pub fn from_bytes(mut bytes: &[u8]) -> anyhow::Result<Option<Self>> {
+        let frame = Self::read_next(&mut bytes)?;
+        if !bytes.is_empty() {
+            anyhow::bail!("{} unexpected bytes after the frame", bytes.len());
+        }
+        Ok(frame)
+    }
 }
 
 // ----------------------------------------------------------------------------
 
+/// Deserialize the [`FrameMeta`] at the start of a `PFD2` or newer frame.
+#[cfg(feature = "serialization")]
+pub(crate) fn deserialize_meta(header: &[u8; 4], bytes: &[u8]) -> anyhow::Result<FrameMeta> {
+    use anyhow::Context as _;
+    use bincode::Options as _;
+
+    /// [`FrameMeta`] before the wall clock time was added (PFD8).
+    #[derive(serde::Deserialize)]
+    struct LegacyFrameMeta {
+        frame_index: FrameIndex,
+        range_ns: (NanoSecond, NanoSecond),
+        num_bytes: usize,
+        num_scopes: usize,
+    }
+
+    if header < b"PFD8" {
+        let LegacyFrameMeta {
+            frame_index,
+            range_ns,
+            num_bytes,
+            num_scopes,
+        } = bincode::options()
+            .deserialize(bytes)
+            .context("bincode deserialize")?;
+        Ok(FrameMeta {
+            frame_index,
+            range_ns,
+            num_bytes,
+            num_scopes,
+            wall_clock_ns: None,
+        })
+    } else {
+        bincode::options()
+            .deserialize(bytes)
+            .context("bincode deserialize")
+    }
+}
+
+/// Deserialize the scope details written after the streams of a `PFD4` or newer frame.
+#[cfg(feature = "serialization")]
+pub(crate) fn deserialize_scope_delta(
+    header: &[u8; 4],
+    bytes: &[u8],
+) -> anyhow::Result<Vec<crate::ScopeDetails>> {
+    use anyhow::Context as _;
+    use bincode::Options as _;
+
+    /// [`crate::ScopeDetails`] before categories were added (PFD4).
+    #[derive(serde::Deserialize)]
+    struct LegacyScopeDetails {
+        scope_id: Option<crate::ScopeId>,
+        scope_name: Option<std::borrow::Cow<'static, str>>,
+        function_name: std::borrow::Cow<'static, str>,
+        file_path: std::borrow::Cow<'static, str>,
+        line_nr: u32,
+    }
+
+    impl From<LegacyScopeDetails> for crate::ScopeDetails {
+        fn from(legacy: LegacyScopeDetails) -> Self {
+            let LegacyScopeDetails {
+                scope_id,
+                scope_name,
+                function_name,
+                file_path,
+                line_nr,
+            } = legacy;
+            Self {
+                scope_id,
+                scope_name,
+                function_name,
+                file_path,
+                line_nr,
+                category: Default::default(),
+            }
+        }
+    }
+
+    if header == b"PFD4" {
+        let legacy: Vec<LegacyScopeDetails> = bincode::options()
+            .deserialize_from(bytes)
+            .context("Can not deserialize scope details")?;
+        Ok(legacy.into_iter().map(Into::into).collect())
+    } else {
+        bincode::options()
+            .deserialize_from(bytes)
+            .context("Can not deserialize scope details")
+    }
+}
+
 impl FrameData {
     /// The index of this frame.
     pub fn frame_index(&self) -> u64 {
@@ -818,18 +1378,25 @@ impl FrameData {
 
 // ----------------------------------------------------------------------------
 
+// `ruzstd` is always enabled by `zstd`, so this covers both.
 #[cfg(feature = "packing")]
-#[cfg(not(target_arch = "wasm32"))]
-#[cfg(feature = "zstd")]
+#[cfg(feature = "ruzstd")]
 fn decode_zstd(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
-    use anyhow::Context as _;
-    zstd::decode_all(bytes).context("zstd decompress failed")
+    cfg_if::cfg_if! {
+        if #[cfg(all(feature = "zstd", not(target_arch = "wasm32")))] {
+            use anyhow::Context as _;
+            zstd::decode_all(bytes).context("zstd decompress failed")
+        } else {
+            decode_ruzstd(bytes)
+        }
+    }
 }
 
+/// Pure-Rust fallback for [`decode_zstd`], for wasm and builds without the native `zstd` library.
 #[cfg(feature = "packing")]
-#[cfg(target_arch = "wasm32")]
-#[cfg(feature = "zstd")]
-fn decode_zstd(mut bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+#[cfg(feature = "ruzstd")]
+#[cfg_attr(all(feature = "zstd", not(target_arch = "wasm32")), allow(dead_code))]
+fn decode_ruzstd(mut bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
     use anyhow::Context as _;
     use std::io::Read as _;
     let mut decoded = Vec::new();
@@ -840,3 +1407,424 @@ fn decode_zstd(mut bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
         .context("zstd decompress")?;
     Ok(decoded)
 }
+
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{ScopeId, ScopeTime, Stream, ThreadInfo, UnpackedFrameData};
+
+    #[test]
+    fn test_slowest_scopes() {
+        let main_stream = {
+            let mut stream = Stream::default();
+            let (top, _) = stream.begin_scope(|| 0, ScopeId::new(1), "");
+            let (a, _) = stream.begin_scope(|| 100, ScopeId::new(2), "");
+            stream.end_scope(a, 700);
+            let (b, _) = stream.begin_scope(|| 700, ScopeId::new(3), "");
+            stream.end_scope(b, 800);
+            stream.end_scope(top, 1000);
+            stream
+        };
+        let other_stream = {
+            let mut stream = Stream::default();
+            let (c, _) = stream.begin_scope(|| 0, ScopeId::new(4), "data");
+            stream.end_scope(c, 400);
+            stream
+        };
+
+        let mut thread_streams = BTreeMap::new();
+        for (name, stream) in [("main", main_stream), ("other", other_stream)] {
+            let (thread_info, stream_info) = crate::test_thread_stream(name, stream);
+            thread_streams.insert(thread_info, stream_info);
+        }
+        let frame = UnpackedFrameData::new(0, thread_streams).unwrap();
+
+        let slowest = frame.slowest_scopes(3).unwrap();
+        let ids: Vec<_> = slowest.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [ScopeId::new(1), ScopeId::new(2), ScopeId::new(4)]);
+        assert_eq!(slowest[0].1.duration_ns, 1000);
+        assert_eq!(slowest[2].1.data, "data");
+
+        // Self time: top = 1000 - 600 - 100 = 300
+        let slowest = frame.slowest_scopes_by(4, ScopeTime::SelfTime).unwrap();
+        let ids: Vec<_> = slowest.iter().map(|(id, _)| *id).collect();
+        assert_eq!(
+            ids,
+            [
+                ScopeId::new(2),
+                ScopeId::new(4),
+                ScopeId::new(1),
+                ScopeId::new(3)
+            ]
+        );
+
+        assert!(frame.slowest_scopes(0).unwrap().is_empty());
+        assert_eq!(frame.slowest_scopes(100).unwrap().len(), 4);
+
+        let tree = frame.scopes_tree().unwrap();
+        assert_eq!(tree.len(), 2);
+        let main = &tree[&ThreadInfo::new(None, "main")];
+        assert_eq!(main.len(), 1);
+        assert_eq!(main[0].scope_id, ScopeId::new(1));
+        assert_eq!(main[0].depth(), 2);
+        let children: Vec<_> = main[0]
+            .children
+            .iter()
+            .map(|child| (child.scope_id, child.start_ns, child.stop_ns()))
+            .collect();
+        assert_eq!(
+            children,
+            [(ScopeId::new(2), 100, 700), (ScopeId::new(3), 700, 800)]
+        );
+        assert!(main[0]
+            .children
+            .iter()
+            .all(|child| child.children.is_empty()));
+        let other = &tree[&ThreadInfo::new(None, "other")];
+        assert_eq!(other[0].data, "data");
+        assert_eq!(other[0].depth(), 1);
+    }
+
+    #[test]
+    fn test_top_scopes_by_duration() {
+        let main_stream = {
+            let mut stream = Stream::default();
+            let (top, _) = stream.begin_scope(|| 0, ScopeId::new(1), "");
+            for start_ns in [100, 300, 500] {
+                let (small, _) = stream.begin_scope(|| start_ns, ScopeId::new(2), "");
+                stream.end_scope(small, start_ns + 150);
+            }
+            let (recursive, _) = stream.begin_scope(|| 700, ScopeId::new(3), "");
+            let (inner, _) = stream.begin_scope(|| 750, ScopeId::new(3), "");
+            stream.end_scope(inner, 800);
+            stream.end_scope(recursive, 900);
+            stream.end_scope(top, 1000);
+            stream
+        };
+        let other_stream = {
+            let mut stream = Stream::default();
+            let (small, _) = stream.begin_scope(|| 0, ScopeId::new(2), "");
+            stream.end_scope(small, 400);
+            stream
+        };
+        let mut thread_streams = BTreeMap::new();
+        for (name, stream) in [("main", main_stream), ("other", other_stream)] {
+            let (thread_info, stream_info) = crate::test_thread_stream(name, stream);
+            thread_streams.insert(thread_info, stream_info);
+        }
+        let frame = UnpackedFrameData::new(0, thread_streams).unwrap();
+
+        assert_eq!(
+            frame.top_scopes_by_duration(3).unwrap(),
+            [
+                (ScopeId::new(1), 1000),
+                (ScopeId::new(2), 3 * 150 + 400),
+                (ScopeId::new(3), 200),
+            ]
+        );
+        assert_eq!(
+            frame.top_scopes_by_duration(1).unwrap(),
+            [(ScopeId::new(1), 1000)]
+        );
+        assert!(frame.top_scopes_by_duration(0).unwrap().is_empty());
+    }
+
+    #[cfg(all(feature = "serialization", not(target_arch = "wasm32")))]
+    #[test]
+    fn legacy_thread_info() {
+        use super::{CompressionKind, FrameData, FrameDataState, PackedStreams};
+        use crate::{NanoSecond, StreamInfo};
+        use bincode::Options as _;
+        use std::sync::Arc;
+
+        // How `ThreadInfo` was serialized before `ThreadInfo::id`:
+        #[derive(serde::Serialize, PartialEq, Eq, PartialOrd, Ord)]
+        struct OldThreadInfo {
+            start_time_ns: Option<NanoSecond>,
+            name: String,
+        }
+
+        let stream_info = {
+            let mut stream = Stream::default();
+            let (offset, _) = stream.begin_scope(|| 0, ScopeId::new(1), "");
+            stream.end_scope(offset, 100);
+            StreamInfo::parse(stream).unwrap()
+        };
+        let old_info = OldThreadInfo {
+            start_time_ns: Some(5),
+            name: "main".to_owned(),
+        };
+        let old_streams = BTreeMap::from([(old_info, Arc::new(stream_info.clone()))]);
+        let bytes = bincode::options().serialize(&old_streams).unwrap();
+
+        // Like a frame read from a file from before `ThreadInfo::id`:
+        let frame = FrameData::new(
+            0,
+            BTreeMap::from([(ThreadInfo::new(None, "unused"), stream_info)]),
+            vec![],
+            false,
+        )
+        .unwrap();
+        *frame.data.write() = FrameDataState::Packed(PackedStreams::new_legacy(
+            CompressionKind::Uncompressed,
+            bytes,
+        ));
+
+        let expected = ThreadInfo::new(Some(5), "main");
+        let thread_streams = &frame.unpacked().unwrap().thread_streams;
+        assert_eq!(thread_streams.keys().collect::<Vec<_>>(), [&expected]);
+
+        // Written again in the current format:
+        let mut written = vec![];
+        frame
+            .write_into(&Default::default(), false, &mut written)
+            .unwrap();
+        let read = FrameData::read_next(&mut written.as_slice())
+            .unwrap()
+            .unwrap();
+        let thread_streams = &read.unpacked().unwrap().thread_streams;
+        assert_eq!(thread_streams.keys().collect::<Vec<_>>(), [&expected]);
+    }
+
+    #[cfg(all(feature = "serialization", not(target_arch = "wasm32")))]
+    #[test]
+    fn test_scope_category_roundtrip() {
+        use std::sync::Arc;
+
+        use crate::{FrameData, ScopeDetails};
+
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| 0, ScopeId::new(1), "");
+        stream.end_scope(offset, 100);
+        let thread_streams = BTreeMap::from([crate::test_thread_stream("main", stream)]);
+        let scope_details = ScopeDetails::from_scope_id(ScopeId::new(1))
+            .with_scope_name("physics.step")
+            .with_category("Physics");
+        let frame =
+            FrameData::new(0, thread_streams, vec![Arc::new(scope_details)], false).unwrap();
+
+        let mut bytes = vec![];
+        frame
+            .write_into(&Default::default(), false, &mut bytes)
+            .unwrap();
+        let read = FrameData::read_next(&mut bytes.as_slice())
+            .unwrap()
+            .unwrap();
+        assert_eq!(read.scope_delta.len(), 1);
+        assert_eq!(read.scope_delta[0].category, "Physics");
+    }
+
+    #[cfg(all(feature = "serialization", not(target_arch = "wasm32")))]
+    #[test]
+    fn test_bytes_roundtrip() {
+        use std::sync::Arc;
+
+        use crate::{FrameData, ScopeDetails};
+
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| 0, ScopeId::new(1), "");
+        stream.end_scope(offset, 100);
+        let thread_streams = BTreeMap::from([crate::test_thread_stream("main", stream)]);
+        let scope_details = ScopeDetails::from_scope_id(ScopeId::new(1)).with_scope_name("work");
+        let frame =
+            FrameData::new(7, thread_streams, vec![Arc::new(scope_details)], false).unwrap();
+
+        let mut bytes = frame.to_bytes(&Default::default(), false).unwrap();
+        let read = FrameData::from_bytes(&bytes).unwrap().unwrap();
+        assert_eq!(read.frame_index(), 7);
+        assert_eq!(read.meta().num_scopes, 1);
+        assert_eq!(read.scope_delta[0].name(), "work");
+
+        assert!(FrameData::from_bytes(&[]).unwrap().is_none());
+        bytes.push(0);
+        assert!(FrameData::from_bytes(&bytes).is_err());
+    }
+
+    #[cfg(all(feature = "serialization", not(target_arch = "wasm32")))]
+    #[test]
+    fn test_counters_roundtrip() {
+        use crate::FrameData;
+
+        let main_stream = {
+            let mut stream = Stream::default();
+            let (offset, _) = stream.begin_scope(|| 0, ScopeId::new(1), "");
+            stream.record_counter(ScopeId::new(10), 50, 1.5);
+            stream.end_scope(offset, 100);
+            stream.record_counter(ScopeId::new(10), 150, 2.5);
+            stream
+        };
+        let other_stream = {
+            let mut stream = Stream::default();
+            stream.record_counter(ScopeId::new(11), 120, 7.0);
+            stream
+        };
+
+        let mut thread_streams = BTreeMap::new();
+        for (name, stream) in [("main", main_stream), ("other", other_stream)] {
+            let (thread_info, stream_info) = crate::test_thread_stream(name, stream);
+            thread_streams.insert(thread_info, stream_info);
+        }
+        let frame = FrameData::new(0, thread_streams, vec![], false).unwrap();
+        assert_eq!(frame.range_ns(), (0, 150));
+        assert_eq!(frame.meta().num_scopes, 1);
+
+        let mut bytes = vec![];
+        frame
+            .write_into(&Default::default(), false, &mut bytes)
+            .unwrap();
+        assert_eq!(&bytes[..4], b"PFDD");
+        let read = FrameData::read_next(&mut bytes.as_slice())
+            .unwrap()
+            .unwrap();
+
+        let counters: Vec<_> = read
+            .unpacked()
+            .unwrap()
+            .counters()
+            .unwrap()
+            .iter()
+            .map(|counter| (counter.id, counter.time_ns, counter.value))
+            .collect();
+        assert_eq!(
+            counters,
+            [
+                (ScopeId::new(10), 50, 1.5),
+                (ScopeId::new(11), 120, 7.0),
+                (ScopeId::new(10), 150, 2.5),
+            ]
+        );
+    }
+
+    #[cfg(feature = "packing")]
+    #[test]
+    fn test_retain_threads() {
+        use crate::FrameData;
+
+        let mut thread_streams = BTreeMap::new();
+        for (name, range_ns, num_scopes) in [("main", (0, 1000), 3), ("other", (200, 400), 1)] {
+            let mut stream = Stream::default();
+            for _ in 0..num_scopes {
+                let (offset, _) = stream.begin_scope(|| range_ns.0, ScopeId::new(1), "");
+                stream.end_scope(offset, range_ns.1);
+            }
+            let (thread_info, stream_info) = crate::test_thread_stream(name, stream);
+            thread_streams.insert(thread_info, stream_info);
+        }
+        let mut unpacked = UnpackedFrameData::new(3, thread_streams).unwrap();
+        unpacked.meta.wall_clock_ns = Some(1_000_000);
+        let frame = FrameData::from_unpacked(std::sync::Arc::new(unpacked), vec![], false);
+        assert_eq!(frame.meta().num_scopes, 4);
+
+        let other = frame
+            .retain_threads(|info| info.name == "other")
+            .unwrap()
+            .unwrap();
+        assert_eq!(other.frame_index(), 3);
+        assert_eq!(other.range_ns(), (200, 400));
+        assert_eq!(other.meta().num_scopes, 1);
+        assert_eq!(other.meta().wall_clock_ns, Some(1_000_200));
+        assert_eq!(other.unpacked().unwrap().thread_streams.len(), 1);
+
+        assert!(frame.retain_threads(|_| false).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_estimated_overhead() {
+        let meta = super::FrameMeta {
+            frame_index: 0,
+            range_ns: (0, 1_000_000),
+            num_bytes: 0,
+            num_scopes: 5_000,
+            wall_clock_ns: None,
+        };
+        assert_eq!(meta.estimated_overhead_ns(200.0), 1_000_000.0);
+        assert_eq!(meta.estimated_overhead_ns(0.0), 0.0);
+    }
+
+    #[cfg(all(feature = "serialization", not(target_arch = "wasm32")))]
+    #[test]
+    fn test_wall_clock_roundtrip() {
+        use bincode::Options as _;
+
+        use crate::FrameData;
+
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| 100, ScopeId::new(1), "");
+        stream.end_scope(offset, 200);
+        let thread_streams = BTreeMap::from([crate::test_thread_stream("main", stream)]);
+        let mut unpacked = UnpackedFrameData::new(0, thread_streams).unwrap();
+        unpacked.meta.wall_clock_ns = Some(1_700_000_000_000_000_000);
+        let frame = FrameData::from_unpacked(std::sync::Arc::new(unpacked), vec![], false);
+
+        let mut bytes = vec![];
+        frame
+            .write_into(&Default::default(), false, &mut bytes)
+            .unwrap();
+        let read = FrameData::read_next(&mut bytes.as_slice())
+            .unwrap()
+            .unwrap();
+        assert_eq!(read.meta().wall_clock_ns, Some(1_700_000_000_000_000_000));
+        assert_eq!(
+            read.meta().wall_clock_ns_at(150),
+            Some(1_700_000_000_000_000_050)
+        );
+
+        // Older versions didn't write a wall clock time:
+        let legacy_meta = bincode::options()
+            .serialize(&(7_u64, (100_i64, 200_i64), 10_usize, 1_usize))
+            .unwrap();
+        let meta = super::deserialize_meta(b"PFD7", &legacy_meta).unwrap();
+        assert_eq!(meta.frame_index, 7);
+        assert_eq!(meta.wall_clock_ns, None);
+        assert_eq!(meta.wall_clock_ns_at(150), None);
+    }
+
+    #[cfg(all(feature = "serialization", feature = "ruzstd"))]
+    #[test]
+    fn read_legacy_pfd1() {
+        let bytes: &[u8] = include_bytes!("../test_data/pfd1.puffin");
+        let frame_view = crate::FrameView::read(&mut &bytes[..]).unwrap();
+        let frames: Vec<_> = frame_view.all_uniq().collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].frame_index(), 7);
+        assert_eq!(frames[1].frame_index(), 8);
+        assert_eq!(frames[1].meta().num_scopes, 2);
+
+        // Even if the native `zstd` library is used above, the pure-Rust fallback must work too:
+        let compressed_length = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let serialized = super::decode_ruzstd(&bytes[12..12 + compressed_length]).unwrap();
+        assert_eq!(serialized[0], 7, "Expected the frame index first");
+    }
+
+    #[cfg(all(feature = "packing", feature = "zstd"))]
+    #[test]
+    fn higher_compression_level_is_smaller() {
+        use super::{CompressionAlgorithm, CompressionConfig, PackedStreams};
+
+        let mut stream = Stream::default();
+        for i in 0..1_000_u32 {
+            let start_ns = i64::from(i) * 100;
+            let (scope, _) = stream.begin_scope(|| start_ns, ScopeId::new(i % 7 + 1), "data");
+            stream.end_scope(scope, start_ns + 50 + i64::from(i % 13));
+        }
+        let (thread_info, stream_info) = crate::test_thread_stream("main", stream);
+        let streams = BTreeMap::from([(thread_info, std::sync::Arc::new(stream_info))]);
+
+        let pack = |level| {
+            let config = CompressionConfig {
+                algorithm: CompressionAlgorithm::Zstd,
+                level,
+            };
+            PackedStreams::pack(&streams, &config).num_bytes()
+        };
+        let fast = pack(1);
+        let small = pack(19);
+        assert!(
+            small < fast,
+            "Expected level 19 ({small} B) < level 1 ({fast} B)"
+        );
+    }
+}
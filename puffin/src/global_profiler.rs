@@ -3,21 +3,32 @@ use std::{collections::BTreeMap, sync::Arc};
 use once_cell::sync::Lazy;
 
 use crate::{
-    fetch_add_scope_id, Error, FrameData, FrameIndex, FrameSinkId, ScopeCollection, ScopeDetails,
-    ScopeId, StreamInfo, StreamInfoRef, ThreadInfo,
+    fetch_add_scope_id, intake::ThreadIntake, Error, FrameData, FrameIndex, FrameSinkId,
+    ScopeCollection, ScopeDetails, ScopeId, StreamInfo, StreamInfoRef, ThreadInfo,
+    UnpackedFrameData,
 };
 
-/// Add these to [`GlobalProfiler`] with [`GlobalProfiler::add_sink()`].
+use crate::NanoSecond;
+
+/// Add these to a [`Profiler`] with [`Profiler::add_sink()`].
 pub type FrameSink = Box<dyn Fn(Arc<FrameData>) + Send>;
 
-/// Singleton. Collects profiling data from multiple threads
+/// Add these to a [`Profiler`] with [`Profiler::add_unpacked_sink()`].
+pub type UnpackedFrameSink = Box<dyn Fn(Arc<UnpackedFrameData>) + Send>;
+
+/// Collects profiling data from multiple threads
 /// and passes them on to different [`FrameSink`]s.
-pub struct GlobalProfiler {
+///
+/// The profile macros report to the [`GlobalProfiler`] singleton by default.
+/// You can also create your own [`Profiler`]s, e.g. one per subsystem,
+/// and direct the scopes of a thread to one with [`crate::ThreadProfiler::initialize_with`].
+pub struct Profiler {
     current_frame_index: FrameIndex,
     current_frame: BTreeMap<ThreadInfo, StreamInfo>,
 
     next_sink_id: FrameSinkId,
     sinks: std::collections::HashMap<FrameSinkId, FrameSink>,
+    unpacked_sinks: std::collections::HashMap<FrameSinkId, UnpackedFrameSink>,
     // When true will propagate a full snapshot from `scope_collection` to every sink.
     propagate_all_scope_details: bool,
     // The new scopes' details, or also the first time macro or external library detected a scope.
@@ -25,30 +36,142 @@ pub struct GlobalProfiler {
     // Store an absolute collection of scope details such that sinks can request a total state by setting `propagate_all_scope_details`.
     // This should not be mutable accessible to external applications as frame views store there own copy.
     scope_collection: ScopeCollection,
+    // Streams queued by the threads reporting to this profiler, collected at each `new_frame`.
+    pub(crate) intake: Arc<ThreadIntake>,
+    // When `new_frame` was last called, so that automatic frames don't follow manual ones too closely.
+    last_new_frame_ns: NanoSecond,
+    // Set `FrameMeta::wall_clock_ns` of new frames.
+    record_wall_clock: bool,
 }
 
-impl Default for GlobalProfiler {
+impl Default for Profiler {
     fn default() -> Self {
         Self {
             current_frame_index: 0,
             current_frame: Default::default(),
             next_sink_id: FrameSinkId(1),
             sinks: Default::default(),
+            unpacked_sinks: Default::default(),
             propagate_all_scope_details: Default::default(),
             new_scopes: Default::default(),
             scope_collection: Default::default(),
+            intake: Default::default(),
+            last_new_frame_ns: 0,
+            record_wall_clock: false,
         }
     }
 }
 
+/// The [`Profiler`] singleton that the profile macros report to,
+/// unless a thread was directed elsewhere with [`crate::ThreadProfiler::initialize_with`].
+///
+/// Access it with [`GlobalProfiler::lock`]. It derefs to the [`Profiler`] it wraps,
+/// so e.g. `GlobalProfiler::lock().new_frame()` works like [`Profiler::new_frame`].
+///
+/// The switches that affect all profilers, e.g. [`Self::set_measure_sink_overhead`], live here too.
+#[derive(Default)]
+pub struct GlobalProfiler {
+    profiler: Profiler,
+}
+
+impl std::ops::Deref for GlobalProfiler {
+    type Target = Profiler;
+
+    fn deref(&self) -> &Profiler {
+        &self.profiler
+    }
+}
+
+impl std::ops::DerefMut for GlobalProfiler {
+    fn deref_mut(&mut self) -> &mut Profiler {
+        &mut self.profiler
+    }
+}
+
 impl GlobalProfiler {
     /// Access to the global profiler singleton.
+    ///
+    /// With the `lock_contention` feature, time spent waiting for the lock
+    /// is recorded and can be queried with `lock_contention`.
     pub fn lock() -> parking_lot::MutexGuard<'static, Self> {
-        static GLOBAL_PROFILER: Lazy<parking_lot::Mutex<GlobalProfiler>> =
-            Lazy::new(Default::default);
-        GLOBAL_PROFILER.lock()
+        static GLOBAL_PROFILER: Lazy<parking_lot::Mutex<GlobalProfiler>> = Lazy::new(|| {
+            parking_lot::Mutex::new(GlobalProfiler {
+                profiler: Profiler {
+                    intake: ThreadIntake::global().clone(),
+                    ..Default::default()
+                },
+            })
+        });
+
+        #[cfg(feature = "lock_contention")]
+        {
+            contention::lock(&GLOBAL_PROFILER)
+        }
+
+        #[cfg(not(feature = "lock_contention"))]
+        {
+            GLOBAL_PROFILER.lock()
+        }
+    }
+
+    /// How much time has been spent waiting for [`Self::lock`] so far
+    /// (or since the last call to [`Self::reset_lock_contention`]).
+    ///
+    /// Use this to quantify profiler-induced contention in heavily threaded applications.
+    #[cfg(feature = "lock_contention")]
+    pub fn lock_contention() -> LockContention {
+        contention::get()
+    }
+
+    /// Reset the counters returned by [`Self::lock_contention`].
+    #[cfg(feature = "lock_contention")]
+    pub fn reset_lock_contention() {
+        contention::reset();
     }
 
+    /// Measure how long the sinks take to process each frame,
+    /// i.e. how much time [`Profiler::new_frame`] spends handing the frame to
+    /// (and packing, sending, or saving it in) the sinks, for all profilers.
+    ///
+    /// Query the result with [`Self::sink_overhead`]. Off by default.
+    pub fn set_measure_sink_overhead(measure: bool) {
+        sink_overhead::set_enabled(measure);
+    }
+
+    /// Are we measuring the [`Self::sink_overhead`]?
+    pub fn is_measuring_sink_overhead() -> bool {
+        sink_overhead::is_enabled()
+    }
+
+    /// How much time the sinks have spent on processing frames so far
+    /// (or since the last call to [`Self::reset_sink_overhead`]).
+    ///
+    /// Only collected after calling [`Self::set_measure_sink_overhead`].
+    /// Use this to detect hitches caused by the profiler itself.
+    pub fn sink_overhead() -> SinkOverhead {
+        sink_overhead::get()
+    }
+
+    /// Reset the counters returned by [`Self::sink_overhead`].
+    pub fn reset_sink_overhead() {
+        sink_overhead::reset();
+    }
+
+    /// Call [`Profiler::new_frame`] on the global profiler from a background thread,
+    /// whenever `interval` has passed without a call to it.
+    ///
+    /// This is useful for applications without a main loop (e.g. servers),
+    /// which would otherwise have no good place to call [`Profiler::new_frame`].
+    /// Manual calls to [`Profiler::new_frame`] still work, and postpone the next automatic frame.
+    ///
+    /// Pass `None` to stop the background thread. Off by default.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_auto_frame_interval(interval: Option<std::time::Duration>) {
+        auto_frame::set_interval(interval);
+    }
+}
+
+impl Profiler {
     /// You need to call this once at the start of every frame.
     ///
     /// It is fine to call this from within a profile scope.
@@ -56,6 +179,20 @@ impl GlobalProfiler {
     /// This takes all completed profiling scopes from all threads,
     /// and sends it to the sinks.
     pub fn new_frame(&mut self) {
+        self.new_frame_and_take();
+    }
+
+    /// Like [`Self::new_frame`], but also returns the frame that was sent to the sinks.
+    ///
+    /// Handy for tests and one-off profiling, where installing a sink is overkill.
+    /// Returns `None` if nothing was reported since the last frame.
+    pub fn new_frame_and_take(&mut self) -> Option<Arc<FrameData>> {
+        self.last_new_frame_ns = crate::now_ns();
+
+        // Collect everything the threads have reported since last frame:
+        self.intake
+            .drain_into(&mut self.current_frame, &mut self.new_scopes);
+
         let current_frame_index = self.current_frame_index;
         self.current_frame_index += 1;
 
@@ -76,23 +213,52 @@ impl GlobalProfiler {
             scope_deltas.extend(self.scope_collection.scopes_by_id().values().cloned());
         }
 
-        let new_frame = match FrameData::new(
-            current_frame_index,
-            current_frame_scope,
-            scope_deltas,
-            propagate_full_delta,
-        ) {
-            Ok(new_frame) => Arc::new(new_frame),
+        let new_frame = match UnpackedFrameData::new(current_frame_index, current_frame_scope) {
+            Ok(mut unpacked) => {
+                if self.record_wall_clock {
+                    unpacked.meta.wall_clock_ns = crate::wall_clock_ns()
+                        .map(|now| now - (self.last_new_frame_ns - unpacked.meta.range_ns.0));
+                }
+                Arc::new(FrameData::from_unpacked(
+                    Arc::new(unpacked),
+                    scope_deltas,
+                    propagate_full_delta,
+                ))
+            }
             Err(Error::Empty) => {
-                return; // don't warn about empty frames, just ignore them
+                return None; // don't warn about empty frames, just ignore them
             }
             Err(err) => {
                 eprintln!("puffin ERROR: Bad frame: {err:?}");
-                return;
+                return None;
             }
         };
 
-        self.add_frame(new_frame);
+        self.add_frame(new_frame.clone());
+        Some(new_frame)
+    }
+
+    /// Whether new frames record the wall clock time, see [`Self::set_record_wall_clock`].
+    pub fn record_wall_clock(&self) -> bool {
+        self.record_wall_clock
+    }
+
+    /// Record the wall clock time ([`std::time::SystemTime`]) in [`crate::FrameMeta::wall_clock_ns`]
+    /// of each new frame, so you can line up the scopes with e.g. logs that use system time.
+    ///
+    /// This assumes the scopes are timed with [`crate::now_ns`], which is the default.
+    /// Off by default.
+    pub fn set_record_wall_clock(&mut self, record_wall_clock: bool) {
+        self.record_wall_clock = record_wall_clock;
+    }
+
+    /// Send everything reported so far to the sinks right away,
+    /// without waiting for the next call to [`Self::new_frame`].
+    ///
+    /// Use this in tests and before shutting down, so that the sinks see the last scopes.
+    /// The scopes are emitted as a frame of their own, and nothing is sent if there are none.
+    pub fn flush(&mut self) {
+        self.new_frame();
     }
 
     /// Manually add frame data.
@@ -101,9 +267,32 @@ impl GlobalProfiler {
             self.scope_collection.insert(delta.clone());
         }
 
+        let start_ns = sink_overhead::is_enabled().then(crate::now_ns);
+
         for sink in self.sinks.values() {
             sink(new_frame.clone());
         }
+
+        if !self.unpacked_sinks.is_empty() {
+            // Unpack once, and share the result with all the sinks.
+            match new_frame.unpacked().ok() {
+                Some(unpacked) => {
+                    for sink in self.unpacked_sinks.values() {
+                        sink(unpacked.clone());
+                    }
+                }
+                None => {
+                    eprintln!(
+                        "puffin ERROR: Failed to unpack frame {}",
+                        new_frame.frame_index()
+                    );
+                }
+            }
+        }
+
+        if let Some(start_ns) = start_ns {
+            sink_overhead::record(crate::now_ns() - start_ns);
+        }
     }
 
     /// Inserts user scopes into puffin.
@@ -126,6 +315,13 @@ impl GlobalProfiler {
         new_scope_ids
     }
 
+    /// Forget all registered scopes, see [`crate::reset_scope_ids`].
+    #[cfg(test)]
+    pub(crate) fn clear_scopes(&mut self) {
+        self.new_scopes.clear();
+        self.scope_collection = Default::default();
+    }
+
     /// Reports some profiling data. Called from [`crate::ThreadProfiler`].
     pub fn report(
         &mut self,
@@ -154,7 +350,32 @@ impl GlobalProfiler {
             .extend(stream_scope_times);
     }
 
-    /// Tells [`GlobalProfiler`] to call this function with each new finished frame.
+    /// Reports a scope with times measured outside of puffin, e.g. with GPU timestamp queries,
+    /// on the pseudo-thread `thread_name`.
+    ///
+    /// `scope_id` should first be registered with [`Self::register_user_scopes`],
+    /// and the times should be in the same time base as [`crate::now_ns`].
+    /// The scope is added to the current frame as a top-level scope,
+    /// so scopes of the same pseudo-thread must be reported in chronological order and must not overlap.
+    /// Build a [`crate::Stream`] and use [`Self::report_user_scopes`] for nested scopes.
+    ///
+    /// See also [`crate::ThreadProfiler::report_external_scope`], which doesn't need the lock.
+    /// Don't mix the two for the same pseudo-thread: scopes queued by threads are only collected
+    /// by [`Self::new_frame`], after the ones reported here.
+    pub fn report_external_scope(
+        &mut self,
+        thread_name: &str,
+        scope_id: ScopeId,
+        start_ns: NanoSecond,
+        stop_ns: NanoSecond,
+        data: &str,
+    ) {
+        let info = ThreadInfo::new(None, thread_name);
+        let stream_info = StreamInfo::from_scope(scope_id, start_ns, stop_ns, data);
+        self.report_user_scopes(info, &stream_info.as_stream_into_ref());
+    }
+
+    /// Tells the [`Profiler`] to call this function with each new finished frame.
     ///
     /// The returned [`FrameSinkId`] can be used to remove the sink with [`Self::remove_sink()`].
     /// If the sink is registered later in the application make sure to call [`Self::emit_scope_snapshot()`] to send a snapshot of all scopes.
@@ -165,14 +386,419 @@ impl GlobalProfiler {
         id
     }
 
-    /// Removes a sink from the global profiler.
+    /// Removes a sink from the profiler.
     pub fn remove_sink(&mut self, id: FrameSinkId) -> Option<FrameSink> {
         self.sinks.remove(&id)
     }
 
+    /// Like [`Self::add_sink`], but the sink is given the unpacked frame data.
+    ///
+    /// Each frame is unpacked only once, no matter how many unpacked sinks there are.
+    ///
+    /// The returned [`FrameSinkId`] can be used to remove the sink with [`Self::remove_unpacked_sink()`].
+    pub fn add_unpacked_sink(&mut self, sink: UnpackedFrameSink) -> FrameSinkId {
+        let id = self.next_sink_id;
+        self.next_sink_id.0 += 1;
+        self.unpacked_sinks.insert(id, sink);
+        id
+    }
+
+    /// Removes a sink added with [`Self::add_unpacked_sink`] from the profiler.
+    pub fn remove_unpacked_sink(&mut self, id: FrameSinkId) -> Option<UnpackedFrameSink> {
+        self.unpacked_sinks.remove(&id)
+    }
+
     /// Sends a snapshot of all scopes to all sinks via the frame data.
     /// This is useful for if a sink is initialized after scopes are registered.
     pub fn emit_scope_snapshot(&mut self) {
         self.propagate_all_scope_details = true;
     }
 }
+
+// ----------------------------------------------------------------------------
+
+#[cfg(not(target_arch = "wasm32"))]
+mod auto_frame {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::GlobalProfiler;
+
+    /// Dropping this stops the background thread.
+    static STOP_TX: parking_lot::Mutex<Option<mpsc::Sender<()>>> = parking_lot::const_mutex(None);
+
+    pub fn set_interval(interval: Option<Duration>) {
+        let mut stop_tx = STOP_TX.lock();
+        *stop_tx = None; // stop any previous thread
+
+        let Some(interval) = interval else {
+            return;
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let spawned = std::thread::Builder::new()
+            .name("puffin-auto-frame".to_owned())
+            .spawn(move || run(interval, &rx));
+        match spawned {
+            Ok(_) => *stop_tx = Some(tx),
+            Err(err) => eprintln!("puffin ERROR: Failed to start auto frame thread: {err}"),
+        }
+    }
+
+    fn run(interval: Duration, stop_rx: &mpsc::Receiver<()>) {
+        let interval_ns = interval.as_nanos() as i64;
+        let mut wait = interval;
+        loop {
+            match stop_rx.recv_timeout(wait) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+
+            let mut profiler = GlobalProfiler::lock();
+            let since_last_frame_ns = crate::now_ns() - profiler.last_new_frame_ns;
+            if interval_ns <= since_last_frame_ns {
+                profiler.new_frame();
+                wait = interval;
+            } else {
+                // Someone else called `new_frame` in the meantime.
+                wait = Duration::from_nanos((interval_ns - since_last_frame_ns) as u64);
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// How much time the sinks spent on processing frames.
+///
+/// Only collected after calling [`GlobalProfiler::set_measure_sink_overhead`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SinkOverhead {
+    /// Number of frames measured.
+    pub num_frames: u64,
+
+    /// Time spent in the sinks for the latest frame.
+    pub last_ns: NanoSecond,
+
+    /// Total time spent in the sinks.
+    pub total_ns: NanoSecond,
+
+    /// The slowest frame to process.
+    pub max_ns: NanoSecond,
+}
+
+impl SinkOverhead {
+    /// Average time spent in the sinks per frame.
+    pub fn mean_ns(&self) -> NanoSecond {
+        if self.num_frames == 0 {
+            0
+        } else {
+            self.total_ns / self.num_frames as NanoSecond
+        }
+    }
+}
+
+mod sink_overhead {
+    use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering::Relaxed};
+
+    use super::SinkOverhead;
+
+    // Statics rather than fields, so that they can be read without taking the `GlobalProfiler` lock,
+    // which a sink may be waiting on.
+    static ENABLED: AtomicBool = AtomicBool::new(false);
+    static NUM_FRAMES: AtomicU64 = AtomicU64::new(0);
+    static LAST_NS: AtomicI64 = AtomicI64::new(0);
+    static TOTAL_NS: AtomicI64 = AtomicI64::new(0);
+    static MAX_NS: AtomicI64 = AtomicI64::new(0);
+
+    pub fn set_enabled(enabled: bool) {
+        ENABLED.store(enabled, Relaxed);
+    }
+
+    pub fn is_enabled() -> bool {
+        ENABLED.load(Relaxed)
+    }
+
+    pub fn record(duration_ns: i64) {
+        NUM_FRAMES.fetch_add(1, Relaxed);
+        LAST_NS.store(duration_ns, Relaxed);
+        TOTAL_NS.fetch_add(duration_ns, Relaxed);
+        MAX_NS.fetch_max(duration_ns, Relaxed);
+    }
+
+    pub fn get() -> SinkOverhead {
+        SinkOverhead {
+            num_frames: NUM_FRAMES.load(Relaxed),
+            last_ns: LAST_NS.load(Relaxed),
+            total_ns: TOTAL_NS.load(Relaxed),
+            max_ns: MAX_NS.load(Relaxed),
+        }
+    }
+
+    pub fn reset() {
+        NUM_FRAMES.store(0, Relaxed);
+        LAST_NS.store(0, Relaxed);
+        TOTAL_NS.store(0, Relaxed);
+        MAX_NS.store(0, Relaxed);
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// Statistics about waiting for [`GlobalProfiler::lock`].
+///
+/// Only collected with the `lock_contention` feature.
+#[cfg(feature = "lock_contention")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LockContention {
+    /// Number of times the lock was taken.
+    pub num_locks: u64,
+
+    /// Number of times the lock was held by someone else, so we had to wait.
+    pub num_contended: u64,
+
+    /// Total time spent waiting for the lock.
+    pub total_wait_ns: NanoSecond,
+
+    /// The longest single wait for the lock.
+    pub max_wait_ns: NanoSecond,
+}
+
+#[cfg(feature = "lock_contention")]
+mod contention {
+    use std::sync::atomic::{AtomicI64, AtomicU64, Ordering::Relaxed};
+
+    use super::{GlobalProfiler, LockContention};
+
+    // Plain relaxed counters, so that measuring doesn't serialize the threads any further.
+    static NUM_LOCKS: AtomicU64 = AtomicU64::new(0);
+    static NUM_CONTENDED: AtomicU64 = AtomicU64::new(0);
+    static TOTAL_WAIT_NS: AtomicI64 = AtomicI64::new(0);
+    static MAX_WAIT_NS: AtomicI64 = AtomicI64::new(0);
+
+    pub fn lock(
+        mutex: &'static parking_lot::Mutex<GlobalProfiler>,
+    ) -> parking_lot::MutexGuard<'static, GlobalProfiler> {
+        NUM_LOCKS.fetch_add(1, Relaxed);
+
+        // Only pay for reading the clock when we actually have to wait:
+        if let Some(guard) = mutex.try_lock() {
+            return guard;
+        }
+
+        let start_ns = crate::now_ns();
+        let guard = mutex.lock();
+        let wait_ns = crate::now_ns() - start_ns;
+
+        NUM_CONTENDED.fetch_add(1, Relaxed);
+        TOTAL_WAIT_NS.fetch_add(wait_ns, Relaxed);
+        MAX_WAIT_NS.fetch_max(wait_ns, Relaxed);
+
+        guard
+    }
+
+    pub fn get() -> LockContention {
+        LockContention {
+            num_locks: NUM_LOCKS.load(Relaxed),
+            num_contended: NUM_CONTENDED.load(Relaxed),
+            total_wait_ns: TOTAL_WAIT_NS.load(Relaxed),
+            max_wait_ns: MAX_WAIT_NS.load(Relaxed),
+        }
+    }
+
+    pub fn reset() {
+        NUM_LOCKS.store(0, Relaxed);
+        NUM_CONTENDED.store(0, Relaxed);
+        TOTAL_WAIT_NS.store(0, Relaxed);
+        MAX_WAIT_NS.store(0, Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        FrameData, GlobalProfiler, Profiler, ScopeId, Stream, StreamInfo, ThreadInfo,
+        ThreadProfiler,
+    };
+
+    #[test]
+    fn intake_collects_all_threads_before_emit() {
+        let mut profiler = Profiler::default();
+        let intake = &profiler.intake.clone();
+
+        let frames = Arc::new(parking_lot::Mutex::new(vec![]));
+        let frames_clone = frames.clone();
+        profiler.add_sink(Box::new(move |frame| frames_clone.lock().push(frame)));
+
+        let num_threads = 8;
+        std::thread::scope(|s| {
+            for i in 0..num_threads {
+                s.spawn(move || {
+                    let mut stream = Stream::default();
+                    let (offset, _) = stream.begin_scope(|| 100 * i, ScopeId::new(1), "");
+                    stream.end_scope(offset, 100 * i + 50);
+                    let stream_info = StreamInfo::parse(stream).unwrap();
+
+                    let info = ThreadInfo::new(Some(i), format!("thread_{i}"));
+                    intake.report(info, &[], &stream_info.as_stream_into_ref());
+                });
+            }
+        });
+
+        profiler.new_frame();
+
+        let frames = frames.lock();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].meta().num_scopes, num_threads as usize);
+        assert_eq!(frames[0].range_ns(), (0, 100 * (num_threads - 1) + 50));
+    }
+
+    #[test]
+    fn new_frame_and_take() {
+        let mut profiler = Profiler::default();
+        let sunk = Arc::new(parking_lot::Mutex::new(vec![]));
+        profiler.add_sink(Box::new({
+            let sunk = sunk.clone();
+            move |frame| sunk.lock().push(frame)
+        }));
+
+        assert!(profiler.new_frame_and_take().is_none());
+
+        let mut stream = Stream::default();
+        let (outer, _) = stream.begin_scope(|| 0, ScopeId::new(1), "");
+        let (inner, _) = stream.begin_scope(|| 10, ScopeId::new(2), "");
+        stream.end_scope(inner, 20);
+        stream.end_scope(outer, 30);
+        let (thread_info, stream_info) = crate::test_thread_stream("main", stream);
+        profiler.report_user_scopes(thread_info, &stream_info.as_stream_into_ref());
+
+        let frame = profiler.new_frame_and_take().unwrap();
+        assert_eq!(frame.meta().num_scopes, 2);
+
+        // The sinks still get the frame:
+        let sunk = sunk.lock();
+        assert_eq!(sunk.len(), 1);
+        assert!(Arc::ptr_eq(&sunk[0], &frame));
+    }
+
+    #[test]
+    fn report_external_scope() {
+        let mut profiler = Profiler::default();
+        // Not registered, so the test doesn't race other tests for the global scope ids:
+        let gpu_scope = ScopeId::new(1);
+        let gpu_scopes = |frame: &FrameData| {
+            let unpacked = frame.unpacked().unwrap();
+            let (thread_info, stream_info) = unpacked.thread_streams.iter().next().unwrap();
+            assert_eq!(thread_info.name, "GPU");
+            let scopes = crate::Reader::from_start(&stream_info.stream)
+                .read_top_scopes()
+                .unwrap();
+            scopes
+                .iter()
+                .map(|scope| (scope.record.start_ns, scope.record.duration_ns))
+                .collect::<Vec<_>>()
+        };
+
+        ThreadProfiler::initialize_with(&profiler);
+        ThreadProfiler::call(|tp| {
+            tp.report_external_scope("GPU", gpu_scope, 100, 200, "shadows");
+            tp.report_external_scope("GPU", gpu_scope, 300, 350, "");
+        });
+        let frame = profiler.new_frame_and_take().unwrap();
+        assert_eq!(frame.meta().num_scopes, 2);
+        assert_eq!(frame.range_ns(), (100, 350));
+        assert_eq!(gpu_scopes(&frame), [(100, 100), (300, 50)]);
+
+        profiler.report_external_scope("GPU", gpu_scope, 400, 410, "");
+        let frame = profiler.new_frame_and_take().unwrap();
+        assert_eq!(gpu_scopes(&frame), [(400, 10)]);
+    }
+
+    #[test]
+    fn measure_sink_overhead() {
+        let mut profiler = Profiler::default();
+        profiler.add_sink(Box::new(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }));
+
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| 0, ScopeId::new(1), "");
+        stream.end_scope(offset, 10);
+        let (thread_info, stream_info) = crate::test_thread_stream("main", stream);
+        profiler.report_user_scopes(thread_info, &stream_info.as_stream_into_ref());
+
+        GlobalProfiler::set_measure_sink_overhead(true);
+        profiler.new_frame();
+        GlobalProfiler::set_measure_sink_overhead(false);
+
+        // Other tests may be adding frames concurrently, so only check what they can't undo:
+        let overhead = GlobalProfiler::sink_overhead();
+        assert!(1 <= overhead.num_frames);
+        assert!(2_000_000 <= overhead.max_ns);
+    }
+
+    #[cfg(feature = "packing")]
+    #[test]
+    fn unpacked_sinks_share_one_unpack() {
+        let mut profiler = Profiler::default();
+
+        let raw_frames = Arc::new(parking_lot::Mutex::new(vec![]));
+        let raw_frames_clone = raw_frames.clone();
+        profiler.add_sink(Box::new(move |frame| raw_frames_clone.lock().push(frame)));
+
+        let unpacked_frames = Arc::new(parking_lot::Mutex::new(vec![]));
+        for _ in 0..2 {
+            let unpacked_frames = unpacked_frames.clone();
+            profiler.add_unpacked_sink(Box::new(move |frame| unpacked_frames.lock().push(frame)));
+        }
+
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| 0, ScopeId::new(1), "");
+        stream.end_scope(offset, 10);
+        let thread_streams = [crate::test_thread_stream("main", stream)].into();
+        let frame = Arc::new(FrameData::new(0, thread_streams, vec![], false).unwrap());
+        frame.pack();
+        assert!(!frame.has_unpacked());
+
+        profiler.add_frame(frame);
+
+        assert_eq!(raw_frames.lock().len(), 1);
+        let unpacked_frames = unpacked_frames.lock();
+        assert_eq!(unpacked_frames.len(), 2);
+        assert!(Arc::ptr_eq(&unpacked_frames[0], &unpacked_frames[1]));
+        assert_eq!(unpacked_frames[0].meta.num_scopes, 1);
+    }
+
+    #[test]
+    fn record_wall_clock() {
+        let frames = Arc::new(parking_lot::Mutex::new(vec![]));
+        let mut profiler = Profiler::default();
+        profiler.add_sink(Box::new({
+            let frames = frames.clone();
+            move |frame| frames.lock().push(frame)
+        }));
+
+        let report = |profiler: &mut Profiler| {
+            let start_ns = crate::now_ns();
+            let mut stream = Stream::default();
+            let (offset, _) = stream.begin_scope(|| start_ns, ScopeId::new(1), "");
+            stream.end_scope(offset, start_ns + 10);
+            let (thread_info, stream_info) = crate::test_thread_stream("main", stream);
+            profiler.report_user_scopes(thread_info, &stream_info.as_stream_into_ref());
+            profiler.new_frame();
+        };
+
+        report(&mut profiler);
+        profiler.set_record_wall_clock(true);
+        let before_ns = crate::wall_clock_ns().unwrap();
+        report(&mut profiler);
+        let after_ns = crate::wall_clock_ns().unwrap();
+
+        let frames = frames.lock();
+        assert_eq!(frames[0].meta().wall_clock_ns, None);
+        let wall_clock_ns = frames[1].meta().wall_clock_ns.unwrap();
+        // Allow for `now_ns` and the system clock drifting apart a bit:
+        assert!((before_ns - 1_000_000..=after_ns).contains(&wall_clock_ns));
+    }
+}
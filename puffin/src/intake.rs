@@ -0,0 +1,104 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use once_cell::sync::Lazy;
+
+use crate::{ScopeDetails, StreamInfo, StreamInfoRef, ThreadInfo};
+
+/// Number of independent queues that reporting threads are spread over.
+const NUM_SHARDS: usize = 16;
+
+/// Streams reported by threads, waiting to be picked up by the next [`crate::Profiler::new_frame`].
+#[derive(Default)]
+struct Shard {
+    streams: BTreeMap<ThreadInfo, StreamInfo>,
+    new_scopes: Vec<Arc<ScopeDetails>>,
+}
+
+/// Sharded intake of thread streams.
+///
+/// Threads report into their own shard, so they only contend with the (few) other
+/// threads sharing that shard, instead of with every thread and the frame assembly
+/// behind the single [`crate::Profiler`] mutex.
+pub(crate) struct ThreadIntake {
+    shards: [parking_lot::Mutex<Shard>; NUM_SHARDS],
+}
+
+impl Default for ThreadIntake {
+    fn default() -> Self {
+        Self {
+            shards: std::array::from_fn(|_| Default::default()),
+        }
+    }
+}
+
+impl ThreadIntake {
+    /// The intake of the [`crate::GlobalProfiler`], used by [`crate::internal_profile_reporter`].
+    pub fn global() -> &'static Arc<Self> {
+        static GLOBAL_INTAKE: Lazy<Arc<ThreadIntake>> = Lazy::new(Default::default);
+        &GLOBAL_INTAKE
+    }
+
+    /// Queue some profiling data, to be collected by [`Self::drain_into`].
+    pub fn report(
+        &self,
+        info: ThreadInfo,
+        scope_details: &[ScopeDetails],
+        stream_scope_times: &StreamInfoRef<'_>,
+    ) {
+        let mut shard = self.shards[shard_index()].lock();
+
+        if !scope_details.is_empty() {
+            shard
+                .new_scopes
+                .extend(scope_details.iter().map(|x| Arc::new(x.clone())));
+        }
+
+        shard
+            .streams
+            .entry(info)
+            .or_default()
+            .extend(stream_scope_times);
+    }
+
+    /// Move everything reported so far into the given frame.
+    pub fn drain_into(
+        &self,
+        current_frame: &mut BTreeMap<ThreadInfo, StreamInfo>,
+        new_scopes: &mut Vec<Arc<ScopeDetails>>,
+    ) {
+        for shard in &self.shards {
+            let Shard {
+                streams,
+                new_scopes: shard_scopes,
+            } = std::mem::take(&mut *shard.lock());
+
+            new_scopes.extend(shard_scopes);
+
+            for (info, stream_info) in streams {
+                match current_frame.entry(info) {
+                    std::collections::btree_map::Entry::Vacant(entry) => {
+                        entry.insert(stream_info);
+                    }
+                    std::collections::btree_map::Entry::Occupied(mut entry) => {
+                        entry.get_mut().extend(&stream_info.as_stream_into_ref());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Each thread sticks to one shard, so that its streams stay in order.
+fn shard_index() -> usize {
+    static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SHARD_INDEX: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % NUM_SHARDS;
+    }
+    SHARD_INDEX.with(|index| *index)
+}
@@ -23,11 +23,20 @@
 #![deny(missing_docs)]
 
 mod data;
+#[cfg(feature = "serialization")]
+mod file_index;
+#[cfg(all(feature = "serialization", not(target_arch = "wasm32")))]
+mod file_recorder;
 mod frame_data;
 mod global_profiler;
+mod intake;
 mod merge;
+#[cfg(feature = "perfetto")]
+mod perfetto;
 mod profile_view;
+mod replayer;
 mod scope_details;
+mod task;
 mod thread_profiler;
 mod utils;
 
@@ -35,14 +44,39 @@ use std::num::NonZeroU32;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 /// TODO: Improve encapsulation.
-pub use data::{Error, Reader, Result, Scope, ScopeRecord, Stream, StreamInfo, StreamInfoRef};
-pub use frame_data::{FrameData, FrameMeta, UnpackedFrameData};
-pub use global_profiler::{FrameSink, GlobalProfiler};
-pub use merge::{merge_scopes_for_thread, MergeScope};
-pub use profile_view::{select_slowest, FrameStats, FrameView, GlobalFrameView};
+pub use data::{
+    CounterRecord, Error, FlowRecord, MarkerRecord, Reader, Result, Scope, ScopeRecord, ScopeTags,
+    Stream, StreamInfo, StreamInfoRef, TaskEvent, TaskRecord,
+};
+#[cfg(feature = "serialization")]
+pub use file_index::{FileIndex, FileIndexEntry};
+#[cfg(all(feature = "serialization", not(target_arch = "wasm32")))]
+pub use file_recorder::FileRecorder;
+pub use frame_data::{
+    CompressionAlgorithm, CompressionConfig, FrameData, FrameMeta, ScopeNode, ScopeTime,
+    UnpackedFrameData,
+};
+#[cfg(feature = "lock_contention")]
+pub use global_profiler::LockContention;
+pub use global_profiler::{FrameSink, GlobalProfiler, Profiler, SinkOverhead, UnpackedFrameSink};
+pub use merge::{
+    average_frame, merge_scopes_for_thread, merge_scopes_for_threads, task_timelines,
+    DurationDistribution, MergeScope, TaskPoll, TaskTimeline,
+};
+pub use profile_view::{
+    select_slowest, FrameStats, FrameView, GlobalFrameView, OnSlowFrame, PackPolicy, ThreadStats,
+};
+pub use replayer::Replayer;
 pub use scope_details::{ScopeCollection, ScopeDetails, ScopeType};
-pub use thread_profiler::{internal_profile_reporter, ThreadInfo, ThreadProfiler};
-pub use utils::{clean_function_name, short_file_name, shorten_rust_function_name, type_name_of};
+pub use task::ProfiledTask;
+pub use thread_profiler::{
+    internal_profile_reporter, DiscardableScope, ScopeEvent, ScopeListener, ThreadInfo,
+    ThreadProfiler, ThreadProfilerHandle,
+};
+pub use utils::{
+    clean_function_name, full_function_name, short_file_name, shorten_rust_function_name,
+    type_name_of,
+};
 
 static MACROS_ON: AtomicBool = AtomicBool::new(false);
 
@@ -61,6 +95,62 @@ pub fn are_scopes_on() -> bool {
     MACROS_ON.load(Ordering::Relaxed)
 }
 
+/// Decides whether a scope is recorded, see [`set_scope_filter`].
+pub type ScopeFilter = Box<dyn Fn(&ScopeDetails) -> bool + Send + Sync>;
+
+static SCOPE_FILTER: parking_lot::RwLock<Option<ScopeFilter>> = parking_lot::const_rwlock(None);
+
+/// Only record the scopes for which `filter` returns `true`, e.g. to cut the overhead
+/// of profiling a subsystem you are not interested in.
+///
+/// The filter is called once for each scope, when the scope is registered
+/// (e.g. with [`ThreadProfiler::register_named_scope`]), and the profile macros cache the
+/// decision at their call site. So the filter must be set before the scopes are first entered,
+/// and changing it later only affects scopes that haven't been entered yet.
+/// Rejected scopes get [`ScopeId::DISABLED`], and cost almost nothing.
+///
+/// `None` records all scopes again (the default).
+pub fn set_scope_filter(filter: Option<ScopeFilter>) {
+    *SCOPE_FILTER.write() = filter;
+}
+
+/// Should the scope be recorded, according to [`set_scope_filter`]?
+fn passes_scope_filter(scope_details: &ScopeDetails) -> bool {
+    SCOPE_FILTER
+        .read()
+        .as_ref()
+        .map_or(true, |filter| filter(scope_details))
+}
+
+/// Name the calling thread in the profiler, e.g. "render" or "audio".
+///
+/// This overrides the name of the OS thread (see [`std::thread::Builder::name`]),
+/// which may be missing or generic (e.g. "tokio-runtime-worker").
+/// It can be called at any time, and affects the scopes reported from then on.
+/// An empty `role` goes back to using the name of the OS thread.
+///
+/// Only affects the thread local [`ThreadProfiler`].
+pub fn set_thread_role(role: impl Into<String>) {
+    let role = role.into();
+    let role = (!role.is_empty()).then_some(role);
+    ThreadProfiler::call(|tp| tp.set_role(role.clone()));
+}
+
+/// How many profiler scopes are currently open on the calling thread.
+///
+/// Useful to cap the profiling overhead of deeply recursive code, e.g.
+/// `puffin::profile_scope_if!(puffin::current_depth() < 8, "deep");`
+///
+/// Returns `0` if [`are_scopes_on`] is `false`, without touching the thread local [`ThreadProfiler`].
+#[inline]
+pub fn current_depth() -> usize {
+    if are_scopes_on() {
+        ThreadProfiler::call(|tp| tp.current_depth())
+    } else {
+        0
+    }
+}
+
 /// All times are expressed as integer nanoseconds since some event.
 pub type NanoSecond = i64;
 
@@ -80,7 +170,31 @@ fn fetch_add_scope_id() -> ScopeId {
     )
 }
 
-/// Identifies a specific [`FrameSink`] when added to [`GlobalProfiler`].
+/// Make the next registered scope get [`ScopeId::new(1)`] again,
+/// and forget the scopes registered with the [`GlobalProfiler`].
+///
+/// Only for tests that assert on scope ids. Scopes whose ids are cached at their call site,
+/// like those of [`profile_scope!`], keep their old ids.
+/// Hold on to the returned guard while registering scopes, so such tests don't interfere.
+#[cfg(test)]
+pub(crate) fn reset_scope_ids() -> parking_lot::MutexGuard<'static, ()> {
+    static LOCK: parking_lot::Mutex<()> = parking_lot::const_mutex(());
+    let guard = LOCK.lock();
+    GlobalProfiler::lock().clear_scopes();
+    SCOPE_ID_TRACKER.store(1, std::sync::atomic::Ordering::Relaxed);
+    guard
+}
+
+/// A thread called `name` that reported `stream`, for the thread streams of test frames.
+#[cfg(test)]
+pub(crate) fn test_thread_stream(name: &str, stream: Stream) -> (ThreadInfo, StreamInfo) {
+    (
+        ThreadInfo::new(None, name),
+        StreamInfo::parse(stream).unwrap(),
+    )
+}
+
+/// Identifies a specific [`FrameSink`] when added to a [`Profiler`].
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct FrameSinkId(u64);
 
@@ -122,6 +236,32 @@ pub fn now_ns() -> NanoSecond {
     panic!("Wasm without the `web` feature requires passing a custom source of time via `ThreadProfiler::initialize`");
 }
 
+/// The current Unix time in nanoseconds according to the system clock,
+/// or [`None`] if the system clock is set before 1970.
+///
+/// Unlike [`now_ns`] this follows adjustments of the system clock, so it is not monotonic.
+#[cfg(any(not(target_arch = "wasm32"), feature = "web"))]
+pub(crate) fn wall_clock_ns() -> Option<NanoSecond> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        Some((js_sys::Date::new_0().get_time() * 1e6) as _)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::UNIX_EPOCH
+            .elapsed()
+            .ok()
+            .map(|duration_since_epoch| duration_since_epoch.as_nanos() as NanoSecond)
+    }
+}
+
+/// Wasm without the `web` feature has no system clock.
+#[cfg(all(target_arch = "wasm32", not(feature = "web")))]
+pub(crate) fn wall_clock_ns() -> Option<NanoSecond> {
+    None
+}
+
 // We currently store an Option<ProfilerScope> on the stack (None when profiling is off).
 // This currently takes up 16 bytes of stack space. TODO: get this down to 4 bytes.
 /// Created by the `puffin::profile*!(...)` macros.
@@ -140,17 +280,75 @@ impl ProfilerScope {
     /// `data` can be changing, i.e. a name of a mesh or a texture.
     #[inline]
     pub fn new(scope_id: ScopeId, data: impl AsRef<str>) -> Self {
+        let start_stream_offset = if scope_id == ScopeId::DISABLED {
+            Self::DISABLED_OFFSET
+        } else {
+            ThreadProfiler::call(|tp| tp.begin_scope(scope_id, data.as_ref()))
+        };
+        Self {
+            start_stream_offset,
+            _dont_send_me: Default::default(),
+        }
+    }
+
+    /// Like [`Self::new`], but also records key-value tags, see [`profile_scope_tags`].
+    #[inline]
+    pub fn with_tags(scope_id: ScopeId, data: impl AsRef<str>, tags: &[(&str, &str)]) -> Self {
+        let start_stream_offset = if scope_id == ScopeId::DISABLED {
+            Self::DISABLED_OFFSET
+        } else {
+            ThreadProfiler::call(|tp| tp.begin_scope_with_tags(scope_id, data.as_ref(), tags))
+        };
         Self {
-            start_stream_offset: ThreadProfiler::call(|tp| tp.begin_scope(scope_id, data.as_ref())),
+            start_stream_offset,
             _dont_send_me: Default::default(),
         }
     }
+
+    /// Marks a scope with [`ScopeId::DISABLED`], which is not recorded.
+    const DISABLED_OFFSET: usize = usize::MAX;
 }
 
 impl Drop for ProfilerScope {
     #[inline]
     fn drop(&mut self) {
-        ThreadProfiler::call(|tp| tp.end_scope(self.start_stream_offset));
+        if self.start_stream_offset != Self::DISABLED_OFFSET {
+            ThreadProfiler::call(|tp| tp.end_scope(self.start_stream_offset));
+        }
+    }
+}
+
+/// Created by [`profile_scope_min`]: a [`ProfilerScope`] that is discarded
+/// if it lasted less than a minimum duration.
+pub struct MinDurationScope {
+    /// `None` for scopes with [`ScopeId::DISABLED`].
+    scope: Option<DiscardableScope>,
+    min_duration_ns: NanoSecond,
+
+    /// Prevent the scope from being sent between threads, see [`ProfilerScope`].
+    _dont_send_me: std::marker::PhantomData<*const ()>,
+}
+
+impl MinDurationScope {
+    /// Like [`ProfilerScope::new`], but the scope is only kept if it lasts at least `min_duration_ns`.
+    #[inline]
+    pub fn new(scope_id: ScopeId, min_duration_ns: NanoSecond, data: impl AsRef<str>) -> Self {
+        Self {
+            scope: (scope_id != ScopeId::DISABLED).then(|| {
+                ThreadProfiler::call(|tp| tp.begin_discardable_scope(scope_id, data.as_ref()))
+            }),
+            min_duration_ns,
+            _dont_send_me: Default::default(),
+        }
+    }
+}
+
+impl Drop for MinDurationScope {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(scope) = self.scope {
+            ThreadProfiler::call(|tp| tp.end_scope_min_duration(scope, self.min_duration_ns));
+        }
     }
 }
 
@@ -163,6 +361,9 @@ impl Drop for ProfilerScope {
 pub struct ScopeId(pub NonZeroU32);
 
 impl ScopeId {
+    /// Given to scopes that are rejected by [`set_scope_filter`]. They are not recorded.
+    pub const DISABLED: Self = Self(NonZeroU32::MAX);
+
     #[cfg(test)]
     pub(crate) fn new(id: u32) -> Self {
         ScopeId(NonZeroU32::new(id).expect("Scope id was not non-zero u32"))
@@ -213,9 +414,26 @@ macro_rules! current_function_name {
 /// If the puffin profiler is turned off ([`crate::are_scopes_on`] is `false`),
 /// the cost is only checking an `AtomicBool`, which is less than 1ns.
 ///
+/// The scope is named after the function and its parent module, e.g. `physics::update`.
+/// Use `@full` to name it after the full module path instead, e.g. `my_game::physics::update`,
+/// to tell apart functions with the same name in modules with the same name:
+///
+/// ```
+/// fn update() {
+///     puffin::profile_function!(@full);
+///     /* … */
+/// }
+/// ```
+///
 /// You can conditionally profile a function with [`profile_function_if`].
 #[macro_export]
 macro_rules! profile_function {
+    (@full) => {
+        $crate::profile_function_if!(@full, true, "");
+    };
+    (@full, $data:expr) => {
+        $crate::profile_function_if!(@full, true, $data);
+    };
     () => {
         $crate::profile_function_if!(true, "");
     };
@@ -251,18 +469,28 @@ macro_rules! profile_function {
 /// ```
 ///
 /// If [`crate::are_scopes_on`] is `false`, the condition is not evaluated.
+///
+/// Like [`profile_function`], this takes `@full` first to name the scope after the full module path:
+/// `puffin::profile_function_if!(@full, num_jobs > 1000)`.
 #[macro_export]
 macro_rules! profile_function_if {
-    ($condition:expr) => {
-        $crate::profile_function_if!($condition, "");
+    (@full, $condition:expr) => {
+        $crate::profile_function_if!(@full, $condition, "");
     };
-    ($condition:expr, $data:expr) => {
+    (@full, $condition:expr, $data:expr) => {
+        $crate::profile_function_if!(
+            @function_name $crate::full_function_name($crate::current_function_name!()),
+            $condition,
+            $data
+        );
+    };
+    (@function_name $function_name:expr, $condition:expr, $data:expr) => {
         let _profiler_scope = if $crate::are_scopes_on() && ($condition) {
             static SCOPE_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
             let scope_id = SCOPE_ID.get_or_init(|| {
                 $crate::ThreadProfiler::call(|tp| {
                     let id = tp.register_function_scope(
-                        $crate::clean_function_name($crate::current_function_name!()),
+                        $function_name,
                         $crate::short_file_name(file!()),
                         line!(),
                     );
@@ -275,6 +503,16 @@ macro_rules! profile_function_if {
             None
         };
     };
+    ($condition:expr) => {
+        $crate::profile_function_if!($condition, "");
+    };
+    ($condition:expr, $data:expr) => {
+        $crate::profile_function_if!(
+            @function_name $crate::clean_function_name($crate::current_function_name!()),
+            $condition,
+            $data
+        );
+    };
 }
 
 /// Profile the current scope with the given name (unique in the parent scope).
@@ -391,15 +629,404 @@ macro_rules! profile_scope_if {
     };
 }
 
+/// Like [`profile_scope`], but the scope is only recorded if it lasted at least
+/// `min_duration_ns` nanoseconds.
+///
+/// Useful for scopes that are entered thousands of times per frame and are usually too fast to matter,
+/// which would otherwise flood the flamegraph.
+///
+/// Example: `profile_scope_min!("load_mesh", 100_000);` only records loads that took 0.1 ms or longer.
+///
+/// An optional third argument can be a string (e.g. a mesh name) to help diagnose what was slow.
+///
+/// When a scope is too short, everything recorded within it (child scopes, counters and task events)
+/// is discarded with it. The scope still costs about as much as a [`profile_scope`] while it runs.
+#[macro_export]
+macro_rules! profile_scope_min {
+    ($name:expr, $min_duration_ns:expr) => {
+        $crate::profile_scope_min!($name, $min_duration_ns, "");
+    };
+    ($name:expr, $min_duration_ns:expr, $data:expr) => {
+        let _profiler_scope = if $crate::are_scopes_on() {
+            static SCOPE_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
+            let scope_id = SCOPE_ID.get_or_init(|| {
+                $crate::ThreadProfiler::call(|tp| {
+                    let id = tp.register_named_scope(
+                        $name,
+                        $crate::clean_function_name($crate::current_function_name!()),
+                        $crate::short_file_name(file!()),
+                        line!(),
+                    );
+                    id
+                })
+            });
+            Some($crate::MinDurationScope::new(
+                *scope_id,
+                $min_duration_ns,
+                $data,
+            ))
+        } else {
+            None
+        };
+    };
+}
+
+/// Like [`profile_scope`], but also records key-value tags with the scope.
+///
+/// The tags are shown in the tooltip of the scope in `puffin_egui`.
+/// The values can be anything that implements [`std::fmt::Display`].
+///
+/// Example: `profile_scope_tags!("load_mesh", "mesh" => name, "lod" => lod);`
+///
+/// The tags are only formatted when scopes are on, but they cost a string allocation each,
+/// so prefer [`profile_scope`] for scopes that are entered very often.
+#[macro_export]
+macro_rules! profile_scope_tags {
+    ($name:expr, $($key:expr => $value:expr),+ $(,)?) => {
+        let _profiler_scope = if $crate::are_scopes_on() {
+            static SCOPE_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
+            let scope_id = SCOPE_ID.get_or_init(|| {
+                $crate::ThreadProfiler::call(|tp| {
+                    let id = tp.register_named_scope(
+                        $name,
+                        $crate::clean_function_name($crate::current_function_name!()),
+                        $crate::short_file_name(file!()),
+                        line!(),
+                    );
+                    id
+                })
+            });
+            Some($crate::ProfilerScope::with_tags(
+                *scope_id,
+                "",
+                &[$(($key, ::std::string::ToString::to_string(&$value).as_str())),+],
+            ))
+        } else {
+            None
+        };
+    };
+}
+
+/// Like [`profile_function`], but also puts the scope in a category, e.g. "Physics".
+///
+/// Categories can be used to group and filter scopes in the UI, and `puffin_egui` colors scopes by them.
+///
+/// Example: `profile_function_cat!("Physics");`
+///
+/// An optional second argument can be a string (e.g. a mesh name) to help diagnose what was slow.
+#[macro_export]
+macro_rules! profile_function_cat {
+    ($category:expr) => {
+        $crate::profile_function_cat!($category, "");
+    };
+    ($category:expr, $data:expr) => {
+        let _profiler_scope = if $crate::are_scopes_on() {
+            static SCOPE_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
+            let scope_id = SCOPE_ID.get_or_init(|| {
+                $crate::ThreadProfiler::call(|tp| {
+                    let id = tp.register_function_scope_in_category(
+                        $category,
+                        $crate::clean_function_name($crate::current_function_name!()),
+                        $crate::short_file_name(file!()),
+                        line!(),
+                    );
+                    id
+                })
+            });
+            Some($crate::ProfilerScope::new(*scope_id, $data))
+        } else {
+            None
+        };
+    };
+}
+
+/// Like [`profile_scope`], but also puts the scope in a category, e.g. "Physics".
+///
+/// Categories can be used to group and filter scopes in the UI, and `puffin_egui` colors scopes by them.
+///
+/// Example: `profile_scope_cat!("physics.step", "Physics");`
+///
+/// An optional third argument can be a string (e.g. a mesh name) to help diagnose what was slow.
+#[macro_export]
+macro_rules! profile_scope_cat {
+    ($name:expr, $category:expr) => {
+        $crate::profile_scope_cat!($name, $category, "");
+    };
+    ($name:expr, $category:expr, $data:expr) => {
+        let _profiler_scope = if $crate::are_scopes_on() {
+            static SCOPE_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
+            let scope_id = SCOPE_ID.get_or_init(|| {
+                $crate::ThreadProfiler::call(|tp| {
+                    let id = tp.register_named_scope_in_category(
+                        $name,
+                        $category,
+                        $crate::clean_function_name($crate::current_function_name!()),
+                        $crate::short_file_name(file!()),
+                        line!(),
+                    );
+                    id
+                })
+            });
+            Some($crate::ProfilerScope::new(*scope_id, $data))
+        } else {
+            None
+        };
+    };
+}
+
+/// Record a sample of a numeric counter, e.g. memory usage, frame rate or queue length.
+///
+/// The counter is identified by its name, which should be unique.
+/// The value is converted to an `f64`.
+///
+/// Example: `profile_counter!("frame_mem_mb", memory_usage_mb);`
+///
+/// Counters are shown as line graphs over time in `puffin_egui`.
+///
+/// If the puffin profiler is turned off ([`crate::are_scopes_on`] is `false`),
+/// the value is not evaluated.
+#[macro_export]
+macro_rules! profile_counter {
+    ($name:expr, $value:expr) => {
+        if $crate::are_scopes_on() {
+            static COUNTER_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
+            let counter_id = COUNTER_ID.get_or_init(|| {
+                $crate::ThreadProfiler::call(|tp| {
+                    let id = tp.register_named_scope(
+                        $name,
+                        $crate::clean_function_name($crate::current_function_name!()),
+                        $crate::short_file_name(file!()),
+                        line!(),
+                    );
+                    id
+                })
+            });
+            let value = $value as f64;
+            $crate::ThreadProfiler::call(|tp| tp.record_counter(*counter_id, value));
+        }
+    };
+}
+
+/// Profile an async task, i.e. a [`std::future::Future`], which may run on several threads.
+///
+/// The [`crate::profile_scope`] family can't be held across an `.await`,
+/// because the task may continue on another thread of the executor.
+/// Instead, wrap the future itself, and `.await` the returned [`crate::ProfiledTask`]:
+///
+/// ```
+/// # async fn load_level() {}
+/// # async fn run() {
+/// puffin::profile_task!("load_level", load_level()).await;
+/// # }
+/// ```
+///
+/// Every time the task is polled, puffin records on which thread it ran and for how long.
+/// These runs are stitched back together into one [`crate::TaskTimeline`] per task
+/// with [`crate::task_timelines`], and shown above the threads in `puffin_egui`.
+///
+/// Note that the measured times are not quite comparable to those of thread scopes:
+/// a task is only considered busy while it is being polled, so time spent waiting
+/// (e.g. on IO or a timer) between polls is not counted,
+/// while any time its thread is preempted by the OS in the middle of a poll is.
+///
+/// The task name should be unique.
+/// If the puffin profiler is turned off ([`crate::are_scopes_on`] is `false`) when the task is created,
+/// the task is not profiled.
+#[macro_export]
+macro_rules! profile_task {
+    ($name:expr, $future:expr) => {{
+        let task_name_id = if $crate::are_scopes_on() {
+            static TASK_NAME_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
+            Some(*TASK_NAME_ID.get_or_init(|| {
+                $crate::ThreadProfiler::call(|tp| {
+                    tp.register_named_scope(
+                        $name,
+                        $crate::clean_function_name($crate::current_function_name!()),
+                        $crate::short_file_name(file!()),
+                        line!(),
+                    )
+                })
+            }))
+        } else {
+            None
+        };
+        $crate::ProfiledTask::new(task_name_id, $future)
+    }};
+}
+
+/// Mark a point of a flow between scopes, e.g. from where work is submitted on one thread
+/// to where it is executed on another.
+///
+/// All points with the same flow id, which is converted to a `u64`, are connected in time order.
+/// The point belongs to the innermost open scope on this thread.
+///
+/// ```
+/// fn submit(job_id: u64) {
+///     puffin::profile_function!();
+///     puffin::profile_flow!(job_id);
+///     // send the job to a worker thread…
+/// }
+///
+/// fn execute(job_id: u64) {
+///     puffin::profile_function!();
+///     puffin::profile_flow!(job_id);
+///     // do the work…
+/// }
+/// ```
+///
+/// Flows are drawn as lines between the scopes in the flamegraph of `puffin_egui`.
+///
+/// If the puffin profiler is turned off ([`crate::are_scopes_on`] is `false`),
+/// the flow id is not evaluated.
+#[macro_export]
+macro_rules! profile_flow {
+    ($flow_id:expr) => {
+        if $crate::are_scopes_on() {
+            let flow_id = $flow_id as u64;
+            $crate::ThreadProfiler::call(|tp| tp.record_flow(flow_id));
+        }
+    };
+}
+
+/// Mark an instantaneous event, e.g. "level loaded" or "GC triggered".
+///
+/// Markers have a time but no duration.
+/// `puffin_egui` draws them as vertical lines across all threads, with the name shown on hover.
+///
+/// Example: `profile_marker!("level_loaded");`
+#[macro_export]
+macro_rules! profile_marker {
+    ($name:expr) => {
+        if $crate::are_scopes_on() {
+            static MARKER_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
+            let marker_id = MARKER_ID.get_or_init(|| {
+                $crate::ThreadProfiler::call(|tp| {
+                    let id = tp.register_named_scope(
+                        $name,
+                        $crate::clean_function_name($crate::current_function_name!()),
+                        $crate::short_file_name(file!()),
+                        line!(),
+                    );
+                    id
+                })
+            });
+            $crate::ThreadProfiler::call(|tp| tp.record_marker(*marker_id));
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
 
     use crate::{set_scopes_on, GlobalFrameView, GlobalProfiler, ScopeId};
 
+    #[test]
+    fn scope_filter() {
+        set_scopes_on(true);
+        let _scope_ids = crate::reset_scope_ids();
+        // Only rejects scopes of this test, as the filter is global:
+        crate::set_scope_filter(Some(Box::new(|scope_details| {
+            !scope_details.name().starts_with("filtered_")
+        })));
+
+        fn filtered() {
+            profile_scope!("filtered_out");
+        }
+
+        let mut profiler = crate::Profiler::default();
+        crate::ThreadProfiler::initialize_with(&profiler);
+        {
+            profile_scope!("kept");
+            filtered();
+            profile_counter!("filtered_counter", 1.0);
+        }
+        crate::set_scope_filter(None);
+        {
+            // The decision is cached at the call site:
+            profile_scope!("kept");
+            filtered();
+        }
+
+        let frame = profiler.new_frame_and_take().unwrap();
+        crate::ThreadProfiler::initialize(crate::now_ns, crate::internal_profile_reporter);
+        let names: Vec<_> = frame
+            .scope_delta
+            .iter()
+            .map(|scope_details| scope_details.name().to_string())
+            .collect();
+        assert_eq!(names, ["kept", "kept"]);
+        assert_eq!(frame.meta().num_scopes, 2);
+        let unpacked = frame.unpacked().unwrap();
+        let stream_info = unpacked.thread_streams.values().next().unwrap();
+        let counters = crate::Reader::from_start(&stream_info.stream)
+            .read_counters()
+            .unwrap();
+        assert!(counters.is_empty());
+    }
+
+    #[test]
+    fn profile_scope_tags() {
+        set_scopes_on(true);
+        let _scope_ids = crate::reset_scope_ids();
+
+        let mut profiler = crate::Profiler::default();
+        crate::ThreadProfiler::initialize_with(&profiler);
+        {
+            let lod = 2;
+            profile_scope_tags!("tagged", "mesh" => "cube.obj", "lod" => lod);
+        }
+        let frame = profiler.new_frame_and_take().unwrap();
+        crate::ThreadProfiler::initialize(crate::now_ns, crate::internal_profile_reporter);
+
+        let unpacked = frame.unpacked().unwrap();
+        let stream_info = unpacked.thread_streams.values().next().unwrap();
+        let scopes = crate::Reader::from_start(&stream_info.stream)
+            .read_top_scopes()
+            .unwrap();
+        assert_eq!(scopes.len(), 1);
+        assert_eq!(
+            scopes[0].record.tags.iter().collect::<Vec<_>>(),
+            [("mesh", "cube.obj"), ("lod", "2")]
+        );
+    }
+
+    #[test]
+    fn profile_marker() {
+        set_scopes_on(true);
+        let _scope_ids = crate::reset_scope_ids();
+
+        let mut profiler = crate::Profiler::default();
+        crate::ThreadProfiler::initialize_with(&profiler);
+        {
+            profile_scope!("loading");
+            profile_marker!("level_loaded");
+        }
+        let frame = profiler.new_frame_and_take().unwrap();
+        crate::ThreadProfiler::initialize(crate::now_ns, crate::internal_profile_reporter);
+
+        let unpacked = frame.unpacked().unwrap();
+        let markers = unpacked.markers().unwrap();
+        assert_eq!(markers.len(), 1);
+        assert!(frame
+            .scope_delta
+            .iter()
+            .any(|details| details.scope_id == Some(markers[0].id)
+                && details.name() == "level_loaded"));
+
+        let stream_info = unpacked.thread_streams.values().next().unwrap();
+        let scopes = crate::Reader::from_start(&stream_info.stream)
+            .read_top_scopes()
+            .unwrap();
+        assert_eq!(scopes.len(), 1);
+        assert!(scopes[0].record.start_ns <= markers[0].time_ns);
+        assert!(markers[0].time_ns <= scopes[0].record.stop_ns());
+    }
+
     #[test]
     fn profile_macros_test() {
         set_scopes_on(true);
+        let _scope_ids = crate::reset_scope_ids();
 
         let frame_view = GlobalFrameView::default();
 
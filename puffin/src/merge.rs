@@ -1,6 +1,6 @@
 use crate::{
-    NanoSecond, Reader, Result, Scope, ScopeCollection, ScopeId, Stream, ThreadInfo,
-    UnpackedFrameData,
+    FrameIndex, NanoSecond, Reader, Result, Scope, ScopeCollection, ScopeId, Stream, StreamInfo,
+    TaskEvent, ThreadInfo, UnpackedFrameData,
 };
 use std::{collections::BTreeMap, hash::Hash};
 
@@ -40,6 +40,8 @@ pub struct MergeScope<'s> {
     pub duration_per_frame_ns: NanoSecond,
     /// The slowest individual piece.
     pub max_duration_ns: NanoSecond,
+    /// The durations of the individual pieces, for percentiles.
+    pub durations: DurationDistribution,
     /// Number of pieces that got merged together to us.
     pub num_pieces: usize,
     /// The common identifier that we merged using.
@@ -58,12 +60,147 @@ impl<'s> MergeScope<'s> {
             total_duration_ns: self.total_duration_ns,
             duration_per_frame_ns: self.duration_per_frame_ns,
             max_duration_ns: self.max_duration_ns,
+            durations: self.durations,
             num_pieces: self.num_pieces,
             id: self.id,
             data: std::borrow::Cow::Owned(self.data.into_owned()),
             children: self.children.into_iter().map(Self::into_owned).collect(),
         }
     }
+
+    /// Iterate over this scope and all its descendants, parents before children.
+    ///
+    /// Yields each scope together with its depth, where `self` has depth `0`.
+    /// Only allocates a stack with room for the siblings along the current path.
+    pub fn iter_depth_first(&self) -> impl Iterator<Item = (&MergeScope<'s>, usize)> {
+        let mut stack = vec![(self, 0)];
+        std::iter::from_fn(move || {
+            let (scope, depth) = stack.pop()?;
+            stack.extend(scope.children.iter().rev().map(|child| (child, depth + 1)));
+            Some((scope, depth))
+        })
+    }
+
+    /// Record this scope and its descendants in `stream` as if they ran in one frame,
+    /// each lasting its [`Self::duration_per_frame_ns`].
+    ///
+    /// `parent_start_ns` is what [`Self::relative_start_ns`] is relative to. See [`average_frame`].
+    pub fn write_average(&self, stream: &mut Stream, parent_start_ns: NanoSecond) {
+        let start_ns = parent_start_ns + self.relative_start_ns;
+        let (offset, _) = stream.begin_scope(|| start_ns, self.id, &self.data);
+        for child in &self.children {
+            child.write_average(stream, start_ns);
+        }
+        stream.end_scope(offset, start_ns + self.duration_per_frame_ns);
+    }
+}
+
+/// The distribution of the durations of the pieces of a [`MergeScope`].
+///
+/// Every duration is kept for up to [`Self::MAX_EXACT`] pieces.
+/// Beyond that they are binned into a fixed number of buckets to bound memory use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DurationDistribution {
+    /// Every duration, sorted from shortest to longest.
+    Exact(Vec<NanoSecond>),
+
+    /// Durations binned into equally wide buckets spanning `min_ns..=max_ns`.
+    Histogram {
+        /// The shortest duration.
+        min_ns: NanoSecond,
+        /// The longest duration.
+        max_ns: NanoSecond,
+        /// Number of durations in each bucket.
+        counts: Vec<usize>,
+    },
+}
+
+impl DurationDistribution {
+    /// Up to this many durations are stored as-is.
+    pub const MAX_EXACT: usize = 1024;
+
+    /// Number of buckets used when there are more than [`Self::MAX_EXACT`] durations.
+    pub const NUM_BUCKETS: usize = 256;
+
+    /// Build a distribution from unsorted durations.
+    pub fn from_durations(mut durations: Vec<NanoSecond>) -> Self {
+        if durations.len() <= Self::MAX_EXACT {
+            durations.sort_unstable();
+            return Self::Exact(durations);
+        }
+
+        let min_ns = durations.iter().copied().min().unwrap_or_default();
+        let max_ns = durations.iter().copied().max().unwrap_or_default();
+        let bucket_width = Self::bucket_width(min_ns, max_ns);
+        let mut counts = vec![0; Self::NUM_BUCKETS];
+        for duration_ns in durations {
+            let bucket = ((duration_ns - min_ns) / bucket_width) as usize;
+            counts[bucket.min(Self::NUM_BUCKETS - 1)] += 1;
+        }
+        Self::Histogram {
+            min_ns,
+            max_ns,
+            counts,
+        }
+    }
+
+    fn bucket_width(min_ns: NanoSecond, max_ns: NanoSecond) -> NanoSecond {
+        ((max_ns - min_ns) / Self::NUM_BUCKETS as NanoSecond + 1).max(1)
+    }
+
+    /// Number of durations in the distribution.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Exact(durations) => durations.len(),
+            Self::Histogram { counts, .. } => counts.iter().sum(),
+        }
+    }
+
+    /// Returns `true` if there are no durations.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The shortest duration, or `0` if empty.
+    pub fn min_ns(&self) -> NanoSecond {
+        match self {
+            Self::Exact(durations) => durations.first().copied().unwrap_or_default(),
+            Self::Histogram { min_ns, .. } => *min_ns,
+        }
+    }
+
+    /// The duration below which `percent` percent of the durations fall (nearest-rank method).
+    ///
+    /// Exact for up to [`Self::MAX_EXACT`] durations,
+    /// otherwise rounded up to the end of the bucket it falls in.
+    /// Returns `0` if empty.
+    pub fn percentile(&self, percent: f64) -> NanoSecond {
+        let len = self.len();
+        if len == 0 {
+            return 0;
+        }
+        let rank = ((percent.clamp(0.0, 100.0) / 100.0 * len as f64).ceil() as usize).max(1);
+
+        match self {
+            Self::Exact(durations) => durations[rank - 1],
+            Self::Histogram {
+                min_ns,
+                max_ns,
+                counts,
+            } => {
+                let bucket_width = Self::bucket_width(*min_ns, *max_ns);
+                let mut seen = 0;
+                for (bucket, count) in counts.iter().enumerate() {
+                    seen += count;
+                    if seen >= rank {
+                        let bucket_end_ns = min_ns + (bucket as NanoSecond + 1) * bucket_width;
+                        return bucket_end_ns.min(*max_ns);
+                    }
+                }
+                *max_ns
+            }
+        }
+    }
 }
 
 impl<'s> MergeNode<'s> {
@@ -117,6 +254,12 @@ impl<'s> MergeNode<'s> {
             total_duration_ns,
             duration_per_frame_ns: total_duration_ns / num_frames,
             max_duration_ns: slowest_ns,
+            durations: DurationDistribution::from_durations(
+                self.pieces
+                    .iter()
+                    .map(|piece| piece.scope.record.duration_ns)
+                    .collect(),
+            ),
             num_pieces,
             id,
             data: data.into(),
@@ -153,11 +296,25 @@ pub fn merge_scopes_for_thread<'s>(
     scope_collection: &ScopeCollection,
     frames: &'s [std::sync::Arc<UnpackedFrameData>],
     thread_info: &ThreadInfo,
+) -> Result<Vec<MergeScope<'s>>> {
+    merge_scopes_for_threads(scope_collection, frames, std::slice::from_ref(thread_info))
+}
+
+/// Like [`merge_scopes_for_thread`], but treating several [`ThreadInfo`]s as the same thread.
+///
+/// Useful for a thread that changed name, e.g. with [`crate::set_thread_role`].
+pub fn merge_scopes_for_threads<'s>(
+    scope_collection: &ScopeCollection,
+    frames: &'s [std::sync::Arc<UnpackedFrameData>],
+    thread_infos: &[ThreadInfo],
 ) -> Result<Vec<MergeScope<'s>>> {
     let mut top_nodes: BTreeMap<MergeId<'s>, MergeNode<'s>> = Default::default();
 
     for frame in frames {
-        if let Some(stream_info) = frame.thread_streams.get(thread_info) {
+        let stream_infos = thread_infos
+            .iter()
+            .filter_map(|thread_info| frame.thread_streams.get(thread_info));
+        for stream_info in stream_infos {
             let offset_ns = frame.meta.range_ns.0 - frames[0].meta.range_ns.0; // make everything relative to first frame
 
             let top_scopes = Reader::from_start(&stream_info.stream).read_top_scopes()?;
@@ -182,6 +339,125 @@ pub fn merge_scopes_for_thread<'s>(
     Ok(build(scope_collection, top_nodes, frames.len() as _))
 }
 
+/// Synthesize one representative frame from the scopes of many frames,
+/// as merged by [`merge_scopes_for_threads`] for each thread.
+///
+/// Each scope lasts its average duration per frame, see [`MergeScope::write_average`],
+/// so the result can be inspected scope by scope like any other frame.
+pub fn average_frame<'a, 's: 'a>(
+    frame_index: FrameIndex,
+    threads: impl IntoIterator<Item = (ThreadInfo, &'a [MergeScope<'s>])>,
+) -> Result<UnpackedFrameData> {
+    let thread_streams = threads
+        .into_iter()
+        .filter(|(_, merged_scopes)| !merged_scopes.is_empty())
+        .map(|(thread_info, merged_scopes)| {
+            let mut stream = Stream::default();
+            for scope in merged_scopes {
+                // Top scopes are relative to the start of the first merged frame.
+                scope.write_average(&mut stream, 0);
+            }
+            Ok((thread_info, StreamInfo::parse(stream)?))
+        })
+        .collect::<Result<_>>()?;
+    UnpackedFrameData::new(frame_index, thread_streams)
+}
+
+/// One stretch of time during which an async task ran on a thread, see [`TaskTimeline`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskPoll {
+    /// The thread the task ran on.
+    pub thread_info: ThreadInfo,
+    /// When the task started being polled.
+    pub start_ns: NanoSecond,
+    /// How long the poll took.
+    pub duration_ns: NanoSecond,
+}
+
+impl TaskPoll {
+    /// When the task stopped being polled.
+    pub fn stop_ns(&self) -> NanoSecond {
+        self.start_ns + self.duration_ns
+    }
+}
+
+/// All the times an async task ran, stitched together from all the threads it ran on.
+///
+/// See [`crate::profile_task`] and [`task_timelines`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskTimeline {
+    /// Identifies the name of the task, like a scope id.
+    pub id: ScopeId,
+    /// Unique for each profiled task.
+    pub task_id: u64,
+    /// Every time the task was polled, earliest first. Never empty.
+    pub polls: Vec<TaskPoll>,
+}
+
+impl TaskTimeline {
+    /// When the task was first polled.
+    pub fn start_ns(&self) -> NanoSecond {
+        self.polls.first().map_or(0, |poll| poll.start_ns)
+    }
+
+    /// When the task was last polled.
+    pub fn stop_ns(&self) -> NanoSecond {
+        self.polls.last().map_or(0, TaskPoll::stop_ns)
+    }
+
+    /// Total time spent polling the task, not counting the time it spent waiting in between.
+    pub fn busy_ns(&self) -> NanoSecond {
+        self.polls.iter().map(|poll| poll.duration_ns).sum()
+    }
+}
+
+/// Reconstruct the timelines of all async tasks that ran in the given frames, earliest first.
+///
+/// Polls that started before the first frame, or ended after the last, are left out.
+pub fn task_timelines(frames: &[std::sync::Arc<UnpackedFrameData>]) -> Result<Vec<TaskTimeline>> {
+    // When each (thread, task) started its current poll:
+    let mut open_polls: BTreeMap<(&ThreadInfo, u64), NanoSecond> = Default::default();
+    let mut timelines: BTreeMap<u64, TaskTimeline> = Default::default();
+
+    for frame in frames {
+        for (thread_info, stream_info) in &frame.thread_streams {
+            for record in Reader::from_start(&stream_info.stream).read_task_records()? {
+                let key = (thread_info, record.task_id);
+                match record.event {
+                    TaskEvent::Enter => {
+                        open_polls.insert(key, record.time_ns);
+                    }
+                    TaskEvent::Exit => {
+                        let Some(start_ns) = open_polls.remove(&key) else {
+                            continue;
+                        };
+                        timelines
+                            .entry(record.task_id)
+                            .or_insert_with(|| TaskTimeline {
+                                id: record.id,
+                                task_id: record.task_id,
+                                polls: vec![],
+                            })
+                            .polls
+                            .push(TaskPoll {
+                                thread_info: thread_info.clone(),
+                                start_ns,
+                                duration_ns: record.time_ns - start_ns,
+                            });
+                    }
+                }
+            }
+        }
+    }
+
+    let mut timelines: Vec<_> = timelines.into_values().collect();
+    for timeline in &mut timelines {
+        timeline.polls.sort_by_key(|poll| poll.start_ns);
+    }
+    timelines.sort_by_key(TaskTimeline::start_ns);
+    Ok(timelines)
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::BTreeMap, sync::Arc};
@@ -236,10 +512,7 @@ mod tests {
 
         let stream_info = StreamInfo::parse(stream).unwrap();
         let mut thread_streams = BTreeMap::new();
-        let thread_info = ThreadInfo {
-            start_time_ns: Some(0),
-            name: "main".to_owned(),
-        };
+        let thread_info = ThreadInfo::new(Some(0), "main");
         thread_streams.insert(thread_info.clone(), stream_info);
         let frame = UnpackedFrameData::new(0, thread_streams).unwrap();
         let frames = [Arc::new(frame)];
@@ -251,6 +524,7 @@ mod tests {
                 total_duration_ns: 2 * 100,
                 duration_per_frame_ns: 2 * 100,
                 max_duration_ns: 100,
+                durations: DurationDistribution::Exact(vec![100, 100]),
                 num_pieces: 2,
                 id: ScopeId::new(1),
                 data: "".into(),
@@ -261,6 +535,7 @@ mod tests {
                 total_duration_ns: 2 * 700,
                 duration_per_frame_ns: 2 * 700,
                 max_duration_ns: 700,
+                durations: DurationDistribution::Exact(vec![700, 700]),
                 num_pieces: 2,
                 id: ScopeId::new(2),
                 data: "".into(),
@@ -270,6 +545,7 @@ mod tests {
                         total_duration_ns: 2 * 200,
                         duration_per_frame_ns: 2 * 200,
                         max_duration_ns: 200,
+                        durations: DurationDistribution::Exact(vec![200, 200]),
                         num_pieces: 2,
                         id: ScopeId::new(3),
                         data: "".into(),
@@ -280,6 +556,7 @@ mod tests {
                         total_duration_ns: 2 * 200,
                         duration_per_frame_ns: 2 * 200,
                         max_duration_ns: 200,
+                        durations: DurationDistribution::Exact(vec![200, 200]),
                         num_pieces: 2,
                         id: ScopeId::new(4),
                         data: "".into(),
@@ -288,6 +565,7 @@ mod tests {
                             total_duration_ns: 2 * 100,
                             duration_per_frame_ns: 2 * 100,
                             max_duration_ns: 100,
+                            durations: DurationDistribution::Exact(vec![100, 100]),
                             num_pieces: 2,
                             id: ScopeId::new(5),
                             data: "".into(),
@@ -302,5 +580,178 @@ mod tests {
             merged, expected,
             "\nGot:\n{merged:#?}\n\n!=\nExpected:\n{expected:#?}",
         );
+
+        let flattened: Vec<_> = merged[1]
+            .iter_depth_first()
+            .map(|(scope, depth)| (scope.id, depth))
+            .collect();
+        assert_eq!(
+            flattened,
+            [
+                (ScopeId::new(2), 0),
+                (ScopeId::new(3), 1),
+                (ScopeId::new(4), 1),
+                (ScopeId::new(5), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_average_frame() {
+        use crate::*;
+
+        let thread_info = ThreadInfo::new(Some(0), "main");
+        let frames: Vec<_> = [(0, 100), (1, 300)]
+            .into_iter()
+            .map(|(frame_index, child_duration_ns)| {
+                let start_ns = 1000 * frame_index as NanoSecond;
+                let mut stream = Stream::default();
+                let (top, _) = stream.begin_scope(|| start_ns, ScopeId::new(1), "");
+                let (child, _) = stream.begin_scope(|| start_ns + 100, ScopeId::new(2), "data");
+                stream.end_scope(child, start_ns + 100 + child_duration_ns);
+                stream.end_scope(top, start_ns + 500);
+                let thread_streams =
+                    BTreeMap::from([(thread_info.clone(), StreamInfo::parse(stream).unwrap())]);
+                Arc::new(UnpackedFrameData::new(frame_index, thread_streams).unwrap())
+            })
+            .collect();
+
+        let merged =
+            merge_scopes_for_thread(&ScopeCollection::default(), &frames, &thread_info).unwrap();
+        let average = average_frame(1, [(thread_info.clone(), merged.as_slice())]).unwrap();
+        assert_eq!(average.frame_index(), 1);
+        assert_eq!(average.meta.num_scopes, 2);
+        assert_eq!(average.range_ns(), (0, 500));
+
+        let stream = &average.thread_streams[&thread_info].stream;
+        let top_scopes = Reader::from_start(stream).read_top_scopes().unwrap();
+        assert_eq!(top_scopes.len(), 1);
+        assert_eq!(top_scopes[0].record.duration_ns, 500);
+        let children = Reader::with_offset(stream, top_scopes[0].child_begin_position)
+            .unwrap()
+            .read_top_scopes()
+            .unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].id, ScopeId::new(2));
+        assert_eq!(children[0].record.start_ns, 100);
+        assert_eq!(children[0].record.duration_ns, 200);
+        assert_eq!(children[0].record.data, "data");
+    }
+
+    #[test]
+    fn test_duration_percentiles() {
+        use crate::DurationDistribution;
+
+        let exact = DurationDistribution::from_durations((1..=100).rev().collect());
+        assert_eq!(exact.len(), 100);
+        assert_eq!(exact.min_ns(), 1);
+        assert_eq!(exact.percentile(50.0), 50);
+        assert_eq!(exact.percentile(95.0), 95);
+        assert_eq!(exact.percentile(99.0), 99);
+        assert_eq!(exact.percentile(100.0), 100);
+
+        let histogram = DurationDistribution::from_durations((1..=10_000).collect());
+        assert!(matches!(histogram, DurationDistribution::Histogram { .. }));
+        assert_eq!(histogram.len(), 10_000);
+        assert_eq!(histogram.min_ns(), 1);
+        for (percent, expected) in [(50.0, 5000), (95.0, 9500), (99.0, 9900)] {
+            let error = histogram.percentile(percent) - expected;
+            assert!((0..=40).contains(&error), "p{percent}: off by {error}");
+        }
+        assert_eq!(histogram.percentile(100.0), 10_000);
+    }
+
+    #[test]
+    fn test_task_timelines() {
+        use crate::*;
+
+        let record = |task_id, time_ns, event| TaskRecord {
+            id: ScopeId::new(1),
+            task_id,
+            time_ns,
+            event,
+        };
+        let thread = |name: &str| ThreadInfo::new(None, name);
+        let frame = |frame_index, streams: Vec<(&str, Vec<TaskRecord>)>| {
+            let thread_streams = streams
+                .into_iter()
+                .map(|(name, records)| {
+                    let mut stream = Stream::default();
+                    for record in records {
+                        stream.record_task_event(record);
+                    }
+                    (thread(name), StreamInfo::parse(stream).unwrap())
+                })
+                .collect();
+            Arc::new(UnpackedFrameData::new(frame_index, thread_streams).unwrap())
+        };
+
+        let frames = [
+            frame(
+                0,
+                vec![
+                    // Task 1 is polled on "a", moves to "b", and back to "a" in the next frame.
+                    (
+                        "a",
+                        vec![
+                            record(1, 0, TaskEvent::Enter),
+                            record(1, 10, TaskEvent::Exit),
+                        ],
+                    ),
+                    (
+                        "b",
+                        vec![
+                            record(1, 30, TaskEvent::Enter),
+                            record(1, 40, TaskEvent::Exit),
+                        ],
+                    ),
+                ],
+            ),
+            frame(
+                1,
+                vec![
+                    (
+                        "a",
+                        vec![
+                            record(1, 100, TaskEvent::Enter),
+                            record(1, 105, TaskEvent::Exit),
+                            // Task 2 is still running at the end of the last frame:
+                            record(2, 110, TaskEvent::Enter),
+                        ],
+                    ),
+                    // Task 3 started running before the first frame:
+                    ("b", vec![record(3, 120, TaskEvent::Exit)]),
+                ],
+            ),
+        ];
+
+        let timelines = task_timelines(&frames).unwrap();
+        assert_eq!(timelines.len(), 1);
+        let timeline = &timelines[0];
+        assert_eq!(timeline.task_id, 1);
+        assert_eq!(
+            timeline.polls,
+            [
+                TaskPoll {
+                    thread_info: thread("a"),
+                    start_ns: 0,
+                    duration_ns: 10,
+                },
+                TaskPoll {
+                    thread_info: thread("b"),
+                    start_ns: 30,
+                    duration_ns: 10,
+                },
+                TaskPoll {
+                    thread_info: thread("a"),
+                    start_ns: 100,
+                    duration_ns: 5,
+                },
+            ]
+        );
+        assert_eq!(
+            (timeline.start_ns(), timeline.stop_ns(), timeline.busy_ns()),
+            (0, 105, 25)
+        );
     }
 }
@@ -0,0 +1,224 @@
+//! Export of frames as a [Perfetto](https://perfetto.dev) protobuf trace,
+//! which can be opened in <https://ui.perfetto.dev>.
+//!
+//! Only the few messages and fields of
+//! [`perfetto_trace.proto`](https://github.com/google/perfetto/blob/master/protos/perfetto/trace/perfetto_trace.proto)
+//! that we need are declared here, with the same field numbers.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{FrameData, NanoSecond, Reader, ScopeCollection, Stream, ThreadInfo};
+
+/// The process id we put all threads in. The real one is not recorded.
+const PID: i32 = 1;
+
+/// A `Trace` is a sequence of `packet` fields with this tag.
+const TRACE_PACKET_TAG: u32 = 1;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct TracePacket {
+    #[prost(uint64, optional, tag = "8")]
+    pub timestamp: Option<u64>,
+    #[prost(uint32, optional, tag = "10")]
+    pub trusted_packet_sequence_id: Option<u32>,
+    #[prost(message, optional, tag = "11")]
+    pub track_event: Option<TrackEvent>,
+    #[prost(message, optional, tag = "60")]
+    pub track_descriptor: Option<TrackDescriptor>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct TrackDescriptor {
+    #[prost(uint64, optional, tag = "1")]
+    pub uuid: Option<u64>,
+    #[prost(message, optional, tag = "4")]
+    pub thread: Option<ThreadDescriptor>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct ThreadDescriptor {
+    #[prost(int32, optional, tag = "1")]
+    pub pid: Option<i32>,
+    #[prost(int32, optional, tag = "2")]
+    pub tid: Option<i32>,
+    #[prost(string, optional, tag = "5")]
+    pub thread_name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct TrackEvent {
+    #[prost(message, repeated, tag = "4")]
+    pub debug_annotations: Vec<DebugAnnotation>,
+    #[prost(enumeration = "TrackEventType", optional, tag = "9")]
+    pub r#type: Option<i32>,
+    #[prost(uint64, optional, tag = "11")]
+    pub track_uuid: Option<u64>,
+    #[prost(string, repeated, tag = "22")]
+    pub categories: Vec<String>,
+    #[prost(string, optional, tag = "23")]
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub(crate) enum TrackEventType {
+    Unspecified = 0,
+    SliceBegin = 1,
+    SliceEnd = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct DebugAnnotation {
+    #[prost(string, optional, tag = "6")]
+    pub string_value: Option<String>,
+    #[prost(string, optional, tag = "10")]
+    pub name: Option<String>,
+}
+
+/// Write the scopes of `frames` as a Perfetto trace,
+/// with one track per thread and a slice per scope.
+pub(crate) fn write_trace<'a>(
+    write: &mut impl std::io::Write,
+    scope_collection: &ScopeCollection,
+    frames: impl Iterator<Item = &'a Arc<FrameData>>,
+) -> anyhow::Result<()> {
+    let mut track_uuids = BTreeMap::<ThreadInfo, u64>::new();
+    let mut buffer = vec![];
+
+    for frame in frames {
+        let Ok(unpacked) = frame.unpacked() else {
+            anyhow::bail!("Failed to unpack frame {}", frame.frame_index());
+        };
+        for (thread_info, stream_info) in &unpacked.thread_streams {
+            let track_uuid = if let Some(&track_uuid) = track_uuids.get(thread_info) {
+                track_uuid
+            } else {
+                let track_uuid = track_uuids.len() as u64 + 1;
+                track_uuids.insert(thread_info.clone(), track_uuid);
+                write_packet(
+                    write,
+                    &mut buffer,
+                    &TracePacket {
+                        trusted_packet_sequence_id: Some(track_uuid as u32),
+                        track_descriptor: Some(TrackDescriptor {
+                            uuid: Some(track_uuid),
+                            thread: Some(ThreadDescriptor {
+                                pid: Some(PID),
+                                tid: Some(track_uuid as i32),
+                                thread_name: Some(thread_info.name.clone()),
+                            }),
+                        }),
+                        ..Default::default()
+                    },
+                )?;
+                track_uuid
+            };
+
+            write_slices(
+                write,
+                &mut buffer,
+                scope_collection,
+                &stream_info.stream,
+                0,
+                track_uuid,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the scopes starting at `offset` (and all their children) as slices on the given track.
+fn write_slices(
+    write: &mut impl std::io::Write,
+    buffer: &mut Vec<u8>,
+    scope_collection: &ScopeCollection,
+    stream: &Stream,
+    offset: u64,
+    track_uuid: u64,
+) -> anyhow::Result<()> {
+    let reader = Reader::with_offset(stream, offset).map_err(|err| anyhow::anyhow!("{err:?}"))?;
+    for scope in reader {
+        let scope = scope.map_err(|err| anyhow::anyhow!("{err:?}"))?;
+
+        let details = scope_collection.fetch_by_id(&scope.id);
+        let name = match details {
+            Some(details) => details.name().to_string(),
+            None => scope.id.0.to_string(),
+        };
+        let categories = details
+            .map(|details| details.category.to_string())
+            .filter(|category| !category.is_empty())
+            .into_iter()
+            .collect();
+        let debug_annotations = if scope.record.data.is_empty() {
+            vec![]
+        } else {
+            vec![DebugAnnotation {
+                name: Some("data".to_owned()),
+                string_value: Some(scope.record.data.to_owned()),
+            }]
+        };
+
+        write_packet(
+            write,
+            buffer,
+            &slice_packet(
+                track_uuid,
+                scope.record.start_ns,
+                TrackEvent {
+                    debug_annotations,
+                    r#type: Some(TrackEventType::SliceBegin as i32),
+                    track_uuid: Some(track_uuid),
+                    categories,
+                    name: Some(name),
+                },
+            ),
+        )?;
+
+        write_slices(
+            write,
+            buffer,
+            scope_collection,
+            stream,
+            scope.child_begin_position,
+            track_uuid,
+        )?;
+
+        write_packet(
+            write,
+            buffer,
+            &slice_packet(
+                track_uuid,
+                scope.record.stop_ns(),
+                TrackEvent {
+                    r#type: Some(TrackEventType::SliceEnd as i32),
+                    track_uuid: Some(track_uuid),
+                    ..Default::default()
+                },
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+fn slice_packet(track_uuid: u64, time_ns: NanoSecond, track_event: TrackEvent) -> TracePacket {
+    TracePacket {
+        timestamp: Some(time_ns.max(0) as u64),
+        trusted_packet_sequence_id: Some(track_uuid as u32),
+        track_event: Some(track_event),
+        ..Default::default()
+    }
+}
+
+/// Write one `packet` field of a `Trace`, so the whole output is a valid `Trace` message.
+fn write_packet(
+    write: &mut impl std::io::Write,
+    buffer: &mut Vec<u8>,
+    packet: &TracePacket,
+) -> anyhow::Result<()> {
+    buffer.clear();
+    prost::encoding::message::encode(TRACE_PACKET_TAG, packet, buffer);
+    write.write_all(buffer)?;
+    Ok(())
+}
@@ -1,11 +1,56 @@
 use itertools::Itertools;
 use std::{
     cmp::Ordering,
-    collections::{BTreeSet, VecDeque},
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
     sync::Arc,
 };
 
-use crate::{FrameData, FrameSinkId, ScopeCollection};
+use crate::{
+    CompressionConfig, FrameData, FrameSinkId, NanoSecond, Reader, ScopeCollection, ScopeDetails,
+    ScopeId, Stream, StreamInfo, ThreadInfo, UnpackedFrameData,
+};
+
+/// Frames that are at most this much older than the latest frame are assumed to
+/// just have arrived out of order, and are dropped.
+/// Older frames are assumed to come from a restarted server, and reset the view.
+const MAX_REORDERED_FRAMES: u64 = 8;
+
+/// The first bytes of a gzip stream, e.g. a gzip-compressed `.puffin` file.
+#[cfg(feature = "serialization")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// When a [`FrameView`] packs (compresses) its frames.
+///
+/// Packing frames will increase CPU time and decrease memory usage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PackPolicy {
+    /// Pack each frame as soon as a newer one arrives.
+    ///
+    /// This assumes there is a viewer looking at the newest frame.
+    #[default]
+    Always,
+
+    /// Never pack frames.
+    Never,
+
+    /// Only pack frames that are kept as one of the slowest frames after leaving the recent history.
+    ///
+    /// Useful if you serialize the frames right away, so there is no point in packing them before that.
+    OnEviction,
+}
+
+/// Set with [`FrameView::set_on_slow_frame`].
+pub type OnSlowFrame = Box<dyn FnMut(&Arc<FrameData>) + Send>;
+
+/// Holds the [`OnSlowFrame`] callback, which is not carried over to clones of the [`FrameView`].
+#[derive(Default)]
+struct SlowFrameCallback(Option<OnSlowFrame>);
+
+impl Clone for SlowFrameCallback {
+    fn clone(&self) -> Self {
+        Self(None)
+    }
+}
 
 /// A view of recent and slowest frames, used by GUIs.
 #[derive(Clone)]
@@ -13,6 +58,7 @@ pub struct FrameView {
     /// newest first
     recent: VecDeque<OrderedByIndex>,
     max_recent: usize,
+    max_recent_bytes: Option<usize>,
 
     slowest_by_index: BTreeSet<OrderedByIndex>,
     slowest_by_duration: BTreeSet<OrderedByDuration>,
@@ -21,12 +67,25 @@ pub struct FrameView {
     /// Minimizes memory usage at the expense of CPU time.
     ///
     /// Only recommended if you set a large max_recent size.
-    pack_frames: bool,
+    pack_policy: PackPolicy,
+
+    /// How frames are compressed when packed.
+    compression_config: CompressionConfig,
 
     /// Maintain stats as we add/remove frames
     stats: FrameStats,
 
     scope_collection: ScopeCollection,
+
+    /// How many times we have cleared everything because of a frame from the past.
+    num_resets: usize,
+
+    /// How many frames we have dropped because they arrived out of order.
+    num_dropped_out_of_order: usize,
+
+    /// Frames longer than this are passed to `on_slow_frame`.
+    slow_frame_threshold: NanoSecond,
+    on_slow_frame: SlowFrameCallback,
 }
 
 impl Default for FrameView {
@@ -37,12 +96,18 @@ impl Default for FrameView {
         Self {
             recent: VecDeque::with_capacity(max_recent),
             max_recent,
+            max_recent_bytes: None,
             slowest_by_index: BTreeSet::new(),
             slowest_by_duration: BTreeSet::new(),
             max_slow,
-            pack_frames: true,
+            pack_policy: PackPolicy::Always,
+            compression_config: Default::default(),
             stats: Default::default(),
             scope_collection: Default::default(),
+            num_resets: 0,
+            num_dropped_out_of_order: 0,
+            slow_frame_threshold: NanoSecond::MAX,
+            on_slow_frame: Default::default(),
         }
     }
 }
@@ -67,10 +132,23 @@ impl FrameView {
         }
 
         if let Some(last) = self.recent.iter().last() {
-            if new_frame.frame_index() <= last.0.frame_index() {
+            let last_index = last.0.frame_index();
+            let new_index = new_frame.frame_index();
+            if new_index <= last_index && last_index - new_index < MAX_REORDERED_FRAMES {
+                eprintln!(
+                    "puffin WARNING: Dropping frame {new_index}, which arrived after frame {last_index}"
+                );
+                self.num_dropped_out_of_order += 1;
+                return;
+            } else if new_index < last_index {
                 // A frame from the past!?
                 // Likely we are `puffin_viewer`, and the server restarted.
                 // The safe choice is to clear everything:
+                eprintln!(
+                    "puffin WARNING: Got frame {new_index} after frame {last_index}. \
+                    Assuming the profiled application restarted, and clearing all frames."
+                );
+                self.num_resets += 1;
                 self.stats.clear();
                 self.recent.clear();
                 self.slowest_by_index.clear();
@@ -81,8 +159,8 @@ impl FrameView {
         if let Some(last) = self.recent.iter().last() {
             // Assume there is a viewer viewing the newest frame,
             // and compress the previously newest frame to save RAM:
-            if self.pack_frames {
-                last.0.pack();
+            if self.pack_policy == PackPolicy::Always {
+                last.0.pack_with_config(&self.compression_config);
             }
 
             self.stats.add(&last.0);
@@ -101,6 +179,82 @@ impl FrameView {
         }
 
         self.add_recent_frame(&new_frame);
+
+        if new_frame.duration_ns() > self.slow_frame_threshold {
+            if let Some(on_slow_frame) = &mut self.on_slow_frame.0 {
+                on_slow_frame(&new_frame);
+            }
+        }
+    }
+
+    /// Adds all frames of another view after the frames of this one,
+    /// e.g. to compare two runs of the same benchmark side by side.
+    ///
+    /// The frames of `other` are re-indexed to come after the frames of `self`.
+    /// Scopes are matched by their [`ScopeDetails`], so the same scope gets the same id in both.
+    /// Scopes only found in `other` keep their id, unless it is already taken by another scope.
+    ///
+    /// Fails if any of the frames of `other` can't be unpacked, in which case `self` is left unchanged.
+    pub fn append(&mut self, other: FrameView) -> anyhow::Result<()> {
+        let (id_map, new_scopes) =
+            scope_id_translation(&self.scope_collection, &other.scope_collection);
+
+        let next_index = self
+            .all_uniq()
+            .last()
+            .map_or(0, |frame| frame.frame_index() + 1);
+        let first_index = other
+            .all_uniq()
+            .next()
+            .map_or(0, |frame| frame.frame_index());
+        let index_offset = next_index.saturating_sub(first_index);
+
+        let mut frames = vec![];
+        for frame in other.all_uniq() {
+            let Ok(unpacked) = frame.unpacked() else {
+                anyhow::bail!("Failed to unpack frame {}", frame.frame_index());
+            };
+            let mut thread_streams = BTreeMap::new();
+            for (thread_info, stream_info) in &unpacked.thread_streams {
+                let mut stream_info = StreamInfo::clone(stream_info);
+                stream_info
+                    .stream
+                    .remap_ids(|id| id_map.get(&id).copied().unwrap_or(id))
+                    .map_err(|err| {
+                        anyhow::format_err!(
+                            "Failed to parse frame {}: {err:?}",
+                            frame.frame_index()
+                        )
+                    })?;
+                thread_streams.insert(thread_info.clone(), Arc::new(stream_info));
+            }
+            let unpacked = UnpackedFrameData {
+                meta: crate::FrameMeta {
+                    frame_index: frame.frame_index() + index_offset,
+                    ..unpacked.meta
+                },
+                thread_streams,
+            };
+            // Let the first frame carry the new scopes, so they are written to `.puffin` files:
+            let scope_delta = if frames.is_empty() {
+                new_scopes.clone()
+            } else {
+                vec![]
+            };
+            frames.push(FrameData::from_unpacked(
+                Arc::new(unpacked),
+                scope_delta,
+                false,
+            ));
+        }
+
+        for scope in new_scopes {
+            self.scope_collection.insert(scope);
+        }
+        for frame in frames {
+            self.add_frame(Arc::new(frame));
+        }
+        Ok(())
     }
 
     fn add_slow_frame(&mut self, new_frame: &Arc<FrameData>) {
@@ -127,16 +281,42 @@ impl FrameView {
     fn add_recent_frame(&mut self, new_frame: &Arc<FrameData>) {
         self.recent.push_back(OrderedByIndex(new_frame.clone()));
 
-        while self.recent.len() > self.max_recent {
+        while self.recent.len() > self.max_recent || self.exceeds_max_recent_bytes() {
             if let Some(removed_frame) = self.recent.pop_front() {
                 // Only remove from stats if the frame is not present in slowest
                 if !self.slowest_by_index.contains(&removed_frame) {
                     self.stats.remove(&removed_frame.0);
+                } else if self.pack_policy == PackPolicy::OnEviction {
+                    // We keep it around, so now is the time to pack it:
+                    self.stats.remove(&removed_frame.0);
+                    removed_frame.0.pack_with_config(&self.compression_config);
+                    self.stats.add(&removed_frame.0);
                 }
             }
         }
     }
 
+    /// Is [`Self::max_recent_bytes`] exceeded, and is there a recent frame we could evict?
+    ///
+    /// The newest frame is always kept.
+    fn exceeds_max_recent_bytes(&self) -> bool {
+        self.recent.len() > 1
+            && self
+                .max_recent_bytes
+                .map_or(false, |max| max < self.stats.bytes_of_ram_used())
+    }
+
+    /// How many times all frames were cleared because a much older frame arrived,
+    /// e.g. because the profiled application restarted.
+    pub fn num_resets(&self) -> usize {
+        self.num_resets
+    }
+
+    /// How many frames were dropped because they arrived slightly out of order.
+    pub fn num_dropped_out_of_order(&self) -> usize {
+        self.num_dropped_out_of_order
+    }
+
     /// The latest fully captured frame of data.
     pub fn latest_frame(&self) -> Option<Arc<FrameData>> {
         self.recent.back().map(|f| f.0.clone())
@@ -169,6 +349,34 @@ impl FrameView {
             .map(|f| &f.0)
     }
 
+    /// All frames overlapping the time range `start_ns..=end_ns`, sorted chronologically.
+    ///
+    /// The times are in the same clock as [`FrameData::range_ns`].
+    pub fn frames_in_range(
+        &self,
+        start_ns: NanoSecond,
+        end_ns: NanoSecond,
+    ) -> impl Iterator<Item = &Arc<FrameData>> {
+        self.all_uniq().filter(move |frame| {
+            let (frame_start_ns, frame_end_ns) = frame.range_ns();
+            frame_start_ns <= end_ns && start_ns <= frame_end_ns
+        })
+    }
+
+    /// All frames overlapping the last `duration` before the end of the newest frame,
+    /// sorted chronologically.
+    pub fn frames_in_last(
+        &self,
+        duration: std::time::Duration,
+    ) -> impl Iterator<Item = &Arc<FrameData>> {
+        let end_ns = self
+            .all_uniq()
+            .last()
+            .map_or(NanoSecond::MIN, |frame| frame.range_ns().1);
+        let duration_ns = NanoSecond::try_from(duration.as_nanos()).unwrap_or(NanoSecond::MAX);
+        self.frames_in_range(end_ns.saturating_sub(duration_ns), end_ns)
+    }
+
     /// Clean history of the slowest frames.
     pub fn clear_slowest(&mut self) {
         for frame in self.slowest_by_index.iter() {
@@ -179,6 +387,37 @@ impl FrameView {
         self.slowest_by_index.clear();
     }
 
+    /// Clean history of the recent frames, but keep the slowest ones.
+    pub fn clear_recent(&mut self) {
+        let newest = self.recent.pop_back();
+
+        for frame in self.recent.drain(..) {
+            // Only remove from stats if the frame is not present in slowest
+            if !self.slowest_by_index.contains(&frame) {
+                self.stats.remove(&frame.0);
+            }
+        }
+
+        // The newest frame is only added to the stats once the next frame arrives,
+        // which will no longer happen:
+        if let Some(newest) = newest {
+            if self.slowest_by_index.contains(&newest) {
+                self.stats.add(&newest.0);
+            }
+        }
+    }
+
+    /// Forget all recent and slowest frames and their stats, e.g. to start a fresh measurement.
+    ///
+    /// The settings and the [`Self::scope_collection`] are kept,
+    /// since new frames usually only contain the scopes that are new to the [`crate::GlobalProfiler`].
+    pub fn clear(&mut self) {
+        self.recent.clear();
+        self.slowest_by_index.clear();
+        self.slowest_by_duration.clear();
+        self.stats.clear();
+    }
+
     /// How many frames of recent history to store.
     pub fn max_recent(&self) -> usize {
         self.max_recent
@@ -189,6 +428,24 @@ impl FrameView {
         self.max_recent = max_recent;
     }
 
+    /// How many bytes of RAM the stored frames may use, see [`Self::set_max_recent_bytes`].
+    pub fn max_recent_bytes(&self) -> Option<usize> {
+        self.max_recent_bytes
+    }
+
+    /// Limit the RAM used by the stored frames, as measured by [`FrameStats::bytes_of_ram_used`].
+    ///
+    /// When a frame is added, the oldest recent frames are evicted until we are within the limit.
+    /// This is enforced in addition to [`Self::set_max_recent`], so whichever limit is hit first applies.
+    /// The slowest frames count towards the limit but are never evicted by it,
+    /// and neither is the newest frame.
+    ///
+    /// Packed frames use a lot less RAM, so see also [`Self::set_pack_policy`].
+    /// `None` (the default) means no limit.
+    pub fn set_max_recent_bytes(&mut self, max_recent_bytes: Option<usize>) {
+        self.max_recent_bytes = max_recent_bytes;
+    }
+
     /// How many slow "spike" frames to store.
     pub fn max_slow(&self) -> usize {
         self.max_slow
@@ -199,15 +456,65 @@ impl FrameView {
         self.max_slow = max_slow;
     }
 
-    /// Returns if frames are packed (compressed).
+    /// Frames longer than this are passed to the callback set with [`Self::set_on_slow_frame`].
+    ///
+    /// Defaults to [`NanoSecond::MAX`], i.e. no frame is considered slow.
+    pub fn slow_frame_threshold(&self) -> NanoSecond {
+        self.slow_frame_threshold
+    }
+
+    /// Frames longer than this are passed to the callback set with [`Self::set_on_slow_frame`].
+    pub fn set_slow_frame_threshold(&mut self, slow_frame_threshold: NanoSecond) {
+        self.slow_frame_threshold = slow_frame_threshold;
+    }
+
+    /// Call `on_slow_frame` for every added frame longer than [`Self::slow_frame_threshold`],
+    /// e.g. to save a spike to disk or log it.
+    ///
+    /// The callback runs inside [`Self::add_frame`], on the thread calling it.
+    /// For a [`crate::GlobalFrameView`] that is the thread calling [`crate::Profiler::new_frame`],
+    /// while both the view and the profiler are locked, so keep the callback short and lock neither from it.
+    ///
+    /// The callback is not carried over when the view is cloned.
+    pub fn set_on_slow_frame(&mut self, on_slow_frame: OnSlowFrame) {
+        self.on_slow_frame = SlowFrameCallback(Some(on_slow_frame));
+    }
+
+    /// Returns if frames are packed (compressed), i.e. the [`PackPolicy`] is not [`PackPolicy::Never`].
     pub fn pack_frames(&self) -> bool {
-        self.pack_frames
+        self.pack_policy != PackPolicy::Never
     }
 
-    /// Sets whether frames should be packed (compressed).
+    /// Sets whether frames should be packed (compressed), using [`PackPolicy::Always`] or [`PackPolicy::Never`].
     /// Packing frames will increase CPU time and decrease memory usage.
     pub fn set_pack_frames(&mut self, pack_frames: bool) {
-        self.pack_frames = pack_frames;
+        self.pack_policy = if pack_frames {
+            PackPolicy::Always
+        } else {
+            PackPolicy::Never
+        };
+    }
+
+    /// When frames are packed (compressed).
+    pub fn pack_policy(&self) -> PackPolicy {
+        self.pack_policy
+    }
+
+    /// Sets when frames should be packed (compressed).
+    pub fn set_pack_policy(&mut self, pack_policy: PackPolicy) {
+        self.pack_policy = pack_policy;
+    }
+
+    /// How frames are compressed when packed, and when written to a `.puffin` file.
+    pub fn compression_config(&self) -> CompressionConfig {
+        self.compression_config
+    }
+
+    /// Sets how frames are compressed when packed, and when written to a `.puffin` file.
+    ///
+    /// Only affects frames packed from now on.
+    pub fn set_compression_config(&mut self, compression_config: CompressionConfig) {
+        self.compression_config = compression_config;
     }
 
     /// Retrieve statistics for added frames. This operation is efficient and suitable when
@@ -223,22 +530,165 @@ impl FrameView {
     }
 
     /// Export profile data as a `.puffin` file/stream.
+    ///
+    /// The frames are followed by a [`crate::FileIndex`].
     #[cfg(feature = "serialization")]
     #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
     pub fn write(&self, write: &mut impl std::io::Write) -> anyhow::Result<()> {
+        self.write_frames(write, self.all_uniq(), false)
+    }
+
+    /// Like [`Self::write`], but gzip-compressed.
+    ///
+    /// [`Self::read`] detects and decompresses these.
+    #[cfg(feature = "gzip")]
+    #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
+    pub fn write_gzipped(&self, write: &mut impl std::io::Write) -> anyhow::Result<()> {
+        let mut encoder = flate2::write::GzEncoder::new(write, flate2::Compression::default());
+        self.write(&mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    /// Export the frames overlapping the time range `[min_ns, max_ns]` as a `.puffin` file/stream.
+    ///
+    /// Frames that only partially overlap the range are included whole.
+    /// All known scope details are written along with the first frame,
+    /// so the result can be opened on its own.
+    #[cfg(feature = "serialization")]
+    #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
+    pub fn write_time_range(
+        &self,
+        write: &mut impl std::io::Write,
+        (min_ns, max_ns): (crate::NanoSecond, crate::NanoSecond),
+    ) -> anyhow::Result<()> {
+        let frames = self.all_uniq().filter(|frame| {
+            let (frame_min_ns, frame_max_ns) = frame.range_ns();
+            frame_min_ns <= max_ns && min_ns <= frame_max_ns
+        });
+        self.write_frames(write, frames, true)
+    }
+
+    #[cfg(feature = "serialization")]
+    #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
+    fn write_frames<'a>(
+        &self,
+        write: &mut impl std::io::Write,
+        frames: impl Iterator<Item = &'a Arc<FrameData>>,
+        mut send_all_scopes: bool,
+    ) -> anyhow::Result<()> {
+        use std::io::Write as _;
+
+        let mut write = crate::file_index::CountingWriter::new(write);
         write.write_all(b"PUF0")?;
 
+        let mut index = crate::FileIndex::default();
+        for frame in frames {
+            index.add_frame(frame, write.num_bytes);
+            frame.create_packed(&self.compression_config);
+            frame.write_into(&self.scope_collection, send_all_scopes, &mut write)?;
+            send_all_scopes = false;
+        }
+        index.write(&mut write)
+    }
+
+    /// Export the frames as a [Perfetto](https://perfetto.dev) protobuf trace,
+    /// which can be opened in <https://ui.perfetto.dev>.
+    ///
+    /// Each thread becomes a track, and each scope a slice on it, with the scope data as its `data` argument.
+    /// The timestamps are the raw nanosecond values of the scopes.
+    #[cfg(feature = "perfetto")]
+    pub fn write_perfetto(&self, write: &mut impl std::io::Write) -> anyhow::Result<()> {
+        crate::perfetto::write_trace(write, &self.scope_collection, self.all_uniq())
+    }
+
+    /// Export the frames in the Chrome tracing JSON format,
+    /// which can be opened in `chrome://tracing`, <https://ui.perfetto.dev> and similar tools.
+    ///
+    /// Each scope becomes a complete (`"X"`) event, with the scope data as its `data` argument.
+    pub fn write_chrome_trace(&self, write: &mut impl std::io::Write) -> anyhow::Result<()> {
+        let mut thread_ids = BTreeMap::<ThreadInfo, usize>::new();
+
+        write!(write, "{{\"traceEvents\":[")?;
+        let mut first = true;
         for frame in self.all_uniq() {
-            frame.write_into(&self.scope_collection, false, write)?;
+            let Ok(unpacked) = frame.unpacked() else {
+                anyhow::bail!("Failed to unpack frame {}", frame.frame_index());
+            };
+            for (thread_info, stream_info) in &unpacked.thread_streams {
+                let num_threads = thread_ids.len();
+                let tid = *thread_ids.entry(thread_info.clone()).or_insert(num_threads);
+                write_chrome_events(
+                    write,
+                    &self.scope_collection,
+                    &stream_info.stream,
+                    0,
+                    tid,
+                    &mut first,
+                )?;
+            }
+        }
+
+        for (thread_info, tid) in &thread_ids {
+            if !std::mem::take(&mut first) {
+                write!(write, ",")?;
+            }
+            write!(
+                write,
+                "{{\"ph\":\"M\",\"name\":\"thread_name\",\"pid\":0,\"tid\":{tid},\"args\":{{\"name\":"
+            )?;
+            write_json_str(write, &thread_info.name)?;
+            write!(write, "}}}}")?;
         }
+        write!(write, "],\"displayTimeUnit\":\"ms\"}}")?;
         Ok(())
     }
 
     /// Import profile data from a `.puffin` file/stream.
+    ///
+    /// Gzip-compressed files are decompressed if the `gzip` feature is enabled.
     #[cfg(feature = "serialization")]
     pub fn read(read: &mut impl std::io::Read) -> anyhow::Result<Self> {
+        Self::read_with_progress(read, |_| true)
+    }
+
+    /// Import profile data from a `.puffin` file/stream,
+    /// calling `progress` with the number of frames read so far after each frame.
+    ///
+    /// If `progress` returns `false` the import is cancelled and an error is returned.
+    #[cfg(feature = "serialization")]
+    pub fn read_with_progress(
+        read: &mut impl std::io::Read,
+        progress: impl FnMut(usize) -> bool,
+    ) -> anyhow::Result<Self> {
         let mut magic = [0_u8; 4];
         read.read_exact(&mut magic)?;
+
+        if magic.starts_with(&GZIP_MAGIC) {
+            #[cfg(feature = "gzip")]
+            {
+                use std::io::Read as _;
+                let mut read = flate2::read::GzDecoder::new(magic.as_slice().chain(read));
+                let mut decompressed_magic = [0_u8; 4];
+                read.read_exact(&mut decompressed_magic)?;
+                return Self::read_frames(decompressed_magic, &mut read, progress);
+            }
+            #[cfg(not(feature = "gzip"))]
+            anyhow::bail!(
+                "This .puffin file is gzip-compressed, which needs the `gzip` feature of puffin"
+            );
+        }
+
+        Self::read_frames(magic, read, progress)
+    }
+
+    /// Read the frames of a `.puffin` stream that started with `magic`.
+    #[cfg(feature = "serialization")]
+    fn read_frames(
+        magic: [u8; 4],
+        read: &mut impl std::io::Read,
+        mut progress: impl FnMut(usize) -> bool,
+    ) -> anyhow::Result<Self> {
         if &magic != b"PUF0" {
             anyhow::bail!("Expected .puffin magic header of 'PUF0', found {:?}", magic);
         }
@@ -247,16 +697,174 @@ impl FrameView {
             max_recent: usize::MAX,
             ..Default::default()
         };
+        let mut num_frames = 0;
         while let Some(frame) = FrameData::read_next(read)? {
             slf.add_frame(frame.into());
+            num_frames += 1;
+            if !progress(num_frames) {
+                anyhow::bail!("Cancelled after reading {num_frames} frames");
+            }
         }
 
         Ok(slf)
     }
+
+    /// Add the frames of a `.puffin` file listed in `index`, but only those this view keeps:
+    /// the [`Self::max_recent`] latest frames, and the [`Self::max_slow`] slowest frames.
+    ///
+    /// Unlike [`Self::read`], the other frames are never read, so this can open recordings
+    /// that are too large to read in whole.
+    /// The frames are read in the order they are stored.
+    ///
+    /// `progress` is called with the number of frames read so far after each frame.
+    /// If it returns `false` the import is cancelled and an error is returned.
+    #[cfg(feature = "serialization")]
+    pub fn add_frames_from_index(
+        &mut self,
+        read: &mut (impl std::io::Read + std::io::Seek),
+        index: &crate::FileIndex,
+        mut progress: impl FnMut(usize) -> bool,
+    ) -> anyhow::Result<()> {
+        let entries = index.entries();
+        let first_recent = entries.len().saturating_sub(self.max_recent);
+        let selected = (0..entries.len())
+            .sorted_by_key(|&i| std::cmp::Reverse(entries[i].duration_ns))
+            .take(self.max_slow)
+            .chain(first_recent..entries.len())
+            .sorted_unstable()
+            .dedup();
+
+        for scope in index.scope_collection().scopes_by_id().values() {
+            self.scope_collection.insert(scope.clone());
+        }
+
+        for (num_frames, i) in (1..).zip(selected) {
+            let frame = index.read_frame(read, &entries[i])?;
+            self.add_frame(frame.into());
+            if !progress(num_frames) {
+                anyhow::bail!("Cancelled after reading {num_frames} frames");
+            }
+        }
+
+        Ok(())
+    }
 }
 
 // ----------------------------------------------------------------------------
 
+/// Maps the scope ids of `source` to those of the same scopes in `target`.
+///
+/// Scopes that are not in `target` keep their id if it is free, or else get a new one.
+/// These are returned too, with their new ids.
+fn scope_id_translation(
+    target: &ScopeCollection,
+    source: &ScopeCollection,
+) -> (HashMap<ScopeId, ScopeId>, Vec<Arc<ScopeDetails>>) {
+    let without_id = |details: &ScopeDetails| ScopeDetails {
+        scope_id: None,
+        ..details.clone()
+    };
+    let target_ids: HashMap<ScopeDetails, ScopeId> = target
+        .scopes_by_id()
+        .iter()
+        .map(|(id, details)| (without_id(details), *id))
+        .collect();
+
+    let mut next_id = target
+        .scopes_by_id()
+        .keys()
+        .chain(source.scopes_by_id().keys())
+        .map(|id| id.0.get())
+        .max()
+        .unwrap_or(0);
+
+    // Sorted, so the new ids don't depend on the order of the `HashMap`:
+    let mut source_scopes: Vec<_> = source.scopes_by_id().iter().collect();
+    source_scopes.sort_by_key(|(id, _)| **id);
+
+    let mut id_map = HashMap::new();
+    let mut new_scopes = vec![];
+    for (&source_id, details) in source_scopes {
+        let details = without_id(details);
+        let target_id = if let Some(&target_id) = target_ids.get(&details) {
+            target_id
+        } else {
+            let target_id = if target.fetch_by_id(&source_id).is_none() {
+                source_id
+            } else {
+                next_id += 1;
+                ScopeId(std::num::NonZeroU32::new(next_id).expect("ran out of scope ids"))
+            };
+            new_scopes.push(Arc::new(ScopeDetails {
+                scope_id: Some(target_id),
+                ..details
+            }));
+            target_id
+        };
+        id_map.insert(source_id, target_id);
+    }
+    (id_map, new_scopes)
+}
+
+/// Write the scopes starting at `offset` (and all their children) as Chrome tracing events.
+fn write_chrome_events(
+    write: &mut impl std::io::Write,
+    scope_collection: &ScopeCollection,
+    stream: &Stream,
+    offset: u64,
+    tid: usize,
+    first: &mut bool,
+) -> anyhow::Result<()> {
+    let reader = Reader::with_offset(stream, offset).map_err(|err| anyhow::anyhow!("{err:?}"))?;
+    for scope in reader {
+        let scope = scope.map_err(|err| anyhow::anyhow!("{err:?}"))?;
+
+        if !std::mem::take(first) {
+            write!(write, ",")?;
+        }
+        write!(write, "{{\"ph\":\"X\",\"name\":")?;
+        match scope_collection.fetch_by_id(&scope.id) {
+            Some(details) => write_json_str(write, details.name())?,
+            None => write_json_str(write, &scope.id.0.to_string())?,
+        }
+        write!(
+            write,
+            ",\"ts\":{:.3},\"dur\":{:.3},\"pid\":0,\"tid\":{tid}",
+            scope.record.start_ns as f64 * 1e-3,
+            scope.record.duration_ns as f64 * 1e-3,
+        )?;
+        if !scope.record.data.is_empty() {
+            write!(write, ",\"args\":{{\"data\":")?;
+            write_json_str(write, scope.record.data)?;
+            write!(write, "}}")?;
+        }
+        write!(write, "}}")?;
+
+        write_chrome_events(
+            write,
+            scope_collection,
+            stream,
+            scope.child_begin_position,
+            tid,
+            first,
+        )?;
+    }
+    Ok(())
+}
+
+fn write_json_str(write: &mut impl std::io::Write, s: &str) -> std::io::Result<()> {
+    write!(write, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(write, "\\\"")?,
+            '\\' => write!(write, "\\\\")?,
+            c if c.is_control() => write!(write, "\\u{:04x}", c as u32)?,
+            c => write!(write, "{c}")?,
+        }
+    }
+    write!(write, "\"")
+}
+
 /// Select the slowest frames, up to a certain count.
 pub fn select_slowest(frames: &[Arc<FrameData>], max: usize) -> Vec<Arc<FrameData>> {
     let mut slowest: std::collections::BinaryHeap<OrderedByDuration> = Default::default();
@@ -368,23 +976,79 @@ impl GlobalFrameView {
     pub fn lock(&self) -> parking_lot::MutexGuard<'_, FrameView> {
         self.view.lock()
     }
+
+    /// Write the frames collected so far to a `.puffin` file, e.g. from a panic hook or a hotkey.
+    ///
+    /// See [`Self::snapshot_to_writer`] for the caveats.
+    #[cfg(feature = "serialization")]
+    #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
+    pub fn snapshot_to_path(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        use std::io::Write as _;
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.snapshot_to_writer(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Write the frames collected so far as a `.puffin` stream, like [`FrameView::write`].
+    ///
+    /// Together with [`FrameView::set_max_recent`] this lets you keep profiling in the background
+    /// into a bounded ring buffer, and only dump it to disk when something goes wrong.
+    ///
+    /// The view is only locked long enough to clone it (which is cheap, as the frames are shared),
+    /// so the profiler can keep adding frames while we write.
+    /// If the view can't be locked within a second, e.g. because the current
+    /// thread panicked while holding [`Self::lock`], an error is returned instead of deadlocking.
+    ///
+    /// Caveats:
+    /// * This allocates and may compress frames, so it is NOT async-signal-safe.
+    ///   Don't call it from a signal handler directly,
+    ///   but from a thread that waits for the signal (e.g. with `signal-hook`).
+    /// * In a panic hook the frame that is still in progress is not included,
+    ///   as it hasn't been passed to the view yet.
+    #[cfg(feature = "serialization")]
+    #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
+    pub fn snapshot_to_writer(&self, write: &mut impl std::io::Write) -> anyhow::Result<()> {
+        let Some(view) = self.view.try_lock_for(std::time::Duration::from_secs(1)) else {
+            anyhow::bail!("Timed out waiting for the frame view lock");
+        };
+        let view = view.clone();
+        view.write(write)
+    }
 }
 
 // ----------------------------------------------------------------------------
 
-fn stats_entry(frame: &FrameData) -> (usize, usize) {
+struct StatsEntry {
+    packed_bytes: usize,
+    unpacked_bytes: usize,
+    /// [`crate::FrameMeta::num_bytes`] of the frame, if it has packed data.
+    packed_original_bytes: usize,
+    unpacked: usize,
+}
+
+fn stats_entry(frame: &FrameData) -> StatsEntry {
     let info = frame.packing_info();
-    (
-        info.packed_size.unwrap_or(0) + info.unpacked_size.unwrap_or(0),
-        info.unpacked_size.is_some() as usize,
-    )
+    StatsEntry {
+        packed_bytes: info.packed_size.unwrap_or(0),
+        unpacked_bytes: info.unpacked_size.unwrap_or(0),
+        packed_original_bytes: if info.packed_size.is_some() {
+            frame.meta().num_bytes
+        } else {
+            0
+        },
+        unpacked: info.unpacked_size.is_some() as usize,
+    }
 }
 
 /// Collect statistics for maintained frames
 #[derive(Clone, Copy, Debug, Default)]
 pub struct FrameStats {
     unique_frames: usize,
-    total_ram_used: usize,
+    packed_bytes: usize,
+    unpacked_bytes: usize,
+    packed_original_bytes: usize,
     unpacked_frames: usize,
 }
 
@@ -402,19 +1066,27 @@ impl FrameStats {
 
     /// Adds a frame's statistics to the `FrameStats`.
     fn add(&mut self, frame: &FrameData) {
-        let (total, unpacked) = stats_entry(frame);
-
-        self.total_ram_used = self.total_ram_used.saturating_add(total);
-        self.unpacked_frames = self.unpacked_frames.saturating_add(unpacked);
+        let entry = stats_entry(frame);
+
+        self.packed_bytes = self.packed_bytes.saturating_add(entry.packed_bytes);
+        self.unpacked_bytes = self.unpacked_bytes.saturating_add(entry.unpacked_bytes);
+        self.packed_original_bytes = self
+            .packed_original_bytes
+            .saturating_add(entry.packed_original_bytes);
+        self.unpacked_frames = self.unpacked_frames.saturating_add(entry.unpacked);
         self.unique_frames = self.unique_frames.saturating_add(1);
     }
 
     /// Removes a frame's statistics from the `FrameStats`.
     fn remove(&mut self, frame: &FrameData) {
-        let (total, unpacked) = stats_entry(frame);
-
-        self.total_ram_used = self.total_ram_used.saturating_sub(total);
-        self.unpacked_frames = self.unpacked_frames.saturating_sub(unpacked);
+        let entry = stats_entry(frame);
+
+        self.packed_bytes = self.packed_bytes.saturating_sub(entry.packed_bytes);
+        self.unpacked_bytes = self.unpacked_bytes.saturating_sub(entry.unpacked_bytes);
+        self.packed_original_bytes = self
+            .packed_original_bytes
+            .saturating_sub(entry.packed_original_bytes);
+        self.unpacked_frames = self.unpacked_frames.saturating_sub(entry.unpacked);
         self.unique_frames = self.unique_frames.saturating_sub(1);
     }
 
@@ -430,13 +1102,538 @@ impl FrameStats {
 
     /// Returns the total bytes of RAM used.
     pub fn bytes_of_ram_used(&self) -> usize {
-        self.total_ram_used
+        self.packed_bytes.saturating_add(self.unpacked_bytes)
+    }
+
+    /// Returns the bytes of RAM used by packed (compressed) frame data.
+    pub fn packed_bytes(&self) -> usize {
+        self.packed_bytes
+    }
+
+    /// Returns the bytes of RAM used by unpacked frame data.
+    ///
+    /// Frames that are both packed and unpacked count towards both this and [`Self::packed_bytes`].
+    pub fn unpacked_bytes(&self) -> usize {
+        self.unpacked_bytes
+    }
+
+    /// The size of the packed data relative to its unpacked size, e.g. `0.42` for 42%.
+    ///
+    /// `None` if no frame is packed.
+    pub fn packed_ratio(&self) -> Option<f32> {
+        (self.packed_original_bytes > 0)
+            .then(|| self.packed_bytes as f32 / self.packed_original_bytes as f32)
     }
 
     /// Clears all statistics in `FrameStats`.
     pub fn clear(&mut self) {
-        self.unique_frames = 0;
-        self.unpacked_frames = 0;
-        self.total_ram_used = 0;
+        *self = Self::default();
+    }
+
+    /// Aggregates per-thread statistics over the given frames.
+    ///
+    /// Threads that are missing from some frames only count the frames they appear in.
+    pub fn per_thread<'a>(
+        frames: impl IntoIterator<Item = &'a UnpackedFrameData>,
+    ) -> BTreeMap<ThreadInfo, ThreadStats> {
+        let mut threads: BTreeMap<ThreadInfo, ThreadStats> = BTreeMap::new();
+
+        for frame in frames {
+            for (thread_info, stream_info) in &frame.thread_streams {
+                let stats = threads.entry(thread_info.clone()).or_default();
+                stats.num_scopes += stream_info.num_scopes;
+                stats.max_depth = stats.max_depth.max(stream_info.depth);
+                stats.total_ns += Reader::from_start(&stream_info.stream)
+                    .map_while(Result::ok)
+                    .map(|scope| scope.record.duration_ns)
+                    .sum::<NanoSecond>();
+            }
+        }
+
+        threads
+    }
+}
+
+/// Statistics for one thread, aggregated over several frames.
+///
+/// See [`FrameStats::per_thread`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ThreadStats {
+    /// Sum of the durations of all top-level scopes.
+    pub total_ns: NanoSecond,
+
+    /// Total number of scopes, at all depths.
+    pub num_scopes: usize,
+
+    /// The depth of the deepest scope in any of the frames.
+    pub max_depth: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+    use crate::{
+        FrameData, FrameStats, FrameView, ScopeDetails, ScopeId, Stream, ThreadInfo, ThreadStats,
+    };
+
+    fn frame(frame_index: u64, duration_ns: i64) -> Arc<FrameData> {
+        frame_with_scopes(frame_index, duration_ns, vec![])
+    }
+
+    fn frame_with_scopes(
+        frame_index: u64,
+        duration_ns: i64,
+        scope_delta: Vec<Arc<ScopeDetails>>,
+    ) -> Arc<FrameData> {
+        let mut stream = Stream::default();
+        let start_ns = frame_index as i64 * 1000;
+        let (offset, _) = stream.begin_scope(|| start_ns, ScopeId::new(1), "");
+        stream.end_scope(offset, start_ns + duration_ns);
+        let thread_streams = BTreeMap::from([crate::test_thread_stream("main", stream)]);
+        Arc::new(FrameData::new(frame_index, thread_streams, scope_delta, false).unwrap())
+    }
+
+    #[test]
+    fn frames_out_of_order() {
+        let mut view = FrameView::default();
+        for i in 100..110 {
+            view.add_frame(frame(i, 100));
+        }
+
+        // A late frame is dropped:
+        view.add_frame(frame(108, 100));
+        assert_eq!(view.num_dropped_out_of_order(), 1);
+        assert_eq!(view.num_resets(), 0);
+        assert_eq!(view.recent_frames().count(), 10);
+        assert_eq!(view.latest_frame().unwrap().frame_index(), 109);
+
+        // A restart clears everything:
+        view.add_frame(frame(0, 100));
+        assert_eq!(view.num_dropped_out_of_order(), 1);
+        assert_eq!(view.num_resets(), 1);
+        assert_eq!(view.recent_frames().count(), 1);
+        assert_eq!(view.latest_frame().unwrap().frame_index(), 0);
+    }
+
+    #[test]
+    fn frames_in_range() {
+        let indices = |frames: &mut dyn Iterator<Item = &Arc<FrameData>>| -> Vec<u64> {
+            frames.map(|frame| frame.frame_index()).collect()
+        };
+
+        let mut view = FrameView::default();
+        assert!(indices(&mut view.frames_in_range(0, 10_000)).is_empty());
+        assert!(indices(&mut view.frames_in_last(Duration::from_secs(1))).is_empty());
+
+        // Frame `i` covers `i * 1000 .. i * 1000 + 500`:
+        for i in 0..5 {
+            view.add_frame(frame(i, 500));
+        }
+
+        assert_eq!(
+            indices(&mut view.frames_in_range(0, 10_000)),
+            [0, 1, 2, 3, 4]
+        );
+        assert_eq!(indices(&mut view.frames_in_range(1400, 2100)), [1, 2]);
+        assert_eq!(indices(&mut view.frames_in_range(2500, 2500)), [2]);
+        assert!(indices(&mut view.frames_in_range(2600, 2900)).is_empty());
+        assert!(indices(&mut view.frames_in_range(5000, 6000)).is_empty());
+
+        // The newest frame ends at 4500:
+        assert_eq!(
+            indices(&mut view.frames_in_last(Duration::from_nanos(1200))),
+            [3, 4]
+        );
+        assert_eq!(
+            indices(&mut view.frames_in_last(Duration::MAX)),
+            [0, 1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn on_slow_frame() {
+        let slow_frames = Arc::new(parking_lot::Mutex::new(vec![]));
+        let mut view = FrameView::default();
+        view.set_slow_frame_threshold(250);
+        view.set_on_slow_frame(Box::new({
+            let slow_frames = slow_frames.clone();
+            move |frame| slow_frames.lock().push(frame.frame_index())
+        }));
+
+        for (i, duration_ns) in [100, 300, 250, 900, 200].into_iter().enumerate() {
+            view.add_frame(frame(i as u64, duration_ns));
+        }
+        assert_eq!(*slow_frames.lock(), [1, 3]);
+
+        // Clones don't call it:
+        view.clone().add_frame(frame(5, 900));
+        assert_eq!(*slow_frames.lock(), [1, 3]);
+    }
+
+    #[test]
+    fn clear_recent_keeps_slowest() {
+        let mut view = FrameView::default();
+        view.set_max_recent(5);
+        view.set_max_slow(3);
+        for (i, duration_ns) in [500, 100, 900, 200, 300, 100, 700, 100]
+            .into_iter()
+            .enumerate()
+        {
+            view.add_frame(frame(i as u64, duration_ns));
+        }
+        // The slowest frames 0, 2 and 6 are kept, 6 is also recent:
+        assert_eq!(view.all_uniq().count(), 7);
+        assert_eq!(view.stats().frames(), 6); // the newest frame is not counted yet
+
+        view.clear_recent();
+        assert_eq!(view.recent_frames().count(), 0);
+        let slowest: Vec<_> = view
+            .slowest_frames_chronological()
+            .map(|frame| frame.frame_index())
+            .collect();
+        assert_eq!(slowest, [0, 2, 6]);
+        assert_eq!(view.stats().frames(), 3);
+
+        view.add_frame(frame(8, 100));
+        view.add_frame(frame(9, 100));
+        assert_eq!(view.stats().frames(), 4);
+    }
+
+    #[test]
+    fn clear_keeps_scopes() {
+        let mut view = FrameView::default();
+        view.set_max_slow(3);
+        let scope_details = ScopeDetails::from_scope_id(ScopeId::new(1)).with_scope_name("work");
+        view.add_frame(frame_with_scopes(0, 100, vec![Arc::new(scope_details)]));
+        for i in 1..10 {
+            view.add_frame(frame(i, 100 * i as i64));
+        }
+        assert!(!view.is_empty());
+
+        view.clear();
+        assert!(view.is_empty());
+        assert_eq!(view.all_uniq().count(), 0);
+        assert_eq!(view.stats().frames(), 0);
+        assert!(view
+            .scope_collection()
+            .fetch_by_id(&ScopeId::new(1))
+            .is_some());
+
+        view.add_frame(frame(10, 100));
+        view.add_frame(frame(11, 100));
+        assert_eq!(view.recent_frames().count(), 2);
+        assert_eq!(view.stats().frames(), 1);
+    }
+
+    #[test]
+    fn max_recent_bytes() {
+        let frame_bytes =
+            FrameStats::from_frames(std::iter::once(&*frame(0, 100))).bytes_of_ram_used();
+        assert!(0 < frame_bytes);
+
+        let mut view = FrameView::default();
+        view.set_pack_policy(crate::PackPolicy::Never);
+        view.set_max_slow(0);
+        view.set_max_recent_bytes(Some(3 * frame_bytes));
+        for i in 0..10 {
+            view.add_frame(frame(i, 100));
+        }
+
+        // The stats don't count the newest frame yet, so that one comes on top:
+        assert!(view.stats().bytes_of_ram_used() <= 3 * frame_bytes);
+        let indices: Vec<_> = view
+            .recent_frames()
+            .map(|frame| frame.frame_index())
+            .collect();
+        assert_eq!(indices, [6, 7, 8, 9]);
+
+        // The frame count limit still applies:
+        view.set_max_recent(2);
+        view.add_frame(frame(10, 100));
+        assert_eq!(view.recent_frames().count(), 2);
+    }
+
+    #[cfg(feature = "packing")]
+    #[test]
+    fn pack_on_eviction() {
+        let mut view = FrameView::default();
+        view.set_pack_policy(crate::PackPolicy::OnEviction);
+        view.set_max_recent(3);
+        view.set_max_slow(2);
+        for (i, duration_ns) in [900, 800, 100, 100, 100].into_iter().enumerate() {
+            view.add_frame(frame(i as u64, duration_ns));
+        }
+
+        // Recent frames are left alone:
+        assert!(view.recent_frames().all(|frame| !frame.has_packed()));
+
+        // The slow frames that left the recent history have been packed:
+        let slowest: Vec<_> = view.slowest_frames_chronological().collect();
+        assert_eq!(slowest.len(), 2);
+        assert!(slowest
+            .iter()
+            .all(|frame| frame.has_packed() && !frame.has_unpacked()));
+        assert_eq!(view.stats().unpacked_frames(), 2); // frame 4 is not counted yet
+        assert_eq!(view.stats().frames(), 4);
+
+        let stats = view.stats();
+        assert!(stats.packed_bytes() > 0);
+        assert!(stats.unpacked_bytes() > 0);
+        assert_eq!(
+            stats.bytes_of_ram_used(),
+            stats.packed_bytes() + stats.unpacked_bytes()
+        );
+        let packed = FrameStats::from_frames(slowest.iter().map(|frame| frame.as_ref()));
+        assert_eq!(packed.unpacked_bytes(), 0);
+        let original: usize = slowest.iter().map(|frame| frame.meta().num_bytes).sum();
+        assert_eq!(
+            packed.packed_ratio(),
+            Some(packed.packed_bytes() as f32 / original as f32)
+        );
+        assert_eq!(FrameStats::default().packed_ratio(), None);
+    }
+
+    #[cfg(all(feature = "serialization", not(target_arch = "wasm32")))]
+    #[test]
+    fn snapshot_global_frame_view() {
+        let global_view = crate::GlobalFrameView::default();
+        global_view.lock().add_frame(frame(1_000_000, 100));
+
+        // Don't deadlock if the view is already locked, e.g. by a panicking thread:
+        {
+            let _guard = global_view.lock();
+            assert!(global_view.snapshot_to_writer(&mut vec![]).is_err());
+        }
+
+        let mut bytes = vec![];
+        global_view.snapshot_to_writer(&mut bytes).unwrap();
+        let view = FrameView::read(&mut bytes.as_slice()).unwrap();
+        assert!(!view.is_empty());
+    }
+
+    #[test]
+    fn chrome_trace() {
+        let mut stream = Stream::default();
+        let (outer, _) = stream.begin_scope(|| 1000, ScopeId::new(1), "");
+        let (inner, _) = stream.begin_scope(|| 1500, ScopeId::new(2), "\"a\\b\"");
+        stream.end_scope(inner, 2000);
+        stream.end_scope(outer, 3000);
+        let thread_streams = BTreeMap::from([crate::test_thread_stream("main", stream)]);
+        let mut view = FrameView::default();
+        view.add_frame(Arc::new(
+            FrameData::new(0, thread_streams, vec![], false).unwrap(),
+        ));
+
+        let mut json = vec![];
+        view.write_chrome_trace(&mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            r#"{"traceEvents":["#.to_owned()
+                + r#"{"ph":"X","name":"1","ts":1.000,"dur":2.000,"pid":0,"tid":0},"#
+                + r#"{"ph":"X","name":"2","ts":1.500,"dur":0.500,"pid":0,"tid":0,"args":{"data":"\"a\\b\""}},"#
+                + r#"{"ph":"M","name":"thread_name","pid":0,"tid":0,"args":{"name":"main"}}"#
+                + r#"],"displayTimeUnit":"ms"}"#
+        );
+    }
+
+    #[cfg(feature = "perfetto")]
+    #[test]
+    fn perfetto_trace() {
+        use prost::Message as _;
+
+        use crate::perfetto::{TracePacket, TrackEventType};
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct Trace {
+            #[prost(message, repeated, tag = "1")]
+            packet: Vec<TracePacket>,
+        }
+
+        let mut stream = Stream::default();
+        let (outer, _) = stream.begin_scope(|| 1000, ScopeId::new(1), "");
+        let (inner, _) = stream.begin_scope(|| 1500, ScopeId::new(2), "mesh.obj");
+        stream.end_scope(inner, 2000);
+        stream.end_scope(outer, 3000);
+        let thread_streams = BTreeMap::from([crate::test_thread_stream("main", stream)]);
+        let mut view = FrameView::default();
+        view.add_frame(Arc::new(
+            FrameData::new(0, thread_streams, vec![], false).unwrap(),
+        ));
+
+        let mut bytes = vec![];
+        view.write_perfetto(&mut bytes).unwrap();
+        let trace = Trace::decode(bytes.as_slice()).unwrap();
+
+        let descriptor = trace.packet[0].track_descriptor.as_ref().unwrap();
+        let thread = descriptor.thread.as_ref().unwrap();
+        assert_eq!(thread.thread_name.as_deref(), Some("main"));
+
+        let events: Vec<_> = trace.packet[1..]
+            .iter()
+            .map(|packet| {
+                let event = packet.track_event.as_ref().unwrap();
+                assert_eq!(event.track_uuid, descriptor.uuid);
+                (
+                    packet.timestamp.unwrap(),
+                    TrackEventType::try_from(event.r#type.unwrap()).unwrap(),
+                    event.name.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (1000, TrackEventType::SliceBegin, Some("1".to_owned())),
+                (1500, TrackEventType::SliceBegin, Some("2".to_owned())),
+                (2000, TrackEventType::SliceEnd, None),
+                (3000, TrackEventType::SliceEnd, None),
+            ]
+        );
+        let data = &trace.packet[2]
+            .track_event
+            .as_ref()
+            .unwrap()
+            .debug_annotations[0];
+        assert_eq!(data.string_value.as_deref(), Some("mesh.obj"));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_roundtrip() {
+        let mut view = FrameView::default();
+        for frame_index in 0..3 {
+            view.add_frame(frame(frame_index, 100));
+        }
+
+        let mut bytes = vec![];
+        view.write_gzipped(&mut bytes).unwrap();
+        assert!(bytes.starts_with(&super::GZIP_MAGIC));
+
+        let read = FrameView::read(&mut bytes.as_slice()).unwrap();
+        let frame_indices: Vec<_> = read.all_uniq().map(|frame| frame.frame_index()).collect();
+        assert_eq!(frame_indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn per_thread_stats() {
+        let mut stream = Stream::default();
+        let (outer, _) = stream.begin_scope(|| 0, ScopeId::new(1), "");
+        let (inner, _) = stream.begin_scope(|| 10, ScopeId::new(2), "");
+        stream.end_scope(inner, 20);
+        stream.end_scope(outer, 50);
+        let (render, stream_info) = crate::test_thread_stream("render", stream);
+        let thread_streams = BTreeMap::from([(render.clone(), stream_info)]);
+        let render_frame = FrameData::new(2, thread_streams, vec![], false).unwrap();
+
+        let frames = [frame(0, 100), frame(1, 200)]
+            .iter()
+            .chain([&Arc::new(render_frame)])
+            .map(|frame| frame.unpacked().unwrap())
+            .collect::<Vec<_>>();
+        let stats = FrameStats::per_thread(frames.iter().map(Arc::as_ref));
+
+        assert_eq!(stats.len(), 2);
+        let main = ThreadInfo::new(None, "main");
+        assert_eq!(
+            stats[&main],
+            ThreadStats {
+                total_ns: 300,
+                num_scopes: 2,
+                max_depth: 1,
+            }
+        );
+        assert_eq!(
+            stats[&render],
+            ThreadStats {
+                total_ns: 50,
+                num_scopes: 2,
+                max_depth: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn append_remaps_scope_ids() {
+        use crate::{Reader, ScopeCollection, ScopeDetails};
+
+        /// A view with one frame per duration, each with one scope per scope id.
+        fn view(scopes: &[(u32, &'static str)], durations: &[i64]) -> FrameView {
+            let mut view = FrameView::default();
+            for (i, &duration_ns) in durations.iter().enumerate() {
+                let mut stream = Stream::default();
+                let start_ns = i as i64 * 1000;
+                for &(id, _) in scopes {
+                    let (offset, _) = stream.begin_scope(|| start_ns, ScopeId::new(id), "");
+                    stream.end_scope(offset, start_ns + duration_ns);
+                }
+                let thread_streams = BTreeMap::from([crate::test_thread_stream("main", stream)]);
+                let scope_delta = scopes
+                    .iter()
+                    .map(|&(id, name)| {
+                        Arc::new(
+                            ScopeDetails::from_scope_id(ScopeId::new(id)).with_function_name(name),
+                        )
+                    })
+                    .collect();
+                let frame = FrameData::new(i as u64, thread_streams, scope_delta, false).unwrap();
+                view.add_frame(Arc::new(frame));
+            }
+            view
+        }
+
+        fn scope_names(scope_collection: &ScopeCollection, frame: &FrameData) -> Vec<String> {
+            let unpacked = frame.unpacked().unwrap();
+            let stream = &unpacked.thread_streams.values().next().unwrap().stream;
+            Reader::from_start(stream)
+                .read_top_scopes()
+                .unwrap()
+                .iter()
+                .map(|scope| {
+                    scope_collection
+                        .fetch_by_id(&scope.id)
+                        .unwrap()
+                        .name()
+                        .to_string()
+                })
+                .collect()
+        }
+
+        let mut first = view(&[(1, "update"), (2, "render")], &[100, 200]);
+        // The same "render" scope, and a new scope with an id taken by "update":
+        let second = view(&[(2, "render"), (1, "physics"), (3, "audio")], &[300, 400]);
+        first.append(second).unwrap();
+
+        let frames: Vec<_> = first.all_uniq().cloned().collect();
+        let frame_indices: Vec<_> = frames.iter().map(|frame| frame.frame_index()).collect();
+        assert_eq!(frame_indices, [0, 1, 2, 3]);
+        assert_eq!(frames[3].duration_ns(), 400);
+
+        let scope_collection = first.scope_collection();
+        assert_eq!(scope_collection.scopes_by_id().len(), 4);
+        assert_eq!(
+            scope_names(scope_collection, &frames[0]),
+            ["update", "render"]
+        );
+        assert_eq!(
+            scope_names(scope_collection, &frames[3]),
+            ["render", "physics", "audio"]
+        );
+        assert_eq!(
+            scope_collection.fetch_by_name("render"),
+            Some(&ScopeId::new(2))
+        );
+        assert_eq!(
+            scope_collection.fetch_by_name("update"),
+            Some(&ScopeId::new(1))
+        );
+        assert_eq!(
+            scope_collection.fetch_by_name("audio"),
+            Some(&ScopeId::new(3))
+        );
+        assert_eq!(
+            scope_collection.fetch_by_name("physics"),
+            Some(&ScopeId::new(4))
+        );
     }
 }
@@ -0,0 +1,301 @@
+use std::sync::Arc;
+
+use crate::{
+    FrameData, FrameIndex, FrameMeta, FrameView, NanoSecond, Profiler, ScopeDetails,
+    UnpackedFrameData,
+};
+
+/// Plays back recorded frames (e.g. from a `.puffin` file) into a [`Profiler`],
+/// so that its sinks see them as if they were being profiled live.
+///
+/// Frames are emitted with the same spacing as when they were recorded, scaled by [`Self::set_speed`].
+/// They are given new, increasing frame indices, so looping the capture
+/// doesn't look like the profiled application restarting.
+///
+/// Call [`Self::update`] regularly (e.g. once per frame of your app) to emit the frames that are due:
+///
+/// ```
+/// # let frame_view = puffin::FrameView::default();
+/// let mut replayer = puffin::Replayer::new(&frame_view);
+/// replayer.set_looping(true);
+/// replayer.update(&mut puffin::GlobalProfiler::lock());
+/// ```
+///
+/// The replayed scopes have the ids they were recorded with, which can clash with the scopes of
+/// the running application, so it is best to replay into a [`Profiler`] of its own
+/// (see `puffin_http::Server::new_custom`).
+pub struct Replayer {
+    /// Chronological.
+    frames: Vec<Arc<FrameData>>,
+
+    /// All the scopes of the capture, sent with the first replayed frame.
+    scopes: Vec<Arc<ScopeDetails>>,
+    sent_scopes: bool,
+
+    speed: f64,
+    looping: bool,
+    paused: bool,
+
+    /// Index into [`Self::frames`] of the next frame to emit.
+    next: usize,
+
+    /// How far into the capture we have played, relative to the start of the first frame.
+    playhead_ns: NanoSecond,
+
+    /// When [`Self::update`] was last called.
+    last_update_ns: Option<NanoSecond>,
+
+    next_frame_index: FrameIndex,
+}
+
+impl Replayer {
+    /// Replay all the frames in the given view.
+    pub fn new(frame_view: &FrameView) -> Self {
+        let frames: Vec<_> = frame_view.all_uniq().cloned().collect();
+        let next_frame_index = frames.first().map_or(0, |frame| frame.frame_index());
+        Self {
+            frames,
+            scopes: frame_view
+                .scope_collection()
+                .scopes_by_id()
+                .values()
+                .cloned()
+                .collect(),
+            sent_scopes: false,
+            speed: 1.0,
+            looping: false,
+            paused: false,
+            next: 0,
+            playhead_ns: 0,
+            last_update_ns: None,
+            next_frame_index,
+        }
+    }
+
+    /// Replay a `.puffin` file/stream.
+    #[cfg(feature = "serialization")]
+    pub fn read(read: &mut impl std::io::Read) -> anyhow::Result<Self> {
+        Ok(Self::new(&FrameView::read(read)?))
+    }
+
+    /// Number of frames in the capture.
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Playback speed, where `1.0` is the speed the capture was recorded at.
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Set the playback speed, e.g. `2.0` to play twice as fast as recorded.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.max(0.0);
+    }
+
+    /// Do we start over after the last frame?
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Start over after the last frame. Off by default.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// Is the playback paused?
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pause or resume the playback. [`Self::step`] still works while paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Have all frames been played, and we are not looping?
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.next >= self.frames.len()
+    }
+
+    /// Start over from the first frame.
+    ///
+    /// The frame indices keep increasing.
+    pub fn rewind(&mut self) {
+        self.next = 0;
+        self.playhead_ns = 0;
+    }
+
+    /// Emit the frames that are due since the last call.
+    ///
+    /// Returns the number of frames emitted.
+    pub fn update(&mut self, profiler: &mut Profiler) -> usize {
+        self.update_at(profiler, crate::now_ns())
+    }
+
+    /// Emit the next frame right away, e.g. while paused.
+    ///
+    /// Returns `false` if there are no more frames.
+    pub fn step(&mut self, profiler: &mut Profiler) -> bool {
+        if self.next >= self.frames.len() {
+            if !self.looping || self.frames.is_empty() {
+                return false;
+            }
+            self.rewind();
+        }
+        self.playhead_ns = self.offset_ns(self.next);
+        self.emit_next(profiler);
+        true
+    }
+
+    fn update_at(&mut self, profiler: &mut Profiler, now_ns: NanoSecond) -> usize {
+        let elapsed_ns = now_ns - self.last_update_ns.unwrap_or(now_ns);
+        self.last_update_ns = Some(now_ns);
+
+        if self.paused || self.frames.is_empty() {
+            return 0;
+        }
+
+        self.playhead_ns += (elapsed_ns as f64 * self.speed) as NanoSecond;
+
+        let mut num_emitted = 0;
+        loop {
+            if self.next >= self.frames.len() {
+                if !self.looping {
+                    break;
+                }
+                self.playhead_ns -= self.loop_ns();
+                self.next = 0;
+            }
+
+            if self.playhead_ns < self.offset_ns(self.next) {
+                break;
+            }
+
+            if num_emitted == self.frames.len() {
+                // We have fallen far behind (or the capture is very short).
+                // Rather than flooding the sinks, skip ahead:
+                self.playhead_ns = self.offset_ns(self.next);
+                break;
+            }
+
+            self.emit_next(profiler);
+            num_emitted += 1;
+        }
+        num_emitted
+    }
+
+    /// When frame `i` starts, relative to the start of the first frame.
+    fn offset_ns(&self, i: usize) -> NanoSecond {
+        self.frames[i].range_ns().0 - self.frames[0].range_ns().0
+    }
+
+    /// The time from the start of the first frame to the end of the last one.
+    fn loop_ns(&self) -> NanoSecond {
+        let (Some(first), Some(last)) = (self.frames.first(), self.frames.last()) else {
+            return 1;
+        };
+        (last.range_ns().1 - first.range_ns().0).max(1)
+    }
+
+    fn emit_next(&mut self, profiler: &mut Profiler) {
+        let frame = self.frames[self.next].clone();
+        self.next += 1;
+
+        let Some(unpacked) = frame.unpacked().ok() else {
+            eprintln!(
+                "puffin ERROR: Failed to unpack frame {}",
+                frame.frame_index()
+            );
+            return;
+        };
+
+        let unpacked = UnpackedFrameData {
+            meta: FrameMeta {
+                frame_index: self.next_frame_index,
+                ..unpacked.meta
+            },
+            thread_streams: unpacked.thread_streams.clone(),
+        };
+        self.next_frame_index += 1;
+
+        let (scope_delta, full_delta) = if std::mem::replace(&mut self.sent_scopes, true) {
+            (frame.scope_delta.clone(), false)
+        } else {
+            (self.scopes.clone(), true)
+        };
+
+        profiler.add_frame(Arc::new(FrameData::from_unpacked(
+            Arc::new(unpacked),
+            scope_delta,
+            full_delta,
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{FrameData, FrameView, Profiler, ScopeId, Stream};
+
+    use super::Replayer;
+
+    fn frame(frame_index: u64, start_ns: i64, end_ns: i64) -> Arc<FrameData> {
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| start_ns, ScopeId::new(1), "");
+        stream.end_scope(offset, end_ns);
+        let thread_streams = [crate::test_thread_stream("main", stream)].into();
+        Arc::new(FrameData::new(frame_index, thread_streams, vec![], false).unwrap())
+    }
+
+    fn setup() -> (Replayer, Profiler, Arc<parking_lot::Mutex<Vec<u64>>>) {
+        let mut frame_view = FrameView::default();
+        frame_view.add_frame(frame(7, 1000, 1010));
+        frame_view.add_frame(frame(8, 1100, 1110));
+        frame_view.add_frame(frame(9, 1200, 1210));
+
+        let mut profiler = Profiler::default();
+        let indices = Arc::new(parking_lot::Mutex::new(vec![]));
+        let indices_clone = indices.clone();
+        profiler.add_sink(Box::new(move |frame| {
+            indices_clone.lock().push(frame.frame_index());
+        }));
+
+        (Replayer::new(&frame_view), profiler, indices)
+    }
+
+    #[test]
+    fn replays_with_recorded_timing() {
+        let (mut replayer, mut profiler, indices) = setup();
+        replayer.set_speed(2.0);
+
+        assert_eq!(replayer.update_at(&mut profiler, 0), 1);
+        assert_eq!(replayer.update_at(&mut profiler, 49), 0);
+        assert_eq!(replayer.update_at(&mut profiler, 100), 2);
+        assert!(replayer.is_finished());
+        assert_eq!(replayer.update_at(&mut profiler, 1000), 0);
+        assert_eq!(*indices.lock(), vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn loop_pause_and_step() {
+        let (mut replayer, mut profiler, indices) = setup();
+        replayer.set_looping(true);
+
+        assert_eq!(replayer.update_at(&mut profiler, 0), 1);
+        assert_eq!(replayer.update_at(&mut profiler, 200), 2);
+        // The capture is 210 ns long, so the first frame comes around again at 210:
+        assert_eq!(replayer.update_at(&mut profiler, 209), 0);
+        assert_eq!(replayer.update_at(&mut profiler, 210), 1);
+
+        replayer.set_paused(true);
+        assert_eq!(replayer.update_at(&mut profiler, 10_000), 0);
+        assert!(replayer.step(&mut profiler));
+        assert!(replayer.step(&mut profiler));
+        assert!(replayer.step(&mut profiler));
+
+        assert!(!replayer.is_finished());
+        assert_eq!(*indices.lock(), vec![7, 8, 9, 10, 11, 12, 13]);
+    }
+}
@@ -109,6 +109,11 @@ pub struct ScopeDetails {
 
     /// The exact line number at which this scope is located.
     pub line_nr: u32,
+
+    /// Optional category used to group scopes (e.g. "Physics" or "IO").
+    ///
+    /// Empty if the scope has no category.
+    pub category: Cow<'static, str>,
 }
 
 impl ScopeDetails {
@@ -123,11 +128,13 @@ impl ScopeDetails {
             function_name: Default::default(),
             file_path: Default::default(),
             line_nr: Default::default(),
+            category: Default::default(),
         }
     }
 
     /// Creates a new user scope with a unique id allocated by puffin.
     /// This function should not be exposed as only puffin should allocate ids for scopes.
+    #[cfg(test)]
     pub(crate) fn from_scope_id(scope_id: ScopeId) -> Self {
         Self {
             scope_id: Some(scope_id),
@@ -135,6 +142,7 @@ impl ScopeDetails {
             function_name: Default::default(),
             file_path: Default::default(),
             line_nr: Default::default(),
+            category: Default::default(),
         }
     }
 
@@ -165,6 +173,16 @@ impl ScopeDetails {
         self
     }
 
+    /// Scope in a category, e.g. "Physics".
+    #[inline]
+    pub fn with_category<T>(mut self, category: T) -> Self
+    where
+        T: Into<Cow<'static, str>>,
+    {
+        self.category = category.into();
+        self
+    }
+
     /// Returns the scope name if this is a profile scope or else the function name.
     pub fn name(&self) -> &Cow<'static, str> {
         self.scope_name.as_ref().map_or(&self.function_name, |x| x)
@@ -0,0 +1,152 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::{ScopeId, TaskEvent, ThreadProfiler};
+
+static NEXT_TASK_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A future that records when and on which thread it runs.
+///
+/// Created by the [`crate::profile_task`] macro.
+///
+/// Unlike [`crate::ProfilerScope`] this is [`Send`] if the wrapped future is,
+/// so the task is free to move between the worker threads of an executor.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ProfiledTask<F> {
+    /// `None` if the profiler was off when the task was created.
+    task_name_id: Option<ScopeId>,
+    task_id: u64,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> ProfiledTask<F> {
+    /// Profile `future` as a task named by `task_name_id`,
+    /// or not at all if `task_name_id` is `None`.
+    ///
+    /// The name is registered like a named scope, e.g. with [`ThreadProfiler::register_named_scope`].
+    pub fn new(task_name_id: Option<ScopeId>, future: F) -> Self {
+        Self {
+            task_name_id: task_name_id.filter(|&id| id != ScopeId::DISABLED),
+            task_id: NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed),
+            future: Box::pin(future),
+        }
+    }
+
+    /// Identifies this task in the [`crate::TaskRecord`]s.
+    pub fn task_id(&self) -> u64 {
+        self.task_id
+    }
+}
+
+impl<F: Future> Future for ProfiledTask<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Some(task_name_id) = self.task_name_id else {
+            return self.future.as_mut().poll(cx);
+        };
+        let task_id = self.task_id;
+
+        ThreadProfiler::call(|tp| tp.record_task_event(task_name_id, task_id, TaskEvent::Enter));
+        let poll = self.future.as_mut().poll(cx);
+        ThreadProfiler::call(|tp| tp.record_task_event(task_name_id, task_id, TaskEvent::Exit));
+
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+    };
+
+    use super::ProfiledTask;
+    use crate::{
+        Reader, ScopeDetails, ScopeId, Stream, StreamInfoRef, TaskEvent, ThreadInfo, ThreadProfiler,
+    };
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Pending on the first poll, ready on the second.
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: std::pin::Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn task_migrates_between_threads() {
+        static REPORTS: parking_lot::Mutex<Vec<(String, Stream)>> =
+            parking_lot::const_mutex(Vec::new());
+        fn reporter(info: ThreadInfo, _: &[ScopeDetails], stream: &StreamInfoRef<'_>) {
+            REPORTS
+                .lock()
+                .push((info.name, Stream::from(stream.stream.to_vec())));
+        }
+
+        let poll_on_thread = |name: &str, mut task: ProfiledTask<YieldOnce>| {
+            std::thread::Builder::new()
+                .name(name.to_owned())
+                .spawn(move || {
+                    // Don't report to the global profiler, so we don't interfere with other tests:
+                    ThreadProfiler::initialize(crate::now_ns, reporter);
+                    let waker = Waker::from(Arc::new(NoopWaker));
+                    let poll = std::pin::Pin::new(&mut task).poll(&mut Context::from_waker(&waker));
+                    (task, poll)
+                })
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+
+        let task = ProfiledTask::new(Some(ScopeId::new(1)), YieldOnce(false));
+        let task_id = task.task_id();
+        let (task, poll) = poll_on_thread("first", task);
+        assert!(poll.is_pending());
+        let (_, poll) = poll_on_thread("second", task);
+        assert!(poll.is_ready());
+
+        let reports = REPORTS.lock();
+        let events: Vec<_> = reports
+            .iter()
+            .flat_map(|(thread, stream)| {
+                Reader::from_start(stream)
+                    .read_task_records()
+                    .unwrap()
+                    .into_iter()
+                    .map(move |record| (thread.clone(), record))
+            })
+            .filter(|(_, record)| record.task_id == task_id)
+            .map(|(thread, record)| (thread, record.event))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("first".to_owned(), TaskEvent::Enter),
+                ("first".to_owned(), TaskEvent::Exit),
+                ("second".to_owned(), TaskEvent::Enter),
+                ("second".to_owned(), TaskEvent::Exit),
+            ]
+        );
+    }
+}
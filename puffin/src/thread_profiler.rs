@@ -1,23 +1,33 @@
 use std::borrow::Cow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
-use crate::GlobalProfiler;
 use crate::NanoSecond;
 use crate::NsSource;
 
 use crate::fetch_add_scope_id;
+use crate::intake::ThreadIntake;
+use crate::FlowRecord;
+use crate::MarkerRecord;
+use crate::Profiler;
 use crate::ScopeDetails;
 use crate::ScopeId;
 use crate::StreamInfo;
 use crate::StreamInfoRef;
+use crate::TaskEvent;
+use crate::TaskRecord;
 
-/// Report a stream of profile data from a thread to the [`GlobalProfiler`] singleton.
+/// Report a stream of profile data from a thread to the [`crate::GlobalProfiler`] singleton.
 /// This is used for internal purposes only
+///
+/// The data is queued without locking the [`crate::GlobalProfiler`],
+/// and is collected by the next call to [`crate::Profiler::new_frame`] on the global profiler.
 pub fn internal_profile_reporter(
     info: ThreadInfo,
     scope_details: &[ScopeDetails],
     stream_scope_times: &StreamInfoRef<'_>,
 ) {
-    GlobalProfiler::lock().report(info, scope_details, stream_scope_times);
+    crate::intake::ThreadIntake::global().report(info, scope_details, stream_scope_times);
 }
 
 /// Collects profiling data for one thread
@@ -28,7 +38,17 @@ pub struct ThreadProfiler {
     depth: usize,
     now_ns: NsSource,
     reporter: ThreadReporter,
+    /// Set by [`Self::initialize_with`], in which case we report here instead of to [`Self::reporter`].
+    intake: Option<Arc<ThreadIntake>>,
     start_time_ns: Option<NanoSecond>,
+    /// See [`ThreadInfo::id`].
+    thread_id: u64,
+    /// Overrides the name of the thread, see [`crate::set_thread_role`].
+    role: Option<String>,
+    /// See [`Self::set_scope_listener`].
+    scope_listener: Option<ScopeListener>,
+    /// Ids of the open scopes, only tracked while there is a [`Self::scope_listener`].
+    open_scope_ids: Vec<ScopeId>,
 }
 
 impl Default for ThreadProfiler {
@@ -39,7 +59,12 @@ impl Default for ThreadProfiler {
             depth: 0,
             now_ns: crate::now_ns,
             reporter: internal_profile_reporter,
+            intake: None,
             start_time_ns: None,
+            thread_id: ThreadInfo::next_thread_id(),
+            role: None,
+            scope_listener: None,
+            open_scope_ids: Vec::new(),
         }
     }
 }
@@ -56,9 +81,47 @@ impl ThreadProfiler {
         ThreadProfiler::call(|tp| {
             tp.now_ns = now_ns;
             tp.reporter = reporter;
+            tp.intake = None;
         });
     }
 
+    /// Report the scopes of this thread to `profiler` instead of the [`crate::GlobalProfiler`].
+    ///
+    /// Like with the global profiler, the scopes are queued without locking `profiler`,
+    /// and collected by the next call to [`Profiler::new_frame`] on it.
+    /// Call [`Self::initialize`] with [`internal_profile_reporter`] to go back to the global profiler.
+    pub fn initialize_with(profiler: &Profiler) {
+        let intake = profiler.intake.clone();
+        ThreadProfiler::call(|tp| tp.intake = Some(intake));
+    }
+
+    /// How many scopes are currently open on this thread, see [`crate::current_depth`].
+    #[inline]
+    pub fn current_depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Report the thread under this name instead of the name of the OS thread.
+    ///
+    /// `None` goes back to using the name of the OS thread.
+    /// See [`crate::set_thread_role`].
+    pub fn set_role(&mut self, role: Option<String>) {
+        self.role = role;
+    }
+
+    /// Call `listener` every time a scope begins or ends on this thread,
+    /// with the time of the event.
+    ///
+    /// The listener runs on the profiled thread, in the middle of [`Self::begin_scope`]/[`Self::end_scope`],
+    /// so it must be fast and non-blocking, and must not open any puffin scopes itself.
+    /// Scopes that are already open when the listener is set are not reported when they end.
+    ///
+    /// `None` removes the listener.
+    pub fn set_scope_listener(&mut self, listener: Option<ScopeListener>) {
+        self.scope_listener = listener;
+        self.open_scope_ids.clear();
+    }
+
     /// Register a function scope.
     #[must_use]
     pub fn register_function_scope(
@@ -67,14 +130,30 @@ impl ThreadProfiler {
         file_path: impl Into<Cow<'static, str>>,
         line_nr: u32,
     ) -> ScopeId {
-        let new_id = fetch_add_scope_id();
-        self.scope_details.push(
-            ScopeDetails::from_scope_id(new_id)
+        self.register_scope(
+            ScopeDetails::default()
                 .with_function_name(function_name)
                 .with_file(file_path)
                 .with_line_nr(line_nr),
-        );
-        new_id
+        )
+    }
+
+    /// Register a function scope in a category, e.g. "Physics".
+    #[must_use]
+    pub fn register_function_scope_in_category(
+        &mut self,
+        category: impl Into<Cow<'static, str>>,
+        function_name: impl Into<Cow<'static, str>>,
+        file_path: impl Into<Cow<'static, str>>,
+        line_nr: u32,
+    ) -> ScopeId {
+        self.register_scope(
+            ScopeDetails::default()
+                .with_category(category)
+                .with_function_name(function_name)
+                .with_file(file_path)
+                .with_line_nr(line_nr),
+        )
     }
 
     /// Register a named scope.
@@ -86,14 +165,42 @@ impl ThreadProfiler {
         file_path: impl Into<Cow<'static, str>>,
         line_nr: u32,
     ) -> ScopeId {
-        let new_id = fetch_add_scope_id();
-        self.scope_details.push(
-            ScopeDetails::from_scope_id(new_id)
+        self.register_scope(
+            ScopeDetails::default()
                 .with_scope_name(scope_name)
                 .with_function_name(function_name)
                 .with_file(file_path)
                 .with_line_nr(line_nr),
-        );
+        )
+    }
+
+    /// Register a named scope in a category, e.g. "Physics".
+    #[must_use]
+    pub fn register_named_scope_in_category(
+        &mut self,
+        scope_name: impl Into<Cow<'static, str>>,
+        category: impl Into<Cow<'static, str>>,
+        function_name: impl Into<Cow<'static, str>>,
+        file_path: impl Into<Cow<'static, str>>,
+        line_nr: u32,
+    ) -> ScopeId {
+        self.register_scope(
+            ScopeDetails::default()
+                .with_scope_name(scope_name)
+                .with_category(category)
+                .with_function_name(function_name)
+                .with_file(file_path)
+                .with_line_nr(line_nr),
+        )
+    }
+
+    /// Give the scope a new id, unless it is rejected by [`crate::set_scope_filter`].
+    fn register_scope(&mut self, scope_details: ScopeDetails) -> ScopeId {
+        if !crate::passes_scope_filter(&scope_details) {
+            return ScopeId::DISABLED;
+        }
+        let new_id = fetch_add_scope_id();
+        self.scope_details.push(scope_details.with_scope_id(new_id));
         new_id
     }
 
@@ -101,23 +208,106 @@ impl ThreadProfiler {
     /// Returns position where to write scope size once the scope is closed.
     #[must_use]
     pub fn begin_scope(&mut self, scope_id: ScopeId, data: &str) -> usize {
+        self.begin_scope_timed(scope_id, data, &[]).0
+    }
+
+    /// Like [`Self::begin_scope`], but with key-value tags, see [`crate::profile_scope_tags`].
+    #[must_use]
+    pub fn begin_scope_with_tags(
+        &mut self,
+        scope_id: ScopeId,
+        data: &str,
+        tags: &[(&str, &str)],
+    ) -> usize {
+        self.begin_scope_timed(scope_id, data, tags).0
+    }
+
+    /// Like [`Self::begin_scope_with_tags`], but also returns the start time of the scope.
+    fn begin_scope_timed(
+        &mut self,
+        scope_id: ScopeId,
+        data: &str,
+        tags: &[(&str, &str)],
+    ) -> (usize, NanoSecond) {
         self.depth += 1;
 
-        let (offset, start_ns) = self
-            .stream_info
-            .stream
-            .begin_scope(self.now_ns, scope_id, data);
+        let (offset, start_ns) =
+            self.stream_info
+                .stream
+                .begin_scope_with_tags(self.now_ns, scope_id, data, tags);
 
         self.stream_info.range_ns.0 = self.stream_info.range_ns.0.min(start_ns);
         self.start_time_ns = Some(self.start_time_ns.unwrap_or(start_ns));
 
-        offset
+        if let Some(listener) = &mut self.scope_listener {
+            self.open_scope_ids.push(scope_id);
+            listener(scope_id, start_ns, ScopeEvent::Begin);
+        }
+
+        (offset, start_ns)
+    }
+
+    /// Marks the beginning of a scope that is discarded if it turns out to be too short,
+    /// see [`Self::end_scope_min_duration`].
+    #[must_use]
+    pub fn begin_discardable_scope(&mut self, scope_id: ScopeId, data: &str) -> DiscardableScope {
+        let stream_len = self.stream_info.stream.len();
+        let num_scopes = self.stream_info.num_scopes;
+        let depth = self.stream_info.depth;
+        let range_ns = self.stream_info.range_ns;
+        let (start_offset, start_ns) = self.begin_scope_timed(scope_id, data, &[]);
+        DiscardableScope {
+            start_offset,
+            start_ns,
+            stream_len,
+            num_scopes,
+            depth,
+            range_ns,
+        }
+    }
+
+    /// Ends a scope started with [`Self::begin_discardable_scope`].
+    ///
+    /// If the scope lasted less than `min_duration_ns` it is removed from the stream,
+    /// together with everything recorded within it, i.e. child scopes, counters and task events.
+    pub fn end_scope_min_duration(&mut self, scope: DiscardableScope, min_duration_ns: NanoSecond) {
+        let now_ns = (self.now_ns)();
+        if now_ns - scope.start_ns >= min_duration_ns {
+            self.end_scope_at(scope.start_offset, now_ns);
+            return;
+        }
+
+        self.stream_info.stream.truncate(scope.stream_len);
+        self.stream_info.num_scopes = scope.num_scopes;
+        self.stream_info.depth = scope.depth;
+        self.stream_info.range_ns = scope.range_ns;
+
+        if self.depth > 0 {
+            self.depth -= 1;
+        } else {
+            eprintln!("puffin ERROR: Mismatched scope begin/end calls");
+        }
+
+        if let Some(listener) = &mut self.scope_listener {
+            if let Some(scope_id) = self.open_scope_ids.pop() {
+                listener(scope_id, now_ns, ScopeEvent::End);
+            }
+        }
+
+        // Any newly registered scopes are reported along with the next non-empty stream.
+        if self.depth == 0 && !self.stream_info.stream.is_empty() {
+            self.report();
+        }
     }
 
     /// Marks the end of the scope.
     /// Returns the current depth.
     pub fn end_scope(&mut self, start_offset: usize) {
         let now_ns = (self.now_ns)();
+        self.end_scope_at(start_offset, now_ns);
+    }
+
+    fn end_scope_at(&mut self, start_offset: usize, now_ns: NanoSecond) {
         self.stream_info.depth = self.stream_info.depth.max(self.depth);
         self.stream_info.num_scopes += 1;
         self.stream_info.range_ns.1 = self.stream_info.range_ns.1.max(now_ns);
@@ -130,27 +320,154 @@ impl ThreadProfiler {
 
         self.stream_info.stream.end_scope(start_offset, now_ns);
 
+        if let Some(listener) = &mut self.scope_listener {
+            if let Some(scope_id) = self.open_scope_ids.pop() {
+                listener(scope_id, now_ns, ScopeEvent::End);
+            }
+        }
+
         if self.depth == 0 {
             // We have no open scopes.
             // This is a good time to report our profiling stream to the global profiler:
-            let info = ThreadInfo {
-                start_time_ns: self.start_time_ns,
-                name: std::thread::current().name().unwrap_or_default().to_owned(),
-            };
-            (self.reporter)(
-                info,
-                &self.scope_details,
-                &self.stream_info.as_stream_into_ref(),
-            );
+            self.report();
+        }
+    }
+
+    /// Records a sample of a counter, see [`crate::profile_counter`].
+    ///
+    /// The counter is registered like a named scope, e.g. with [`Self::register_named_scope`].
+    pub fn record_counter(&mut self, counter_id: ScopeId, value: f64) {
+        if counter_id == ScopeId::DISABLED {
+            return;
+        }
+        let now_ns = (self.now_ns)();
+        self.stream_info
+            .stream
+            .record_counter(counter_id, now_ns, value);
+        self.after_instant_record(now_ns);
+    }
+
+    /// Records an async task starting or stopping to run on this thread, see [`crate::profile_task`].
+    ///
+    /// The task name is registered like a named scope, e.g. with [`Self::register_named_scope`].
+    pub fn record_task_event(&mut self, task_name_id: ScopeId, task_id: u64, event: TaskEvent) {
+        let now_ns = (self.now_ns)();
+        self.stream_info.stream.record_task_event(TaskRecord {
+            id: task_name_id,
+            task_id,
+            time_ns: now_ns,
+            event,
+        });
+        self.after_instant_record(now_ns);
+    }
+
+    /// Records a point of the flow `flow_id` in the current scope, see [`crate::profile_flow`].
+    pub fn record_flow(&mut self, flow_id: u64) {
+        let now_ns = (self.now_ns)();
+        self.stream_info.stream.record_flow(FlowRecord {
+            flow_id,
+            time_ns: now_ns,
+            depth: self.depth,
+        });
+        self.after_instant_record(now_ns);
+    }
+
+    /// Records an instantaneous event, see [`crate::profile_marker`].
+    ///
+    /// The marker is registered like a named scope, e.g. with [`Self::register_named_scope`].
+    pub fn record_marker(&mut self, marker_id: ScopeId) {
+        if marker_id == ScopeId::DISABLED {
+            return;
+        }
+        let now_ns = (self.now_ns)();
+        self.stream_info.stream.record_marker(MarkerRecord {
+            id: marker_id,
+            time_ns: now_ns,
+        });
+        self.after_instant_record(now_ns);
+    }
+
+    /// Reports a scope with times measured outside of puffin, e.g. with GPU timestamp queries,
+    /// on the pseudo-thread `thread_name` instead of this thread.
+    ///
+    /// Like the scopes of this thread, it is queued without locking the profiler
+    /// and ends up in the frame that is current when it is collected.
+    /// The times should be in the same time base as the scopes of the other threads, e.g. [`crate::now_ns`].
+    ///
+    /// The scope is a top-level scope of the pseudo-thread,
+    /// so its scopes must be reported in chronological order, without overlapping, from a single thread.
+    /// Build a [`crate::Stream`] and use [`Profiler::report_user_scopes`] for nested scopes.
+    ///
+    /// See also [`Profiler::report_external_scope`].
+    pub fn report_external_scope(
+        &mut self,
+        thread_name: &str,
+        scope_id: ScopeId,
+        start_ns: NanoSecond,
+        stop_ns: NanoSecond,
+        data: &str,
+    ) {
+        let info = ThreadInfo::new(None, thread_name);
+        let stream_info = StreamInfo::from_scope(scope_id, start_ns, stop_ns, data);
+        self.send(info, &stream_info.as_stream_into_ref());
+    }
+
+    /// Bookkeeping after recording something that is not a scope, at `now_ns`.
+    fn after_instant_record(&mut self, now_ns: NanoSecond) {
+        self.stream_info.range_ns.0 = self.stream_info.range_ns.0.min(now_ns);
+        self.stream_info.range_ns.1 = self.stream_info.range_ns.1.max(now_ns);
+        self.start_time_ns = Some(self.start_time_ns.unwrap_or(now_ns));
 
-            self.scope_details.clear();
-            self.stream_info.clear();
+        if self.depth == 0 {
+            // Outside of any scope there is nothing to wait for:
+            self.report();
+        }
+    }
+
+    fn report(&mut self) {
+        let name = match &self.role {
+            Some(role) => role.clone(),
+            None => std::thread::current().name().unwrap_or_default().to_owned(),
+        };
+        let info = ThreadInfo {
+            start_time_ns: self.start_time_ns,
+            name,
+            id: self.thread_id,
+        };
+        let stream_info = std::mem::take(&mut self.stream_info);
+        self.send(info, &stream_info.as_stream_into_ref());
+        self.stream_info = stream_info;
+        self.stream_info.clear();
+    }
+
+    /// Reports a stream along with any newly registered scopes.
+    fn send(&mut self, info: ThreadInfo, stream_info: &StreamInfoRef<'_>) {
+        if let Some(intake) = &self.intake {
+            intake.report(info, &self.scope_details, stream_info);
+        } else {
+            (self.reporter)(info, &self.scope_details, stream_info);
         }
+        self.scope_details.clear();
+    }
+
+    /// A handle for reporting scopes on a thread that is not an OS thread,
+    /// e.g. a GPU queue, an async executor or a network connection.
+    ///
+    /// The handle reports to the same profiler as this thread,
+    /// and the thread gets the current time as [`ThreadInfo::start_time_ns`],
+    /// so it is sorted among the other threads by when it was created.
+    /// Create it once and keep it: each handle is a separate thread in the viewer.
+    pub fn for_named_thread(name: &str) -> ThreadProfilerHandle {
+        ThreadProfiler::call(|tp| ThreadProfilerHandle {
+            info: ThreadInfo::new(Some((tp.now_ns)()), name),
+            intake: tp.intake.clone(),
+            reporter: tp.reporter,
+        })
     }
 
     /// Do something with the thread local [`ThreadProfiler`]
     #[inline]
-    pub fn call<R>(f: impl Fn(&mut Self) -> R) -> R {
+    pub fn call<R>(f: impl FnOnce(&mut Self) -> R) -> R {
         thread_local! {
             pub static THREAD_PROFILER: std::cell::RefCell<ThreadProfiler> = Default::default();
         }
@@ -158,17 +475,458 @@ impl ThreadProfiler {
     }
 }
 
+/// Reports scopes with externally measured times on a synthetic thread,
+/// see [`ThreadProfiler::for_named_thread`].
+///
+/// The scope ids must first be registered with [`Profiler::register_user_scopes`].
+/// The handle can be sent to and shared between threads,
+/// but the scopes must be reported in chronological order to show up correctly.
+#[derive(Clone)]
+pub struct ThreadProfilerHandle {
+    info: ThreadInfo,
+    intake: Option<Arc<ThreadIntake>>,
+    reporter: ThreadReporter,
+}
+
+impl ThreadProfilerHandle {
+    /// The synthetic thread the scopes are reported under.
+    pub fn thread_info(&self) -> &ThreadInfo {
+        &self.info
+    }
+
+    /// Reports a top-level scope, e.g. measured with GPU timestamp queries.
+    ///
+    /// The times should be in the same time base as the scopes of the other threads, e.g. [`crate::now_ns`],
+    /// and must not overlap with the other scopes of this thread.
+    pub fn report_scope(
+        &self,
+        scope_id: ScopeId,
+        start_ns: NanoSecond,
+        stop_ns: NanoSecond,
+        data: &str,
+    ) {
+        let stream_info = StreamInfo::from_scope(scope_id, start_ns, stop_ns, data);
+        self.report_stream(&stream_info.as_stream_into_ref());
+    }
+
+    /// Reports a stream of scopes, e.g. nested scopes built with [`crate::Stream::begin_scope`].
+    pub fn report_stream(&self, stream_info: &StreamInfoRef<'_>) {
+        if let Some(intake) = &self.intake {
+            intake.report(self.info.clone(), &[], stream_info);
+        } else {
+            (self.reporter)(self.info.clone(), &[], stream_info);
+        }
+    }
+}
+
+/// A scope started with [`ThreadProfiler::begin_discardable_scope`],
+/// remembering what to restore if it is discarded.
+#[derive(Clone, Copy)]
+pub struct DiscardableScope {
+    start_offset: usize,
+    start_ns: NanoSecond,
+    stream_len: usize,
+    num_scopes: usize,
+    depth: usize,
+    range_ns: (NanoSecond, NanoSecond),
+}
+
 /// Used to identify one source of profiling data.
-#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+///
+/// Threads are ordered by [`Self::id`], so that e.g. a pool of threads that are all
+/// called "worker" stay distinct.
+///
+/// Create one with [`Self::new`].
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[non_exhaustive]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(from = "SerdeThreadInfo", into = "SerdeThreadInfo")
+)]
 pub struct ThreadInfo {
     /// Useful for ordering threads.
     pub start_time_ns: Option<NanoSecond>,
     /// Name of the thread
     pub name: String,
+    /// Stays the same for the lifetime of the thread, even if it changes name.
+    ///
+    /// The threads profiled with a [`ThreadProfiler`] are numbered in the order they started profiling,
+    /// so e.g. the main thread usually comes first. Otherwise this is usually [`Self::fallback_id`].
+    pub id: u64,
 }
 
+impl ThreadInfo {
+    /// A thread with the [`Self::fallback_id`] of its start time and name.
+    pub fn new(start_time_ns: Option<NanoSecond>, name: impl Into<String>) -> Self {
+        let name = name.into();
+        Self {
+            id: Self::fallback_id(start_time_ns, &name),
+            start_time_ns,
+            name,
+        }
+    }
+
+    /// Set [`Self::id`].
+    #[must_use]
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// The id of a thread we know nothing more about than its start time and name,
+    /// e.g. a pseudo-thread, or a thread recorded before [`Self::id`] was added.
+    pub fn fallback_id(start_time_ns: Option<NanoSecond>, name: &str) -> u64 {
+        use std::hash::{Hash as _, Hasher as _};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        start_time_ns.hash(&mut hasher);
+        name.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The id of a new [`ThreadProfiler`].
+    fn next_thread_id() -> u64 {
+        static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+        NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl PartialOrd for ThreadInfo {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ThreadInfo {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.id
+            .cmp(&other.id)
+            .then_with(|| self.start_time_ns.cmp(&other.start_time_ns))
+            .then_with(|| self.name.cmp(&other.name))
+    }
+}
+
+/// How a [`ThreadInfo`] is serialized.
+///
+/// The id is optional, so data from before [`ThreadInfo::id`] was added can still be read
+/// from self-describing formats.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize, serde::Serialize)]
+struct SerdeThreadInfo {
+    start_time_ns: Option<NanoSecond>,
+    name: String,
+    #[serde(default)]
+    id: Option<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl From<SerdeThreadInfo> for ThreadInfo {
+    fn from(info: SerdeThreadInfo) -> Self {
+        let SerdeThreadInfo {
+            start_time_ns,
+            name,
+            id,
+        } = info;
+        match id {
+            Some(id) => Self {
+                start_time_ns,
+                name,
+                id,
+            },
+            None => Self::new(start_time_ns, name),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<ThreadInfo> for SerdeThreadInfo {
+    fn from(info: ThreadInfo) -> Self {
+        Self {
+            start_time_ns: info.start_time_ns,
+            name: info.name,
+            id: Some(info.id),
+        }
+    }
+}
+
+/// Whether a scope began or ended, see [`ThreadProfiler::set_scope_listener`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScopeEvent {
+    /// The scope was opened.
+    Begin,
+
+    /// The scope was closed.
+    End,
+}
+
+/// Called on every scope begin and end, see [`ThreadProfiler::set_scope_listener`].
+pub type ScopeListener = Box<dyn FnMut(ScopeId, NanoSecond, ScopeEvent)>;
+
 // Function interface for reporting thread local scope details.
 // The scope details array will contain information about a scope the first time it is seen.
 // The stream will always contain the scope timing details.
 type ThreadReporter = fn(ThreadInfo, &[ScopeDetails], &StreamInfoRef<'_>);
+
+#[cfg(test)]
+mod tests {
+    use super::{ScopeEvent, ThreadInfo, ThreadProfiler};
+    use crate::{
+        MinDurationScope, NanoSecond, ProfilerScope, ScopeDetails, ScopeId, StreamInfoRef,
+    };
+
+    #[test]
+    fn thread_role() {
+        static NAMES: parking_lot::Mutex<Vec<String>> = parking_lot::const_mutex(Vec::new());
+        fn reporter(info: ThreadInfo, _: &[ScopeDetails], _: &StreamInfoRef<'_>) {
+            NAMES.lock().push(info.name);
+        }
+
+        let report_scope = || {
+            ThreadProfiler::call(|tp| {
+                let offset = tp.begin_scope(ScopeId::new(1), "");
+                tp.end_scope(offset);
+            });
+        };
+
+        std::thread::Builder::new()
+            .name("worker".to_owned())
+            .spawn(move || {
+                ThreadProfiler::initialize(crate::now_ns, reporter);
+                report_scope();
+                crate::set_thread_role("render");
+                report_scope();
+                crate::set_thread_role("");
+                report_scope();
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(*NAMES.lock(), ["worker", "render", "worker"]);
+    }
+
+    #[test]
+    fn same_named_threads() {
+        static INFOS: parking_lot::Mutex<Vec<ThreadInfo>> = parking_lot::const_mutex(Vec::new());
+        fn reporter(info: ThreadInfo, _: &[ScopeDetails], _: &StreamInfoRef<'_>) {
+            INFOS.lock().push(info);
+        }
+
+        for _ in 0..2 {
+            std::thread::Builder::new()
+                .name("worker".to_owned())
+                .spawn(|| {
+                    ThreadProfiler::initialize(|| 0, reporter);
+                    for role in ["", "render"] {
+                        crate::set_thread_role(role);
+                        ThreadProfiler::call(|tp| {
+                            let offset = tp.begin_scope(ScopeId::new(1), "");
+                            tp.end_scope(offset);
+                        });
+                    }
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+        }
+
+        // Same name and start time, but still two threads, which keep their ids when renamed:
+        let infos = INFOS.lock();
+        assert_eq!(infos.len(), 4);
+        assert_eq!(infos[0].name, "worker");
+        assert_eq!(infos[1].name, "render");
+        assert_eq!(infos[0].id, infos[1].id);
+        assert_eq!(infos[2].id, infos[3].id);
+        assert_ne!(infos[0], infos[2]);
+        // …and are ordered by when they started profiling:
+        assert!(infos[0] < infos[2]);
+        assert_ne!(infos[0].id, ThreadInfo::new(Some(0), "worker").id);
+    }
+
+    #[test]
+    fn named_thread() {
+        let mut profiler = crate::Profiler::default();
+        ThreadProfiler::initialize_with(&profiler);
+        let gpu = ThreadProfiler::for_named_thread("GPU");
+        assert!(gpu.thread_info().start_time_ns.is_some());
+
+        std::thread::Builder::new()
+            .spawn({
+                let gpu = gpu.clone();
+                move || gpu.report_scope(ScopeId::new(1), 100, 200, "")
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        gpu.report_scope(ScopeId::new(1), 300, 400, "");
+
+        let frame = profiler.new_frame_and_take().unwrap();
+        let unpacked = frame.unpacked().unwrap();
+        let threads: Vec<_> = unpacked.thread_streams.keys().collect();
+        assert_eq!(threads, [gpu.thread_info()]);
+        assert_eq!(frame.meta().num_scopes, 2);
+        assert_eq!(frame.range_ns(), (100, 400));
+    }
+
+    #[test]
+    fn scope_listener() {
+        let events = std::thread::Builder::new()
+            .name("listened".to_owned())
+            .spawn(|| {
+                let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+                let listener_events = events.clone();
+                // Don't report to the global profiler, and use fixed ids instead of registering
+                // new scopes, so we don't interfere with other tests:
+                ThreadProfiler::initialize(crate::now_ns, |_, _, _| {});
+                ThreadProfiler::call(|tp| {
+                    tp.set_scope_listener(Some(Box::new(move |scope_id, ns, event| {
+                        listener_events.borrow_mut().push((scope_id, ns, event));
+                    })));
+                });
+
+                {
+                    let _outer = ProfilerScope::new(ScopeId::new(1), "");
+                    {
+                        let _inner = ProfilerScope::new(ScopeId::new(2), "");
+                    }
+                }
+
+                ThreadProfiler::call(|tp| tp.set_scope_listener(None));
+                {
+                    let _ignored = ProfilerScope::new(ScopeId::new(3), "");
+                }
+
+                events.take()
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let kinds: Vec<_> = events.iter().map(|&(_, _, event)| event).collect();
+        assert_eq!(
+            kinds,
+            [
+                ScopeEvent::Begin,
+                ScopeEvent::Begin,
+                ScopeEvent::End,
+                ScopeEvent::End
+            ]
+        );
+        let ids: Vec<_> = events.iter().map(|&(id, _, _)| id).collect();
+        assert_eq!(
+            ids,
+            [1, 2, 2, 1].map(ScopeId::new),
+            "Expected the inner scope to end before the outer scope"
+        );
+        assert!(
+            events.windows(2).all(|w| w[0].1 <= w[1].1),
+            "Expected the event times to be in order"
+        );
+    }
+
+    #[test]
+    fn min_duration_scope() {
+        thread_local! {
+            static NOW_NS: std::cell::Cell<NanoSecond> = const { std::cell::Cell::new(0) };
+        }
+        fn now_ns() -> NanoSecond {
+            NOW_NS.with(|now_ns| now_ns.get())
+        }
+        fn set_now_ns(ns: NanoSecond) {
+            NOW_NS.with(|now_ns| now_ns.set(ns));
+        }
+
+        static REPORTS: parking_lot::Mutex<Vec<(usize, (NanoSecond, NanoSecond))>> =
+            parking_lot::const_mutex(Vec::new());
+        fn reporter(_: ThreadInfo, _: &[ScopeDetails], stream_info: &StreamInfoRef<'_>) {
+            REPORTS
+                .lock()
+                .push((stream_info.num_scopes, stream_info.range_ns));
+        }
+
+        std::thread::Builder::new()
+            .name("min_duration".to_owned())
+            .spawn(|| {
+                ThreadProfiler::initialize(now_ns, reporter);
+
+                // Too short, so it is discarded along with its child:
+                let scope = MinDurationScope::new(ScopeId::new(1), 100, "");
+                set_now_ns(10);
+                let child = ProfilerScope::new(ScopeId::new(2), "");
+                set_now_ns(20);
+                drop(child);
+                set_now_ns(50);
+                drop(scope);
+
+                // Long enough:
+                set_now_ns(100);
+                let scope = MinDurationScope::new(ScopeId::new(1), 100, "");
+                set_now_ns(300);
+                drop(scope);
+
+                // A short scope within a kept one:
+                set_now_ns(400);
+                let outer = ProfilerScope::new(ScopeId::new(2), "");
+                set_now_ns(410);
+                let scope = MinDurationScope::new(ScopeId::new(1), 100, "");
+                set_now_ns(420);
+                drop(scope);
+                set_now_ns(500);
+                drop(outer);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(*REPORTS.lock(), [(1, (100, 300)), (1, (400, 500))]);
+    }
+
+    #[test]
+    fn current_depth() {
+        let depths = std::thread::Builder::new()
+            .name("depth".to_owned())
+            .spawn(|| {
+                ThreadProfiler::initialize(crate::now_ns, |_, _, _| {});
+                let depth = || ThreadProfiler::call(|tp| tp.current_depth());
+
+                let mut depths = vec![depth()];
+                {
+                    let _outer = ProfilerScope::new(ScopeId::new(1), "");
+                    depths.push(depth());
+                    {
+                        let _inner = ProfilerScope::new(ScopeId::new(2), "");
+                        depths.push(depth());
+                    }
+                    depths.push(depth());
+                }
+                depths.push(depth());
+                depths
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(depths, [0, 1, 2, 1, 0]);
+    }
+
+    #[test]
+    fn initialize_with_profiler() {
+        let mut profiler = crate::Profiler::default();
+        let frames = std::sync::Arc::new(parking_lot::Mutex::new(vec![]));
+        let sink_frames = frames.clone();
+        profiler.add_sink(Box::new(move |frame| sink_frames.lock().push(frame)));
+
+        let profiler = parking_lot::Mutex::new(profiler);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                ThreadProfiler::initialize_with(&profiler.lock());
+                let _scope = ProfilerScope::new(ScopeId::new(1), "");
+            });
+        });
+        profiler.lock().new_frame();
+
+        let frames = frames.lock();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].meta().num_scopes, 1);
+    }
+}
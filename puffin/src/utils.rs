@@ -15,6 +15,16 @@ pub fn clean_function_name(name: &str) -> String {
     shorten_rust_function_name(name.trim_end_matches(USELESS_CLOSURE_SUFFIX))
 }
 
+/// Like [`clean_function_name`], but keeps the full module path (for `profile_function!(@full)`).
+#[doc(hidden)]
+#[inline(never)]
+pub fn full_function_name(name: &str) -> String {
+    let Some(name) = name.strip_suffix(USELESS_SCOPE_NAME_SUFFIX) else {
+        return name.to_owned();
+    };
+    name.trim_end_matches(USELESS_CLOSURE_SUFFIX).to_owned()
+}
+
 /// Shorten a rust function name by removing the leading parts of module paths.
 ///
 /// While the puffin profiling macros takes care of this internally, this function can be
@@ -192,3 +202,15 @@ fn test_clean_function_name() {
         "<ConcreteType as Trait>::function_name"
     );
 }
+
+#[test]
+fn test_full_function_name() {
+    assert_eq!(full_function_name("foo"), "foo");
+    assert_eq!(
+        full_function_name(&format!(
+            "foo::bar::baz{}{}",
+            USELESS_CLOSURE_SUFFIX, USELESS_SCOPE_NAME_SUFFIX
+        )),
+        "foo::bar::baz"
+    );
+}
@@ -0,0 +1,245 @@
+//! Compare the scopes of two sets of frames, e.g. to find out why one frame is slower than another.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use egui::Color32;
+use puffin::*;
+
+use crate::stats::Stats;
+
+const REGRESSION_COLOR: Color32 = Color32::from_rgb(255, 90, 90);
+const IMPROVEMENT_COLOR: Color32 = Color32::from_rgb(90, 200, 90);
+
+#[derive(Clone, Default)]
+pub struct Options {
+    /// The frames to compare the selected frames against.
+    baseline: Option<Vec<Arc<UnpackedFrameData>>>,
+}
+
+/// How the self time of one scope differs between two sets of frames.
+#[derive(Clone, Debug)]
+pub struct ScopeDiff {
+    pub scope_details: Arc<ScopeDetails>,
+
+    /// Mean self time per frame in the first set of frames, zero if the scope isn't in there.
+    pub a_ns: NanoSecond,
+
+    /// Mean self time per frame in the second set of frames, zero if the scope isn't in there.
+    pub b_ns: NanoSecond,
+}
+
+impl ScopeDiff {
+    /// How much slower the scope is in `b` than in `a`. Negative if it got faster.
+    pub fn delta_ns(&self) -> NanoSecond {
+        self.b_ns - self.a_ns
+    }
+}
+
+/// Compare the self time of each scope in the frames of `a` with that in the frames of `b`,
+/// one entry per scope, sorted with the largest regression first.
+///
+/// The times are averaged per frame, so you can compare e.g. a single frame with a range of frames.
+/// Scopes missing from `scope_collection` are skipped.
+pub fn diff_frames(
+    scope_collection: &ScopeCollection,
+    a: &[Arc<UnpackedFrameData>],
+    b: &[Arc<UnpackedFrameData>],
+) -> Vec<ScopeDiff> {
+    let mean_self_ns = |frames: &[Arc<UnpackedFrameData>]| -> HashMap<ScopeId, NanoSecond> {
+        let num_frames = frames.len().max(1) as NanoSecond;
+        Stats::collect(frames)
+            .scopes
+            .into_iter()
+            .map(|(key, stats)| (key.id, stats.total_self_ns / num_frames))
+            .collect()
+    };
+    let a = mean_self_ns(a);
+    let b = mean_self_ns(b);
+
+    let scope_ids: BTreeSet<ScopeId> = a.keys().chain(b.keys()).copied().collect();
+    let mut diffs: Vec<_> = scope_ids
+        .into_iter()
+        .filter_map(|scope_id| {
+            Some(ScopeDiff {
+                scope_details: scope_collection.fetch_by_id(&scope_id)?.clone(),
+                a_ns: a.get(&scope_id).copied().unwrap_or_default(),
+                b_ns: b.get(&scope_id).copied().unwrap_or_default(),
+            })
+        })
+        .collect();
+    diffs.sort_by_key(|diff| std::cmp::Reverse(diff.delta_ns()));
+    diffs
+}
+
+pub fn ui(
+    ui: &mut egui::Ui,
+    options: &mut Options,
+    scope_collection: &ScopeCollection,
+    frames: &[Arc<UnpackedFrameData>],
+) {
+    ui.horizontal(|ui| {
+        if ui
+            .button("Set as A")
+            .on_hover_text("Compare other frames against the selected frames")
+            .clicked()
+        {
+            options.baseline = Some(frames.to_vec());
+        }
+        if options.baseline.is_some() && ui.button("Clear A").clicked() {
+            options.baseline = None;
+        }
+    });
+
+    let Some(baseline) = &options.baseline else {
+        ui.label(
+            "Select a frame (or drag to select several) and click \"Set as A\".\n\
+            Then select the frames to compare with it.",
+        );
+        return;
+    };
+
+    ui.label(format!(
+        "Comparing A: {} with B: {} (the selection).\n\
+        Times are the mean self time per frame, with the largest regressions first.",
+        describe_frames(baseline),
+        describe_frames(frames),
+    ));
+
+    let diffs = diff_frames(scope_collection, baseline, frames);
+
+    egui::ScrollArea::horizontal().show(ui, |ui| {
+        ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
+        ui.spacing_mut().item_spacing.x = 16.0;
+
+        egui_extras::TableBuilder::new(ui)
+            .striped(true)
+            .columns(
+                egui_extras::Column::auto_with_initial_suggestion(200.0).resizable(true),
+                2,
+            )
+            .columns(egui_extras::Column::auto().resizable(false), 3)
+            .header(20.0, |mut header| {
+                for title in ["Scope", "Location", "A", "B", "Delta"] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|mut body| {
+                for diff in &diffs {
+                    body.row(14.0, |mut row| {
+                        row.col(|ui| {
+                            ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Truncate);
+                            ui.label(diff.scope_details.name().as_ref());
+                        });
+                        row.col(|ui| {
+                            ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Truncate);
+                            ui.label(diff.scope_details.location());
+                        });
+                        row.col(|ui| {
+                            ui.monospace(format!("{:>8.1} µs", diff.a_ns as f32 * 1e-3));
+                        });
+                        row.col(|ui| {
+                            ui.monospace(format!("{:>8.1} µs", diff.b_ns as f32 * 1e-3));
+                        });
+                        row.col(|ui| {
+                            let delta_ns = diff.delta_ns();
+                            let color = match delta_ns.cmp(&0) {
+                                std::cmp::Ordering::Greater => REGRESSION_COLOR,
+                                std::cmp::Ordering::Less => IMPROVEMENT_COLOR,
+                                std::cmp::Ordering::Equal => ui.visuals().text_color(),
+                            };
+                            ui.label(
+                                egui::RichText::new(format!("{:>+8.1} µs", delta_ns as f32 * 1e-3))
+                                    .monospace()
+                                    .color(color),
+                            );
+                        });
+                    });
+                }
+            });
+    });
+}
+
+/// E.g. "frame #12" or "3 frames (#12 - #14)".
+fn describe_frames(frames: &[Arc<UnpackedFrameData>]) -> String {
+    match frames {
+        [] => "no frames".to_owned(),
+        [frame] => format!("frame #{}", frame.frame_index()),
+        [first, .., last] => format!(
+            "{} frames (#{} - #{})",
+            frames.len(),
+            first.frame_index(),
+            last.frame_index()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use puffin::{FrameView, Profiler, ScopeDetails, Stream};
+
+    use super::diff_frames;
+
+    #[test]
+    fn diff_sorts_regressions_first() {
+        let frame_view = Arc::new(parking_lot::Mutex::new(FrameView::default()));
+        let mut profiler = Profiler::default();
+        profiler.add_sink(Box::new({
+            let frame_view = frame_view.clone();
+            move |frame| frame_view.lock().add_frame(frame)
+        }));
+
+        let scope_ids = profiler.register_user_scopes(&[
+            ScopeDetails::from_scope_name("update"),
+            ScopeDetails::from_scope_name("draw"),
+            ScopeDetails::from_scope_name("load"),
+            ScopeDetails::from_scope_name("save"),
+        ]);
+        let [update, draw, load, save] = scope_ids[..] else {
+            panic!("Expected four scope ids");
+        };
+
+        // Frame A saves, and frame B loads instead, and draws a lot slower:
+        for scopes in [
+            [(update, 100), (draw, 200), (save, 50)],
+            [(update, 100), (draw, 500), (load, 30)],
+        ] {
+            let mut stream = Stream::default();
+            let mut start_ns = 0;
+            for (scope_id, duration_ns) in scopes {
+                let (offset, _) = stream.begin_scope(|| start_ns, scope_id, "");
+                stream.end_scope(offset, start_ns + duration_ns);
+                start_ns += duration_ns;
+            }
+            let (thread_info, stream_info) = crate::test_thread_stream("main", stream);
+            profiler.report_user_scopes(thread_info, &stream_info.as_stream_into_ref());
+            profiler.new_frame();
+        }
+
+        let frame_view = frame_view.lock();
+        let frames: Vec<_> = frame_view
+            .recent_frames()
+            .map(|frame| frame.unpacked().unwrap())
+            .collect();
+        let diffs = diff_frames(frame_view.scope_collection(), &frames[..1], &frames[1..]);
+        let diffs: Vec<_> = diffs
+            .iter()
+            .map(|diff| (diff.scope_details.name().to_string(), diff.a_ns, diff.b_ns))
+            .collect();
+        assert_eq!(
+            diffs,
+            [
+                ("draw".to_owned(), 200, 500),
+                ("load".to_owned(), 0, 30),
+                ("update".to_owned(), 100, 100),
+                ("save".to_owned(), 50, 0),
+            ]
+        );
+    }
+}
@@ -4,17 +4,31 @@ pub struct Filter {
 }
 
 impl Filter {
-    pub fn ui(&mut self, ui: &mut egui::Ui) {
+    /// Returns `true` if the user pressed Enter in the text field.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
         ui.horizontal(|ui| {
             ui.spacing_mut().item_spacing.x = 4.0;
 
-            ui.add(egui::TextEdit::singleline(&mut self.filter).hint_text("Scope filter"));
+            let response =
+                ui.add(egui::TextEdit::singleline(&mut self.filter).hint_text("Scope filter"));
             self.filter = self.filter.to_lowercase();
 
             if ui.button("ｘ").clicked() {
                 self.filter.clear();
             }
-        });
+
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if submitted {
+                // Keep focus, so that Enter can be pressed again:
+                response.request_focus();
+            }
+            submitted
+        })
+        .inner
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.filter
     }
 
     /// if true, show everything
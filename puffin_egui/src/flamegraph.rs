@@ -1,11 +1,23 @@
 use std::vec;
 
-use super::{SelectedFrames, ERROR_COLOR, HOVER_COLOR};
+use super::{is_nanos_since_epoch, SelectedFrames, ERROR_COLOR, HOVER_COLOR};
 use crate::filter::Filter;
 use egui::*;
 use indexmap::IndexMap;
 use puffin::*;
 
+/// Color of the counter line graphs.
+const COUNTER_COLOR: Color32 = Color32::from_rgb(80, 170, 255);
+
+/// Color of the polls of async tasks.
+const TASK_COLOR: Color32 = Color32::from_rgb(170, 120, 255);
+
+/// Color of the lines connecting the points of a flow.
+const FLOW_COLOR: Color32 = Color32::from_rgb(255, 200, 80);
+
+/// Color of the vertical lines of markers.
+const MARKER_COLOR: Color32 = Color32::from_rgb(120, 220, 120);
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum SortBy {
@@ -75,9 +87,13 @@ impl Sorting {
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct ThreadVisualizationSettings {
     flamegraph_collapse: bool,
     flamegraph_show: bool,
+
+    /// Shown instead of the name of the thread, if set.
+    alias: Option<String>,
 }
 
 impl Default for ThreadVisualizationSettings {
@@ -85,6 +101,276 @@ impl Default for ThreadVisualizationSettings {
         Self {
             flamegraph_collapse: false,
             flamegraph_show: true,
+            alias: None,
+        }
+    }
+}
+
+impl ThreadVisualizationSettings {
+    fn display_name<'a>(&'a self, thread: &'a ThreadInfo) -> &'a str {
+        self.alias.as_deref().unwrap_or(&thread.name)
+    }
+}
+
+/// A thread the user is currently giving an alias.
+#[derive(Clone, Debug)]
+struct RenameThread {
+    /// The original name of the thread.
+    name: String,
+    alias: String,
+    pos: Pos2,
+    /// The time range of the thread, relative to the start of the selected frames.
+    range_ns: Option<(NanoSecond, NanoSecond)>,
+}
+
+/// Whether to paint the profiler canvases light or dark.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum CanvasTheme {
+    /// Follow the light/dark mode of the surrounding egui style.
+    #[default]
+    FollowEgui,
+
+    /// Always use a dark canvas.
+    Dark,
+
+    /// Always use a light canvas.
+    Light,
+}
+
+impl CanvasTheme {
+    /// Switch the visuals of the `ui` to this theme, if needed.
+    pub(crate) fn apply(self, ui: &mut Ui) {
+        let dark_mode = match self {
+            Self::FollowEgui => return,
+            Self::Dark => true,
+            Self::Light => false,
+        };
+        if ui.visuals().dark_mode != dark_mode {
+            *ui.visuals_mut() = if dark_mode {
+                Visuals::dark()
+            } else {
+                Visuals::light()
+            };
+        }
+    }
+
+    /// Show a themed canvas.
+    pub(crate) fn canvas<R>(self, ui: &mut Ui, add_contents: impl FnOnce(&mut Ui) -> R) -> R {
+        ui.scope(|ui| {
+            self.apply(ui);
+            Frame::canvas(ui.style()).show(ui, add_contents).inner
+        })
+        .inner
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Theme:");
+            ui.selectable_value(self, Self::FollowEgui, "Follow egui");
+            ui.selectable_value(self, Self::Dark, "Dark");
+            ui.selectable_value(self, Self::Light, "Light");
+        });
+    }
+}
+
+/// Which way the flamegraph grows from the top-level scopes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum LayoutDirection {
+    /// Top-level scopes at the top, children below them ("icicle" graph).
+    #[default]
+    TopDown,
+
+    /// Top-level scopes at the bottom, children above them ("flame" graph).
+    BottomUp,
+}
+
+impl LayoutDirection {
+    /// The top of the row of the scopes at `depth`,
+    /// given the top of the row of the top-level scopes.
+    fn top_y(self, root_y: f32, depth: usize, row_height: f32) -> f32 {
+        match self {
+            Self::TopDown => root_y + depth as f32 * row_height,
+            Self::BottomUp => root_y - depth as f32 * row_height,
+        }
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Layout:");
+            ui.selectable_value(self, Self::TopDown, "Top-down")
+                .on_hover_text("Top-level scopes at the top (icicle graph)");
+            ui.selectable_value(self, Self::BottomUp, "Bottom-up")
+                .on_hover_text("Top-level scopes at the bottom (flame graph)");
+        });
+    }
+}
+
+/// A mouse button used for dragging on the flamegraph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum DragButton {
+    /// Any mouse button.
+    Any,
+    Primary,
+    Secondary,
+    Middle,
+}
+
+impl DragButton {
+    const ALL: [Self; 4] = [Self::Any, Self::Primary, Self::Secondary, Self::Middle];
+
+    fn is_dragging(self, response: &Response) -> bool {
+        match self {
+            Self::Any => response.dragged(),
+            Self::Primary => response.dragged_by(PointerButton::Primary),
+            Self::Secondary => response.dragged_by(PointerButton::Secondary),
+            Self::Middle => response.dragged_by(PointerButton::Middle),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Any => "any mouse button",
+            Self::Primary => "primary mouse button",
+            Self::Secondary => "secondary mouse button",
+            Self::Middle => "middle mouse button",
+        }
+    }
+}
+
+/// A modifier key that changes what a drag does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ModifierKey {
+    None,
+    Shift,
+    Alt,
+    /// Ctrl on Windows and Linux, Cmd on Mac.
+    Command,
+}
+
+impl ModifierKey {
+    const ALL: [Self; 4] = [Self::None, Self::Shift, Self::Alt, Self::Command];
+
+    fn is_down(self, modifiers: &Modifiers) -> bool {
+        match self {
+            Self::None => false,
+            Self::Shift => modifiers.shift,
+            Self::Alt => modifiers.alt,
+            Self::Command => modifiers.command,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Shift => "Shift",
+            Self::Alt => "Alt",
+            Self::Command => "Ctrl/cmd",
+        }
+    }
+}
+
+/// Which mouse buttons pan and zoom the flamegraph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct InputMapping {
+    /// Dragging with this button pans the view sideways.
+    pub pan_button: DragButton,
+
+    /// Dragging up and down with this button zooms.
+    pub zoom_button: DragButton,
+
+    /// Holding this while dragging with [`Self::pan_button`] zooms instead of panning.
+    pub zoom_modifier: ModifierKey,
+
+    /// Holding this while dragging with [`Self::pan_button`] selects a time range instead of panning.
+    ///
+    /// [`Self::zoom_modifier`] wins if they are the same.
+    pub select_modifier: ModifierKey,
+}
+
+impl Default for InputMapping {
+    fn default() -> Self {
+        Self {
+            pan_button: DragButton::Any,
+            zoom_button: DragButton::Secondary,
+            zoom_modifier: ModifierKey::None,
+            select_modifier: ModifierKey::Shift,
+        }
+    }
+}
+
+impl InputMapping {
+    fn help_text(&self) -> String {
+        let mut zoom = format!(
+            "Zoom: Ctrl/cmd + scroll, or drag with {}",
+            self.zoom_button.label()
+        );
+        if self.zoom_modifier != ModifierKey::None {
+            zoom += &format!(
+                ", or {} + drag with {}",
+                self.zoom_modifier.label(),
+                self.pan_button.label()
+            );
+        }
+        let mut text = format!("Drag with {} to pan.\n{zoom}.", self.pan_button.label());
+        if self.select_modifier != ModifierKey::None && self.select_modifier != self.zoom_modifier {
+            text += &format!(
+                "\n{} + drag with {} to select a time range.",
+                self.select_modifier.label(),
+                self.pan_button.label()
+            );
+        }
+        text
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Pan by dragging with");
+            ComboBox::from_id_source("pan_button")
+                .selected_text(self.pan_button.label())
+                .show_ui(ui, |ui| {
+                    for button in DragButton::ALL {
+                        ui.selectable_value(&mut self.pan_button, button, button.label());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Zoom by dragging with");
+            ComboBox::from_id_source("zoom_button")
+                .selected_text(self.zoom_button.label())
+                .show_ui(ui, |ui| {
+                    for button in DragButton::ALL {
+                        ui.selectable_value(&mut self.zoom_button, button, button.label());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Zoom with pan button while holding");
+            ComboBox::from_id_source("zoom_modifier")
+                .selected_text(self.zoom_modifier.label())
+                .show_ui(ui, |ui| {
+                    for key in ModifierKey::ALL {
+                        ui.selectable_value(&mut self.zoom_modifier, key, key.label());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Select a time range with pan button while holding");
+            ComboBox::from_id_source("select_modifier")
+                .selected_text(self.select_modifier.label())
+                .show_ui(ui, |ui| {
+                    for key in ModifierKey::ALL {
+                        ui.selectable_value(&mut self.select_modifier, key, key.label());
+                    }
+                });
+        });
+        if ui.button("Reset input mapping").clicked() {
+            *self = Default::default();
         }
     }
 }
@@ -101,12 +387,21 @@ pub struct Options {
     /// How much we have panned sideways:
     pub sideways_pan_in_points: f32,
 
+    /// Keep [`Self::canvas_width_ns`] and [`Self::sideways_pan_in_points`] when switching frames,
+    /// instead of zooming out to fit the newly selected frames.
+    ///
+    /// Useful for comparing the same part of several frames.
+    pub lock_time_scale: bool,
+
     // --------------------
     // Visuals:
     /// Events shorter than this many points aren't painted
     pub cull_width: f32,
     /// Draw each item with at least this width (only makes sense if [`Self::cull_width`] is 0)
     pub min_width: f32,
+    /// The children of scopes narrower than this many points are painted as a single block,
+    /// which saves a lot of CPU time for frames with very many scopes. Click the block to zoom in.
+    pub lod_width: f32,
 
     pub rect_height: f32,
     pub spacing: f32,
@@ -127,9 +422,49 @@ pub struct Options {
     /// Interval of vertical timeline indicators.
     grid_spacing_micros: f64,
 
+    /// Label the timeline with wall-clock time (UTC) instead of time since the start of the frame.
+    ///
+    /// Only used when the timestamps are nanoseconds since the Unix epoch.
+    grid_absolute_time: bool,
+
+    /// Light or dark canvas.
+    pub theme: CanvasTheme,
+
+    /// Grow the flamegraph down or up from the top-level scopes.
+    pub layout_direction: LayoutDirection,
+
+    /// Which mouse buttons pan and zoom.
+    pub input_mapping: InputMapping,
+
+    /// Show the number of scopes in the selected frames next to each thread name.
+    pub show_scope_counts: bool,
+
+    /// Color scopes with a category (e.g. from `puffin::profile_scope_cat!`) by their category,
+    /// instead of by their duration.
+    pub color_by_category: bool,
+
     #[cfg_attr(feature = "serde", serde(skip))]
     scope_name_filter: Filter,
 
+    /// The filter text and index of the match we last jumped to with Enter.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    filter_match: Option<(String, usize)>,
+
+    /// Scroll the thread into view on the next frame.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    scroll_to_thread: Option<ThreadInfo>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    rename_thread: Option<RenameThread>,
+
+    /// The last scope the user clicked, to show over time.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) selected_scope: Option<ScopeId>,
+
+    /// The time range the user selected, relative to the start of the selected frames.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    selected_range_ns: Option<(NanoSecond, NanoSecond)>,
+
     /// Set when user clicks a scope.
     /// First part is `now()`, second is range.
     #[cfg_attr(feature = "serde", serde(skip))]
@@ -141,10 +476,12 @@ impl Default for Options {
         Self {
             canvas_width_ns: 0.0,
             sideways_pan_in_points: 0.0,
+            lock_time_scale: false,
 
             // cull_width: 0.5, // save some CPU?
             cull_width: 0.0, // no culling
             min_width: 1.0,
+            lod_width: 2.0,
 
             rect_height: 16.0,
             spacing: 4.0,
@@ -156,9 +493,21 @@ impl Default for Options {
             merge_scopes: false, // off, because it really only works well for single-threaded profiling
 
             grid_spacing_micros: 1.,
+            grid_absolute_time: false,
+
+            theme: Default::default(),
+            layout_direction: Default::default(),
+            input_mapping: Default::default(),
+            show_scope_counts: true,
+            color_by_category: true,
 
             sorting: Default::default(),
             scope_name_filter: Default::default(),
+            filter_match: None,
+            scroll_to_thread: None,
+            rename_thread: None,
+            selected_scope: None,
+            selected_range_ns: None,
 
             zoom_to_relative_ns_range: None,
             flamegraph_threads: IndexMap::new(),
@@ -185,6 +534,10 @@ struct Info<'a> {
     layer_id: LayerId,
 
     font_id: FontId,
+    /// Background of the canvas.
+    bg_color: Color32,
+    /// Text and lines on the canvas.
+    fg_color: Color32,
 
     scope_collection: &'a ScopeCollection,
 }
@@ -202,6 +555,14 @@ impl<'a> Info<'a> {
             + options.sideways_pan_in_points
             + self.canvas.width() * ((ns - self.start_ns) as f32) / options.canvas_width_ns
     }
+
+    /// Inverse of [`Self::point_from_ns`].
+    fn ns_from_point(&self, options: &Options, x: f32) -> NanoSecond {
+        let ns = (x - self.canvas.min.x - options.sideways_pan_in_points) as f64
+            * options.canvas_width_ns as f64
+            / self.canvas.width() as f64;
+        self.start_ns + ns.round() as NanoSecond
+    }
 }
 
 /// Show the flamegraph.
@@ -222,14 +583,23 @@ pub fn ui(
         let num_frames_last_frame =
             ui.memory_mut(|m| m.data.get_temp::<usize>(num_frames_id).unwrap_or_default());
 
-        if num_frames_last_frame != num_frames && !options.merge_scopes {
+        if num_frames_last_frame != num_frames && !options.merge_scopes && !options.lock_time_scale
+        {
             reset_view = true;
         }
+        if num_frames_last_frame != num_frames {
+            options.selected_range_ns = None;
+        }
         ui.memory_mut(|m| m.data.insert_temp(num_frames_id, num_frames));
     }
 
     ui.horizontal(|ui| {
-        options.scope_name_filter.ui(ui);
+        let submitted = options.scope_name_filter.ui(ui);
+        if options.merge_scopes || options.scope_name_filter.is_empty() {
+            options.filter_match = None;
+        } else {
+            filter_match_ui(ui, options, scope_collection, frames, submitted);
+        }
 
         ui.menu_button("🔧 Settings", |ui| {
             ui.set_max_height(500.0);
@@ -244,6 +614,9 @@ pub fn ui(
                 if changed && num_frames > 1 {
                     reset_view = true;
                 }
+                if changed {
+                    options.selected_range_ns = None;
+                }
             }
 
             ui.horizontal(|ui| {
@@ -255,35 +628,99 @@ pub fn ui(
                 grid_spacing_drag.ui(ui);
             });
 
+            ui.checkbox(&mut options.grid_absolute_time, "Wall-clock time on grid")
+                .on_hover_text(
+                    "Label the grid with the time of day (UTC) instead of the time since the start of the frame.\n\
+                    Only works if the profiled timestamps are based on the Unix epoch.",
+                );
+
+            ui.checkbox(&mut options.lock_time_scale, "Lock time scale")
+                .on_hover_text(
+                    "Keep the zoom and pan when switching frames, \
+                    so you can compare the same part of several frames.",
+                );
+
+            ui.checkbox(&mut options.show_scope_counts, "Show scope count per thread");
+            ui.checkbox(&mut options.color_by_category, "Color by category")
+                .on_hover_text(
+                    "Give scopes with a category one color per category.\n\
+                    Other scopes are colored by their duration.",
+                );
+
+            options.theme.ui(ui);
+            options.layout_direction.ui(ui);
+
+            ui.collapsing("Mouse buttons", |ui| {
+                options.input_mapping.ui(ui);
+            });
+
             // The number of threads can change between frames, so always show this even if there currently is only one thread:
             options.sorting.ui(ui);
 
             ui.group(|ui| {
                 ui.strong("Visible Threads");
+                ui.horizontal(|ui| {
+                    if ui.button("Collapse all").clicked() {
+                        for settings in options.flamegraph_threads.values_mut() {
+                            settings.flamegraph_collapse = true;
+                        }
+                    }
+                    if ui.button("Expand all").clicked() {
+                        for settings in options.flamegraph_threads.values_mut() {
+                            settings.flamegraph_collapse = false;
+                        }
+                    }
+                    if ui.button("Show all").clicked() {
+                        for settings in options.flamegraph_threads.values_mut() {
+                            settings.flamegraph_show = true;
+                        }
+                    }
+                });
                 egui::ScrollArea::vertical().id_source("f").show(ui, |ui| {
                     for f in frames.threads.keys() {
                         let entry = options
                             .flamegraph_threads
                             .entry(f.name.clone())
                             .or_default();
-                        ui.checkbox(&mut entry.flamegraph_show, f.name.clone());
+                        let label = entry.display_name(f).to_owned();
+                        let response = ui.checkbox(&mut entry.flamegraph_show, label);
+                        if entry.alias.is_some() {
+                            response.on_hover_text(&f.name);
+                        }
                     }
                 });
             });
         });
 
+        if let Some(range_ns) = options.selected_range_ns {
+            let text = selected_range_text(frames, options.merge_scopes, range_ns);
+            ui.label(&text);
+            if ui
+                .button("📋 Copy")
+                .on_hover_text("Copy the selected time range")
+                .clicked()
+            {
+                ui.ctx().copy_text(text);
+            }
+            if ui.button("✖").on_hover_text("Clear the selection").clicked() {
+                options.selected_range_ns = None;
+            }
+        }
+
         ui.menu_button("❓", |ui| {
-            ui.label(
-                "Drag to pan.\n\
-                        Zoom: Ctrl/cmd + scroll, or drag with secondary mouse button.\n\
-                        Click on a scope to zoom to it.\n\
+            ui.label(format!(
+                "{}\n\
+                        Click on a scope to zoom to it, and to show its duration over the recent frames.\n\
+                        Right-click on a thread name to rename it, zoom to it, or hide all other threads.\n\
+                        Press Enter in the scope filter to jump to the next match (Shift+Enter for the previous).\n\
                         Double-click to reset view.\n\
                         Press spacebar to pause/resume.",
-            );
+                options.input_mapping.help_text()
+            ));
         });
     });
 
-    Frame::dark_canvas(ui.style()).show(ui, |ui| {
+    options.theme.canvas(ui, |ui| {
         ui.visuals_mut().clip_rect_margin = 0.0;
 
         let available_height = ui.max_rect().bottom() - ui.min_rect().bottom();
@@ -309,6 +746,8 @@ pub fn ui(
                 num_frames: frames.frames.len(),
                 layer_id: ui.layer_id(),
                 font_id: TextStyle::Body.resolve(ui.style()),
+                bg_color: ui.visuals().extreme_bg_color,
+                fg_color: ui.visuals().strong_text_color(),
                 scope_collection,
             };
 
@@ -323,7 +762,10 @@ pub fn ui(
 
             let where_to_put_timeline = info.painter.add(Shape::Noop);
 
-            let max_y = ui_canvas(options, &info, frames, (min_ns, max_ns));
+            let (max_y, scroll_to_rect) = ui_canvas(options, &info, frames, (min_ns, max_ns));
+            if let Some(rect) = scroll_to_rect {
+                ui.scroll_to_rect(rect, Some(Align::Center));
+            }
 
             let mut used_rect = canvas;
             used_rect.max.y = max_y;
@@ -335,17 +777,103 @@ pub fn ui(
             info.painter
                 .set(where_to_put_timeline, Shape::Vec(timeline));
 
+            paint_selected_range(&info, used_rect, options);
+
             ui.allocate_rect(used_rect, Sense::hover());
         });
     });
+
+    rename_thread_ui(ui.ctx(), options);
+}
+
+/// Popup for editing the alias of a thread.
+fn rename_thread_ui(ctx: &egui::Context, options: &mut Options) {
+    let Some(rename) = &mut options.rename_thread else {
+        return;
+    };
+
+    let mut close = false;
+    let mut new_alias = None;
+    let mut hide_others = false;
+    let mut zoom = false;
+
+    let response = egui::Area::new(egui::Id::new("puffin_rename_thread"))
+        .order(egui::Order::Foreground)
+        .fixed_pos(rename.pos)
+        .show(ctx, |ui| {
+            Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(format!("Alias for thread {:?}:", rename.name));
+                ui.text_edit_singleline(&mut rename.alias).request_focus();
+                if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    new_alias = Some(rename.alias.trim().to_owned());
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Rename").clicked() {
+                        new_alias = Some(rename.alias.trim().to_owned());
+                    }
+                    if ui.button("Reset to original name").clicked() {
+                        new_alias = Some(String::new());
+                    }
+                    if ui
+                        .button("Hide all but this")
+                        .on_hover_text(
+                            "Hide all other threads. Show them again from the Settings menu.",
+                        )
+                        .clicked()
+                    {
+                        hide_others = true;
+                    }
+                    if ui
+                        .add_enabled(
+                            rename.range_ns.is_some(),
+                            Button::new("Zoom to this thread"),
+                        )
+                        .clicked()
+                    {
+                        zoom = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+            });
+        })
+        .response;
+
+    if let Some(alias) = new_alias {
+        options
+            .flamegraph_threads
+            .entry(rename.name.clone())
+            .or_default()
+            .alias = (!alias.is_empty() && alias != rename.name).then_some(alias);
+        close = true;
+    }
+
+    if hide_others {
+        for (name, settings) in &mut options.flamegraph_threads {
+            settings.flamegraph_show = *name == rename.name;
+        }
+        close = true;
+    }
+
+    if let Some(range_ns) = rename.range_ns.filter(|_| zoom) {
+        options.zoom_to_relative_ns_range = Some((ctx.input(|i| i.time), range_ns));
+        close = true;
+    }
+
+    if close || response.clicked_elsewhere() || ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+        options.rename_thread = None;
+    }
 }
 
+/// Returns the bottom of the painted threads,
+/// and where the thread in [`Options::scroll_to_thread`] was painted.
 fn ui_canvas(
     options: &mut Options,
     info: &Info<'_>,
     frames: &SelectedFrames,
     (min_ns, max_ns): (NanoSecond, NanoSecond),
-) -> f32 {
+) -> (f32, Option<Rect>) {
     puffin::profile_function!();
 
     if options.canvas_width_ns <= 0.0 {
@@ -353,13 +881,23 @@ fn ui_canvas(
         options.zoom_to_relative_ns_range = None;
     }
 
-    // We paint the threads top-down
+    // We paint the threads top-down, even if the flamegraph within each thread grows upwards.
     let mut cursor_y = info.canvas.top();
     cursor_y += info.text_height; // Leave room for time labels
 
+    if !options.merge_scopes {
+        // Merged scopes are not on the same timeline as the counter samples or tasks.
+        cursor_y = paint_counters(info, options, frames, cursor_y);
+        cursor_y = paint_tasks(info, options, frames, cursor_y);
+    }
+
     let threads = frames.threads.keys().cloned().collect();
     let threads = options.sorting.sort(threads);
 
+    let mut rename_thread = None;
+    let mut scroll_to_rect = None;
+    let mut thread_root_ys = std::collections::BTreeMap::new();
+
     for thread_info in threads {
         let thread_visualization = options
             .flamegraph_threads
@@ -375,14 +913,48 @@ fn ui_canvas(
         let line_y = cursor_y;
         cursor_y += 2.0;
 
-        let text_pos = pos2(info.canvas.min.x, cursor_y);
+        let row_height = options.rect_height + options.spacing;
+        let max_depth = frames.threads[&thread_info].max_depth;
+        let scopes_height = if thread_visualization.flamegraph_collapse {
+            0.0
+        } else {
+            max_depth as f32 * row_height
+        };
+
+        // The thread name goes next to the top-level scopes:
+        let (text_y, scopes_top) = match options.layout_direction {
+            LayoutDirection::TopDown => (cursor_y, cursor_y + info.text_height),
+            LayoutDirection::BottomUp => (cursor_y + scopes_height, cursor_y),
+        };
+        let root_y = match options.layout_direction {
+            LayoutDirection::TopDown => scopes_top,
+            LayoutDirection::BottomUp => {
+                scopes_top + max_depth.saturating_sub(1) as f32 * row_height
+            }
+        };
+
+        let text_pos = pos2(info.canvas.min.x, text_y);
 
-        paint_thread_info(
+        let num_scopes = options
+            .show_scope_counts
+            .then(|| frames.threads[&thread_info].num_scopes);
+
+        if paint_thread_info(
             info,
             &thread_info,
+            num_scopes,
             text_pos,
-            &mut thread_visualization.flamegraph_collapse,
-        );
+            thread_visualization,
+        ) {
+            rename_thread = Some(RenameThread {
+                name: thread_info.name.clone(),
+                alias: thread_visualization.display_name(&thread_info).to_owned(),
+                pos: text_pos + vec2(0.0, info.text_height),
+                range_ns: frames.threads[&thread_info]
+                    .range_ns(options.merge_scopes)
+                    .map(|(min_ns, max_ns)| (min_ns - info.start_ns, max_ns - info.start_ns)),
+            });
+        }
 
         // draw on top of thread info background:
         info.painter.line_segment(
@@ -390,23 +962,23 @@ fn ui_canvas(
                 pos2(info.canvas.min.x, line_y),
                 pos2(info.canvas.max.x, line_y),
             ],
-            Stroke::new(1.0, Rgba::from_white_alpha(0.5)),
+            Stroke::new(1.0, info.fg_color.gamma_multiply(0.5)),
         );
 
-        cursor_y += info.text_height;
-
         if !thread_visualization.flamegraph_collapse {
+            thread_root_ys.insert(thread_info.clone(), root_y);
+
             let mut paint_streams = || -> Result<()> {
                 if options.merge_scopes {
                     for merge in &frames.threads[&thread_info].merged_scopes {
-                        paint_merge_scope(info, options, 0, merge, 0, cursor_y);
+                        paint_merge_scope(info, options, 0, merge, 0, root_y);
                     }
                 } else {
                     for stream_info in &frames.threads[&thread_info].streams {
                         let top_scopes =
                             Reader::from_start(&stream_info.stream).read_top_scopes()?;
                         for scope in top_scopes {
-                            paint_scope(info, options, &stream_info.stream, &scope, 0, cursor_y)?;
+                            paint_scope(info, options, &stream_info.stream, &scope, 0, root_y)?;
                         }
                     }
                 }
@@ -416,25 +988,490 @@ fn ui_canvas(
             if let Err(err) = paint_streams() {
                 let text = format!("Profiler stream error: {err:?}");
                 info.painter.text(
-                    pos2(info.canvas.min.x, cursor_y),
+                    pos2(info.canvas.min.x, scopes_top),
                     Align2::LEFT_TOP,
                     text,
                     info.font_id.clone(),
                     ERROR_COLOR,
                 );
             }
+        }
 
-            let max_depth = frames.threads[&thread_info].max_depth;
-            cursor_y += max_depth as f32 * (options.rect_height + options.spacing);
+        if options.scroll_to_thread.as_ref() == Some(&thread_info) {
+            options.scroll_to_thread = None;
+            scroll_to_rect = Some(Rect::from_x_y_ranges(
+                info.canvas.x_range(),
+                line_y..=cursor_y + info.text_height + scopes_height,
+            ));
         }
+
+        cursor_y += info.text_height + scopes_height;
         cursor_y += info.text_height; // Extra spacing between threads
     }
 
+    if !options.merge_scopes {
+        paint_flows(info, options, frames, &thread_root_ys);
+        paint_markers(info, options, frames, cursor_y);
+    }
+
+    if rename_thread.is_some() {
+        options.rename_thread = rename_thread;
+    }
+
+    (cursor_y, scroll_to_rect)
+}
+
+/// A scope matching the scope name filter.
+struct FilterMatch {
+    thread: ThreadInfo,
+    start_ns: NanoSecond,
+    stop_ns: NanoSecond,
+}
+
+/// All scopes matching the scope name filter, on all threads, in time order.
+fn filter_matches(
+    filter: &Filter,
+    scope_collection: &ScopeCollection,
+    frames: &SelectedFrames,
+) -> Result<Vec<FilterMatch>> {
+    fn collect(
+        matches: &mut Vec<FilterMatch>,
+        filter: &Filter,
+        scope_collection: &ScopeCollection,
+        thread: &ThreadInfo,
+        stream: &Stream,
+        offset: u64,
+    ) -> Result<()> {
+        for scope in Reader::with_offset(stream, offset)? {
+            let scope = scope?;
+            if let Some(scope_details) = scope_collection.fetch_by_id(&scope.id) {
+                if filter_includes(filter, scope_details) {
+                    matches.push(FilterMatch {
+                        thread: thread.clone(),
+                        start_ns: scope.record.start_ns,
+                        stop_ns: scope.record.stop_ns(),
+                    });
+                }
+            }
+            collect(
+                matches,
+                filter,
+                scope_collection,
+                thread,
+                stream,
+                scope.child_begin_position,
+            )?;
+        }
+        Ok(())
+    }
+
+    let mut matches = vec![];
+    for (thread, streams) in &frames.threads {
+        for stream_info in &streams.streams {
+            collect(
+                &mut matches,
+                filter,
+                scope_collection,
+                thread,
+                &stream_info.stream,
+                0,
+            )?;
+        }
+    }
+    matches.sort_by_key(|m| (m.start_ns, m.stop_ns));
+    Ok(matches)
+}
+
+/// Does the scope name filter include the scope, by name or category?
+fn filter_includes(filter: &Filter, scope_details: &ScopeDetails) -> bool {
+    filter.include(scope_details.name())
+        || (!scope_details.category.is_empty() && filter.include(&scope_details.category))
+}
+
+/// Show which filter match we are at, and jump to the next one (previous with shift) when `submitted`.
+fn filter_match_ui(
+    ui: &mut Ui,
+    options: &mut Options,
+    scope_collection: &ScopeCollection,
+    frames: &SelectedFrames,
+    submitted: bool,
+) {
+    let filter_text = options.scope_name_filter.as_str();
+    if options
+        .filter_match
+        .as_ref()
+        .is_some_and(|(text, _)| text != filter_text)
+    {
+        options.filter_match = None;
+    }
+
+    let matches = match filter_matches(&options.scope_name_filter, scope_collection, frames) {
+        Ok(matches) => matches,
+        Err(err) => {
+            ui.colored_label(ERROR_COLOR, format!("Profiler stream error: {err:?}"));
+            return;
+        }
+    };
+    if matches.is_empty() {
+        ui.label("No matches");
+        return;
+    }
+
+    if submitted {
+        let backwards = ui.input(|i| i.modifiers.shift);
+        let index = match options.filter_match {
+            None if backwards => matches.len() - 1,
+            None => 0,
+            Some((_, index)) if backwards => (index + matches.len() - 1) % matches.len(),
+            Some((_, index)) => (index + 1) % matches.len(),
+        };
+        let filter_match = &matches[index];
+
+        let thread_settings = options
+            .flamegraph_threads
+            .entry(filter_match.thread.name.clone())
+            .or_default();
+        thread_settings.flamegraph_show = true;
+        thread_settings.flamegraph_collapse = false;
+
+        let start_ns = frames.raw_range_ns.0;
+        options.zoom_to_relative_ns_range = Some((
+            ui.input(|i| i.time),
+            (
+                filter_match.start_ns - start_ns,
+                filter_match.stop_ns - start_ns,
+            ),
+        ));
+        options.scroll_to_thread = Some(filter_match.thread.clone());
+        options.filter_match = Some((filter_text.to_owned(), index));
+    }
+
+    match &options.filter_match {
+        Some((_, index)) if *index < matches.len() => {
+            ui.label(format!(
+                "Match {} of {} on {}",
+                index + 1,
+                matches.len(),
+                matches[*index].thread.name
+            ));
+        }
+        _ => {
+            ui.label(format!("{} matches", matches.len()))
+                .on_hover_text("Press Enter to go to the next match, Shift+Enter for the previous");
+        }
+    }
+}
+
+/// Paint each counter (see [`puffin::profile_counter`]) as a line graph of its value over time.
+///
+/// Returns the y coordinate below the last counter.
+fn paint_counters(
+    info: &Info<'_>,
+    options: &Options,
+    frames: &SelectedFrames,
+    mut cursor_y: f32,
+) -> f32 {
+    puffin::profile_function!();
+
+    let mut counters = std::collections::BTreeMap::<ScopeId, Vec<CounterRecord>>::new();
+    for frame in &frames.frames {
+        // Errors in the streams are reported when painting the threads.
+        for counter in frame.counters().unwrap_or_default() {
+            counters.entry(counter.id).or_default().push(counter);
+        }
+    }
+
+    let graph_height = 3.0 * options.rect_height;
+    let stroke = Stroke::new(1.5, COUNTER_COLOR);
+
+    for (counter_id, samples) in &mut counters {
+        samples.sort_by_key(|sample| sample.time_ns);
+
+        let (min, max) = samples
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), sample| {
+                (min.min(sample.value), max.max(sample.value))
+            });
+
+        cursor_y += options.spacing;
+        let name = info.scope_collection.fetch_by_id(counter_id).map_or_else(
+            || format!("{counter_id:?}"),
+            |details| details.name().to_string(),
+        );
+        let last = samples.last().map_or(0.0, |sample| sample.value);
+        info.painter.text(
+            pos2(info.canvas.min.x, cursor_y),
+            Align2::LEFT_TOP,
+            format!("{name}: {last:.2}  (min {min:.2}, max {max:.2})"),
+            info.font_id.clone(),
+            info.fg_color,
+        );
+        cursor_y += info.text_height;
+
+        let graph =
+            Rect::from_x_y_ranges(info.canvas.x_range(), cursor_y..=cursor_y + graph_height);
+        let y_from_value = |value: f64| {
+            if min < max {
+                remap(value, min..=max, graph.bottom() as f64..=graph.top() as f64) as f32
+            } else {
+                graph.center().y
+            }
+        };
+        let points: Vec<Pos2> = samples
+            .iter()
+            .map(|sample| {
+                pos2(
+                    info.point_from_ns(options, sample.time_ns),
+                    y_from_value(sample.value),
+                )
+            })
+            .collect();
+        if let [point] = points[..] {
+            info.painter.circle_filled(point, 2.0, COUNTER_COLOR);
+        } else {
+            info.painter.add(Shape::line(points, stroke));
+        }
+
+        cursor_y = graph.bottom();
+    }
+
+    if !counters.is_empty() {
+        cursor_y += info.text_height; // Spacing before the threads
+    }
+
+    cursor_y
+}
+
+/// Connect the points of each flow (see [`puffin::profile_flow`]) with lines,
+/// going through the scopes they were recorded in.
+///
+/// `thread_root_ys` has the `root_y` of each thread whose scopes are shown.
+fn paint_flows(
+    info: &Info<'_>,
+    options: &Options,
+    frames: &SelectedFrames,
+    thread_root_ys: &std::collections::BTreeMap<ThreadInfo, f32>,
+) {
+    puffin::profile_function!();
+
+    let row_height = options.rect_height + options.spacing;
+    let mut flows = std::collections::BTreeMap::<u64, Vec<Pos2>>::new();
+    for frame in &frames.frames {
+        // Errors in the streams are reported when painting the threads.
+        for (thread_info, flow) in frame.flows().unwrap_or_default() {
+            let Some(&root_y) = thread_root_ys.get(&thread_info) else {
+                continue; // thread is hidden or collapsed
+            };
+            let top_y =
+                options
+                    .layout_direction
+                    .top_y(root_y, flow.depth.saturating_sub(1), row_height);
+            flows.entry(flow.flow_id).or_default().push(pos2(
+                info.point_from_ns(options, flow.time_ns),
+                top_y + 0.5 * options.rect_height,
+            ));
+        }
+    }
+
+    let clip_rect = info.painter.clip_rect();
+    let stroke = Stroke::new(1.5, FLOW_COLOR.gamma_multiply(0.6));
+    for points in flows.values() {
+        for (&from, &to) in points.iter().zip(points.iter().skip(1)) {
+            if !Rect::from_two_pos(from, to).intersects(clip_rect) {
+                continue;
+            }
+            info.painter.line_segment([from, to], stroke);
+            info.painter.circle_filled(to, 2.5, stroke.color);
+        }
+    }
+}
+
+/// Paint each marker (see [`puffin::profile_marker`]) as a dashed vertical line across all threads,
+/// down to `bottom_y`.
+fn paint_markers(info: &Info<'_>, options: &Options, frames: &SelectedFrames, bottom_y: f32) {
+    puffin::profile_function!();
+
+    let top_y = info.canvas.top() + info.text_height;
+    let stroke = Stroke::new(1.0, MARKER_COLOR);
+    let mut hovered = None;
+    for frame in &frames.frames {
+        // Errors in the streams are reported when painting the threads.
+        for marker in frame.markers().unwrap_or_default() {
+            let x = info.point_from_ns(options, marker.time_ns);
+            if x < info.canvas.min.x || info.canvas.max.x < x {
+                continue;
+            }
+
+            let is_hovered = info.response.hover_pos().map_or(false, |mouse_pos| {
+                (mouse_pos.x - x).abs() <= 3.0 && (top_y..=bottom_y).contains(&mouse_pos.y)
+            });
+            let stroke = if is_hovered {
+                hovered = Some(marker);
+                Stroke::new(2.0, HOVER_COLOR)
+            } else {
+                stroke
+            };
+            info.painter.extend(Shape::dashed_line(
+                &[pos2(x, top_y), pos2(x, bottom_y)],
+                stroke,
+                6.0,
+                4.0,
+            ));
+        }
+    }
+
+    if let Some(marker) = hovered {
+        let name = info.scope_collection.fetch_by_id(&marker.id).map_or_else(
+            || format!("{:?}", marker.id),
+            |details| details.name().to_string(),
+        );
+        egui::show_tooltip_at_pointer(
+            &info.ctx,
+            info.layer_id,
+            Id::new("puffin_profiler_tooltip"),
+            |ui| {
+                ui.monospace(format!("marker: {name}"));
+                ui.monospace(format!(
+                    "time:   {:.3} ms",
+                    to_ms(marker.time_ns - info.start_ns)
+                ));
+            },
+        );
+    }
+}
+
+/// Paint each async task (see [`puffin::profile_task`]) as a row of the times it was polled,
+/// connected by a line while it was waiting.
+///
+/// Returns the y coordinate below the last task.
+fn paint_tasks(
+    info: &Info<'_>,
+    options: &Options,
+    frames: &SelectedFrames,
+    mut cursor_y: f32,
+) -> f32 {
+    puffin::profile_function!();
+
+    // Errors in the streams are reported when painting the threads.
+    let timelines = task_timelines(frames.frames.as_slice()).unwrap_or_default();
+
+    for timeline in &timelines {
+        cursor_y += options.spacing;
+        let name = info.scope_collection.fetch_by_id(&timeline.id).map_or_else(
+            || format!("{:?}", timeline.id),
+            |details| details.name().to_string(),
+        );
+        let num_threads = timeline
+            .polls
+            .iter()
+            .map(|poll| &poll.thread_info)
+            .collect::<std::collections::BTreeSet<_>>()
+            .len();
+        info.painter.text(
+            pos2(info.canvas.min.x, cursor_y),
+            Align2::LEFT_TOP,
+            format!(
+                "{name} (task #{}): {:.3} ms busy over {:.3} ms, on {num_threads} thread(s)",
+                timeline.task_id,
+                to_ms(timeline.busy_ns()),
+                to_ms(timeline.stop_ns() - timeline.start_ns()),
+            ),
+            info.font_id.clone(),
+            info.fg_color,
+        );
+        cursor_y += info.text_height;
+
+        let top_y = cursor_y;
+        let bottom_y = top_y + options.rect_height;
+        info.painter.line_segment(
+            [
+                pos2(info.point_from_ns(options, timeline.start_ns()), top_y),
+                pos2(info.point_from_ns(options, timeline.stop_ns()), top_y),
+            ]
+            .map(|pos| pos + vec2(0.0, 0.5 * options.rect_height)),
+            Stroke::new(1.0, TASK_COLOR),
+        );
+
+        for poll in &timeline.polls {
+            let rect = Rect::from_x_y_ranges(
+                info.point_from_ns(options, poll.start_ns)
+                    ..=info.point_from_ns(options, poll.stop_ns()),
+                top_y..=bottom_y,
+            );
+            if info.canvas.max.x < rect.min.x || rect.max.x < info.canvas.min.x {
+                continue;
+            }
+
+            let is_hovered = info.response.hover_pos().map_or(false, |mouse_pos| {
+                rect.expand2(vec2(1.0, 0.0)).contains(mouse_pos)
+            });
+            let color = if is_hovered {
+                HOVER_COLOR
+            } else {
+                TASK_COLOR.into()
+            };
+            if rect.width() <= options.min_width {
+                info.painter.line_segment(
+                    [rect.center_top(), rect.center_bottom()],
+                    Stroke::new(options.min_width, color),
+                );
+            } else {
+                info.painter.rect_filled(rect, options.rounding, color);
+            }
+
+            if is_hovered {
+                egui::show_tooltip_at_pointer(
+                    &info.ctx,
+                    info.layer_id,
+                    Id::new("puffin_profiler_tooltip"),
+                    |ui| {
+                        ui.monospace(format!("task:     {name} (#{})", timeline.task_id));
+                        ui.monospace(format!("thread:   {}", poll.thread_info.name));
+                        ui.monospace(format!("duration: {:7.3} ms", to_ms(poll.duration_ns)));
+                    },
+                );
+            }
+        }
+
+        cursor_y = bottom_y;
+    }
+
+    if !timelines.is_empty() {
+        cursor_y += info.text_height; // Spacing before the threads
+    }
+
     cursor_y
 }
 
 fn interact_with_canvas(options: &mut Options, response: &Response, info: &Info<'_>) {
-    if response.drag_delta().x != 0.0 {
+    let input_mapping = options.input_mapping;
+    let modifier_zoom = input_mapping.pan_button.is_dragging(response)
+        && info
+            .ctx
+            .input(|i| input_mapping.zoom_modifier.is_down(&i.modifiers));
+    let modifier_select = input_mapping.pan_button.is_dragging(response)
+        && !modifier_zoom
+        && info
+            .ctx
+            .input(|i| input_mapping.select_modifier.is_down(&i.modifiers));
+    let drag_zoom = input_mapping.zoom_button.is_dragging(response) || modifier_zoom;
+    let drag_pan =
+        input_mapping.pan_button.is_dragging(response) && !modifier_zoom && !modifier_select;
+
+    if modifier_select {
+        let press_origin = info.ctx.input(|i| i.pointer.press_origin());
+        if let (Some(origin), Some(pos)) = (press_origin, response.interact_pointer_pos()) {
+            let a = info.ns_from_point(options, origin.x) - info.start_ns;
+            let b = info.ns_from_point(options, pos.x) - info.start_ns;
+            options.selected_range_ns = Some((a.min(b).max(0), a.max(b).max(0)));
+        }
+    }
+
+    if response.hovered() && info.ctx.input(|i| i.key_pressed(Key::Escape)) {
+        options.selected_range_ns = None;
+    }
+
+    if drag_pan && response.drag_delta().x != 0.0 {
         options.sideways_pan_in_points += response.drag_delta().x;
         options.zoom_to_relative_ns_range = None;
     }
@@ -448,7 +1485,7 @@ fn interact_with_canvas(options: &mut Options, response: &Response, info: &Info<
 
         let mut zoom_factor = info.ctx.input(|i| i.zoom_delta_2d().x);
 
-        if response.dragged_by(PointerButton::Secondary) {
+        if drag_zoom {
             zoom_factor *= (response.drag_delta().y * 0.01).exp();
         }
 
@@ -531,6 +1568,16 @@ fn paint_timeline(
     let medium_alpha = remap_clamp(zoom_factor, 0.0..=1.0, 0.1..=0.5);
     let tiny_alpha = remap_clamp(zoom_factor, 0.0..=1.0, 0.0..=0.1);
 
+    let absolute_time = options.grid_absolute_time && is_nanos_since_epoch(start_ns);
+
+    // How much room there is for a label before the next labeled line:
+    let label_spacing_ns = if tiny_alpha > 0.0 {
+        grid_spacing_ns * 10
+    } else {
+        grid_spacing_ns * 100
+    };
+    let label_spacing_points = canvas.width() * label_spacing_ns as f32 / options.canvas_width_ns;
+
     let mut grid_ns = 0;
 
     loop {
@@ -553,7 +1600,10 @@ fn paint_timeline(
 
             shapes.push(egui::Shape::line_segment(
                 [pos2(line_x, canvas.min.y), pos2(line_x, canvas.max.y)],
-                Stroke::new(1.0, Rgba::from_white_alpha(line_alpha * alpha_multiplier)),
+                Stroke::new(
+                    1.0,
+                    Rgba::from(info.fg_color) * (line_alpha * alpha_multiplier),
+                ),
             ));
 
             let text_alpha = if big_line {
@@ -565,33 +1615,35 @@ fn paint_timeline(
             };
 
             if text_alpha > 0.0 {
-                let text = grid_text(grid_ns);
+                let text = if absolute_time {
+                    wall_clock_grid_text(start_ns + grid_ns, grid_spacing_ns * 10)
+                        .unwrap_or_else(|| grid_text(grid_ns))
+                } else {
+                    grid_text(grid_ns)
+                };
                 let text_x = line_x + 4.0;
-                let text_color = Rgba::from_white_alpha((text_alpha * 2.0).min(1.0)).into();
+                let text_color = (Rgba::from(info.fg_color) * (text_alpha * 2.0).min(1.0)).into();
+
+                let galley = info
+                    .painter
+                    .layout_no_wrap(text, info.font_id.clone(), text_color);
 
-                info.painter.fonts(|f| {
+                // Don't let labels run into each other:
+                if galley.size().x + 8.0 < label_spacing_points {
                     // Text at top:
-                    shapes.push(egui::Shape::text(
-                        f,
+                    shapes.push(egui::Shape::galley(
                         pos2(text_x, canvas.min.y),
-                        Align2::LEFT_TOP,
-                        &text,
-                        info.font_id.clone(),
+                        galley.clone(),
                         text_color,
                     ));
-                });
 
-                info.painter.fonts(|f| {
                     // Text at bottom:
-                    shapes.push(egui::Shape::text(
-                        f,
+                    shapes.push(egui::Shape::galley(
                         pos2(text_x, canvas.max.y - info.text_height),
-                        Align2::LEFT_TOP,
-                        &text,
-                        info.font_id.clone(),
+                        galley,
                         text_color,
                     ));
-                });
+                }
             }
         }
 
@@ -601,6 +1653,49 @@ fn paint_timeline(
     shapes
 }
 
+fn paint_selected_range(info: &Info<'_>, canvas: Rect, options: &Options) {
+    let Some((start_ns, end_ns)) = options.selected_range_ns else {
+        return;
+    };
+
+    let x_range = info.point_from_ns(options, info.start_ns + start_ns)
+        ..=info.point_from_ns(options, info.start_ns + end_ns);
+    let rect = Rect::from_x_y_ranges(x_range, canvas.y_range());
+    info.painter
+        .rect_filled(rect, 0.0, info.fg_color.gamma_multiply(0.1));
+    for x in [rect.left(), rect.right()] {
+        info.painter.line_segment(
+            [pos2(x, canvas.top()), pos2(x, canvas.bottom())],
+            Stroke::new(1.0, info.fg_color),
+        );
+    }
+    info.painter.text(
+        pos2(rect.center().x, canvas.top() + info.text_height),
+        Align2::CENTER_TOP,
+        format!("{:.3} ms", to_ms(end_ns - start_ns)),
+        info.font_id.clone(),
+        info.fg_color,
+    );
+}
+
+/// E.g. "frame 412, 3.200–4.800 ms", with the range relative to the start of the (first) frame.
+fn selected_range_text(
+    frames: &SelectedFrames,
+    merged: bool,
+    (start_ns, end_ns): (NanoSecond, NanoSecond),
+) -> String {
+    let first = frames.frames.first().frame_index();
+    let last = frames.frames.last().frame_index();
+    let frames = if first == last {
+        format!("frame {first}")
+    } else if merged {
+        format!("frames {first}–{last} (merged)")
+    } else {
+        format!("frames {first}–{last}")
+    };
+    format!("{frames}, {:.3}–{:.3} ms", to_ms(start_ns), to_ms(end_ns))
+}
+
 fn grid_text(grid_ns: NanoSecond) -> String {
     let grid_ms = to_ms(grid_ns);
     if grid_ns % 1_000_000 == 0 {
@@ -614,6 +1709,41 @@ fn grid_text(grid_ns: NanoSecond) -> String {
     }
 }
 
+/// Time of day (UTC), with enough decimals to tell grid lines `label_spacing_ns` apart.
+fn wall_clock_grid_text(ns: NanoSecond, label_spacing_ns: NanoSecond) -> Option<String> {
+    use time::macros::format_description;
+
+    let time = time::OffsetDateTime::from_unix_timestamp_nanos(ns as i128).ok()?;
+    let text = if label_spacing_ns >= 1_000_000_000 {
+        time.format(format_description!("[hour]:[minute]:[second]"))
+    } else if label_spacing_ns >= 100_000_000 {
+        time.format(format_description!(
+            "[hour]:[minute]:[second].[subsecond digits:1]"
+        ))
+    } else if label_spacing_ns >= 10_000_000 {
+        time.format(format_description!(
+            "[hour]:[minute]:[second].[subsecond digits:2]"
+        ))
+    } else if label_spacing_ns >= 1_000_000 {
+        time.format(format_description!(
+            "[hour]:[minute]:[second].[subsecond digits:3]"
+        ))
+    } else if label_spacing_ns >= 100_000 {
+        time.format(format_description!(
+            "[hour]:[minute]:[second].[subsecond digits:4]"
+        ))
+    } else if label_spacing_ns >= 10_000 {
+        time.format(format_description!(
+            "[hour]:[minute]:[second].[subsecond digits:5]"
+        ))
+    } else {
+        time.format(format_description!(
+            "[hour]:[minute]:[second].[subsecond digits:6]"
+        ))
+    };
+    text.ok()
+}
+
 #[allow(clippy::too_many_arguments)]
 fn paint_record(
     info: &Info<'_>,
@@ -656,6 +1786,7 @@ fn paint_record(
             }
         }
     } else if is_hovered && info.response.clicked() {
+        options.selected_scope = Some(scope_id);
         options.zoom_to_relative_ns_range = Some((
             info.ctx.input(|i| i.time),
             (
@@ -667,6 +1798,8 @@ fn paint_record(
 
     let mut rect_color = if is_hovered {
         HOVER_COLOR
+    } else if options.color_by_category && !scope_details.category.is_empty() {
+        color_from_category(&scope_details.category)
     } else {
         color_from_duration(scope_data.duration_ns)
     };
@@ -674,12 +1807,12 @@ fn paint_record(
     let mut min_width = options.min_width;
 
     if !options.scope_name_filter.is_empty() {
-        if options.scope_name_filter.include(scope_details.name()) {
+        if filter_includes(&options.scope_name_filter, scope_details) {
             // keep full opacity
             min_width *= 2.0; // make it more visible even when thin
         } else {
             // fade to highlight others
-            rect_color = lerp(Rgba::BLACK..=rect_color, 0.075);
+            rect_color = lerp(Rgba::from(info.bg_color)..=rect_color, 0.075);
         }
     }
 
@@ -724,13 +1857,12 @@ fn paint_record(
             top_y + 0.5 * (options.rect_height - info.text_height),
         );
         let pos = painter.round_pos_to_pixels(pos);
-        const TEXT_COLOR: Color32 = Color32::BLACK;
         painter.text(
             pos,
             Align2::LEFT_TOP,
             text,
             info.font_id.clone(),
-            TEXT_COLOR,
+            text_color_on(rect_color),
         );
     }
 
@@ -752,6 +1884,31 @@ fn color_from_duration(ns: NanoSecond) -> Rgba {
     Rgba::from_rgb(r, g, b) * a
 }
 
+/// The same color for the same category, in every run and on every machine.
+fn color_from_category(category: &str) -> Rgba {
+    // FNV-1a, since the std hashers are not guaranteed to be stable:
+    let hash = category
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    let hue = (hash % 360) as f32 / 360.0;
+    let a = 0.9;
+    Rgba::from(egui::ecolor::Hsva::new(hue, 0.6, 0.8, 1.0)) * a
+}
+
+/// Black or white, whichever is most readable on top of the given background.
+fn text_color_on(background: Rgba) -> Color32 {
+    // Rgba is linear, so this is the relative luminance:
+    let luminance = 0.2126 * background.r() + 0.7152 * background.g() + 0.0722 * background.b();
+    // The luminance where black and white text have the same contrast ratio:
+    if luminance > 0.179 {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    }
+}
+
 fn to_ms(ns: NanoSecond) -> f64 {
     ns as f64 * 1e-6
 }
@@ -762,29 +1919,39 @@ fn paint_scope(
     stream: &Stream,
     scope: &Scope<'_>,
     depth: usize,
-    min_y: f32,
+    root_y: f32,
 ) -> Result<PaintResult> {
-    let top_y = min_y + (depth as f32) * (options.rect_height + options.spacing);
+    let top_y =
+        options
+            .layout_direction
+            .top_y(root_y, depth, options.rect_height + options.spacing);
 
     let result = paint_record(info, options, "", "", scope.id, &scope.record, top_y);
 
     if result != PaintResult::Culled {
-        let mut num_children = 0;
-        for child_scope in Reader::with_offset(stream, scope.child_begin_position)? {
-            paint_scope(info, options, stream, &child_scope?, depth + 1, min_y)?;
-            num_children += 1;
+        let width = info.point_from_ns(options, scope.record.stop_ns())
+            - info.point_from_ns(options, scope.record.start_ns);
+        let has_children = scope.child_begin_position < scope.child_end_position;
+        if has_children && width < options.lod_width {
+            paint_descendants_block(info, options, stream, scope, depth + 1, root_y)?;
+        } else {
+            for child_scope in Reader::with_offset(stream, scope.child_begin_position)? {
+                paint_scope(info, options, stream, &child_scope?, depth + 1, root_y)?;
+            }
         }
 
         if result == PaintResult::Hovered {
             let Some(scope_details) = info.scope_collection.fetch_by_id(&scope.id) else {
                 return Ok(PaintResult::Culled);
             };
+            let num_children = Reader::with_offset(stream, scope.child_begin_position)?.count();
             egui::show_tooltip_at_pointer(
                 &info.ctx,
                 info.layer_id,
                 Id::new("puffin_profiler_tooltip"),
                 |ui| {
                     paint_scope_details(ui, scope.id, scope.record.data, scope_details);
+                    paint_scope_tags(ui, scope.record.tags);
 
                     ui.monospace(format!(
                         "duration: {:7.3} ms",
@@ -799,15 +1966,88 @@ fn paint_scope(
     Ok(result)
 }
 
+/// Paint all scopes below `scope` as one block at `depth`, instead of painting each of them.
+///
+/// Used when `scope` is narrower than [`Options::lod_width`], so its children would be too thin to see anyway.
+fn paint_descendants_block(
+    info: &Info<'_>,
+    options: &mut Options,
+    stream: &Stream,
+    scope: &Scope<'_>,
+    depth: usize,
+    root_y: f32,
+) -> Result<()> {
+    let top_y =
+        options
+            .layout_direction
+            .top_y(root_y, depth, options.rect_height + options.spacing);
+    let rect = Rect::from_x_y_ranges(
+        info.point_from_ns(options, scope.record.start_ns)
+            ..=info.point_from_ns(options, scope.record.stop_ns()),
+        top_y..=top_y + options.rect_height,
+    );
+    let is_hovered = info
+        .response
+        .hover_pos()
+        .map_or(false, |mouse_pos| rect.contains(mouse_pos));
+
+    let color = if is_hovered {
+        HOVER_COLOR.into()
+    } else {
+        info.fg_color.gamma_multiply(0.3)
+    };
+    info.painter.line_segment(
+        [rect.center_top(), rect.center_bottom()],
+        Stroke::new(rect.width().max(options.min_width), color),
+    );
+
+    if is_hovered {
+        if info.response.clicked() {
+            options.zoom_to_relative_ns_range = Some((
+                info.ctx.input(|i| i.time),
+                (
+                    scope.record.start_ns - info.start_ns,
+                    scope.record.stop_ns() - info.start_ns,
+                ),
+            ));
+        }
+
+        let num_descendants = count_descendants(stream, scope)?;
+        egui::show_tooltip_at_pointer(
+            &info.ctx,
+            info.layer_id,
+            Id::new("puffin_profiler_tooltip"),
+            |ui| {
+                ui.monospace(format!("(+{} scopes)", format_count(num_descendants)));
+                ui.label("Click to zoom in");
+            },
+        );
+    }
+
+    Ok(())
+}
+
+/// The number of scopes below `scope`, at any depth.
+fn count_descendants(stream: &Stream, scope: &Scope<'_>) -> Result<usize> {
+    let mut count = 0;
+    for child_scope in Reader::with_offset(stream, scope.child_begin_position)? {
+        count += 1 + count_descendants(stream, &child_scope?)?;
+    }
+    Ok(count)
+}
+
 fn paint_merge_scope(
     info: &Info<'_>,
     options: &mut Options,
     ns_offset: NanoSecond,
     merge: &MergeScope<'_>,
     depth: usize,
-    min_y: f32,
+    root_y: f32,
 ) -> PaintResult {
-    let top_y = min_y + (depth as f32) * (options.rect_height + options.spacing);
+    let top_y =
+        options
+            .layout_direction
+            .top_y(root_y, depth, options.rect_height + options.spacing);
 
     let prefix = if info.num_frames <= 1 {
         if merge.num_pieces <= 1 {
@@ -834,13 +2074,14 @@ fn paint_merge_scope(
         start_ns: ns_offset + merge.relative_start_ns,
         duration_ns: merge.duration_per_frame_ns,
         data: &merge.data,
+        tags: Default::default(),
     };
 
     let result = paint_record(info, options, &prefix, suffix, merge.id, &record, top_y);
 
     if result != PaintResult::Culled {
         for child in &merge.children {
-            paint_merge_scope(info, options, record.start_ns, child, depth + 1, min_y);
+            paint_merge_scope(info, options, record.start_ns, child, depth + 1, root_y);
         }
 
         if result == PaintResult::Hovered {
@@ -876,6 +2117,12 @@ fn paint_scope_details(ui: &mut Ui, scope_id: ScopeId, data: &str, scope_details
                 ui.end_row();
             }
 
+            if !scope_details.category.is_empty() {
+                ui.monospace("category");
+                ui.monospace(scope_details.category.as_ref());
+                ui.end_row();
+            }
+
             if !scope_details.file_path.is_empty() {
                 ui.monospace("location");
                 ui.monospace(scope_details.location());
@@ -894,6 +2141,24 @@ fn paint_scope_details(ui: &mut Ui, scope_id: ScopeId, data: &str, scope_details
         });
 }
 
+/// The key-value tags of a scope, see [`puffin::profile_scope_tags`].
+fn paint_scope_tags(ui: &mut Ui, tags: ScopeTags<'_>) {
+    if tags.is_empty() {
+        return;
+    }
+    ui.separator();
+    egui::Grid::new("scope_tags_tooltip")
+        .num_columns(2)
+        .show(ui, |ui| {
+            for (key, value) in tags.iter() {
+                ui.monospace(key);
+                ui.monospace(value);
+                ui.end_row();
+            }
+        });
+    ui.separator();
+}
+
 fn merge_scope_tooltip(
     ui: &mut egui::Ui,
     scope_collection: &ScopeCollection,
@@ -925,6 +2190,7 @@ fn merge_scope_tooltip(
                 to_ms(merge.duration_per_frame_ns) / (merge.num_pieces as f64),
             ));
             ui.monospace(format!("max:   {:7.3} ms", to_ms(merge.max_duration_ns)));
+            merge_percentiles_ui(ui, merge);
         }
     } else {
         ui.monospace(format!(
@@ -955,21 +2221,67 @@ fn merge_scope_tooltip(
             "{:7.3} ms for slowest call",
             to_ms(merge.max_duration_ns)
         ));
+        if merge.num_pieces > 1 {
+            merge_percentiles_ui(ui, merge);
+        }
     }
 }
 
-fn paint_thread_info(info: &Info<'_>, thread: &ThreadInfo, pos: Pos2, collapsed: &mut bool) {
-    let collapsed_symbol = if *collapsed { "⏵" } else { "⏷" };
+fn merge_percentiles_ui(ui: &mut egui::Ui, merge: &MergeScope<'_>) {
+    let durations = &merge.durations;
+    ui.monospace(format!("min:   {:7.3} ms", to_ms(durations.min_ns())));
+    ui.monospace(format!(
+        "p50 / p95 / p99: {:.3} / {:.3} / {:.3} ms",
+        to_ms(durations.percentile(50.0)),
+        to_ms(durations.percentile(95.0)),
+        to_ms(durations.percentile(99.0)),
+    ));
+}
 
+/// Returns `true` if the user asked to rename the thread.
+fn paint_thread_info(
+    info: &Info<'_>,
+    thread: &ThreadInfo,
+    num_scopes: Option<usize>,
+    pos: Pos2,
+    settings: &mut ThreadVisualizationSettings,
+) -> bool {
+    let collapsed_symbol = if settings.flamegraph_collapse {
+        "⏵"
+    } else {
+        "⏷"
+    };
+
+    let count_galley = num_scopes.map(|num_scopes| {
+        info.ctx.fonts(|f| {
+            f.layout_no_wrap(
+                format!(" ({} scopes)", format_count(num_scopes)),
+                info.font_id.clone(),
+                egui::Color32::PLACEHOLDER,
+            )
+        })
+    });
+    let count_width = count_galley.as_ref().map_or(0.0, |galley| galley.size().x);
+
+    // Truncate long thread names, so that the scope count stays on screen:
     let galley = info.ctx.fonts(|f| {
-        f.layout_no_wrap(
-            format!("{} {}", collapsed_symbol, thread.name.clone()),
+        let mut job = text::LayoutJob::simple_singleline(
+            format!("{} {}", collapsed_symbol, settings.display_name(thread)),
             info.font_id.clone(),
             egui::Color32::PLACEHOLDER,
-        )
+        );
+        job.wrap = text::TextWrapping::truncate_at_width(
+            (info.canvas.max.x - pos.x - count_width).max(info.text_height),
+        );
+        f.layout_job(job)
     });
 
-    let rect = Rect::from_min_size(pos, galley.size());
+    let mut rect = Rect::from_min_size(pos, galley.size());
+    let count_pos = rect.right_top();
+    if let Some(count_galley) = &count_galley {
+        rect.max.x += count_galley.size().x;
+        rect.max.y = rect.max.y.max(rect.min.y + count_galley.size().y);
+    }
 
     let is_hovered = if let Some(mouse_pos) = info.response.hover_pos() {
         rect.contains(mouse_pos)
@@ -978,20 +2290,136 @@ fn paint_thread_info(info: &Info<'_>, thread: &ThreadInfo, pos: Pos2, collapsed:
     };
 
     let text_color = if is_hovered {
-        Color32::WHITE
+        info.fg_color
     } else {
-        Color32::from_white_alpha(229)
+        info.fg_color.gamma_multiply(0.9)
     };
     let back_color = if is_hovered {
-        Color32::from_black_alpha(100)
+        info.bg_color.gamma_multiply(0.4)
     } else {
-        Color32::BLACK
+        info.bg_color
     };
 
     info.painter.rect_filled(rect.expand(2.0), 0.0, back_color);
     info.painter.galley(rect.min, galley, text_color);
+    if let Some(count_galley) = count_galley {
+        info.painter
+            .galley(count_pos, count_galley, text_color.gamma_multiply(0.6));
+    }
 
     if is_hovered && info.response.clicked() {
-        *collapsed = !(*collapsed);
+        settings.flamegraph_collapse = !settings.flamegraph_collapse;
+    }
+
+    is_hovered && info.response.secondary_clicked()
+}
+
+/// Format a count with thousands separators, e.g. `1,234`.
+fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i != 0 && (digits.len() - i) % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn color_from_category() {
+        let physics = super::color_from_category("Physics");
+        assert_eq!(physics, super::color_from_category("Physics"));
+        assert_ne!(physics, super::color_from_category("AI"));
+    }
+
+    #[test]
+    fn format_count() {
+        assert_eq!(super::format_count(0), "0");
+        assert_eq!(super::format_count(999), "999");
+        assert_eq!(super::format_count(1_234), "1,234");
+        assert_eq!(super::format_count(1_234_567), "1,234,567");
+    }
+
+    /// Paints a frame with a thousand one-point wide scopes, each with a deep subtree,
+    /// and returns the number of shapes painted.
+    fn num_shapes_painted(options: &mut super::Options) -> usize {
+        use puffin::{FrameView, Profiler, ScopeDetails, Stream};
+
+        let mut profiler = Profiler::default();
+        let frame_view = std::sync::Arc::new(parking_lot::Mutex::new(FrameView::default()));
+        let frame_view_clone = frame_view.clone();
+        profiler.add_sink(Box::new(move |frame| {
+            frame_view_clone.lock().add_frame(frame)
+        }));
+        let scope_ids = profiler.register_user_scopes(&[
+            ScopeDetails::from_scope_name("top"),
+            ScopeDetails::from_scope_name("wide"),
+            ScopeDetails::from_scope_name("deep"),
+        ]);
+
+        let mut stream = Stream::default();
+        let (top, _) = stream.begin_scope(|| 0, scope_ids[0], "");
+        for i in 0..1_000 {
+            let start_ns = i * 1_000;
+            let (wide, _) = stream.begin_scope(|| start_ns, scope_ids[1], "");
+            let deep: Vec<_> = (0..20)
+                .map(|depth| stream.begin_scope(|| start_ns + depth, scope_ids[2], "").0)
+                .collect();
+            for offset in deep.into_iter().rev() {
+                stream.end_scope(offset, start_ns + 500);
+            }
+            stream.end_scope(wide, start_ns + 1_000);
+        }
+        stream.end_scope(top, 1_000_000);
+        let (thread_info, stream_info) = crate::test_thread_stream("main", stream);
+        profiler.report_user_scopes(thread_info, &stream_info.as_stream_into_ref());
+        profiler.new_frame();
+
+        let frame_view = frame_view.lock();
+        let frame = frame_view.latest_frame().unwrap().unpacked().unwrap();
+        let frames = crate::SelectedFrames::try_from_iter(
+            frame_view.scope_collection(),
+            std::iter::once(frame),
+        )
+        .unwrap();
+
+        let ctx = egui::Context::default();
+        let raw_input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(1_000.0, 800.0),
+            )),
+            ..Default::default()
+        };
+        let mut num_shapes = 0;
+        // The first pass is needed to figure out the sizes of things.
+        for _ in 0..2 {
+            num_shapes = ctx
+                .run(raw_input.clone(), |ctx| {
+                    egui::CentralPanel::default().show(ctx, |ui| {
+                        super::ui(ui, options, frame_view.scope_collection(), &frames);
+                    });
+                })
+                .shapes
+                .len();
+        }
+        num_shapes
+    }
+
+    #[test]
+    fn narrow_scopes_paint_descendants_as_one_block() {
+        let mut options = super::Options {
+            lod_width: 0.0,
+            ..Default::default()
+        };
+        let all_scopes = num_shapes_painted(&mut options);
+        assert!(20_000 < all_scopes, "{all_scopes}");
+
+        let with_lod = num_shapes_painted(&mut Default::default());
+        assert!(with_lod < all_scopes / 5, "{with_lod} vs. {all_scopes}");
     }
 }
@@ -0,0 +1,290 @@
+//! Render the flamegraph without a window, e.g. for snapshots in CI.
+
+use std::{collections::HashMap, sync::Arc};
+
+use egui::{epaint, Color32, Pos2, Rect, TextureId};
+use puffin::{ScopeCollection, UnpackedFrameData};
+
+use crate::{flamegraph, SelectedFrames};
+
+/// Paint the flamegraph of the given frames into a PNG image of `size` pixels.
+///
+/// This uses a software rasterizer, so no GPU or window is needed.
+/// The result only depends on the input, so it can be used for visual regression tests.
+///
+/// Returns an error if `frames` is empty, or if the PNG encoding fails.
+pub fn render_frame_to_image(
+    scope_collection: &ScopeCollection,
+    frames: &[Arc<UnpackedFrameData>],
+    size: [u32; 2],
+) -> std::io::Result<Vec<u8>> {
+    puffin::profile_function!();
+
+    let frames = SelectedFrames::try_from_iter(scope_collection, frames.iter().cloned())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "No frames"))?;
+
+    let ctx = egui::Context::default();
+    ctx.set_visuals(egui::Visuals::dark());
+
+    let raw_input = egui::RawInput {
+        screen_rect: Some(Rect::from_min_size(
+            Pos2::ZERO,
+            egui::vec2(size[0] as f32, size[1] as f32),
+        )),
+        time: Some(0.0),
+        ..Default::default()
+    };
+
+    let mut options = flamegraph::Options::default();
+    let mut textures = Textures::default();
+    let mut full_output = Default::default();
+
+    // The first pass is needed to figure out the sizes of things.
+    for _ in 0..2 {
+        full_output = ctx.run(raw_input.clone(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                flamegraph::ui(ui, &mut options, scope_collection, &frames);
+            });
+        });
+        textures.update(&full_output.textures_delta);
+    }
+
+    let primitives = ctx.tessellate(full_output.shapes, full_output.pixels_per_point);
+
+    let mut image = Image::new(size, Color32::BLACK);
+    for epaint::ClippedPrimitive {
+        clip_rect,
+        primitive,
+    } in &primitives
+    {
+        if let epaint::Primitive::Mesh(mesh) = primitive {
+            image.paint_mesh(*clip_rect, mesh, &textures);
+        }
+    }
+
+    image.encode_png()
+}
+
+/// The textures egui asked us to keep, e.g. the font atlas.
+#[derive(Default)]
+struct Textures {
+    textures: HashMap<TextureId, epaint::ColorImage>,
+}
+
+impl Textures {
+    fn update(&mut self, delta: &egui::TexturesDelta) {
+        for (id, image_delta) in &delta.set {
+            let pixels: Vec<Color32> = match &image_delta.image {
+                egui::ImageData::Color(image) => image.pixels.clone(),
+                egui::ImageData::Font(image) => image.srgba_pixels(None).collect(),
+            };
+            let patch_size = image_delta.image.size();
+
+            match image_delta.pos {
+                None => {
+                    self.textures.insert(
+                        *id,
+                        epaint::ColorImage {
+                            size: patch_size,
+                            pixels,
+                        },
+                    );
+                }
+                Some([x0, y0]) => {
+                    let Some(texture) = self.textures.get_mut(id) else {
+                        continue;
+                    };
+                    for y in 0..patch_size[1] {
+                        for x in 0..patch_size[0] {
+                            texture.pixels[(y0 + y) * texture.size[0] + x0 + x] =
+                                pixels[y * patch_size[0] + x];
+                        }
+                    }
+                }
+            }
+        }
+
+        for id in &delta.free {
+            self.textures.remove(id);
+        }
+    }
+
+    /// Nearest-neighbor lookup.
+    fn sample(&self, id: TextureId, uv: Pos2) -> Color32 {
+        let Some(texture) = self.textures.get(&id) else {
+            return Color32::WHITE;
+        };
+        let [w, h] = texture.size;
+        let x = ((uv.x * w as f32) as usize).min(w.saturating_sub(1));
+        let y = ((uv.y * h as f32) as usize).min(h.saturating_sub(1));
+        texture.pixels[y * w + x]
+    }
+}
+
+/// Premultiplied sRGBA pixels.
+struct Image {
+    size: [u32; 2],
+    pixels: Vec<Color32>,
+}
+
+impl Image {
+    fn new(size: [u32; 2], background: Color32) -> Self {
+        Self {
+            size,
+            pixels: vec![background; size[0] as usize * size[1] as usize],
+        }
+    }
+
+    fn paint_mesh(&mut self, clip_rect: Rect, mesh: &epaint::Mesh, textures: &Textures) {
+        let clip_rect = clip_rect.intersect(Rect::from_min_size(
+            Pos2::ZERO,
+            egui::vec2(self.size[0] as f32, self.size[1] as f32),
+        ));
+        if !clip_rect.is_positive() {
+            return;
+        }
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+            self.paint_triangle(clip_rect, [a, b, c], mesh.texture_id, textures);
+        }
+    }
+
+    fn paint_triangle(
+        &mut self,
+        clip_rect: Rect,
+        [a, b, c]: [&epaint::Vertex; 3],
+        texture_id: TextureId,
+        textures: &Textures,
+    ) {
+        let area = edge(a.pos, b.pos, c.pos);
+        if area == 0.0 {
+            return;
+        }
+
+        let bounds = Rect::from_points(&[a.pos, b.pos, c.pos]).intersect(clip_rect);
+        if !bounds.is_positive() {
+            return;
+        }
+
+        let x_range = (bounds.min.x.floor() as u32)..(bounds.max.x.ceil() as u32);
+        for y in (bounds.min.y.floor() as u32)..(bounds.max.y.ceil() as u32) {
+            for x in x_range.clone() {
+                let p = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                if !clip_rect.contains(p) {
+                    continue;
+                }
+
+                // Barycentric coordinates, which works for both windings:
+                let wa = edge(b.pos, c.pos, p) / area;
+                let wb = edge(c.pos, a.pos, p) / area;
+                let wc = edge(a.pos, b.pos, p) / area;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+
+                let uv = Pos2::new(
+                    wa * a.uv.x + wb * b.uv.x + wc * c.uv.x,
+                    wa * a.uv.y + wb * b.uv.y + wc * c.uv.y,
+                );
+                let color = interpolate([a.color, b.color, c.color], [wa, wb, wc]);
+                let src = multiply(color, textures.sample(texture_id, uv));
+
+                let dst = &mut self.pixels[(y * self.size[0] + x) as usize];
+                *dst = blend(src, *dst);
+            }
+        }
+    }
+
+    fn encode_png(&self) -> std::io::Result<Vec<u8>> {
+        let mut png = vec![];
+        let mut encoder = png::Encoder::new(&mut png, self.size[0], self.size[1]);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let rgba: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|color| color.to_srgba_unmultiplied())
+            .collect();
+        encoder.write_header()?.write_image_data(&rgba)?;
+        Ok(png)
+    }
+}
+
+/// Twice the signed area of the triangle `a, b, p`.
+fn edge(a: Pos2, b: Pos2, p: Pos2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+fn interpolate(colors: [Color32; 3], weights: [f32; 3]) -> Color32 {
+    let channel = |i: usize| {
+        let value: f32 = (0..3)
+            .map(|v| weights[v] * colors[v].to_array()[i] as f32)
+            .sum();
+        value.round().clamp(0.0, 255.0) as u8
+    };
+    Color32::from_rgba_premultiplied(channel(0), channel(1), channel(2), channel(3))
+}
+
+fn multiply(a: Color32, b: Color32) -> Color32 {
+    let channel = |x: u8, y: u8| ((x as u32 * y as u32 + 127) / 255) as u8;
+    Color32::from_rgba_premultiplied(
+        channel(a.r(), b.r()),
+        channel(a.g(), b.g()),
+        channel(a.b(), b.b()),
+        channel(a.a(), b.a()),
+    )
+}
+
+/// Premultiplied "over" blending.
+fn blend(src: Color32, dst: Color32) -> Color32 {
+    let inv_alpha = 255 - src.a() as u32;
+    let channel = |s: u8, d: u8| (s as u32 + (d as u32 * inv_alpha + 127) / 255).min(255) as u8;
+    Color32::from_rgba_premultiplied(
+        channel(src.r(), dst.r()),
+        channel(src.g(), dst.g()),
+        channel(src.b(), dst.b()),
+        channel(src.a(), dst.a()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use puffin::{FrameView, Profiler, ScopeDetails, Stream};
+
+    #[test]
+    fn render_is_deterministic() {
+        let mut profiler = Profiler::default();
+        let frame_view = Arc::new(parking_lot::Mutex::new(FrameView::default()));
+        let frame_view_clone = frame_view.clone();
+        profiler.add_sink(Box::new(move |frame| {
+            frame_view_clone.lock().add_frame(frame)
+        }));
+
+        let scope_ids = profiler.register_user_scopes(&[ScopeDetails::from_scope_name("top")]);
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| 0, scope_ids[0], "");
+        stream.end_scope(offset, 1_000_000);
+        let (thread_info, stream_info) = crate::test_thread_stream("main", stream);
+        profiler.report_user_scopes(thread_info, &stream_info.as_stream_into_ref());
+        profiler.new_frame();
+
+        let frame_view = frame_view.lock();
+        let frame = frame_view.latest_frame().unwrap().unpacked().unwrap();
+        let render = || {
+            super::render_frame_to_image(
+                frame_view.scope_collection(),
+                &[frame.clone()],
+                [320, 200],
+            )
+            .unwrap()
+        };
+
+        let first = render();
+        assert!(first.starts_with(b"\x89PNG"));
+        assert_eq!(first, render());
+    }
+}
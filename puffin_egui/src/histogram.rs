@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use egui::*;
+use puffin::*;
+
+use crate::HOVER_COLOR;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Options {
+    /// Number of buckets to sort the frames into.
+    pub num_buckets: usize,
+
+    /// Space the buckets logarithmically, which gives more room to the short frames.
+    pub logarithmic: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            num_buckets: 32,
+            logarithmic: false,
+        }
+    }
+}
+
+/// The frames whose durations fall within `min_ns..max_ns`.
+#[derive(Clone)]
+pub struct Bucket {
+    pub min_ns: NanoSecond,
+    pub max_ns: NanoSecond,
+    pub frames: Vec<Arc<FrameData>>,
+}
+
+/// Sort the frames into `num_buckets` buckets by their duration,
+/// spanning the shortest to the longest frame.
+///
+/// Returns no buckets if there are no frames.
+pub fn buckets(frames: &[Arc<FrameData>], num_buckets: usize, logarithmic: bool) -> Vec<Bucket> {
+    let num_buckets = num_buckets.max(1);
+    let (Some(min_ns), Some(max_ns)) = (
+        frames.iter().map(|frame| frame.duration_ns()).min(),
+        frames.iter().map(|frame| frame.duration_ns()).max(),
+    ) else {
+        return vec![];
+    };
+
+    // Bucket edges in a space where the buckets are equally wide:
+    let to_space = |ns: NanoSecond| {
+        if logarithmic {
+            (ns.max(1) as f64).ln()
+        } else {
+            ns as f64
+        }
+    };
+    let from_space = |x: f64| {
+        if logarithmic {
+            x.exp().round() as NanoSecond
+        } else {
+            x.round() as NanoSecond
+        }
+    };
+    let (min_x, max_x) = (to_space(min_ns), to_space(max_ns));
+    let width = (max_x - min_x) / num_buckets as f64;
+
+    let mut buckets: Vec<Bucket> = (0..num_buckets)
+        .map(|i| Bucket {
+            min_ns: from_space(min_x + i as f64 * width),
+            max_ns: from_space(min_x + (i + 1) as f64 * width),
+            frames: vec![],
+        })
+        .collect();
+    // Don't let rounding errors drop frames at the edges:
+    buckets[0].min_ns = min_ns;
+    buckets[num_buckets - 1].max_ns = max_ns;
+
+    for frame in frames {
+        let index = if 0.0 < width {
+            ((to_space(frame.duration_ns()) - min_x) / width) as usize
+        } else {
+            0
+        };
+        buckets[index.min(num_buckets - 1)]
+            .frames
+            .push(frame.clone());
+    }
+
+    buckets
+}
+
+/// Show a histogram of the durations of the given frames.
+///
+/// Returns the frames of the bucket the user clicked, if any.
+pub fn ui(
+    ui: &mut egui::Ui,
+    options: &mut Options,
+    frames: &[Arc<FrameData>],
+) -> Option<Vec<Arc<FrameData>>> {
+    puffin::profile_function!();
+
+    ui.horizontal(|ui| {
+        ui.label("Buckets:");
+        ui.add(DragValue::new(&mut options.num_buckets).range(1..=200));
+        ui.checkbox(&mut options.logarithmic, "Logarithmic")
+            .on_hover_text(
+                "Space the buckets logarithmically, which gives more room to the short frames",
+            );
+    });
+
+    let buckets = buckets(frames, options.num_buckets, options.logarithmic);
+    if buckets.is_empty() {
+        ui.label("No frames");
+        return None;
+    }
+
+    ui.label(format!(
+        "Durations of the {} recent and slowest frames, from {:.1} ms to {:.1} ms.",
+        frames.len(),
+        buckets[0].min_ns as f64 * 1e-6,
+        buckets[buckets.len() - 1].max_ns as f64 * 1e-6,
+    ));
+
+    let max_count = buckets
+        .iter()
+        .map(|bucket| bucket.frames.len())
+        .max()
+        .unwrap_or(0);
+
+    let height = ui.available_height().clamp(64.0, 256.0);
+    let (rect, response) =
+        ui.allocate_exact_size(vec2(ui.available_width(), height), Sense::click());
+    let painter = ui.painter_at(rect);
+
+    let bar_color = ui.visuals().text_color();
+    let column_width = rect.width() / buckets.len() as f32;
+
+    let mut hovered = None;
+    for (i, bucket) in buckets.iter().enumerate() {
+        let left = rect.left() + i as f32 * column_width;
+        let column = Rect::from_x_y_ranges(left..=left + column_width, rect.y_range());
+        let is_hovered = response
+            .hover_pos()
+            .map_or(false, |pos| column.x_range().contains(pos.x));
+        if is_hovered {
+            hovered = Some(bucket);
+        }
+
+        if bucket.frames.is_empty() {
+            continue;
+        }
+        let color = if is_hovered {
+            HOVER_COLOR.into()
+        } else {
+            bar_color
+        };
+        let bar_height = (rect.height() * bucket.frames.len() as f32 / max_count as f32).max(1.0);
+        let bar = Rect::from_x_y_ranges(
+            column.left() + 1.0..=(column.right() - 1.0).max(column.left() + 1.0),
+            rect.bottom() - bar_height..=rect.bottom(),
+        );
+        painter.rect_filled(bar, 0.0, color);
+    }
+
+    let bucket = hovered?;
+    let clicked = response.clicked();
+    response.on_hover_text(format!(
+        "{} frame(s) of {:.3} - {:.3} ms\nClick to view these frames.",
+        bucket.frames.len(),
+        bucket.min_ns as f64 * 1e-6,
+        bucket.max_ns as f64 * 1e-6,
+    ));
+
+    (clicked && !bucket.frames.is_empty()).then(|| bucket.frames.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Arc};
+
+    use puffin::{FrameData, ScopeId, Stream};
+
+    fn frame(frame_index: u64, duration_ns: i64) -> Arc<FrameData> {
+        let scope_id = ScopeId(std::num::NonZeroU32::new(1).unwrap());
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| 0, scope_id, "");
+        stream.end_scope(offset, duration_ns);
+        let thread_streams = BTreeMap::from([crate::test_thread_stream("main", stream)]);
+        Arc::new(FrameData::new(frame_index, thread_streams, vec![], false).unwrap())
+    }
+
+    fn counts(buckets: &[super::Bucket]) -> Vec<usize> {
+        buckets.iter().map(|bucket| bucket.frames.len()).collect()
+    }
+
+    #[test]
+    fn linear_and_logarithmic_buckets() {
+        let frames: Vec<_> = [10, 20, 30, 40, 200, 1000]
+            .into_iter()
+            .enumerate()
+            .map(|(i, duration_ns)| frame(i as u64, duration_ns))
+            .collect();
+
+        let linear = super::buckets(&frames, 3, false);
+        assert_eq!(counts(&linear), [5, 0, 1]);
+        assert_eq!((linear[0].min_ns, linear[0].max_ns), (10, 340));
+        assert_eq!(linear[2].max_ns, 1000);
+
+        // Edges at 10, 100 and 1000:
+        let logarithmic = super::buckets(&frames, 2, true);
+        assert_eq!(counts(&logarithmic), [4, 2]);
+        assert_eq!(logarithmic[0].max_ns, 100);
+
+        assert!(super::buckets(&[], 3, false).is_empty());
+        assert_eq!(counts(&super::buckets(&frames[..1], 3, false)), [1, 0, 0]);
+    }
+}
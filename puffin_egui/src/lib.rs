@@ -11,26 +11,42 @@
 // crate-specific exceptions:
 #![allow(clippy::float_cmp, clippy::manual_range_contains)]
 
+pub mod diff;
 mod filter;
 mod flamegraph;
+#[cfg(feature = "headless")]
+mod headless;
+pub mod histogram;
 mod maybe_mut_ref;
-mod stats;
-
-pub use {egui, maybe_mut_ref::MaybeMutRef, puffin};
+mod scope_timeline;
+pub mod stats;
+
+#[cfg(feature = "headless")]
+pub use headless::render_frame_to_image;
+pub use {
+    egui,
+    flamegraph::{CanvasTheme, DragButton, InputMapping, LayoutDirection, ModifierKey},
+    maybe_mut_ref::MaybeMutRef,
+    puffin,
+};
 
 use egui::*;
 use puffin::*;
-use std::{
-    collections::{BTreeMap, BTreeSet},
-    fmt::Write as _,
-    iter,
-    sync::Arc,
-};
+use std::{collections::BTreeMap, fmt::Write as _, iter, sync::Arc};
 use time::OffsetDateTime;
 
 const ERROR_COLOR: Color32 = Color32::RED;
 const HOVER_COLOR: Rgba = Rgba::from_rgb(0.8, 0.8, 0.8);
 
+/// A thread called `name` that reported `stream`, for the thread streams of test frames.
+#[cfg(test)]
+fn test_thread_stream(name: &str, stream: Stream) -> (ThreadInfo, StreamInfo) {
+    (
+        ThreadInfo::new(None, name),
+        StreamInfo::parse(stream).unwrap(),
+    )
+}
+
 // ----------------------------------------------------------------------------
 
 /// Show the puffin profiler if [`puffin::are_scopes_on`] is true,
@@ -41,6 +57,8 @@ const HOVER_COLOR: Rgba = Rgba::from_rgb(0.8, 0.8, 0.8);
 /// else it will be shown in a floating [`egui::Window`].
 ///
 /// Closing the viewport or window will call `puffin::set_scopes_on(false)`.
+/// Unlike [`profiler_ui`], it has no toggle for [`puffin::set_scopes_on`],
+/// since turning the scopes off closes it, and there would be no way to turn them back on.
 pub fn show_viewport_if_enabled(ctx: &egui::Context) {
     if !puffin::are_scopes_on() {
         return;
@@ -56,11 +74,14 @@ pub fn show_viewport_if_enabled(ctx: &egui::Context) {
                 egui::Window::new("Puffin Profiler")
                     .default_size([1024.0, 600.0])
                     .open(&mut open)
-                    .show(ctx, profiler_ui);
-                puffin::set_scopes_on(open);
+                    .show(ctx, viewport_profiler_ui);
+                if !open {
+                    // Don't turn it back on if it was turned off from within the window.
+                    puffin::set_scopes_on(false);
+                }
             } else {
                 // A proper viewport!
-                egui::CentralPanel::default().show(ctx, profiler_ui);
+                egui::CentralPanel::default().show(ctx, viewport_profiler_ui);
                 if ctx.input(|i| i.viewport().close_requested()) {
                     puffin::set_scopes_on(false);
                 }
@@ -96,6 +117,11 @@ pub fn profiler_ui(ui: &mut egui::Ui) {
     profile_ui.ui(ui);
 }
 
+/// [`profiler_ui`] without the toggle for [`puffin::set_scopes_on`], see [`show_viewport_if_enabled`].
+fn viewport_profiler_ui(ui: &mut egui::Ui) {
+    PROFILE_UI.lock().frames_ui(ui);
+}
+
 // ----------------------------------------------------------------------------
 
 /// Show [`puffin::GlobalProfiler`], i.e. profile the app we are running in.
@@ -125,6 +151,12 @@ impl GlobalProfilerUi {
     ///
     /// Call this from within an [`egui::Window`], or use [`Self::window`] instead.
     pub fn ui(&mut self, ui: &mut egui::Ui) {
+        scopes_on_ui(ui);
+        self.frames_ui(ui);
+    }
+
+    /// [`Self::ui`] without the toggle for [`puffin::set_scopes_on`].
+    fn frames_ui(&mut self, ui: &mut egui::Ui) {
         let mut frame_view = self.global_frame_view.lock();
         self.profiler_ui
             .ui(ui, &mut MaybeMutRef::MutRef(&mut frame_view));
@@ -134,6 +166,11 @@ impl GlobalProfilerUi {
     pub fn global_frame_view(&self) -> &GlobalFrameView {
         &self.global_frame_view
     }
+
+    /// Forget all frames recorded so far, see [`ProfilerUi::clear`].
+    pub fn clear(&mut self) {
+        self.profiler_ui.clear(&mut self.global_frame_view.lock());
+    }
 }
 
 // ----------------------------------------------------------------------------
@@ -164,40 +201,72 @@ pub struct Streams {
     streams: Vec<Arc<StreamInfo>>,
     merged_scopes: Vec<MergeScope<'static>>,
     max_depth: usize,
+    /// Total number of scopes in all the streams.
+    num_scopes: usize,
 }
 
 impl Streams {
+    /// `thread_infos` are all the names the thread had in the `frames`.
     fn new(
         scope_collection: &ScopeCollection,
         frames: &[Arc<UnpackedFrameData>],
-        thread_info: &ThreadInfo,
+        thread_infos: &[ThreadInfo],
     ) -> Self {
         crate::profile_function!();
 
         let mut streams = vec![];
         for frame in frames {
-            if let Some(stream_info) = frame.thread_streams.get(thread_info) {
-                streams.push(stream_info.clone());
+            for thread_info in thread_infos {
+                if let Some(stream_info) = frame.thread_streams.get(thread_info) {
+                    streams.push(stream_info.clone());
+                }
             }
         }
 
         let merges = {
             puffin::profile_scope!("merge_scopes_for_thread");
-            puffin::merge_scopes_for_thread(scope_collection, frames, thread_info).unwrap()
+            puffin::merge_scopes_for_threads(scope_collection, frames, thread_infos).unwrap()
         };
         let merges = merges.into_iter().map(|ms| ms.into_owned()).collect();
 
         let mut max_depth = 0;
+        let mut num_scopes = 0;
         for stream_info in &streams {
             max_depth = stream_info.depth.max(max_depth);
+            num_scopes += stream_info.num_scopes;
         }
 
         Self {
             streams,
             merged_scopes: merges,
             max_depth,
+            num_scopes,
         }
     }
+
+    /// The time range of the thread, in the merged or raw view, or `None` if it has no scopes.
+    pub(crate) fn range_ns(&self, merged: bool) -> Option<(NanoSecond, NanoSecond)> {
+        let ranges: Vec<_> = if merged {
+            self.merged_scopes
+                .iter()
+                .map(|scope| {
+                    (
+                        scope.relative_start_ns,
+                        scope.relative_start_ns + scope.duration_per_frame_ns,
+                    )
+                })
+                .collect()
+        } else {
+            self.streams
+                .iter()
+                .filter(|stream_info| stream_info.num_scopes > 0)
+                .map(|stream_info| stream_info.range_ns)
+                .collect()
+        };
+        let min_ns = ranges.iter().map(|range| range.0).min()?;
+        let max_ns = ranges.iter().map(|range| range.1).max()?;
+        Some((min_ns, max_ns))
+    }
 }
 
 /// Selected frames ready to be viewed.
@@ -232,16 +301,27 @@ impl SelectedFrames {
         frames.sort_by_key(|f| f.frame_index());
         frames.dedup_by_key(|f| f.frame_index());
 
-        let mut threads: BTreeSet<ThreadInfo> = BTreeSet::new();
+        // A thread can change name (e.g. with `puffin::set_thread_role`), but keeps its id.
+        // The thread is shown under its latest name.
+        let mut threads: BTreeMap<u64, (ThreadInfo, Vec<ThreadInfo>)> = BTreeMap::new();
         for frame in &frames {
             for ti in frame.thread_streams.keys() {
-                threads.insert(ti.clone());
+                let (latest, infos) = threads.entry(ti.id).or_insert_with(|| (ti.clone(), vec![]));
+                ti.clone_into(latest);
+                if !infos.contains(ti) {
+                    infos.push(ti.clone());
+                }
             }
         }
 
         let threads: BTreeMap<ThreadInfo, Streams> = threads
-            .iter()
-            .map(|ti| (ti.clone(), Streams::new(scope_collection, &frames, ti)))
+            .into_values()
+            .map(|(latest, thread_infos)| {
+                (
+                    latest,
+                    Streams::new(scope_collection, &frames, &thread_infos),
+                )
+            })
             .collect();
 
         let mut merged_min_ns = NanoSecond::MAX;
@@ -268,6 +348,22 @@ impl SelectedFrames {
     pub fn contains(&self, frame_index: u64) -> bool {
         self.frames.iter().any(|f| f.frame_index() == frame_index)
     }
+
+    /// One synthesized frame in which each scope lasts its average duration per selected frame,
+    /// see [`puffin::average_frame`].
+    fn average(&self, scope_collection: &ScopeCollection) -> Option<Self> {
+        let average = puffin::average_frame(
+            self.frames.last().frame_index(),
+            self.threads.iter().map(|(thread_info, streams)| {
+                (thread_info.clone(), streams.merged_scopes.as_slice())
+            }),
+        )
+        .ok()?;
+        Some(Self::from_vec1(
+            scope_collection,
+            vec1::vec1![Arc::new(average)],
+        ))
+    }
 }
 
 #[derive(Clone)]
@@ -282,6 +378,8 @@ pub struct Paused {
 pub enum View {
     Flamegraph,
     Stats,
+    Histogram,
+    Diff,
 }
 
 impl Default for View {
@@ -290,6 +388,30 @@ impl Default for View {
     }
 }
 
+/// Frames that had to be decompressed on demand in order to be shown.
+#[derive(Clone, Copy, Default)]
+struct LazyUnpacks {
+    num_frames: usize,
+    duration: web_time::Duration,
+}
+
+impl LazyUnpacks {
+    /// Like [`FrameData::unpacked`], but keeps track of frames that were not already unpacked.
+    fn unpack(
+        &mut self,
+        frame: &FrameData,
+    ) -> std::result::Result<Arc<UnpackedFrameData>, impl std::fmt::Display> {
+        if frame.has_unpacked() {
+            return frame.unpacked();
+        }
+        let start = web_time::Instant::now();
+        let unpacked = frame.unpacked();
+        self.num_frames += 1;
+        self.duration += start.elapsed();
+        unpacked
+    }
+}
+
 /// Contains settings for the profiler.
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
@@ -301,6 +423,11 @@ pub struct ProfilerUi {
     /// Options for configuring how the stats page is displayed.
     #[cfg_attr(feature = "serde", serde(skip))]
     pub stats_options: stats::Options,
+    /// Options for configuring how the frame time histogram is displayed.
+    pub histogram_options: histogram::Options,
+    /// The frames the diff view compares against.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub diff_options: diff::Options,
 
     /// What view is active.
     pub view: View,
@@ -312,12 +439,32 @@ pub struct ProfilerUi {
     /// How many frames should be used for latest view
     max_num_latest: usize,
 
+    /// Show one average frame instead of all the selected frames, see [`SelectedFrames::average`].
+    average_frames: bool,
+
     /// Used to normalize frame height in frame view
     slowest_frame: f32,
 
+    /// How often (in seconds) to pack (compress) the frames that are not being viewed.
+    ///
+    /// Lower values save memory, higher values save CPU. Zero means never pack automatically.
+    pub pack_pass_interval_secs: f32,
+
     /// When did we last run a pass to pack all the frames?
     #[cfg_attr(feature = "serde", serde(skip))]
     last_pack_pass: Option<web_time::Instant>,
+
+    /// When did we last have to unpack frames on demand, and how many?
+    #[cfg_attr(feature = "serde", serde(skip))]
+    last_lazy_unpack: Option<(web_time::Instant, LazyUnpacks)>,
+
+    /// Durations of the selected scope over the recent frames.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    scope_timeline: scope_timeline::ScopeTimeline,
+
+    /// Frames the user pinned, so they stay around as new frames stream in. Ordered by frame index.
+    #[cfg_attr(feature = "serde", serde(with = "pinned_frames"))]
+    pinned: Vec<Arc<FrameData>>,
 }
 
 impl Default for ProfilerUi {
@@ -325,11 +472,18 @@ impl Default for ProfilerUi {
         Self {
             flamegraph_options: Default::default(),
             stats_options: Default::default(),
+            histogram_options: Default::default(),
+            diff_options: Default::default(),
             view: Default::default(),
             paused: None,
             max_num_latest: 1,
+            average_frames: false,
             slowest_frame: 0.16,
+            pack_pass_interval_secs: 1.0,
             last_pack_pass: None,
+            last_lazy_unpack: None,
+            scope_timeline: Default::default(),
+            pinned: Default::default(),
         }
     }
 }
@@ -339,6 +493,45 @@ impl ProfilerUi {
         self.paused = None;
     }
 
+    /// Forget all frames in `frame_view`, and stop showing any of them, e.g. to start a fresh measurement.
+    ///
+    /// Unlike [`Self::reset`], this also unpins all frames. See [`FrameView::clear`].
+    pub fn clear(&mut self, frame_view: &mut FrameView) {
+        frame_view.clear();
+        self.reset();
+        self.pinned.clear();
+        self.scope_timeline = Default::default();
+    }
+
+    /// The frames the user pinned, ordered by frame index.
+    pub fn pinned(&self) -> &[Arc<FrameData>] {
+        &self.pinned
+    }
+
+    /// Pin the frame, or unpin it if it is already pinned.
+    ///
+    /// Pinned frames are kept unpacked and shown in their own row, no matter how many new frames arrive.
+    pub fn toggle_pinned(&mut self, frame: Arc<FrameData>) {
+        if let Some(index) = self.pinned_index(frame.frame_index()) {
+            self.pinned.remove(index);
+        } else {
+            let _ = frame.unpacked(); // Make sure we can show it quickly
+            self.pinned.push(frame);
+            self.pinned.sort_by_key(|frame| frame.frame_index());
+        }
+    }
+
+    /// Forget all pinned frames.
+    pub fn clear_pinned(&mut self) {
+        self.pinned.clear();
+    }
+
+    fn pinned_index(&self, frame_index: u64) -> Option<usize> {
+        self.pinned
+            .iter()
+            .position(|frame| frame.frame_index() == frame_index)
+    }
+
     /// Show an [`egui::Window`] with the profiler contents.
     ///
     /// If you want to control the window yourself, use [`Self::ui`] instead.
@@ -386,6 +579,55 @@ impl ProfilerUi {
         }
     }
 
+    /// When paused on a single frame, step to the previous/next frame with the arrow keys,
+    /// or jump to the oldest/newest frame with Home/End.
+    fn step_paused_frame_with_keys(
+        &mut self,
+        ui: &egui::Ui,
+        frame_view: &FrameView,
+        lazy_unpacks: &mut LazyUnpacks,
+    ) {
+        let Some(paused) = &self.paused else {
+            return;
+        };
+        if paused.selected.frames.len() != 1 || ui.memory(|m| m.focused().is_some()) {
+            return;
+        }
+
+        let uniq = &paused.frames.uniq;
+        let selected_index = paused.selected.frames.first().frame_index();
+        let Some(current) = uniq
+            .iter()
+            .position(|frame| frame.frame_index() == selected_index)
+        else {
+            return;
+        };
+        let last = uniq.len() - 1;
+
+        let new = ui.input(|i| {
+            if i.key_pressed(egui::Key::ArrowLeft) {
+                Some(if current == 0 { last } else { current - 1 })
+            } else if i.key_pressed(egui::Key::ArrowRight) {
+                Some(if current == last { 0 } else { current + 1 })
+            } else if i.key_pressed(egui::Key::Home) {
+                Some(0)
+            } else if i.key_pressed(egui::Key::End) {
+                Some(last)
+            } else {
+                None
+            }
+        });
+
+        if let Some(new) = new.filter(|&new| new != current) {
+            if let Ok(frame) = lazy_unpacks.unpack(&uniq[new]) {
+                self.pause_and_select(
+                    frame_view,
+                    SelectedFrames::from_vec1(frame_view.scope_collection(), vec1::vec1![frame]),
+                );
+            }
+        }
+    }
+
     fn is_selected(&self, frame_view: &FrameView, frame_index: u64) -> bool {
         if let Some(paused) = &self.paused {
             paused.selected.contains(frame_index)
@@ -407,17 +649,20 @@ impl ProfilerUi {
     }
 
     fn run_pack_pass_if_needed(&mut self, frame_view: &FrameView) {
-        if !frame_view.pack_frames() {
+        if frame_view.pack_policy() != PackPolicy::Always || self.pack_pass_interval_secs <= 0.0 {
             return;
         }
         let last_pack_pass = self
             .last_pack_pass
             .get_or_insert_with(web_time::Instant::now);
         let time_since_last_pack = last_pack_pass.elapsed();
-        if time_since_last_pack > web_time::Duration::from_secs(1) {
+        if time_since_last_pack.as_secs_f32() > self.pack_pass_interval_secs {
             puffin::profile_scope!("pack_pass");
             for frame in self.all_known_frames(frame_view) {
-                if !self.is_selected(frame_view, frame.frame_index()) {
+                let frame_index = frame.frame_index();
+                if !self.is_selected(frame_view, frame_index)
+                    && self.pinned_index(frame_index).is_none()
+                {
                     frame.pack();
                 }
             }
@@ -435,8 +680,13 @@ impl ProfilerUi {
         self.run_pack_pass_if_needed(frame_view);
 
         if !puffin::are_scopes_on() {
-            ui.colored_label(ERROR_COLOR, "The puffin profiler is OFF!")
-                .on_hover_text("Turn it on with puffin::set_scopes_on(true)");
+            ui.horizontal(|ui| {
+                ui.colored_label(ERROR_COLOR, "The puffin profiler is OFF!")
+                    .on_hover_text("Turn it on with puffin::set_scopes_on(true)");
+                if ui.button("Turn on").clicked() {
+                    puffin::set_scopes_on(true);
+                }
+            });
         }
 
         if frame_view.is_empty() {
@@ -452,6 +702,9 @@ impl ProfilerUi {
 
     fn ui_impl(&mut self, ui: &mut egui::Ui, frame_view: &mut MaybeMutRef<'_, FrameView>) {
         let mut hovered_frame = None;
+        let mut lazy_unpacks = LazyUnpacks::default();
+
+        self.step_paused_frame_with_keys(ui, frame_view, &mut lazy_unpacks);
 
         egui::CollapsingHeader::new("Frame history")
             .default_open(false)
@@ -460,7 +713,7 @@ impl ProfilerUi {
             });
 
         let frames = if let Some(frame) = hovered_frame {
-            match frame.unpacked() {
+            match lazy_unpacks.unpack(&frame) {
                 Ok(frame) => {
                     SelectedFrames::try_from_iter(frame_view.scope_collection(), iter::once(frame))
                 }
@@ -475,7 +728,7 @@ impl ProfilerUi {
             puffin::profile_scope!("select_latest_frames");
             let latest = frame_view
                 .latest_frames(self.max_num_latest)
-                .map(|frame| frame.unpacked())
+                .map(|frame| lazy_unpacks.unpack(frame))
                 .filter_map(|unpacked| unpacked.ok());
 
             SelectedFrames::try_from_iter(frame_view.scope_collection(), latest)
@@ -496,7 +749,10 @@ impl ProfilerUi {
             if self.paused.is_some() {
                 if ui
                     .add_sized(play_pause_button_size, egui::Button::new("▶"))
-                    .on_hover_text("Show latest data. Toggle with space.")
+                    .on_hover_text(
+                        "Show latest data. Toggle with space.\n\
+                        Step through the frames with the arrow keys, and Home/End.",
+                    )
                     .clicked()
                     || space_pressed
                 {
@@ -512,7 +768,7 @@ impl ProfilerUi {
                     {
                         let latest = frame_view.latest_frame();
                         if let Some(latest) = latest {
-                            if let Ok(latest) = latest.unpacked() {
+                            if let Ok(latest) = lazy_unpacks.unpack(&latest) {
                                 self.pause_and_select(
                                     frame_view,
                                     SelectedFrames::from_vec1(
@@ -527,6 +783,7 @@ impl ProfilerUi {
             }
 
             frames_info_ui(ui, &frames);
+            self.lazy_unpack_ui(ui, lazy_unpacks);
         });
 
         if frames.frames.len() == 1 {
@@ -534,7 +791,7 @@ impl ProfilerUi {
 
             let num_scopes = frame.meta.num_scopes;
             let realistic_ns_overhead = 200.0; // Micro-benchmarks puts it at 50ns, but real-life tests show it's much higher.
-            let overhead_ms = num_scopes as f64 * 1.0e-6 * realistic_ns_overhead;
+            let overhead_ms = frame.meta.estimated_overhead_ns(realistic_ns_overhead) * 1.0e-6;
             if overhead_ms > 1.0 {
                 let overhead = if overhead_ms < 2.0 {
                     format!("{:.1} ms", overhead_ms)
@@ -558,22 +815,134 @@ impl ProfilerUi {
             ui.label("View:");
             ui.selectable_value(&mut self.view, View::Flamegraph, "Flamegraph");
             ui.selectable_value(&mut self.view, View::Stats, "Table");
+            ui.selectable_value(&mut self.view, View::Histogram, "Histogram");
+            ui.selectable_value(&mut self.view, View::Diff, "Diff");
+
+            if frames.frames.len() > 1 {
+                ui.separator();
+                ui.checkbox(&mut self.average_frames, "Average frame")
+                    .on_hover_text(
+                        "Show one synthesized frame in which each scope lasts its average duration \
+                        over the selected frames, so the flamegraph and table can be inspected scope by scope.",
+                    );
+            }
         });
 
+        let frames = if self.average_frames && frames.frames.len() > 1 {
+            if let Some(average) = frames.average(frame_view.scope_collection()) {
+                ui.label(format!(
+                    "Average of {} frames: all durations are per-frame averages.",
+                    frames.frames.len()
+                ));
+                average
+            } else {
+                frames
+            }
+        } else {
+            frames
+        };
+
         match self.view {
-            View::Flamegraph => flamegraph::ui(
+            View::Flamegraph => {
+                self.scope_timeline_ui(ui, frame_view);
+                flamegraph::ui(
+                    ui,
+                    &mut self.flamegraph_options,
+                    frame_view.scope_collection(),
+                    &frames,
+                );
+            }
+            View::Stats => stats::ui(
                 ui,
-                &mut self.flamegraph_options,
+                &mut self.stats_options,
                 frame_view.scope_collection(),
-                &frames,
+                &frames.frames,
             ),
-            View::Stats => stats::ui(
+            View::Diff => diff::ui(
                 ui,
-                &mut self.stats_options,
+                &mut self.diff_options,
                 frame_view.scope_collection(),
                 &frames.frames,
             ),
+            View::Histogram => {
+                let uniq = self.frames(frame_view).uniq;
+                let clicked = histogram::ui(ui, &mut self.histogram_options, &uniq);
+                if let Some(clicked) = clicked {
+                    let unpacked = clicked.iter().filter_map(|frame| frame.unpacked().ok());
+                    if let Some(selected) =
+                        SelectedFrames::try_from_iter(frame_view.scope_collection(), unpacked)
+                    {
+                        self.pause_and_select(frame_view, selected);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Show the duration of the selected scope over the recent frames.
+    fn scope_timeline_ui(&mut self, ui: &mut egui::Ui, frame_view: &FrameView) {
+        let Some(scope_id) = self.flamegraph_options.selected_scope else {
+            return;
+        };
+
+        let name = frame_view
+            .scope_collection()
+            .fetch_by_id(&scope_id)
+            .map_or_else(|| "?".to_owned(), |details| details.name().to_string());
+        ui.horizontal(|ui| {
+            ui.label(format!("{name} over the recent frames:"));
+            if ui
+                .small_button("ｘ")
+                .on_hover_text("Deselect scope")
+                .clicked()
+            {
+                self.flamegraph_options.selected_scope = None;
+            }
+        });
+
+        let recent = self.frames(frame_view).recent;
+        let clicked = self
+            .scope_timeline
+            .ui(ui, scope_id, frame_view.scope_collection(), &recent);
+        if let Some(frame) = clicked {
+            if let Ok(frame) = frame.unpacked() {
+                self.pause_and_select(
+                    frame_view,
+                    SelectedFrames::from_vec1(frame_view.scope_collection(), vec1::vec1![frame]),
+                );
+            }
+        }
+    }
+
+    /// Show a subtle note for a while after frames had to be unpacked on demand.
+    fn lazy_unpack_ui(&mut self, ui: &mut egui::Ui, lazy_unpacks: LazyUnpacks) {
+        const SHOW_FOR: web_time::Duration = web_time::Duration::from_secs(2);
+
+        if lazy_unpacks.num_frames > 0 {
+            self.last_lazy_unpack = Some((web_time::Instant::now(), lazy_unpacks));
+        }
+
+        let Some((when, lazy_unpacks)) = self.last_lazy_unpack else {
+            return;
+        };
+        let elapsed = when.elapsed();
+        if SHOW_FOR < elapsed {
+            self.last_lazy_unpack = None;
+            return;
         }
+        ui.ctx().request_repaint_after(SHOW_FOR - elapsed);
+
+        ui.weak(format!(
+            "(unpacked {} frame(s) in {:.1} ms)",
+            lazy_unpacks.num_frames,
+            lazy_unpacks.duration.as_secs_f64() * 1e3
+        ))
+        .on_hover_text(
+            "Older frames are kept packed (compressed) to save memory, \
+            and are unpacked on demand when viewed.\n\
+            If this causes stutter, consider increasing the number of recent frames kept \
+            unpacked (max_recent), or disabling frame packing.",
+        );
     }
 
     /// Returns hovered, if any
@@ -604,6 +973,15 @@ impl ProfilerUi {
                         stats.bytes_of_ram_used() as f64 * 1e-6
                     ));
 
+                    #[cfg(feature = "lock_contention")]
+                    lock_contention_ui(ui);
+
+                    sink_overhead_ui(ui);
+
+                    if frame_view.pack_policy() == PackPolicy::Always {
+                        pack_pass_interval_ui(ui, &mut self.pack_pass_interval_secs);
+                    }
+
                     if let Some(frame_view) = frame_view.as_mut() {
                         max_frames_ui(ui, frame_view, uniq);
                         if self.paused.is_none() {
@@ -611,12 +989,44 @@ impl ProfilerUi {
                         }
                     }
                 });
+
+                if let Some(frame_view) = frame_view.as_mut() {
+                    if ui
+                        .button("Clear all")
+                        .on_hover_text("Forget all frames, e.g. to start a fresh measurement")
+                        .clicked()
+                    {
+                        self.clear(frame_view);
+                    }
+                }
             });
             ui.end_row();
 
-            ui.label("Recent:");
+            if !frames.recent.is_empty() {
+                ui.label("Scopes:").on_hover_text(
+                    "Number of scopes in each recent frame.\n\
+                    A steady climb suggests scopes are piling up, e.g. one per item in a growing list.",
+                );
+                num_scopes_sparkline_ui(ui, &frames.recent);
+                ui.end_row();
+            }
+
+            ui.vertical(|ui| {
+                ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+                ui.label("Recent:");
+                if let Some(frame_view) = frame_view.as_mut() {
+                    if ui
+                        .button("Clear")
+                        .on_hover_text("Clear the recent frames, but keep the slowest ones")
+                        .clicked()
+                    {
+                        frame_view.clear_recent();
+                    }
+                }
+            });
 
-            Frame::dark_canvas(ui.style()).show(ui, |ui| {
+            let theme = self.flamegraph_options.theme;
+            theme.canvas(ui, |ui| {
                 egui::ScrollArea::horizontal()
                     .stick_to_right(true)
                     .drag_to_scroll(false)
@@ -648,7 +1058,7 @@ impl ProfilerUi {
             });
 
             // Show as many slow frames as we fit in the view:
-            Frame::dark_canvas(ui.style()).show(ui, |ui| {
+            theme.canvas(ui, |ui| {
                 let num_fit = (ui.available_size_before_wrap().x
                     / self.flamegraph_options.frame_width)
                     .floor();
@@ -669,6 +1079,40 @@ impl ProfilerUi {
                     slowest_frame as f32,
                 );
             });
+            ui.end_row();
+
+            if !self.pinned.is_empty() {
+                ui.vertical(|ui| {
+                    ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+                    ui.label("Pinned:");
+                    if ui.button("Clear").clicked() {
+                        self.clear_pinned();
+                    }
+                });
+
+                theme.canvas(ui, |ui| {
+                    let pinned = self.pinned.clone();
+                    let slowest_frame = pinned
+                        .iter()
+                        .map(|frame| frame.duration_ns())
+                        .max()
+                        .unwrap_or_default();
+                    egui::ScrollArea::horizontal()
+                        .id_source("pinned_frames")
+                        .drag_to_scroll(false)
+                        .show(ui, |ui| {
+                            self.show_frame_list(
+                                ui,
+                                frame_view,
+                                &pinned,
+                                true,
+                                &mut hovered_frame,
+                                slowest_frame as f32,
+                            );
+                        });
+                });
+                ui.end_row();
+            }
         });
 
         hovered_frame
@@ -747,10 +1191,20 @@ impl ProfilerUi {
                         Id::new("puffin_frame_tooltip"),
                         |ui| {
                             ui.label(format!("{:.1} ms", frame.duration_ns() as f64 * 1e-6));
+                            let pin_text = if self.pinned_index(frame.frame_index()).is_some() {
+                                "Press P to unpin"
+                            } else {
+                                "Press P to pin"
+                            };
+                            ui.weak(pin_text);
                         },
                     );
                 }
 
+                if is_hovered && ui.input(|i| i.key_pressed(Key::P)) {
+                    self.toggle_pinned(frame.clone());
+                }
+
                 if response.dragged() {
                     if let (Some(start), Some(curr)) =
                         ui.input(|i| (i.pointer.press_origin(), i.pointer.interact_pos()))
@@ -767,7 +1221,7 @@ impl ProfilerUi {
                 }
 
                 let color = if is_selected {
-                    Rgba::WHITE
+                    ui.visuals().strong_text_color().into()
                 } else if is_hovered {
                     HOVER_COLOR
                 } else {
@@ -802,6 +1256,71 @@ impl ProfilerUi {
     }
 }
 
+/// A toggle button for [`puffin::set_scopes_on`], showing the current state.
+fn scopes_on_ui(ui: &mut egui::Ui) {
+    let mut on = puffin::are_scopes_on();
+    let text = if on {
+        RichText::new("⏺ Profiling").color(ERROR_COLOR)
+    } else {
+        RichText::new("⏺ Profiling")
+    };
+    if ui
+        .toggle_value(&mut on, text)
+        .on_hover_text("Turn the puffin profiler scopes on or off (puffin::set_scopes_on)")
+        .changed()
+    {
+        puffin::set_scopes_on(on);
+    }
+}
+
+/// Plot the number of scopes of each frame, oldest first, showing the exact count on hover.
+fn num_scopes_sparkline_ui(ui: &mut egui::Ui, frames: &[Arc<FrameData>]) {
+    let Some(max_scopes) = frames.iter().map(|frame| frame.meta().num_scopes).max() else {
+        return;
+    };
+
+    let (rect, response) = ui.allocate_exact_size(vec2(ui.available_width(), 24.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+
+    let last = frames.len() - 1;
+    let x = |i: usize| {
+        if last == 0 {
+            rect.right()
+        } else {
+            lerp(rect.x_range(), i as f32 / last as f32)
+        }
+    };
+    let y = |num_scopes: usize| {
+        rect.bottom() - rect.height() * num_scopes as f32 / max_scopes.max(1) as f32
+    };
+
+    let points = frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| pos2(x(i), y(frame.meta().num_scopes)))
+        .collect();
+    painter.add(Shape::line(
+        points,
+        Stroke::new(1.0, ui.visuals().text_color()),
+    ));
+
+    let Some(pos) = response.hover_pos() else {
+        return;
+    };
+    let i = if last == 0 {
+        0
+    } else {
+        (remap(pos.x, rect.x_range(), 0.0..=last as f32).round() as usize).min(last)
+    };
+    let frame = &frames[i];
+    painter.circle_filled(pos2(x(i), y(frame.meta().num_scopes)), 2.5, HOVER_COLOR);
+    response.on_hover_text(format!(
+        "Frame #{}: {} scopes (max {max_scopes})",
+        frame.frame_index(),
+        frame.meta().num_scopes
+    ));
+}
+
 fn frames_info_ui(ui: &mut egui::Ui, selection: &SelectedFrames) {
     let mut sum_ns = 0;
     let mut sum_scopes = 0;
@@ -832,27 +1351,47 @@ fn frames_info_ui(ui: &mut egui::Ui, selection: &SelectedFrames) {
         sum_ns as f64 * 1e-6,
         selection.threads.len(),
     );
-    if let Some(time) = format_time(selection.raw_range_ns.0) {
+    let start_ns = selection.raw_range_ns.0;
+    let recorded_ns = selection
+        .frames
+        .first()
+        .meta
+        .wall_clock_ns_at(start_ns)
+        .or_else(|| is_nanos_since_epoch(start_ns).then_some(start_ns));
+    if let Some(time) = recorded_ns.and_then(format_time) {
         let _ = write!(&mut info, " Recorded {time}.");
     }
 
-    ui.label(info);
+    let thread_stats = FrameStats::per_thread(selection.frames.iter().map(Arc::as_ref));
+    let thread_times = thread_stats
+        .iter()
+        .map(|(thread_info, stats)| {
+            format!(
+                "{}: {:.1} ms",
+                thread_info.name,
+                stats.total_ns as f64 * 1e-6
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    ui.label(info).on_hover_text(thread_times);
 }
 
-fn format_time(nanos: NanoSecond) -> Option<String> {
+/// Heuristic: is this timestamp counting from the Unix epoch (as opposed to e.g. app start)?
+fn is_nanos_since_epoch(nanos: NanoSecond) -> bool {
     let years_since_epoch = nanos / 1_000_000_000 / 60 / 60 / 24 / 365;
-    if 50 <= years_since_epoch && years_since_epoch <= 150 {
-        let offset = OffsetDateTime::from_unix_timestamp_nanos(nanos as i128).ok()?;
+    (50..=150).contains(&years_since_epoch)
+}
 
-        let format_desc = time::macros::format_description!(
-            "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3]"
-        );
-        let datetime = offset.format(&format_desc).ok()?;
+/// Format a Unix time in nanoseconds.
+fn format_time(nanos: NanoSecond) -> Option<String> {
+    let offset = OffsetDateTime::from_unix_timestamp_nanos(nanos as i128).ok()?;
 
-        Some(datetime)
-    } else {
-        None // `nanos` is likely not counting from epoch.
-    }
+    let format_desc = time::macros::format_description!(
+        "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3]"
+    );
+    offset.format(&format_desc).ok()
 }
 
 fn max_frames_ui(ui: &mut egui::Ui, frame_view: &mut FrameView, uniq: &[Arc<FrameData>]) {
@@ -880,6 +1419,77 @@ fn max_frames_ui(ui: &mut egui::Ui, frame_view: &mut FrameView, uniq: &[Arc<Fram
             memory_length as f64 / 60.0 / frames_per_second,
             memory_length as f64 * bytes as f64 / uniq.len() as f64 * 1e-6,
         ));
+
+        if let Some(ratio) = stats.packed_ratio() {
+            ui.label(format!(
+                "packed frames are {:.0}% of original size",
+                ratio * 100.0
+            ))
+            .on_hover_text(format!(
+                "{:.1} MB packed, {:.1} MB unpacked",
+                stats.packed_bytes() as f64 * 1e-6,
+                stats.unpacked_bytes() as f64 * 1e-6,
+            ));
+        }
+    });
+}
+
+#[cfg(feature = "lock_contention")]
+fn lock_contention_ui(ui: &mut egui::Ui) {
+    let contention = GlobalProfiler::lock_contention();
+
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "GlobalProfiler lock: {} of {} locks contended, waited {:.1} ms in total ({:.1} µs max).",
+            contention.num_contended,
+            contention.num_locks,
+            contention.total_wait_ns as f64 * 1e-6,
+            contention.max_wait_ns as f64 * 1e-3,
+        ));
+        if ui.button("Reset").clicked() {
+            GlobalProfiler::reset_lock_contention();
+        }
+    });
+}
+
+fn sink_overhead_ui(ui: &mut egui::Ui) {
+    let mut measure = GlobalProfiler::is_measuring_sink_overhead();
+    if ui
+        .checkbox(&mut measure, "Measure profiler overhead")
+        .on_hover_text("Measure how long puffin spends handing each finished frame to its sinks (this viewer, servers, recorders, …)")
+        .changed()
+    {
+        GlobalProfiler::set_measure_sink_overhead(measure);
+    }
+
+    if !measure {
+        return;
+    }
+
+    let overhead = GlobalProfiler::sink_overhead();
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "Profiler overhead this frame: {:.2} ms ({:.2} ms mean, {:.2} ms max over {} frames).",
+            overhead.last_ns as f64 * 1e-6,
+            overhead.mean_ns() as f64 * 1e-6,
+            overhead.max_ns as f64 * 1e-6,
+            overhead.num_frames,
+        ));
+        if ui.button("Reset").clicked() {
+            GlobalProfiler::reset_sink_overhead();
+        }
+    });
+}
+
+fn pack_pass_interval_ui(ui: &mut egui::Ui, pack_pass_interval_secs: &mut f32) {
+    ui.horizontal(|ui| {
+        ui.label("Pack frames every:");
+        ui.add(
+            egui::Slider::new(pack_pass_interval_secs, 0.0..=60.0)
+                .logarithmic(true)
+                .suffix(" s"),
+        )
+        .on_hover_text("How often to compress frames that are not being viewed. 0 = never.");
     });
 }
 
@@ -889,3 +1499,56 @@ fn max_num_latest_ui(ui: &mut egui::Ui, max_num_latest: &mut usize) {
         ui.add(egui::Slider::new(max_num_latest, 1..=100).logarithmic(true));
     });
 }
+
+/// Serializes the pinned frames of [`ProfilerUi`] as their unpacked streams.
+#[cfg(feature = "serde")]
+mod pinned_frames {
+    use std::{collections::BTreeMap, sync::Arc};
+
+    use puffin::{FrameData, FrameIndex, StreamInfo, ThreadInfo};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize)]
+    struct PinnedRef<'a> {
+        frame_index: FrameIndex,
+        thread_streams: &'a BTreeMap<ThreadInfo, Arc<StreamInfo>>,
+    }
+
+    #[derive(Deserialize)]
+    struct Pinned {
+        frame_index: FrameIndex,
+        thread_streams: BTreeMap<ThreadInfo, StreamInfo>,
+    }
+
+    pub fn serialize<S: Serializer>(
+        frames: &[Arc<FrameData>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        // Frames that fail to unpack are dropped.
+        let unpacked: Vec<_> = frames
+            .iter()
+            .filter_map(|frame| frame.unpacked().ok())
+            .collect();
+        let pinned: Vec<_> = unpacked
+            .iter()
+            .map(|frame| PinnedRef {
+                frame_index: frame.frame_index(),
+                thread_streams: &frame.thread_streams,
+            })
+            .collect();
+        pinned.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Arc<FrameData>>, D::Error> {
+        let pinned = Vec::<Pinned>::deserialize(deserializer)?;
+        Ok(pinned
+            .into_iter()
+            .filter_map(|pinned| {
+                FrameData::new(pinned.frame_index, pinned.thread_streams, vec![], false).ok()
+            })
+            .map(Arc::new)
+            .collect())
+    }
+}
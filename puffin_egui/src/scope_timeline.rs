@@ -0,0 +1,199 @@
+use std::{collections::HashMap, sync::Arc};
+
+use egui::*;
+use puffin::*;
+
+use crate::HOVER_COLOR;
+
+/// How long a scope took in each of the recent frames, one pixel column per frame.
+///
+/// Unpacking the frames is expensive, so the durations are cached per frame,
+/// and only recomputed when another scope is selected.
+#[derive(Clone, Default)]
+pub struct ScopeTimeline {
+    scope_id: Option<ScopeId>,
+
+    /// `None` if the scope didn't run during that frame.
+    durations: HashMap<FrameIndex, Option<NanoSecond>>,
+}
+
+impl ScopeTimeline {
+    /// Returns the frame the user clicked, if any.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        scope_id: ScopeId,
+        scope_collection: &ScopeCollection,
+        frames: &[Arc<FrameData>],
+    ) -> Option<Arc<FrameData>> {
+        puffin::profile_function!();
+
+        if self.scope_id != Some(scope_id) {
+            self.scope_id = Some(scope_id);
+            self.durations.clear();
+        }
+
+        // Forget frames that are no longer around:
+        if self.durations.len() > frames.len() {
+            let indices: std::collections::HashSet<FrameIndex> =
+                frames.iter().map(|frame| frame.frame_index()).collect();
+            self.durations.retain(|index, _| indices.contains(index));
+        }
+
+        let durations: Vec<(&Arc<FrameData>, Option<NanoSecond>)> = frames
+            .iter()
+            .map(|frame| {
+                let duration = *self
+                    .durations
+                    .entry(frame.frame_index())
+                    .or_insert_with(|| {
+                        frame
+                            .unpacked()
+                            .ok()
+                            .and_then(|frame| scope_duration_in_frame(&frame, scope_id))
+                    });
+                (frame, duration)
+            })
+            .collect();
+
+        let max_ns = durations
+            .iter()
+            .filter_map(|(_, duration)| *duration)
+            .max()
+            .unwrap_or(0);
+
+        let (rect, response) =
+            ui.allocate_exact_size(vec2(ui.available_width(), 32.0), Sense::click());
+        let painter = ui.painter_at(rect);
+
+        let bar_color = ui.visuals().text_color();
+        let gap_color = ui.visuals().weak_text_color().gamma_multiply(0.5);
+
+        // Newest frame to the right, one pixel per frame:
+        let num_fit = (rect.width().floor() as usize).min(durations.len());
+        let shown = &durations[durations.len() - num_fit..];
+        let left = rect.right() - num_fit as f32;
+
+        let mut hovered = None;
+        for (i, (frame, duration)) in shown.iter().enumerate() {
+            let column =
+                Rect::from_x_y_ranges(left + i as f32..=left + i as f32 + 1.0, rect.y_range());
+            let is_hovered = response
+                .hover_pos()
+                .map_or(false, |pos| column.x_range().contains(pos.x));
+            if is_hovered {
+                hovered = Some((frame, *duration));
+            }
+
+            let color = if is_hovered {
+                HOVER_COLOR.into()
+            } else {
+                bar_color
+            };
+            match duration {
+                Some(duration) if max_ns > 0 => {
+                    let height = (rect.height() * *duration as f32 / max_ns as f32).max(1.0);
+                    let bar = Rect::from_x_y_ranges(
+                        column.x_range(),
+                        rect.bottom() - height..=rect.bottom(),
+                    );
+                    painter.rect_filled(bar, 0.0, color);
+                }
+                _ => {
+                    // The scope didn't run in this frame:
+                    let tick = Rect::from_x_y_ranges(
+                        column.x_range(),
+                        rect.bottom() - 1.0..=rect.bottom(),
+                    );
+                    painter.rect_filled(tick, 0.0, gap_color);
+                }
+            }
+        }
+
+        let (frame, duration) = hovered?;
+        let name = scope_collection
+            .fetch_by_id(&scope_id)
+            .map_or_else(|| "scope".to_owned(), |details| details.name().to_string());
+        let text = match duration {
+            Some(duration) => format!(
+                "Frame #{}: {name} took {:.3} ms",
+                frame.frame_index(),
+                duration as f64 * 1e-6
+            ),
+            None => format!("Frame #{}: {name} did not run", frame.frame_index()),
+        };
+        let clicked = response.clicked();
+        response.on_hover_text(format!("{text}\nClick to view this frame."));
+
+        clicked.then(|| (*frame).clone())
+    }
+}
+
+/// Total time spent in the scope in all threads, or `None` if it never ran.
+///
+/// Recursive calls are only counted once.
+fn scope_duration_in_frame(frame: &UnpackedFrameData, scope_id: ScopeId) -> Option<NanoSecond> {
+    let mut total: Option<NanoSecond> = None;
+    for stream_info in frame.thread_streams.values() {
+        for scope in Reader::from_start(&stream_info.stream).flatten() {
+            add_scope_duration(&stream_info.stream, &scope, scope_id, &mut total);
+        }
+    }
+    total
+}
+
+fn add_scope_duration(
+    stream: &Stream,
+    scope: &Scope<'_>,
+    scope_id: ScopeId,
+    total: &mut Option<NanoSecond>,
+) {
+    if scope.id == scope_id {
+        *total.get_or_insert(0) += scope.record.duration_ns;
+        return;
+    }
+    let Ok(children) = Reader::with_offset(stream, scope.child_begin_position) else {
+        return;
+    };
+    for child in children.flatten() {
+        add_scope_duration(stream, &child, scope_id, total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use puffin::{Profiler, ScopeDetails, Stream, UnpackedFrameData};
+
+    #[test]
+    fn recursive_and_missing_scopes() {
+        let scope_ids = Profiler::default().register_user_scopes(&[
+            ScopeDetails::from_scope_name("outer"),
+            ScopeDetails::from_scope_name("inner"),
+            ScopeDetails::from_scope_name("unused"),
+        ]);
+        let [outer, inner, unused] = [scope_ids[0], scope_ids[1], scope_ids[2]];
+
+        // inner(0..100) { inner(10..50) }, outer(100..400) { inner(200..300) }
+        let mut stream = Stream::default();
+        let (a, _) = stream.begin_scope(|| 0, inner, "");
+        let (b, _) = stream.begin_scope(|| 10, inner, "");
+        stream.end_scope(b, 50);
+        stream.end_scope(a, 100);
+        let (c, _) = stream.begin_scope(|| 100, outer, "");
+        let (d, _) = stream.begin_scope(|| 200, inner, "");
+        stream.end_scope(d, 300);
+        stream.end_scope(c, 400);
+
+        let frame = UnpackedFrameData::new(
+            0,
+            BTreeMap::from([crate::test_thread_stream("main", stream)]),
+        )
+        .unwrap();
+
+        assert_eq!(super::scope_duration_in_frame(&frame, inner), Some(200));
+        assert_eq!(super::scope_duration_in_frame(&frame, outer), Some(300));
+        assert_eq!(super::scope_duration_in_frame(&frame, unused), None);
+    }
+}
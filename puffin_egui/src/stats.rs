@@ -1,3 +1,7 @@
+//! The stats table, for finding scopes that are called a lot.
+
+use std::{collections::BTreeSet, sync::Arc};
+
 use egui::TextBuffer;
 use puffin::*;
 
@@ -6,29 +10,142 @@ use crate::filter::Filter;
 #[derive(Clone, Debug, Default)]
 pub struct Options {
     filter: Filter,
+
+    /// Names of the threads whose scopes are left out of the table.
+    ///
+    /// Stored this way around so that new threads are shown by default.
+    hidden_threads: BTreeSet<String>,
+
+    /// URL to open when ctrl-clicking the location of a scope, e.g. to jump to it in an editor.
+    ///
+    /// `{path}` is replaced with the file path of the scope and `{line}` with its line number,
+    /// e.g. `vscode://file/home/me/my_project/{path}:{line}`.
+    /// The file paths are usually relative to the crate, so the template needs to include the root.
+    ///
+    /// Ctrl-clicking always copies `file:line` to the clipboard.
+    pub location_url: Option<String>,
+
+    sort_by: SortBy,
+}
+
+/// The column the stats table is sorted by, largest first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum SortBy {
+    #[default]
+    Count,
+    TotalTime,
+    TotalSelfTime,
+    MaxSelfTime,
+}
+
+impl SortBy {
+    fn key(self, row: &StatsRow) -> NanoSecond {
+        match self {
+            Self::Count => row.count as NanoSecond,
+            Self::TotalTime => row.total_ns,
+            Self::TotalSelfTime => row.total_self_ns,
+            Self::MaxSelfTime => row.max_ns,
+        }
+    }
+}
+
+/// One row of the stats table: the aggregated statistics of one scope.
+#[derive(Clone, Debug)]
+pub struct StatsRow {
+    pub scope_details: Arc<ScopeDetails>,
+
+    /// How many times the scope was entered.
+    pub count: usize,
+
+    /// Bytes used by the scope in the profile streams.
+    pub bytes: usize,
+
+    /// Time covered by all scopes, including their child scopes.
+    ///
+    /// Recursive scopes only count the outermost one, so the time isn't counted twice.
+    pub total_ns: NanoSecond,
+
+    /// Time covered by all scopes, minus those covered by child scopes.
+    pub total_self_ns: NanoSecond,
+
+    /// Time covered by the slowest scope, minus those covered by child scopes.
+    pub max_ns: NanoSecond,
+
+    /// Names of the threads the scope was seen on.
+    pub threads: BTreeSet<String>,
+}
+
+impl StatsRow {
+    /// Mean self time of the scope.
+    pub fn mean_self_ns(&self) -> f64 {
+        self.total_self_ns as f64 / (self.count as f64)
+    }
+}
+
+/// Aggregate the scopes of the given frames, one row per scope,
+/// sorted with the most called scopes first.
+///
+/// Scopes missing from `scope_collection` are skipped.
+pub fn collect_rows(
+    scope_collection: &ScopeCollection,
+    frames: &[Arc<UnpackedFrameData>],
+) -> Vec<StatsRow> {
+    let stats = Stats::collect(frames);
+
+    let mut scopes: Vec<_> = stats.scopes.into_iter().collect();
+    scopes.sort_by_key(|(key, _)| key.clone());
+    scopes.sort_by_key(|(_key, scope_stats)| scope_stats.count);
+    scopes.reverse();
+
+    scopes
+        .into_iter()
+        .filter_map(|(key, stats)| {
+            Some(StatsRow {
+                scope_details: scope_collection.fetch_by_id(&key.id)?.clone(),
+                count: stats.count,
+                bytes: stats.bytes,
+                total_ns: stats.total_ns,
+                total_self_ns: stats.total_self_ns,
+                max_ns: stats.max_ns,
+                threads: stats.threads,
+            })
+        })
+        .collect()
 }
 
 pub fn ui(
     ui: &mut egui::Ui,
     options: &mut Options,
     scope_infos: &ScopeCollection,
-    frames: &[std::sync::Arc<UnpackedFrameData>],
+    frames: &[Arc<UnpackedFrameData>],
 ) {
-    let mut threads = std::collections::HashSet::<&ThreadInfo>::new();
-    let mut stats = Stats::default();
-
+    let mut threads = BTreeSet::<&str>::new();
     for frame in frames {
-        threads.extend(frame.thread_streams.keys());
-        for stream in frame.thread_streams.values() {
-            collect_stream(&mut stats, &stream.stream).ok();
-        }
+        threads.extend(frame.thread_streams.keys().map(|info| info.name.as_str()));
+    }
+
+    let mut rows = collect_rows(scope_infos, frames);
+    if options.sort_by != SortBy::Count {
+        // Stable, so ties are still sorted by count:
+        rows.sort_by_key(|row| std::cmp::Reverse(options.sort_by.key(row)));
+    }
+    if !options.hidden_threads.is_empty() {
+        rows.retain(|row| {
+            row.threads
+                .iter()
+                .any(|thread| !options.hidden_threads.contains(thread))
+        });
     }
+    let num_shown_threads = threads
+        .iter()
+        .filter(|thread| !options.hidden_threads.contains(**thread))
+        .count();
 
     let mut total_bytes = 0;
     let mut total_ns = 0;
-    for scope in stats.scopes.values() {
-        total_bytes += scope.bytes;
-        total_ns += scope.total_self_ns;
+    for row in &rows {
+        total_bytes += row.bytes;
+        total_ns += row.total_self_ns;
     }
 
     ui.label("This view can be used to find functions that are called a lot.\n\
@@ -36,22 +153,44 @@ pub fn ui(
 
     ui.label(format!(
         "Currently viewing {} unique scopes, using a total of {:.1} kB, covering {:.1} ms over {} thread(s)",
-        stats.scopes.len(),
+        rows.len(),
         total_bytes as f32 * 1e-3,
         total_ns as f32 * 1e-6,
-        threads.len()
+        num_shown_threads
     ));
 
-    options.filter.ui(ui);
+    if !options.filter.is_empty() {
+        rows.retain(|row| {
+            let scope_details = &row.scope_details;
+            let mut matches = options.filter.include(&scope_details.function_name);
+            if let Some(scope_name) = &scope_details.scope_name {
+                matches |= options.filter.include(scope_name);
+            }
+            matches
+        });
+    }
 
-    let mut scopes: Vec<_> = stats
-        .scopes
-        .iter()
-        .map(|(key, value)| (key, *value))
-        .collect();
-    scopes.sort_by_key(|(key, _)| *key);
-    scopes.sort_by_key(|(_key, scope_stats)| scope_stats.count);
-    scopes.reverse();
+    ui.horizontal(|ui| {
+        options.filter.ui(ui);
+        threads_ui(ui, &mut options.hidden_threads, &threads, num_shown_threads);
+
+        if ui
+            .button("📋 Copy as Markdown table")
+            .on_hover_text("Copy the scopes below as a GitHub-flavored Markdown table")
+            .clicked()
+        {
+            ui.ctx().copy_text(markdown_table(&rows));
+        }
+
+        #[cfg(feature = "csv_export")]
+        if ui
+            .button("💾 Export CSV")
+            .on_hover_text("Save the scopes below as a CSV file")
+            .clicked()
+        {
+            save_csv(&csv_table(&rows));
+        }
+    });
 
     egui::ScrollArea::horizontal().show(ui, |ui| {
         ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
@@ -63,7 +202,7 @@ pub fn ui(
                 egui_extras::Column::auto_with_initial_suggestion(200.0).resizable(true),
                 3,
             )
-            .columns(egui_extras::Column::auto().resizable(false), 6)
+            .columns(egui_extras::Column::auto().resizable(false), 7)
             .header(20.0, |mut header| {
                 header.col(|ui| {
                     ui.strong("Location");
@@ -75,51 +214,50 @@ pub fn ui(
                     ui.strong("Scope Name");
                 });
                 header.col(|ui| {
-                    ui.strong("Count");
+                    sort_header(ui, &mut options.sort_by, SortBy::Count, "Count");
                 });
                 header.col(|ui| {
                     ui.strong("Size");
                 });
                 header.col(|ui| {
-                    ui.strong("Total self time");
+                    sort_header(ui, &mut options.sort_by, SortBy::TotalTime, "Total time")
+                        .on_hover_text("Including the time of child scopes");
+                });
+                header.col(|ui| {
+                    sort_header(
+                        ui,
+                        &mut options.sort_by,
+                        SortBy::TotalSelfTime,
+                        "Total self time",
+                    )
+                    .on_hover_text("Excluding the time of child scopes");
                 });
                 header.col(|ui| {
                     ui.strong("Mean self time");
                 });
                 header.col(|ui| {
-                    ui.strong("Max self time");
+                    sort_header(
+                        ui,
+                        &mut options.sort_by,
+                        SortBy::MaxSelfTime,
+                        "Max self time",
+                    );
                 });
             })
             .body(|mut body| {
-                for (key, stats) in &scopes {
-                    let Some(scope_details) = scope_infos.fetch_by_id(&key.id) else {
-                        continue;
-                    };
-
-                    if !options.filter.is_empty() {
-                        let mut matches = options.filter.include(&scope_details.function_name);
-
-                        if let Some(scope_name) = &scope_details.scope_name {
-                            matches |= options.filter.include(scope_name);
-                        }
-
-                        if !matches {
-                            continue;
-                        }
-                    }
-
+                for stats in &rows {
                     body.row(14.0, |mut row| {
                         row.col(|ui| {
                             ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Truncate);
-                            ui.label(scope_details.location());
+                            location_ui(ui, &stats.scope_details, options.location_url.as_deref());
                         });
                         row.col(|ui| {
                             ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Truncate);
-                            ui.label(scope_details.function_name.as_str());
+                            ui.label(stats.scope_details.function_name.as_str());
                         });
 
                         row.col(|ui| {
-                            if let Some(name) = &scope_details.scope_name {
+                            if let Some(name) = &stats.scope_details.scope_name {
                                 ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Truncate);
                                 ui.label(name.as_ref());
                             }
@@ -142,14 +280,14 @@ pub fn ui(
                         row.col(|ui| {
                             ui.monospace(format!("{:>6.1} kB", stats.bytes as f32 * 1e-3));
                         });
+                        row.col(|ui| {
+                            ui.monospace(format!("{:>8.1} µs", stats.total_ns as f32 * 1e-3));
+                        });
                         row.col(|ui| {
                             ui.monospace(format!("{:>8.1} µs", stats.total_self_ns as f32 * 1e-3));
                         });
                         row.col(|ui| {
-                            ui.monospace(format!(
-                                "{:>8.1} µs",
-                                stats.total_self_ns as f32 * 1e-3 / (stats.count as f32)
-                            ));
+                            ui.monospace(format!("{:>8.1} µs", stats.mean_self_ns() * 1e-3));
                         });
                         row.col(|ui| {
                             ui.monospace(format!("{:>8.1} µs", stats.max_ns as f32 * 1e-3));
@@ -160,46 +298,261 @@ pub fn ui(
     });
 }
 
+/// A column header that sorts the table by `column` when clicked.
+fn sort_header(
+    ui: &mut egui::Ui,
+    sort_by: &mut SortBy,
+    column: SortBy,
+    text: &str,
+) -> egui::Response {
+    let selected = *sort_by == column;
+    let text = if selected {
+        format!("{text} ⏷")
+    } else {
+        text.to_owned()
+    };
+    let response = ui
+        .selectable_label(selected, egui::RichText::new(text).strong())
+        .on_hover_text("Click to sort by this column");
+    if response.clicked() {
+        *sort_by = column;
+    }
+    response
+}
+
+/// The location of a scope, which is copied to the clipboard when ctrl-clicked,
+/// and opened with the `location_url` template, if any.
+fn location_ui(ui: &mut egui::Ui, scope_details: &ScopeDetails, location_url: Option<&str>) {
+    let location = scope_details.location();
+    let hover_text = if location_url.is_some() {
+        "Ctrl-click to copy the location and open it"
+    } else {
+        "Ctrl-click to copy the location"
+    };
+    let response = ui
+        .add(egui::Label::new(&location).sense(egui::Sense::click()))
+        .on_hover_text(hover_text);
+
+    if response.clicked() && ui.input(|i| i.modifiers.command) {
+        ui.ctx().copy_text(location);
+        if let Some(template) = location_url {
+            let url = location_url_for(template, scope_details);
+            ui.ctx().open_url(egui::OpenUrl::new_tab(url));
+        }
+    }
+}
+
+/// Fill in the `{path}` and `{line}` of a [`Options::location_url`] template.
+fn location_url_for(template: &str, scope_details: &ScopeDetails) -> String {
+    template
+        .replace("{path}", &scope_details.file_path)
+        .replace("{line}", &scope_details.line_nr.max(1).to_string())
+}
+
+/// Dropdown for picking the threads whose scopes to show.
+fn threads_ui(
+    ui: &mut egui::Ui,
+    hidden_threads: &mut BTreeSet<String>,
+    threads: &BTreeSet<&str>,
+    num_shown_threads: usize,
+) {
+    let text = if num_shown_threads == threads.len() {
+        "Threads: all".to_owned()
+    } else {
+        format!("Threads: {num_shown_threads}/{}", threads.len())
+    };
+    ui.menu_button(text, |ui| {
+        ui.horizontal(|ui| {
+            if ui.button("All").clicked() {
+                hidden_threads.clear();
+            }
+            if ui.button("None").clicked() {
+                hidden_threads.extend(threads.iter().map(|thread| (*thread).to_owned()));
+            }
+        });
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .id_source("stats_threads")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for &thread in threads {
+                    let mut shown = !hidden_threads.contains(thread);
+                    if ui.checkbox(&mut shown, thread).changed() {
+                        if shown {
+                            hidden_threads.remove(thread);
+                        } else {
+                            hidden_threads.insert(thread.to_owned());
+                        }
+                    }
+                }
+            });
+    })
+    .response
+    .on_hover_text("Only show scopes that were seen on these threads");
+}
+
+/// Format the rows of the stats table as a GitHub-flavored Markdown table.
+fn markdown_table(rows: &[StatsRow]) -> String {
+    use std::fmt::Write as _;
+
+    let mut table = String::new();
+    table +=
+        "| Scope | Count | Total self time (µs) | Mean self time (µs) | Max self time (µs) |\n";
+    table += "| :--- | ---: | ---: | ---: | ---: |\n";
+
+    for row in rows {
+        writeln!(
+            table,
+            "| `{}` | {} | {:.1} | {:.1} | {:.1} |",
+            row.scope_details
+                .name()
+                .replace('|', "\\|")
+                .replace('`', "'"),
+            row.count,
+            row.total_self_ns as f64 * 1e-3,
+            row.mean_self_ns() * 1e-3,
+            row.max_ns as f64 * 1e-3,
+        )
+        .ok();
+    }
+
+    table
+}
+
+/// Format the rows of the stats table as CSV, with all times in nanoseconds.
+///
+/// This is what the "Export CSV" button saves.
+pub fn csv_table(rows: &[StatsRow]) -> String {
+    use std::fmt::Write as _;
+
+    fn escape(field: &str) -> std::borrow::Cow<'_, str> {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\"")).into()
+        } else {
+            field.into()
+        }
+    }
+
+    let mut table = String::new();
+    table += "scope,location,count,total_self_ns,mean_self_ns,max_self_ns\n";
+
+    for row in rows {
+        writeln!(
+            table,
+            "{},{},{},{},{:.0},{}",
+            escape(row.scope_details.name()),
+            escape(&row.scope_details.location()),
+            row.count,
+            row.total_self_ns,
+            row.mean_self_ns(),
+            row.max_ns,
+        )
+        .ok();
+    }
+
+    table
+}
+
+/// Let the user save the CSV to a file.
+#[cfg(all(feature = "csv_export", not(target_arch = "wasm32")))]
+fn save_csv(csv: &str) {
+    if let Some(path) = rfd::FileDialog::new()
+        .add_filter("CSV", &["csv"])
+        .set_file_name("puffin_stats.csv")
+        .save_file()
+    {
+        std::fs::write(path, csv).ok();
+    }
+}
+
+/// Offer the CSV as a download in the browser.
+#[cfg(all(feature = "csv_export", target_arch = "wasm32"))]
+fn save_csv(csv: &str) {
+    use wasm_bindgen::JsCast as _;
+
+    let download = || -> Option<()> {
+        let parts = js_sys::Array::of1(&wasm_bindgen::JsValue::from_str(csv));
+        let mut options = web_sys::BlobPropertyBag::new();
+        options.type_("text/csv");
+        let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options).ok()?;
+        let url = web_sys::Url::create_object_url_with_blob(&blob).ok()?;
+
+        let document = web_sys::window()?.document()?;
+        let anchor: web_sys::HtmlAnchorElement =
+            document.create_element("a").ok()?.dyn_into().ok()?;
+        anchor.set_href(&url);
+        anchor.set_download("puffin_stats.csv");
+        anchor.click();
+
+        web_sys::Url::revoke_object_url(&url).ok()
+    };
+    download();
+}
+
 #[derive(Default)]
-struct Stats {
-    scopes: std::collections::HashMap<Key, ScopeStats>,
+pub(crate) struct Stats {
+    pub scopes: std::collections::HashMap<Key, ScopeStats>,
+}
+
+impl Stats {
+    /// Aggregate the scopes of all threads of the given frames.
+    pub fn collect(frames: &[Arc<UnpackedFrameData>]) -> Self {
+        let mut stats = Self::default();
+        for frame in frames {
+            for (thread_info, stream) in &frame.thread_streams {
+                collect_stream(&mut stats, &thread_info.name, &stream.stream).ok();
+            }
+        }
+        stats
+    }
 }
 
 #[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
-struct Key {
-    id: ScopeId,
+pub(crate) struct Key {
+    pub id: ScopeId,
 }
 
-#[derive(Copy, Clone, Default)]
-struct ScopeStats {
-    count: usize,
-    bytes: usize,
+#[derive(Clone, Default)]
+pub(crate) struct ScopeStats {
+    pub count: usize,
+    pub bytes: usize,
+    /// Time covered by all scopes, including their child scopes.
+    /// Only the outermost of recursive scopes is counted.
+    pub total_ns: NanoSecond,
     /// Time covered by all scopes, minus those covered by child scopes.
     /// A lot of time == useful scope.
-    total_self_ns: NanoSecond,
+    pub total_self_ns: NanoSecond,
     /// Time covered by the slowest scope, minus those covered by child scopes.
     /// A lot of time == useful scope.
-    max_ns: NanoSecond,
+    pub max_ns: NanoSecond,
+    /// Names of the threads the scope was seen on.
+    pub threads: BTreeSet<String>,
 }
 
-fn collect_stream(stats: &mut Stats, stream: &puffin::Stream) -> puffin::Result<()> {
+fn collect_stream(stats: &mut Stats, thread: &str, stream: &puffin::Stream) -> puffin::Result<()> {
+    let mut open_ids = Vec::new();
     for scope in puffin::Reader::from_start(stream) {
-        collect_scope(stats, stream, &scope?)?;
+        collect_scope(stats, thread, stream, &scope?, &mut open_ids)?;
     }
     Ok(())
 }
 
 fn collect_scope<'s>(
     stats: &mut Stats,
+    thread: &str,
     stream: &'s puffin::Stream,
     scope: &puffin::Scope<'s>,
+    open_ids: &mut Vec<ScopeId>,
 ) -> puffin::Result<()> {
+    let is_recursive = open_ids.contains(&scope.id);
+    open_ids.push(scope.id);
     let mut ns_used_by_children = 0;
     for child_scope in Reader::with_offset(stream, scope.child_begin_position)? {
         let child_scope = &child_scope?;
-        collect_scope(stats, stream, child_scope)?;
+        collect_scope(stats, thread, stream, child_scope, open_ids)?;
         ns_used_by_children += child_scope.record.duration_ns;
     }
+    open_ids.pop();
 
     let self_time = scope.record.duration_ns.saturating_sub(ns_used_by_children);
 
@@ -207,8 +560,14 @@ fn collect_scope<'s>(
     let scope_stats = stats.scopes.entry(key).or_default();
     scope_stats.count += 1;
     scope_stats.bytes += scope_byte_size(scope);
+    if !is_recursive {
+        scope_stats.total_ns += scope.record.duration_ns;
+    }
     scope_stats.total_self_ns += self_time;
     scope_stats.max_ns = scope_stats.max_ns.max(self_time);
+    if !scope_stats.threads.contains(thread) {
+        scope_stats.threads.insert(thread.to_owned());
+    }
 
     Ok(())
 }
@@ -222,3 +581,140 @@ fn scope_byte_size(scope: &puffin::Scope<'_>) -> usize {
     1 + // `)` sentinel
     8 // stop time
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Arc};
+
+    use puffin::{FrameView, Profiler, ScopeDetails, Stream, UnpackedFrameData};
+
+    use super::{collect_rows, csv_table, location_url_for, markdown_table, Key, Stats, StatsRow};
+
+    fn row(name: &'static str) -> StatsRow {
+        StatsRow {
+            scope_details: Arc::new(ScopeDetails::from_scope_name(name)),
+            count: 4,
+            bytes: 0,
+            total_ns: 10_000,
+            total_self_ns: 10_000,
+            max_ns: 4_000,
+            threads: Default::default(),
+        }
+    }
+
+    #[test]
+    fn markdown_table_escapes_names() {
+        let table = markdown_table(&[row("a|b")]);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], r"| `a\|b` | 4 | 10.0 | 2.5 | 4.0 |");
+    }
+
+    #[test]
+    fn recursive_scopes_count_total_time_once() {
+        let ids = Profiler::default().register_user_scopes(&[
+            ScopeDetails::from_scope_name("a"),
+            ScopeDetails::from_scope_name("b"),
+        ]);
+        let (a, b) = (ids[0], ids[1]);
+        let mut stream = Stream::default();
+        let (outer, _) = stream.begin_scope(|| 0, a, "");
+        let (inner, _) = stream.begin_scope(|| 10, a, "");
+        let (leaf, _) = stream.begin_scope(|| 20, b, "");
+        stream.end_scope(leaf, 40);
+        stream.end_scope(inner, 60);
+        stream.end_scope(outer, 100);
+        let frame = UnpackedFrameData::new(
+            0,
+            BTreeMap::from([crate::test_thread_stream("main", stream)]),
+        )
+        .unwrap();
+
+        let stats = Stats::collect(&[Arc::new(frame)]);
+        let a = &stats.scopes[&Key { id: a }];
+        assert_eq!(a.count, 2);
+        assert_eq!(a.total_ns, 100);
+        assert_eq!(a.total_self_ns, 50 + 30);
+        let b = &stats.scopes[&Key { id: b }];
+        assert_eq!(b.total_ns, 20);
+        assert_eq!(b.total_self_ns, 20);
+    }
+
+    #[test]
+    fn location_url() {
+        let template = "vscode://file/root/{path}:{line}";
+        let details = ScopeDetails::from_scope_name("scope")
+            .with_file("src/main.rs")
+            .with_line_nr(42);
+        assert_eq!(
+            location_url_for(template, &details),
+            "vscode://file/root/src/main.rs:42"
+        );
+
+        // Unknown line numbers go to the top of the file:
+        let details = ScopeDetails::from_scope_name("scope").with_file("src/main.rs");
+        assert_eq!(
+            location_url_for(template, &details),
+            "vscode://file/root/src/main.rs:1"
+        );
+    }
+
+    #[test]
+    fn csv_table_escapes_fields() {
+        let table = csv_table(&[row("plain"), row("a,\"b\"")]);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "scope,location,count,total_self_ns,mean_self_ns,max_self_ns",
+                "plain,,4,10000,2500,4000",
+                r#""a,""b""",,4,10000,2500,4000"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn rows_know_their_threads() {
+        let frame_view = Arc::new(parking_lot::Mutex::new(FrameView::default()));
+        let mut profiler = Profiler::default();
+        profiler.add_sink(Box::new({
+            let frame_view = frame_view.clone();
+            move |frame| frame_view.lock().add_frame(frame)
+        }));
+
+        let scope_ids = profiler.register_user_scopes(&[
+            ScopeDetails::from_scope_name("update"),
+            ScopeDetails::from_scope_name("draw"),
+            ScopeDetails::from_scope_name("present"),
+        ]);
+        for (thread, scopes) in [("main", &scope_ids[..2]), ("render", &scope_ids[1..])] {
+            let mut stream = Stream::default();
+            for &scope_id in scopes {
+                let (offset, _) = stream.begin_scope(|| 0, scope_id, "");
+                stream.end_scope(offset, 10);
+            }
+            let (thread_info, stream_info) = crate::test_thread_stream(thread, stream);
+            profiler.report_user_scopes(thread_info, &stream_info.as_stream_into_ref());
+        }
+        profiler.new_frame();
+
+        let frame_view = frame_view.lock();
+        let frame = frame_view.latest_frame().unwrap().unpacked().unwrap();
+        let rows = collect_rows(frame_view.scope_collection(), &[frame]);
+        let threads: BTreeMap<_, _> = rows
+            .iter()
+            .map(|row| {
+                let threads: Vec<_> = row.threads.iter().map(String::as_str).collect();
+                (row.scope_details.name().to_string(), threads)
+            })
+            .collect();
+        assert_eq!(
+            threads,
+            BTreeMap::from([
+                ("update".to_owned(), vec!["main"]),
+                ("draw".to_owned(), vec!["main", "render"]),
+                ("present".to_owned(), vec!["render"]),
+            ])
+        );
+    }
+}
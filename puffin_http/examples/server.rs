@@ -35,6 +35,8 @@ fn main() {
             puffin::profile_scope!("very thin");
         }
 
+        puffin::profile_counter!("frame_counter_mod_10", frame_counter % 10);
+
         frame_counter += 1;
     }
 }
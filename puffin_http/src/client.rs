@@ -1,19 +1,66 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering::SeqCst},
-    Arc,
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
 };
 
 use puffin::{FrameData, FrameView};
 
+/// How long to wait before the first reconnection attempt.
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+
+/// The delay between reconnection attempts doubles up to this.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(4);
+
 /// Connect to a [`crate::Server`], reading profile data
 /// and feeding it to a [`puffin::FrameView`].
 ///
-/// Will retry connection until it succeeds, and reconnect on failures.
+/// Will retry connection until it succeeds, and reconnect on failures,
+/// with exponential backoff. See [`Client::set_auto_reconnect`].
 pub struct Client {
     addr: String,
     connected: Arc<AtomicBool>,
     alive: Arc<AtomicBool>,
+    auto_reconnect: Arc<AtomicBool>,
+    connection_generation: Arc<AtomicU64>,
+    error: Arc<parking_lot::Mutex<Option<String>>>,
     frame_view: Arc<parking_lot::Mutex<FrameView>>,
+    upstream: Arc<parking_lot::Mutex<Upstream>>,
+}
+
+/// What we asked the server for, and how to tell it.
+#[derive(Default)]
+struct Upstream {
+    /// Set while connected.
+    stream: Option<std::net::TcpStream>,
+    paused: bool,
+    max_fps: Option<f32>,
+}
+
+impl Upstream {
+    /// Tell the server, if we are connected.
+    ///
+    /// Failures are only logged, since the reading side will notice the broken connection.
+    fn send(&mut self, message: crate::ToServer) {
+        if let Some(stream) = &mut self.stream {
+            if let Err(err) = message.write_to(stream) {
+                log::debug!("Failed to send {:?} to puffin server: {}", message, err);
+            }
+        }
+    }
+
+    /// Tell a newly connected server everything that isn't the default.
+    fn connect(&mut self, stream: std::net::TcpStream) {
+        self.stream = Some(stream);
+        if self.paused {
+            self.send(crate::ToServer::Pause);
+        }
+        if self.max_fps.is_some() {
+            self.send(crate::ToServer::SetMaxFps(self.max_fps));
+        }
+    }
 }
 
 impl Drop for Client {
@@ -35,25 +82,50 @@ impl Client {
     pub fn new(addr: String) -> Self {
         let alive = Arc::new(AtomicBool::new(true));
         let connected = Arc::new(AtomicBool::new(false));
+        let auto_reconnect = Arc::new(AtomicBool::new(true));
+        let connection_generation = Arc::new(AtomicU64::new(0));
+        let error = Arc::new(parking_lot::Mutex::new(None));
         let frame_view = Arc::new(parking_lot::Mutex::new(FrameView::default()));
+        let upstream = Arc::new(parking_lot::Mutex::new(Upstream::default()));
 
         let client = Self {
             addr: addr.clone(),
             connected: connected.clone(),
             alive: alive.clone(),
+            auto_reconnect: auto_reconnect.clone(),
+            connection_generation: connection_generation.clone(),
+            error: error.clone(),
             frame_view: frame_view.clone(),
+            upstream: upstream.clone(),
         };
 
         let _ = std::thread::Builder::new()
             .name("http_client_thread".to_string())
             .spawn(move || {
                 log::info!("Connecting to {}…", addr);
+                let mut reconnect_delay = MIN_RECONNECT_DELAY;
                 while alive.load(SeqCst) {
-                    match std::net::TcpStream::connect(&addr) {
-                        Ok(mut stream) => {
+                    let connection = std::net::TcpStream::connect(&addr)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut stream| {
+                            // Don't hold back our small messages to the server:
+                            stream.set_nodelay(true)?;
+                            let flags = crate::read_handshake(&mut stream)?;
+                            // Accept everything we support of what the server offers:
+                            crate::write_handshake(&mut stream, flags)?;
+                            let upstream_stream = stream.try_clone()?;
+                            Ok((decompressed(stream, flags)?, upstream_stream))
+                        });
+                    match connection {
+                        Ok((mut stream, upstream_stream)) => {
+                            *error.lock() = None;
+                            // Scope ids from a previous connection may mean something else now:
                             *frame_view.lock() = FrameView::default();
                             log::info!("Connected to {}", addr);
+                            connection_generation.fetch_add(1, SeqCst);
+                            upstream.lock().connect(upstream_stream);
                             connected.store(true, SeqCst);
+                            reconnect_delay = MIN_RECONNECT_DELAY;
                             while alive.load(SeqCst) {
                                 match consume_message(&mut stream) {
                                     Ok(frame_data) => {
@@ -66,6 +138,7 @@ impl Client {
                                             "Connection to puffin server closed: {}",
                                             error_display_chain(err.as_ref())
                                         );
+                                        upstream.lock().stream = None;
                                         connected.store(false, SeqCst);
                                         break;
                                     }
@@ -73,32 +146,117 @@ impl Client {
                             }
                         }
                         Err(err) => {
-                            log::debug!("Failed to connect to {}: {}", addr, err);
-                            std::thread::sleep(std::time::Duration::from_secs(1));
+                            log::debug!("Failed to connect to {}: {:#}", addr, err);
+                            if !err.is::<std::io::Error>() {
+                                // Connected, but can't talk to the server:
+                                *error.lock() = Some(err.to_string());
+                            }
+                            std::thread::sleep(reconnect_delay);
+                            reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
                         }
                     }
+
+                    // Wait until we are allowed to reconnect again:
+                    while alive.load(SeqCst) && !auto_reconnect.load(SeqCst) {
+                        std::thread::sleep(MIN_RECONNECT_DELAY);
+                    }
                 }
             });
 
         client
     }
 
+    /// Should we try to connect again when the connection fails or is closed?
+    ///
+    /// On by default. When off, the client stays disconnected until this is turned on again.
+    pub fn set_auto_reconnect(&self, auto_reconnect: bool) {
+        self.auto_reconnect.store(auto_reconnect, SeqCst);
+    }
+
+    /// See [`Self::set_auto_reconnect`].
+    pub fn auto_reconnect(&self) -> bool {
+        self.auto_reconnect.load(SeqCst)
+    }
+
+    /// How many times we have connected to the server.
+    ///
+    /// This changes each time we (re)connect, after which [`Self::frame_view`] starts out empty.
+    pub fn connection_generation(&self) -> u64 {
+        self.connection_generation.load(SeqCst)
+    }
+
     /// The address we are connected to or trying to connect to.
     pub fn addr(&self) -> &str {
         &self.addr
     }
 
+    /// Why we can't talk to the server we connected to, e.g. because it uses another protocol version.
+    ///
+    /// Meant to be shown to the user. Cleared once we connect successfully.
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().clone()
+    }
+
     /// Are we currently connect to the server?
     pub fn connected(&self) -> bool {
         self.connected.load(SeqCst)
     }
 
+    /// Ask the server to stop sending frames, or to start again.
+    ///
+    /// Other clients of the same server are not affected. Remembered across reconnections.
+    pub fn set_paused(&self, paused: bool) {
+        let mut upstream = self.upstream.lock();
+        if upstream.paused != paused {
+            upstream.paused = paused;
+            upstream.send(if paused {
+                crate::ToServer::Pause
+            } else {
+                crate::ToServer::Resume
+            });
+        }
+    }
+
+    /// See [`Self::set_paused`].
+    pub fn paused(&self) -> bool {
+        self.upstream.lock().paused
+    }
+
+    /// Ask the server to send at most this many frames per second, or all of them if `None`.
+    ///
+    /// Other clients of the same server are not affected. Remembered across reconnections.
+    pub fn set_max_fps(&self, max_fps: Option<f32>) {
+        let mut upstream = self.upstream.lock();
+        if upstream.max_fps != max_fps {
+            upstream.max_fps = max_fps;
+            upstream.send(crate::ToServer::SetMaxFps(max_fps));
+        }
+    }
+
+    /// See [`Self::set_max_fps`].
+    pub fn max_fps(&self) -> Option<f32> {
+        self.upstream.lock().max_fps
+    }
+
     /// Get the current data.
     pub fn frame_view(&self) -> parking_lot::MutexGuard<'_, FrameView> {
         self.frame_view.lock()
     }
 }
 
+/// Wrap the stream from the server in a decompressor, if we accepted that in the handshake `flags`.
+fn decompressed(
+    stream: std::net::TcpStream,
+    flags: u16,
+) -> anyhow::Result<Box<dyn std::io::Read + Send>> {
+    #[cfg(all(feature = "zstd", not(target_arch = "wasm32")))]
+    if flags & crate::FLAG_ZSTD != 0 {
+        return Ok(Box::new(zstd::stream::read::Decoder::new(stream)?));
+    }
+    debug_assert_eq!(flags, 0, "We only accept the flags we support");
+    Ok(Box::new(stream))
+}
+
 /// Read a `puffin_http` message from a stream.
 pub fn consume_message(stream: &mut impl std::io::Read) -> anyhow::Result<puffin::FrameData> {
     let mut server_version = [0_u8; 2];
@@ -139,3 +297,71 @@ fn error_display_chain(error: &dyn std::error::Error) -> String {
     }
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write as _, net::TcpListener, time::Duration};
+
+    use super::Client;
+
+    fn wait_for(mut condition: impl FnMut() -> bool) -> bool {
+        for _ in 0..100 {
+            if condition() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    #[test]
+    fn reconnects_after_server_closes() {
+        let addr = "127.0.0.1:8598";
+        let listener = TcpListener::bind(addr).unwrap();
+        let client = Client::new(addr.to_owned());
+
+        let (mut connection, _) = listener.accept().unwrap();
+        crate::write_handshake(&mut connection, 0).unwrap();
+        assert!(wait_for(|| client.connection_generation() == 1));
+        drop(connection);
+
+        let (mut connection, _) = listener.accept().unwrap();
+        crate::write_handshake(&mut connection, 0).unwrap();
+        assert!(wait_for(|| client.connection_generation() == 2));
+
+        client.set_auto_reconnect(false);
+        drop(connection);
+        assert!(wait_for(|| !client.connected()));
+        std::thread::sleep(Duration::from_millis(500));
+        assert_eq!(
+            client.connection_generation(),
+            2,
+            "Expected no reconnection with auto-reconnect off"
+        );
+    }
+
+    #[test]
+    fn reports_protocol_version_mismatch() {
+        let addr = "127.0.0.1:8596";
+        let listener = TcpListener::bind(addr).unwrap();
+        let client = Client::new(addr.to_owned());
+
+        let (mut connection, _) = listener.accept().unwrap();
+        connection.write_all(b"PUFH").unwrap();
+        connection.write_all(&99_u16.to_le_bytes()).unwrap();
+        connection.write_all(&0_u16.to_le_bytes()).unwrap();
+        assert!(wait_for(|| client.error().is_some()));
+        let error = client.error().unwrap();
+        assert!(error.contains("v99"), "{error}");
+        assert!(!client.connected());
+        assert_eq!(client.connection_generation(), 0);
+
+        // A server from before the handshake starts with the version of the first frame:
+        let (mut connection, _) = listener.accept().unwrap();
+        connection.write_all(&3_u16.to_le_bytes()).unwrap();
+        connection.write_all(b"PFD9").unwrap();
+        assert!(wait_for(|| client
+            .error()
+            .map_or(false, |error| error.contains("v3"))));
+    }
+}
@@ -11,8 +11,161 @@
 //! puffin::set_scopes_on(true);
 //! ```
 
-/// Bumped on protocol breakage.
-pub const PROTOCOL_VERSION: u16 = 2;
+/// Bumped on protocol breakage, including each change to the format of the frames:
+///
+/// * v3: frames with scope categories (`PFD5`).
+/// * v4: the handshake, and frames with counters, tasks, wall clock time,
+///   longer scope data, and flows (`PFD6` to `PFDA`).
+/// * v5: the client can ask the server to pause its stream, or to limit its frame rate.
+/// * v6: frames with scope tags (`PFDB`).
+/// * v7: frames with thread ids (`PFDC`).
+/// * v8: frames with markers (`PFDD`).
+/// * v9: the client replies to the handshake with the flags it accepts.
+pub const PROTOCOL_VERSION: u16 = 9;
+
+/// Starts the handshake the server sends once to each client when it connects, before any frames.
+///
+/// The server offers the features it wants to use with the flags of the handshake,
+/// and the client replies with a handshake of its own, with the flags it accepts.
+/// The features are only used once the client has accepted them.
+///
+/// The handshake was added in protocol version 4, and the reply in version 9.
+const HANDSHAKE_MAGIC: [u8; 4] = *b"PUFH";
+
+/// Handshake flag: everything after the handshake is a zstd stream.
+const FLAG_ZSTD: u16 = 1;
+
+/// Write the handshake: [`HANDSHAKE_MAGIC`], then [`PROTOCOL_VERSION`] and flags as little endian `u16`s.
+fn write_handshake(write: &mut impl std::io::Write, flags: u16) -> std::io::Result<()> {
+    let mut handshake = HANDSHAKE_MAGIC.to_vec();
+    handshake.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    handshake.extend_from_slice(&flags.to_le_bytes());
+    write.write_all(&handshake)
+}
+
+/// Read the handshake written by [`write_handshake`],
+/// and return the flags we support of those the server offers.
+///
+/// Fails with a message for the user if the server speaks another protocol version.
+fn read_handshake(read: &mut impl std::io::Read) -> anyhow::Result<u16> {
+    let mut magic = [0_u8; 4];
+    read.read_exact(&mut magic)?;
+    let (server_version, flags) = if magic == HANDSHAKE_MAGIC {
+        let mut version_and_flags = [0_u8; 4];
+        read.read_exact(&mut version_and_flags)?;
+        (
+            u16::from_le_bytes([version_and_flags[0], version_and_flags[1]]),
+            u16::from_le_bytes([version_and_flags[2], version_and_flags[3]]),
+        )
+    } else {
+        // Servers from before the handshake start right away with the version of the first frame:
+        let legacy_version = u16::from_le_bytes([magic[0], magic[1]]);
+        if PROTOCOL_VERSION <= legacy_version {
+            anyhow::bail!("Unexpected handshake {magic:?}. Is this a puffin server?");
+        }
+        (legacy_version, 0)
+    };
+
+    match server_version.cmp(&PROTOCOL_VERSION) {
+        std::cmp::Ordering::Less => anyhow::bail!(
+            "puffin server speaks protocol v{server_version}, but the viewer expects v{PROTOCOL_VERSION}. \
+            Please update puffin_http in the profiled application."
+        ),
+        std::cmp::Ordering::Equal => {}
+        std::cmp::Ordering::Greater => anyhow::bail!(
+            "puffin server speaks protocol v{server_version}, but the viewer expects v{PROTOCOL_VERSION}. \
+            Please update puffin_viewer with 'cargo install puffin_viewer --locked'."
+        ),
+    }
+
+    let supported_flags = if cfg!(all(feature = "zstd", not(target_arch = "wasm32"))) {
+        FLAG_ZSTD
+    } else {
+        0
+    };
+    Ok(flags & supported_flags)
+}
+
+/// Read the reply of a client to the handshake, and return the flags it accepts.
+#[cfg(not(target_arch = "wasm32"))]
+fn read_handshake_reply(read: &mut impl std::io::Read) -> anyhow::Result<u16> {
+    let mut reply = [0_u8; 8];
+    read.read_exact(&mut reply)?;
+    anyhow::ensure!(
+        reply[..4] == HANDSHAKE_MAGIC,
+        "Unexpected handshake reply {reply:?}"
+    );
+    let client_version = u16::from_le_bytes([reply[4], reply[5]]);
+    anyhow::ensure!(
+        client_version == PROTOCOL_VERSION,
+        "Client speaks protocol v{client_version}, but the server v{PROTOCOL_VERSION}"
+    );
+    Ok(u16::from_le_bytes([reply[6], reply[7]]))
+}
+
+/// Messages a [`Client`] sends upstream to the server, to control what the server sends it.
+///
+/// Each is a tag byte, followed by its payload.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ToServer {
+    /// Stop sending frames until [`Self::Resume`].
+    Pause,
+
+    /// Start sending frames again after [`Self::Pause`].
+    Resume,
+
+    /// Send at most this many frames per second, or any number if `None`.
+    SetMaxFps(Option<f32>),
+}
+
+impl ToServer {
+    const TAG_PAUSE: u8 = b'P';
+    const TAG_RESUME: u8 = b'R';
+    const TAG_SET_MAX_FPS: u8 = b'F';
+
+    fn write_to(self, write: &mut impl std::io::Write) -> std::io::Result<()> {
+        match self {
+            Self::Pause => write.write_all(&[Self::TAG_PAUSE]),
+            Self::Resume => write.write_all(&[Self::TAG_RESUME]),
+            Self::SetMaxFps(max_fps) => {
+                // Zero means no limit:
+                let max_fps = max_fps.unwrap_or(0.0).to_le_bytes();
+                write.write_all(&[
+                    Self::TAG_SET_MAX_FPS,
+                    max_fps[0],
+                    max_fps[1],
+                    max_fps[2],
+                    max_fps[3],
+                ])
+            }
+        }
+    }
+
+    /// Decode the message written by [`Self::write_to`] at the start of `bytes`,
+    /// and return it with its length in bytes.
+    ///
+    /// Returns `None` if `bytes` doesn't hold a whole message yet.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn decode(bytes: &[u8]) -> anyhow::Result<Option<(Self, usize)>> {
+        let Some(&tag) = bytes.first() else {
+            return Ok(None);
+        };
+        let message = match tag {
+            Self::TAG_PAUSE => (Self::Pause, 1),
+            Self::TAG_RESUME => (Self::Resume, 1),
+            Self::TAG_SET_MAX_FPS => {
+                let Some(&[b0, b1, b2, b3]) = bytes.get(1..5) else {
+                    return Ok(None);
+                };
+                let max_fps = f32::from_le_bytes([b0, b1, b2, b3]);
+                let max_fps = (max_fps.is_finite() && 0.0 < max_fps).then_some(max_fps);
+                (Self::SetMaxFps(max_fps), 5)
+            }
+            tag => anyhow::bail!("Unknown message tag {tag:#x} from puffin client"),
+        };
+        Ok(Some(message))
+    }
+}
 
 /// The default TCP port used.
 pub const DEFAULT_PORT: u16 = 8585;
@@ -25,4 +178,4 @@ mod server;
 pub use client::Client;
 
 #[cfg(not(target_arch = "wasm32"))]
-pub use server::Server;
+pub use server::{Server, ServerBuilder, ThreadFilter};
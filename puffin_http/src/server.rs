@@ -1,34 +1,118 @@
 use anyhow::Context as _;
 use puffin::{FrameSinkId, FrameView, GlobalProfiler};
 use std::{
-    io::Write,
+    io::{Read as _, Write},
     net::{SocketAddr, TcpListener, TcpStream},
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 /// Maximum size of the backlog of packets to send to a client if they aren't reading fast enough.
 const MAX_FRAMES_IN_QUEUE: usize = 30;
 
+/// Clients that don't read anything for this long are disconnected.
+const TCP_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for a client to reply to the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often to check for messages from a client while there is nothing to send to it.
+const MESSAGE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A fast zstd level, so compression doesn't slow down the profiled application much.
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 1;
+
 /// Listens for incoming connections
 /// and streams them puffin profiler data.
 ///
+/// Each client can pause its stream or limit its frame rate,
+/// see [`crate::Client::set_paused`] and [`crate::Client::set_max_fps`].
+///
 /// Drop to stop transmitting and listening for new connections.
 #[must_use = "When Server is dropped, the server is closed, so keep it around!"]
 pub struct Server {
+    listener_id: usize,
+    num_clients: Arc<AtomicUsize>,
+    dropped_frames: Arc<AtomicU64>,
+    nodelay: Arc<AtomicBool>,
+    keepalive: Arc<parking_lot::Mutex<Option<Duration>>>,
+    #[cfg(feature = "zstd")]
+    compress: Arc<AtomicBool>,
+    shared: Arc<SharedSink>,
+}
+
+/// The sink and serialization thread, shared by all servers created with [`Server::new_sharing`].
+struct SharedSink {
     sink_id: FrameSinkId,
     join_handle: Option<std::thread::JoinHandle<()>>,
-    num_clients: Arc<AtomicUsize>,
     sink_remove: fn(FrameSinkId) -> (),
+    control_tx: crossbeam_channel::Sender<Control>,
+    next_listener_id: AtomicUsize,
 }
 
-impl Server {
-    /// Start listening for connections on this addr (e.g. "0.0.0.0:8585")
-    ///
-    /// Connects to the [GlobalProfiler]
-    pub fn new(bind_addr: &str) -> anyhow::Result<Self> {
+impl Drop for SharedSink {
+    fn drop(&mut self) {
+        // Remove ourselves from the profiler
+        (self.sink_remove)(self.sink_id);
+
+        // Take care to send everything before we shut down:
+        if let Some(join_handle) = self.join_handle.take() {
+            join_handle.join().ok();
+        }
+    }
+}
+
+/// Decides which threads a [`Server`] sends, see [`Server::set_thread_filter`].
+pub type ThreadFilter = fn(&puffin::ThreadInfo) -> bool;
+
+/// Messages from a [`Server`] to the thread serializing the frames.
+enum Control {
+    AddListener(Listener),
+    SetThreadFilter(Option<ThreadFilter>),
+    SetMaxSendFps(Option<f32>),
+    RemoveListener {
+        listener_id: usize,
+        /// Dropped once the clients of the listener are shut down.
+        done: crossbeam_channel::Sender<()>,
+    },
+    Flush {
+        /// Dropped once all queued packets have been sent (or the clients have disconnected).
+        done: crossbeam_channel::Sender<()>,
+    },
+}
+
+/// Configures and starts a [`Server`].
+///
+/// By default, the server streams the frames of the [`GlobalProfiler`]
+/// on `127.0.0.1:`[`crate::DEFAULT_PORT`].
+///
+/// ```no_run
+/// let server = puffin_http::ServerBuilder::default()
+///     .bind_addr("0.0.0.0:8585")
+///     .max_send_fps(Some(60.0))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+#[must_use]
+pub struct ServerBuilder {
+    bind_addr: String,
+    sink_install: fn(puffin::FrameSink) -> FrameSinkId,
+    sink_remove: fn(FrameSinkId),
+    nodelay: bool,
+    keepalive: Option<Duration>,
+    #[cfg(feature = "zstd")]
+    compress: bool,
+    thread_filter: Option<ThreadFilter>,
+    max_send_fps: Option<f32>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
         fn global_add(sink: puffin::FrameSink) -> FrameSinkId {
             GlobalProfiler::lock().add_sink(sink)
         }
@@ -36,7 +120,223 @@ impl Server {
             GlobalProfiler::lock().remove_sink(id);
         }
 
-        Self::new_custom(bind_addr, global_add, global_remove)
+        Self {
+            bind_addr: format!("127.0.0.1:{}", crate::DEFAULT_PORT),
+            sink_install: global_add,
+            sink_remove: global_remove,
+            nodelay: true,
+            keepalive: None,
+            #[cfg(feature = "zstd")]
+            compress: false,
+            thread_filter: None,
+            max_send_fps: None,
+        }
+    }
+}
+
+impl ServerBuilder {
+    /// The address to listen for connections on, e.g. "0.0.0.0:8585".
+    pub fn bind_addr(mut self, bind_addr: &str) -> Self {
+        self.bind_addr = bind_addr.to_owned();
+        self
+    }
+
+    /// Installs the sink of the server into a [`puffin::Profiler`], and returns its [`FrameSinkId`].
+    ///
+    /// Defaults to the [`GlobalProfiler`]. See [`Server::new_custom`] for how to use this.
+    pub fn sink_install(mut self, sink_install: fn(puffin::FrameSink) -> FrameSinkId) -> Self {
+        self.sink_install = sink_install;
+        self
+    }
+
+    /// Reverts [`Self::sink_install`] when the server is dropped.
+    pub fn sink_remove(mut self, sink_remove: fn(FrameSinkId)) -> Self {
+        self.sink_remove = sink_remove;
+        self
+    }
+
+    /// See [`Server::set_nodelay`].
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// See [`Server::set_keepalive`].
+    pub fn keepalive(mut self, idle_time: Option<Duration>) -> Self {
+        self.keepalive = idle_time;
+        self
+    }
+
+    /// See [`Server::set_compression`].
+    #[cfg(feature = "zstd")]
+    pub fn compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// See [`Server::set_thread_filter`].
+    pub fn thread_filter(mut self, filter: Option<ThreadFilter>) -> Self {
+        self.thread_filter = filter;
+        self
+    }
+
+    /// See [`Server::set_max_send_fps`].
+    pub fn max_send_fps(mut self, max_fps: Option<f32>) -> Self {
+        self.max_send_fps = max_fps;
+        self
+    }
+
+    /// Start listening for connections, and streaming the frames of the profiler to them.
+    pub fn build(self) -> anyhow::Result<Server> {
+        let Self {
+            bind_addr,
+            sink_install,
+            sink_remove,
+            nodelay,
+            keepalive,
+            #[cfg(feature = "zstd")]
+            compress,
+            thread_filter,
+            max_send_fps,
+        } = self;
+
+        let listener = Listener::bind(&bind_addr, 0)?;
+        listener.nodelay.store(nodelay, Ordering::Relaxed);
+        *listener.keepalive.lock() = keepalive;
+        #[cfg(feature = "zstd")]
+        listener.compress.store(compress, Ordering::Relaxed);
+        let num_clients = listener.num_clients.clone();
+        let dropped_frames = listener.dropped_frames.clone();
+        let nodelay = listener.nodelay.clone();
+        let keepalive = listener.keepalive.clone();
+        #[cfg(feature = "zstd")]
+        let compress = listener.compress.clone();
+
+        // We use crossbeam_channel instead of `mpsc`,
+        // because on shutdown we want all frames to be sent.
+        // `mpsc::Receiver` stops receiving as soon as the `Sender` is dropped,
+        // but `crossbeam_channel` will continue until the channel is empty.
+        let (tx, rx): (crossbeam_channel::Sender<Arc<puffin::FrameData>>, _) =
+            crossbeam_channel::unbounded();
+
+        let (control_tx, control_rx) = crossbeam_channel::unbounded();
+
+        let join_handle = std::thread::Builder::new()
+            .name("puffin-server".to_owned())
+            .spawn(move || {
+                let mut server_impl = PuffinServerImpl {
+                    listeners: vec![listener],
+                    frame_view: Default::default(),
+                    thread_filter,
+                    max_send_fps,
+                    last_sent: None,
+                    pending_scope_delta: vec![],
+                };
+
+                loop {
+                    crossbeam_channel::select! {
+                        recv(rx) -> frame => {
+                            let Ok(frame) = frame else {
+                                break; // The sink was removed and all frames have been sent.
+                            };
+                            server_impl.add_frame(frame);
+                        }
+                        recv(control_rx) -> control => {
+                            if let Ok(control) = control {
+                                if matches!(control, Control::Flush { .. }) {
+                                    // Make sure the frames that reached the sink before the flush are sent too:
+                                    for frame in rx.try_iter() {
+                                        server_impl.add_frame(frame);
+                                    }
+                                }
+                                server_impl.control(control);
+                            }
+                        }
+                    }
+                }
+            })
+            .context("Couldn't spawn thread")?;
+
+        // Call the `install` function to add ourselves as a sink
+        let sink_id = sink_install(Box::new(move |frame| {
+            tx.send(frame).ok();
+        }));
+
+        Ok(Server {
+            listener_id: 0,
+            num_clients,
+            dropped_frames,
+            nodelay,
+            keepalive,
+            #[cfg(feature = "zstd")]
+            compress,
+            shared: Arc::new(SharedSink {
+                sink_id,
+                join_handle: Some(join_handle),
+                sink_remove,
+                control_tx,
+                next_listener_id: AtomicUsize::new(1),
+            }),
+        })
+    }
+}
+
+impl Server {
+    /// Start listening for connections on this addr (e.g. "0.0.0.0:8585")
+    ///
+    /// Connects to the [`GlobalProfiler`]
+    ///
+    /// See [`ServerBuilder`] for more options.
+    pub fn new(bind_addr: &str) -> anyhow::Result<Self> {
+        ServerBuilder::default().bind_addr(bind_addr).build()
+    }
+
+    /// Start listening for connections on this addr (e.g. "0.0.0.0:8586"),
+    /// streaming the same frames as `existing`.
+    ///
+    /// Unlike creating another [`Server`] for the same profiler, each frame is only serialized once
+    /// for both servers (and any other servers sharing with them), which saves CPU time when
+    /// you want to connect several viewers to the same data.
+    ///
+    /// The servers can be dropped in any order. The frames are sent until the last one is dropped.
+    ///
+    /// ```no_run
+    /// let server = puffin_http::Server::new("127.0.0.1:8585").unwrap();
+    /// let second_server = puffin_http::Server::new_sharing("127.0.0.1:8586", &server).unwrap();
+    /// ```
+    pub fn new_sharing(bind_addr: &str, existing: &Server) -> anyhow::Result<Self> {
+        let listener = Listener::bind(
+            bind_addr,
+            existing
+                .shared
+                .next_listener_id
+                .fetch_add(1, Ordering::Relaxed),
+        )?;
+        let listener_id = listener.id;
+        let num_clients = listener.num_clients.clone();
+        let dropped_frames = listener.dropped_frames.clone();
+        let nodelay = listener.nodelay.clone();
+        let keepalive = listener.keepalive.clone();
+        #[cfg(feature = "zstd")]
+        let compress = listener.compress.clone();
+
+        existing
+            .shared
+            .control_tx
+            .send(Control::AddListener(listener))
+            .ok()
+            .context("The puffin server thread has stopped")?;
+
+        Ok(Self {
+            listener_id,
+            num_clients,
+            dropped_frames,
+            nodelay,
+            keepalive,
+            #[cfg(feature = "zstd")]
+            compress,
+            shared: existing.shared.clone(),
+        })
     }
 
     /// Starts a new puffin server, with a custom function for installing the server's sink
@@ -45,9 +345,9 @@ impl Server {
     /// * `bind_addr` - The address to bind to, when listening for connections
     /// (e.g. "localhost:8585" or "127.0.0.1:8585")
     /// * `sink_install` - A function that installs the [Server]'s sink into
-    /// a [`GlobalProfiler`], and then returns the [`FrameSinkId`] so that the sink can be removed later
+    /// a [`puffin::Profiler`], and then returns the [`FrameSinkId`] so that the sink can be removed later
     /// * `sink_remove` - A function that reverts `sink_install`.
-    /// This should be a call to remove the sink from the profiler ([GlobalProfiler::remove_sink])
+    /// This should be a call to remove the sink from the profiler ([`puffin::Profiler::remove_sink`])
     ///
     /// # Example
     ///
@@ -58,8 +358,7 @@ impl Server {
     ///
     /// ## Per-Thread Profiling
     /// ```
-    /// # use puffin::GlobalProfiler;
-    /// # use puffin::{StreamInfoRef, ThreadInfo, ScopeDetails};
+    /// # use puffin::{GlobalProfiler, Profiler};
     /// # use puffin_http::Server;
     /// # use puffin::ThreadProfiler;
     /// #
@@ -70,12 +369,12 @@ impl Server {
     /// let default_server = Server::new("localhost:8585").expect("failed to create default profiling server");
     /// puffin::profile_scope!("main_scope");
     ///
-    /// // Create a new [GlobalProfiler] instance. This is where we will be sending the events to for our threads.
+    /// // Create a new [Profiler] instance. This is where we will be sending the events to for our threads.
     /// // [OnceLock] and [Mutex] are there so that we can safely get exclusive mutable access.
-    /// static CUSTOM_PROFILER: std::sync::OnceLock<std::sync::Mutex<GlobalProfiler>> = std::sync::OnceLock::new();
+    /// static CUSTOM_PROFILER: std::sync::OnceLock<std::sync::Mutex<Profiler>> = std::sync::OnceLock::new();
     /// // Helper function to access the profiler
-    /// fn get_custom_profiler() -> std::sync::MutexGuard<'static, GlobalProfiler> {
-    ///    CUSTOM_PROFILER.get_or_init(|| std::sync::Mutex::new(GlobalProfiler::default()))
+    /// fn get_custom_profiler() -> std::sync::MutexGuard<'static, Profiler> {
+    ///    CUSTOM_PROFILER.get_or_init(|| std::sync::Mutex::new(Profiler::default()))
     ///         .lock().expect("failed to lock custom profiler")
     /// }
     /// // Create the custom profiling server that uses our custom profiler instead of the global/default one
@@ -90,15 +389,9 @@ impl Server {
     /// // Create some custom threads where we use the custom profiler and server
     /// std::thread::scope(|scope| {
     ///     scope.spawn(move ||{
-    ///         // Tell this thread to use the custom profiler
-    ///         let _ = ThreadProfiler::initialize(
-    ///             // Use the same time source as default puffin
-    ///             puffin::now_ns,
-    ///             // However redirect the events to our `custom_profiler`, instead of the default
-    ///             // which would be the one returned by [GlobalProfiler::lock()]
-    ///             |info: ThreadInfo, details: &[ScopeDetails], stream: &StreamInfoRef<'_>|
-    ///                 get_custom_profiler().report(info, details, stream)
-    ///         );
+    ///         // Tell this thread to send its events to our custom profiler,
+    ///         // instead of the one returned by [GlobalProfiler::lock()]
+    ///         ThreadProfiler::initialize_with(&get_custom_profiler());
     ///
     ///         // Do work
     ///         {
@@ -175,14 +468,9 @@ impl Server {
     ///                         )
     ///                     });
     ///
-    ///                 #[doc = concat!("A custom reporter for the ", std::stringify!([< $name:lower >]), " thread reporter")]
-    ///                 pub fn [< $name:lower _profiler_reporter >] (info: puffin::ThreadInfo, details: &[puffin::ScopeDetails],  stream: &puffin::StreamInfoRef<'_>) {
-    ///                     [< $name:lower _profiler_lock >]().report(info, details, stream)
-    ///                 }
-    ///
     ///                 #[doc = concat!("Accessor for the ", std::stringify!([< $name:lower >]), " thread reporter")]
-    ///                 pub fn [< $name:lower _profiler_lock >]() -> std::sync::MutexGuard<'static, puffin::GlobalProfiler> {
-    ///                     static [< $name _PROFILER >] : once_cell::sync::Lazy<std::sync::Mutex<puffin::GlobalProfiler>> = once_cell::sync::Lazy::new(Default::default);
+    ///                 pub fn [< $name:lower _profiler_lock >]() -> std::sync::MutexGuard<'static, puffin::Profiler> {
+    ///                     static [< $name _PROFILER >] : once_cell::sync::Lazy<std::sync::Mutex<puffin::Profiler>> = once_cell::sync::Lazy::new(Default::default);
     ///                     [< $name _PROFILER >].lock().expect("poisoned std::sync::mutex")
     ///                 }
     ///
@@ -192,7 +480,7 @@ impl Server {
     ///                     eprintln!("init thread profiler \"{}\"", std::stringify!([<$name:lower>]));
     ///                     std::mem::drop([< $name:upper _PROFILER_SERVER >].lock());
     ///                     eprintln!("set thread custom profiler \"{}\"", std::stringify!([<$name:lower>]));
-    ///                     puffin::ThreadProfiler::initialize(::puffin::now_ns, [< $name:lower _profiler_reporter >]);
+    ///                     puffin::ThreadProfiler::initialize_with(&[< $name:lower _profiler_lock >]());
     ///                 }
     ///         }
     ///     };
@@ -226,82 +514,189 @@ impl Server {
         sink_install: fn(puffin::FrameSink) -> FrameSinkId,
         sink_remove: fn(FrameSinkId) -> (),
     ) -> anyhow::Result<Self> {
-        let tcp_listener = TcpListener::bind(bind_addr).context("binding server TCP socket")?;
-        tcp_listener
-            .set_nonblocking(true)
-            .context("TCP set_nonblocking")?;
+        ServerBuilder::default()
+            .bind_addr(bind_addr)
+            .sink_install(sink_install)
+            .sink_remove(sink_remove)
+            .build()
+    }
 
-        // We use crossbeam_channel instead of `mpsc`,
-        // because on shutdown we want all frames to be sent.
-        // `mpsc::Receiver` stops receiving as soon as the `Sender` is dropped,
-        // but `crossbeam_channel` will continue until the channel is empty.
-        let (tx, rx): (crossbeam_channel::Sender<Arc<puffin::FrameData>>, _) =
-            crossbeam_channel::unbounded();
+    /// Number of clients currently connected to this server.
+    pub fn num_clients(&self) -> usize {
+        self.num_clients.load(Ordering::SeqCst)
+    }
 
-        let num_clients = Arc::new(AtomicUsize::default());
-        let num_clients_cloned = num_clients.clone();
+    /// Total number of frames the clients of this server missed because they
+    /// didn't read them fast enough, since the server started.
+    ///
+    /// Frames skipped on purpose, e.g. because of [`Self::set_max_send_fps`], are not counted.
+    pub fn total_dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
 
-        let join_handle = std::thread::Builder::new()
-            .name("puffin-server".to_owned())
-            .spawn(move || {
-                let mut server_impl = PuffinServerImpl {
-                    tcp_listener,
-                    clients: Default::default(),
-                    num_clients: num_clients_cloned,
-                    send_all_scopes: false,
-                    frame_view: Default::default(),
-                };
+    /// Send each frame to the clients right away (`TCP_NODELAY`), instead of letting
+    /// the OS wait to batch it with more data (Nagle's algorithm).
+    ///
+    /// On by default, since a live profiler should show frames with as little delay as possible.
+    /// Turn it off to save some bandwidth on slow links.
+    /// Only affects clients that connect after the call.
+    pub fn set_nodelay(&self, nodelay: bool) {
+        self.nodelay.store(nodelay, Ordering::Relaxed);
+    }
 
-                while let Ok(frame) = rx.recv() {
-                    server_impl.frame_view.add_frame(frame.clone());
-                    if let Err(err) = server_impl.accept_new_clients() {
-                        log::warn!("puffin server failure: {}", err);
-                    }
+    /// Send TCP keepalive probes to idle clients after `idle_time`,
+    /// so that clients that vanished without closing the connection are noticed.
+    ///
+    /// Off (`None`) by default.
+    /// Only affects clients that connect after the call.
+    pub fn set_keepalive(&self, idle_time: Option<Duration>) {
+        *self.keepalive.lock() = idle_time;
+    }
 
-                    if let Err(err) = server_impl.send(&frame) {
-                        log::warn!("puffin server failure: {}", err);
-                    }
-                }
-            })
-            .context("Couldn't spawn thread")?;
+    /// Compress the stream to each client with zstd.
+    ///
+    /// The frames are already compressed, but this still makes the stream about 2.2 to 2.5 times smaller,
+    /// at a cost of roughly 5 µs (1 scope per thread) to 450 µs (1000 scopes per thread)
+    /// of CPU time per frame on the server thread, measured with 3 threads in a release build.
+    /// So it is only worth it on slow links; leave it off (the default) on a LAN.
+    ///
+    /// Only affects clients that connect after the call.
+    /// The server offers compression in the handshake, and only compresses the stream
+    /// to clients that accept it, i.e. that are built with the `zstd` feature too, like `puffin_viewer`.
+    #[cfg(feature = "zstd")]
+    pub fn set_compression(&self, compress: bool) {
+        self.compress.store(compress, Ordering::Relaxed);
+    }
 
-        // Call the `install` function to add ourselves as a sink
-        let sink_id = sink_install(Box::new(move |frame| {
-            tx.send(frame).ok();
-        }));
+    /// Only send the threads for which `filter` returns `true`, or all threads if `None` (the default).
+    ///
+    /// Useful to save bandwidth on slow links, e.g. to an embedded target,
+    /// at the cost of not seeing the other threads in the viewer at all.
+    /// The frames are only filtered when sent, so the [`puffin::FrameMeta`] the clients see,
+    /// e.g. the range and number of scopes, only covers the sent threads.
+    /// Frames where no thread passes the filter are not sent at all.
+    ///
+    /// Servers created with [`Self::new_sharing`] share their frames, so this filters all of them.
+    ///
+    /// ```no_run
+    /// let server = puffin_http::Server::new("127.0.0.1:8585").unwrap();
+    /// server.set_thread_filter(Some(|thread_info| thread_info.name == "main"));
+    /// ```
+    pub fn set_thread_filter(&self, filter: Option<ThreadFilter>) {
+        self.shared
+            .control_tx
+            .send(Control::SetThreadFilter(filter))
+            .ok();
+    }
 
-        Ok(Server {
-            sink_id,
-            join_handle: Some(join_handle),
-            num_clients,
-            sink_remove,
-        })
+    /// Send at most this many frames per second, or all of them if `None` (the default).
+    ///
+    /// Useful when the profiled application runs at a much higher frame rate than a viewer
+    /// can show, e.g. a headless simulation: skipping frames on the server is cheaper
+    /// than serializing them all, and then having clients that can't keep up drop them.
+    /// The new scopes of skipped frames are sent with the next frame that is sent.
+    ///
+    /// Servers created with [`Self::new_sharing`] share their frames, so this limits all of them.
+    /// Each client can also ask for fewer frames itself, see [`crate::Client::set_max_fps`].
+    pub fn set_max_send_fps(&self, max_fps: Option<f32>) {
+        self.shared
+            .control_tx
+            .send(Control::SetMaxSendFps(max_fps))
+            .ok();
     }
 
-    /// Number of clients currently connected.
-    pub fn num_clients(&self) -> usize {
-        self.num_clients.load(Ordering::SeqCst)
+    /// Blocks until all frames given to the server so far have been sent to the connected clients.
+    ///
+    /// Clients that stop reading are disconnected after a few seconds,
+    /// so this won't block forever. Frames dropped because a client couldn't keep up are not resent.
+    ///
+    /// This only covers frames the profiler has already finished, so call
+    /// [`puffin::Profiler::flush`] first to include the scopes of the current frame:
+    ///
+    /// ```no_run
+    /// # let server = puffin_http::Server::new("127.0.0.1:8585").unwrap();
+    /// puffin::GlobalProfiler::lock().flush();
+    /// server.flush();
+    /// ```
+    ///
+    /// It also waits until the messages the clients sent before the call,
+    /// e.g. to pause their stream, have been handled.
+    ///
+    /// Servers created with [`Self::new_sharing`] share their frames, so this flushes all of them.
+    pub fn flush(&self) {
+        let (done, done_rx) = crossbeam_channel::bounded(0);
+        if self.shared.control_tx.send(Control::Flush { done }).is_ok() {
+            // Returns once all clones of `done` are dropped:
+            done_rx.recv().ok();
+        }
     }
 }
 
 impl Drop for Server {
     fn drop(&mut self) {
-        // Remove ourselves from the profiler
-        (self.sink_remove)(self.sink_id);
-
-        // Take care to send everything before we shut down:
-        if let Some(join_handle) = self.join_handle.take() {
-            join_handle.join().ok();
+        // If we are the last server, dropping `self.shared` sends all remaining frames and shuts down.
+        // Otherwise only stop our own listener, and leave the rest running for the other servers:
+        if Arc::strong_count(&self.shared) > 1 {
+            let (done, done_rx) = crossbeam_channel::bounded(1);
+            let remove = Control::RemoveListener {
+                listener_id: self.listener_id,
+                done,
+            };
+            if self.shared.control_tx.send(remove).is_ok() {
+                done_rx.recv().ok();
+            }
         }
     }
 }
 
 type Packet = Arc<[u8]>;
 
+/// Messages from the server thread to the thread of a [`Client`].
+enum ToClient {
+    Packet(Packet),
+    /// Dropped once all previous packets have been sent.
+    Flush(crossbeam_channel::Sender<()>),
+}
+
+/// What a client asked for with [`crate::ToServer`] messages.
+#[derive(Default)]
+struct ClientState {
+    paused: bool,
+    max_fps: Option<f32>,
+}
+
 struct Client {
     client_addr: SocketAddr,
-    packet_tx: Option<crossbeam_channel::Sender<Packet>>,
+    packet_tx: Option<crossbeam_channel::Sender<ToClient>>,
     join_handle: Option<std::thread::JoinHandle<()>>,
+    /// Shut down when the client is dropped, once everything has been sent.
+    tcp_stream: TcpStream,
+    /// Updated by the thread of the client, with the messages it reads from it.
+    state: Arc<parking_lot::Mutex<ClientState>>,
+    /// When the client was last sent a frame.
+    last_sent: Option<Instant>,
+    /// Set until the client has been sent a frame with all scopes.
+    needs_all_scopes: bool,
+    /// Set for each frame the client doesn't want, see [`Self::wants_frame`].
+    skip_frame: bool,
+    /// Frames dropped because the client wasn't reading fast enough.
+    dropped_frames: u64,
+}
+
+impl Client {
+    /// Should the client be sent a frame now, or is it paused or limited to fewer frames per second?
+    fn wants_frame(&self, now: Instant) -> bool {
+        let state = self.state.lock();
+        if state.paused {
+            return false;
+        }
+        match (state.max_fps, self.last_sent) {
+            (Some(max_fps), Some(last_sent)) => {
+                1.0 / max_fps <= now.duration_since(last_sent).as_secs_f32()
+            }
+            _ => true,
+        }
+    }
 }
 
 impl Drop for Client {
@@ -315,20 +710,46 @@ impl Drop for Client {
         if let Some(join_handle) = self.join_handle.take() {
             join_handle.join().ok();
         }
+
+        self.tcp_stream.shutdown(std::net::Shutdown::Both).ok();
     }
 }
 
-/// Listens for incoming connections
-/// and streams them puffin profiler data.
-struct PuffinServerImpl {
+/// The socket and clients of one [`Server`].
+struct Listener {
+    id: usize,
     tcp_listener: TcpListener,
     clients: Vec<Client>,
     num_clients: Arc<AtomicUsize>,
-    send_all_scopes: bool,
-    frame_view: FrameView,
+    /// Frames dropped because a client wasn't reading fast enough.
+    dropped_frames: Arc<AtomicU64>,
+    /// Set `TCP_NODELAY` on new clients.
+    nodelay: Arc<AtomicBool>,
+    /// Enable TCP keepalive on new clients, with this idle time.
+    keepalive: Arc<parking_lot::Mutex<Option<Duration>>>,
+    /// Compress the stream to new clients with zstd.
+    compress: Arc<AtomicBool>,
 }
 
-impl PuffinServerImpl {
+impl Listener {
+    fn bind(bind_addr: &str, id: usize) -> anyhow::Result<Self> {
+        let tcp_listener = TcpListener::bind(bind_addr).context("binding server TCP socket")?;
+        tcp_listener
+            .set_nonblocking(true)
+            .context("TCP set_nonblocking")?;
+
+        Ok(Self {
+            id,
+            tcp_listener,
+            clients: Default::default(),
+            num_clients: Default::default(),
+            dropped_frames: Default::default(),
+            nodelay: Arc::new(AtomicBool::new(true)),
+            keepalive: Default::default(),
+            compress: Default::default(),
+        })
+    }
+
     fn accept_new_clients(&mut self) -> anyhow::Result<()> {
         loop {
             match self.tcp_listener.accept() {
@@ -336,22 +757,49 @@ impl PuffinServerImpl {
                     tcp_stream
                         .set_nonblocking(false)
                         .context("stream.set_nonblocking")?;
+                    tcp_stream
+                        .set_write_timeout(Some(TCP_WRITE_TIMEOUT))
+                        .context("stream.set_write_timeout")?;
+                    tcp_stream
+                        .set_nodelay(self.nodelay.load(Ordering::Relaxed))
+                        .context("stream.set_nodelay")?;
+                    if let Some(idle_time) = *self.keepalive.lock() {
+                        socket2::SockRef::from(&tcp_stream)
+                            .set_tcp_keepalive(&socket2::TcpKeepalive::new().with_time(idle_time))
+                            .context("stream.set_tcp_keepalive")?;
+                    }
 
                     log::info!("{} connected", client_addr);
 
                     let (packet_tx, packet_rx) = crossbeam_channel::bounded(MAX_FRAMES_IN_QUEUE);
+                    let compress = self.compress.load(Ordering::Relaxed);
+                    let state = Arc::<parking_lot::Mutex<ClientState>>::default();
 
+                    let write_stream = tcp_stream.try_clone().context("stream.try_clone")?;
+                    let client_state = state.clone();
                     let join_handle = std::thread::Builder::new()
                         .name("puffin-server-client".to_owned())
-                        .spawn(move || client_loop(packet_rx, client_addr, tcp_stream))
+                        .spawn(move || {
+                            client_loop(
+                                packet_rx,
+                                client_addr,
+                                write_stream,
+                                compress,
+                                client_state,
+                            );
+                        })
                         .context("Couldn't spawn thread")?;
 
-                    // Send all scopes when new client connects.
-                    self.send_all_scopes = true;
                     self.clients.push(Client {
                         client_addr,
                         packet_tx: Some(packet_tx),
                         join_handle: Some(join_handle),
+                        tcp_stream,
+                        state,
+                        last_sent: None,
+                        needs_all_scopes: true,
+                        skip_frame: false,
+                        dropped_frames: 0,
                     });
                     self.num_clients.store(self.clients.len(), Ordering::SeqCst);
                 }
@@ -366,12 +814,203 @@ impl PuffinServerImpl {
         Ok(())
     }
 
+    fn send(&mut self, with_all_scopes: &Packet, scope_delta: &Packet, now: Instant) {
+        self.clients.retain_mut(|client| {
+            let Some(packet_tx) = &client.packet_tx else {
+                return false;
+            };
+            if client.skip_frame {
+                return true;
+            }
+            let packet = if client.needs_all_scopes {
+                with_all_scopes
+            } else {
+                scope_delta
+            };
+            match packet_tx.try_send(ToClient::Packet(packet.clone())) {
+                Ok(()) => {
+                    client.needs_all_scopes = false;
+                    client.last_sent = Some(now);
+                    true
+                }
+                Err(crossbeam_channel::TrySendError::Disconnected(_)) => false,
+                Err(crossbeam_channel::TrySendError::Full(_)) => {
+                    client.dropped_frames += 1;
+                    self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+                    log::info!(
+                        "puffin client {} is not accepting data fast enough; dropping a frame ({} so far)",
+                        client.client_addr,
+                        client.dropped_frames
+                    );
+                    true
+                }
+            }
+        });
+        self.num_clients.store(self.clients.len(), Ordering::SeqCst);
+    }
+
+    /// Give each client a clone of `done`, to drop once it has sent everything queued so far.
+    fn flush(&mut self, done: &crossbeam_channel::Sender<()>) {
+        for client in &self.clients {
+            if let Some(packet_tx) = &client.packet_tx {
+                // Blocks if the queue is full, but the client either makes progress
+                // or disconnects within `TCP_WRITE_TIMEOUT`.
+                packet_tx.send(ToClient::Flush(done.clone())).ok();
+            }
+        }
+    }
+}
+
+/// Serializes each frame once, and streams it to the clients of all listeners.
+struct PuffinServerImpl {
+    listeners: Vec<Listener>,
+    frame_view: FrameView,
+    thread_filter: Option<ThreadFilter>,
+    max_send_fps: Option<f32>,
+    /// When we last sent a frame, for [`Self::max_send_fps`].
+    last_sent: Option<Instant>,
+
+    /// New scopes of frames that weren't sent because no thread passed the filter,
+    /// or because of [`Self::max_send_fps`], to be sent with the next frame instead.
+    pending_scope_delta: Vec<Arc<puffin::ScopeDetails>>,
+}
+
+impl PuffinServerImpl {
+    fn control(&mut self, control: Control) {
+        match control {
+            Control::AddListener(listener) => {
+                self.listeners.push(listener);
+            }
+            Control::SetThreadFilter(thread_filter) => {
+                self.thread_filter = thread_filter;
+            }
+            Control::SetMaxSendFps(max_send_fps) => {
+                self.max_send_fps = max_send_fps;
+            }
+            Control::RemoveListener { listener_id, done } => {
+                // Dropping the listener waits for its clients to shut down.
+                self.listeners.retain(|listener| listener.id != listener_id);
+                drop(done);
+            }
+            Control::Flush { done } => {
+                for listener in &mut self.listeners {
+                    listener.flush(&done);
+                }
+            }
+        }
+    }
+
+    fn add_frame(&mut self, frame: Arc<puffin::FrameData>) {
+        self.frame_view.add_frame(frame.clone());
+        self.accept_new_clients();
+
+        let now = Instant::now();
+        if self.throttled(now) {
+            self.pending_scope_delta
+                .extend(frame.scope_delta.iter().cloned());
+            return;
+        }
+
+        let frame = match self.filter_threads(frame) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return,
+            Err(err) => {
+                log::warn!("puffin server failure: {}", err);
+                return;
+            }
+        };
+        self.last_sent = Some(now);
+
+        if let Err(err) = self.send(&frame) {
+            log::warn!("puffin server failure: {}", err);
+        }
+    }
+
+    /// Is it too soon to send another frame, according to [`Server::set_max_send_fps`]?
+    fn throttled(&self, now: Instant) -> bool {
+        match (self.max_send_fps, self.last_sent) {
+            (Some(max_fps), Some(last_sent)) => {
+                now.duration_since(last_sent).as_secs_f32() < 1.0 / max_fps
+            }
+            _ => false,
+        }
+    }
+
+    /// Remove the threads that don't pass the [`Server::set_thread_filter`] filter.
+    ///
+    /// Returns `None` if no thread is left, in which case the scope delta of the frame
+    /// is held back until the next frame that is sent.
+    fn filter_threads(
+        &mut self,
+        frame: Arc<puffin::FrameData>,
+    ) -> anyhow::Result<Option<Arc<puffin::FrameData>>> {
+        if self.thread_filter.is_none() && self.pending_scope_delta.is_empty() {
+            return Ok(Some(frame));
+        }
+        let keep = self.thread_filter.unwrap_or(|_| true);
+
+        let Some(mut retained) = frame.retain_threads(keep)? else {
+            self.pending_scope_delta
+                .extend(frame.scope_delta.iter().cloned());
+            return Ok(None);
+        };
+        retained
+            .scope_delta
+            .splice(0..0, self.pending_scope_delta.drain(..));
+        Ok(Some(Arc::new(retained)))
+    }
+
+    fn accept_new_clients(&mut self) {
+        for listener in &mut self.listeners {
+            if let Err(err) = listener.accept_new_clients() {
+                log::warn!("puffin server failure: {}", err);
+            }
+        }
+    }
+
     pub fn send(&mut self, frame: &puffin::FrameData) -> anyhow::Result<()> {
-        if self.clients.is_empty() {
+        let now = Instant::now();
+        let clients = self
+            .listeners
+            .iter_mut()
+            .flat_map(|listener| &mut listener.clients);
+        let (mut any_new_clients, mut any_old_clients) = (false, false);
+        for client in clients {
+            client.skip_frame = !client.wants_frame(now);
+            if client.skip_frame {
+                // It misses the new scopes of this frame, so send all of them with the next one:
+                client.needs_all_scopes = true;
+            } else {
+                any_new_clients |= client.needs_all_scopes;
+                any_old_clients |= !client.needs_all_scopes;
+            }
+        }
+        if !any_new_clients && !any_old_clients {
             return Ok(());
         }
         puffin::profile_function!();
 
+        // Clients that just connected need all the scopes, the rest only the new ones.
+        let with_all_scopes = any_new_clients
+            .then(|| self.encode(frame, true))
+            .transpose()?;
+        let scope_delta = any_old_clients
+            .then(|| self.encode(frame, false))
+            .transpose()?;
+        let (with_all_scopes, scope_delta) = match (with_all_scopes, scope_delta) {
+            (Some(with_all_scopes), Some(scope_delta)) => (with_all_scopes, scope_delta),
+            (Some(packet), None) | (None, Some(packet)) => (packet.clone(), packet),
+            (None, None) => return Ok(()),
+        };
+
+        for listener in &mut self.listeners {
+            listener.send(&with_all_scopes, &scope_delta, now);
+        }
+
+        Ok(())
+    }
+
+    fn encode(&self, frame: &puffin::FrameData, send_all_scopes: bool) -> anyhow::Result<Packet> {
         let mut packet = vec![];
 
         packet
@@ -381,41 +1020,85 @@ impl PuffinServerImpl {
         frame
             .write_into(
                 self.frame_view.scope_collection(),
-                self.send_all_scopes,
+                send_all_scopes,
                 &mut packet,
             )
             .context("Encode puffin frame")?;
-        self.send_all_scopes = false;
-
-        let packet: Packet = packet.into();
-
-        self.clients.retain(|client| match &client.packet_tx {
-            None => false,
-            Some(packet_tx) => match packet_tx.try_send(packet.clone()) {
-                Ok(()) => true,
-                Err(crossbeam_channel::TrySendError::Disconnected(_)) => false,
-                Err(crossbeam_channel::TrySendError::Full(_)) => {
-                    log::info!(
-                        "puffin client {} is not accepting data fast enough; dropping a frame",
-                        client.client_addr
-                    );
-                    true
-                }
-            },
-        });
-        self.num_clients.store(self.clients.len(), Ordering::SeqCst);
 
-        Ok(())
+        Ok(packet.into())
     }
 }
 
 fn client_loop(
-    packet_rx: crossbeam_channel::Receiver<Packet>,
+    packet_rx: crossbeam_channel::Receiver<ToClient>,
     client_addr: SocketAddr,
     mut tcp_stream: TcpStream,
+    compress: bool,
+    state: Arc<parking_lot::Mutex<ClientState>>,
 ) {
-    while let Ok(packet) = packet_rx.recv() {
-        if let Err(err) = tcp_stream.write_all(&packet) {
+    let offered_flags = if compress { crate::FLAG_ZSTD } else { 0 };
+    let flags = match handshake(&mut tcp_stream, offered_flags) {
+        Ok(flags) => flags,
+        Err(err) => {
+            log::info!(
+                "puffin server failed the handshake with {}: {:#}",
+                client_addr,
+                err
+            );
+            return;
+        }
+    };
+
+    let mut read_stream = match tcp_stream.try_clone() {
+        Ok(read_stream) => read_stream,
+        Err(err) => {
+            log::warn!("puffin server failed to clone the stream: {}", err);
+            return;
+        }
+    };
+
+    let mut write: Box<dyn Write> = if flags & crate::FLAG_ZSTD != 0 {
+        match zstd_encoder(tcp_stream) {
+            Ok(encoder) => encoder,
+            Err(err) => {
+                log::warn!("puffin server failed to start compressing: {}", err);
+                return;
+            }
+        }
+    } else {
+        Box::new(tcp_stream)
+    };
+
+    // The start of a message from the client that hasn't been fully received yet:
+    let mut received = Vec::new();
+
+    loop {
+        let message = match packet_rx.recv_timeout(MESSAGE_POLL_INTERVAL) {
+            Ok(message) => Some(message),
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => None,
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
+        };
+
+        // Also before each flush, so that it covers the messages sent before it:
+        if let Err(err) = read_messages(&mut read_stream, &mut received, client_addr, &state) {
+            log::info!(
+                "puffin server stopped reading from {}: {:#}",
+                client_addr,
+                err
+            );
+            break;
+        }
+
+        let packet = match message {
+            Some(ToClient::Packet(packet)) => packet,
+            Some(ToClient::Flush(done)) => {
+                drop(done);
+                continue;
+            }
+            None => continue,
+        };
+        // Flush right away, so compressed frames aren't held back until the next one:
+        if let Err(err) = write.write_all(&packet).and_then(|()| write.flush()) {
             log::info!(
                 "puffin server failed sending to {}: {} (kind: {:?})",
                 client_addr,
@@ -426,3 +1109,331 @@ fn client_loop(
         }
     }
 }
+
+/// Offer `flags` to a new client, and return those it accepts.
+fn handshake(tcp_stream: &mut TcpStream, flags: u16) -> anyhow::Result<u16> {
+    crate::write_handshake(tcp_stream, flags)?;
+    // Don't wait forever on clients that don't reply, e.g. because they speak another protocol version:
+    tcp_stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let accepted = crate::read_handshake_reply(tcp_stream)?;
+    tcp_stream.set_read_timeout(None)?;
+    Ok(accepted & flags)
+}
+
+/// Reads the [`crate::ToServer`] messages the client has sent so far into its `state`, without blocking.
+///
+/// `received` holds on to the start of a message that hasn't been fully received yet.
+fn read_messages(
+    tcp_stream: &mut TcpStream,
+    received: &mut Vec<u8>,
+    client_addr: SocketAddr,
+    state: &parking_lot::Mutex<ClientState>,
+) -> anyhow::Result<()> {
+    // Only while reading, since we write to the same socket in blocking mode:
+    tcp_stream.set_nonblocking(true)?;
+    let read = read_available(tcp_stream, received);
+    tcp_stream.set_nonblocking(false)?;
+    read?;
+
+    let mut offset = 0;
+    while let Some((message, len)) = crate::ToServer::decode(&received[offset..])? {
+        offset += len;
+        log::debug!("puffin client {} sent {:?}", client_addr, message);
+        let mut state = state.lock();
+        match message {
+            crate::ToServer::Pause => state.paused = true,
+            crate::ToServer::Resume => state.paused = false,
+            crate::ToServer::SetMaxFps(max_fps) => state.max_fps = max_fps,
+        }
+    }
+    received.drain(..offset);
+    Ok(())
+}
+
+/// Append everything received so far on the non-blocking `tcp_stream` to `received`.
+fn read_available(tcp_stream: &mut TcpStream, received: &mut Vec<u8>) -> anyhow::Result<()> {
+    let mut buffer = [0_u8; 256];
+    loop {
+        match tcp_stream.read(&mut buffer) {
+            Ok(0) => anyhow::bail!("End of stream"),
+            Ok(num_bytes) => received.extend_from_slice(&buffer[..num_bytes]),
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_encoder(tcp_stream: TcpStream) -> std::io::Result<Box<dyn Write>> {
+    Ok(Box::new(
+        zstd::stream::write::Encoder::new(tcp_stream, ZSTD_LEVEL)?.auto_finish(),
+    ))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_encoder(_tcp_stream: TcpStream) -> std::io::Result<Box<dyn Write>> {
+    unreachable!("`Server::set_compression` requires the `zstd` feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpStream;
+
+    use puffin::{
+        GlobalFrameView, GlobalProfiler, Profiler, ScopeDetails, ScopeId, Stream, StreamInfo,
+        ThreadInfo,
+    };
+
+    /// Start a [`super::Server`] for a [`Profiler`] of its own,
+    /// so that tests running in parallel don't see each other's frames.
+    ///
+    /// Evaluates to the server and the profiler.
+    macro_rules! test_server {
+        ($addr:expr) => {{
+            static PROFILER: once_cell::sync::Lazy<parking_lot::Mutex<Profiler>> =
+                once_cell::sync::Lazy::new(Default::default);
+            fn install(sink: puffin::FrameSink) -> puffin::FrameSinkId {
+                PROFILER.lock().add_sink(sink)
+            }
+            fn remove(id: puffin::FrameSinkId) {
+                PROFILER.lock().remove_sink(id);
+            }
+            let server = super::Server::new_custom($addr, install, remove).unwrap();
+            (server, &*PROFILER)
+        }};
+    }
+
+    /// Report a single scope on the given thread.
+    fn report(profiler: &parking_lot::Mutex<Profiler>, thread_name: &str, scope_id: ScopeId) {
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| 100, scope_id, "");
+        stream.end_scope(offset, 200);
+        profiler.lock().report_user_scopes(
+            ThreadInfo::new(None, thread_name),
+            &StreamInfo::parse(stream).unwrap().as_stream_into_ref(),
+        );
+    }
+
+    /// Report a single scope on the main thread, and finish the frame.
+    fn report_frame(profiler: &parking_lot::Mutex<Profiler>, scope_id: ScopeId) {
+        report(profiler, "main", scope_id);
+        profiler.lock().new_frame();
+    }
+
+    fn register_scope(profiler: &parking_lot::Mutex<Profiler>, name: &'static str) -> ScopeId {
+        profiler
+            .lock()
+            .register_user_scopes(&[ScopeDetails::from_scope_name(name)])[0]
+    }
+
+    /// Do the client side of the handshake, accepting what the server offers, and return the flags.
+    ///
+    /// The server only sends the handshake once it has accepted the client, i.e. with the first frame.
+    fn accept_handshake(client: &mut TcpStream) -> u16 {
+        // Like `crate::Client`, so that messages reach the server before the next flush:
+        client.set_nodelay(true).unwrap();
+        let flags = crate::read_handshake(client).unwrap();
+        crate::write_handshake(client, flags).unwrap();
+        flags
+    }
+
+    #[test]
+    fn flush_sends_current_frame() {
+        let addr = "127.0.0.1:8599";
+        let server = super::Server::new(addr).unwrap();
+        let frame_view = GlobalFrameView::default();
+        let mut client = TcpStream::connect(addr).unwrap();
+
+        puffin::set_scopes_on(true);
+        {
+            puffin::profile_scope!("flushed");
+        }
+        GlobalProfiler::lock().flush();
+        accept_handshake(&mut client);
+        server.flush();
+
+        // The frame view is a plain sink, so it has the frame as soon as the profiler is flushed:
+        let latest = frame_view.lock().latest_frame().unwrap();
+        assert_eq!(latest.meta().num_scopes, 1);
+
+        // …while the server needs the explicit flush:
+        assert_eq!(server.num_clients(), 1);
+        let received = crate::client::consume_message(&mut client).unwrap();
+        assert_eq!(received.frame_index(), latest.frame_index());
+        assert_eq!(received.meta().num_scopes, 1);
+    }
+
+    #[test]
+    fn thread_filter() {
+        let (server, profiler) = test_server!("127.0.0.1:8597");
+        server.set_thread_filter(Some(|thread_info| thread_info.name == "main"));
+        let mut client = TcpStream::connect("127.0.0.1:8597").unwrap();
+
+        let work = register_scope(profiler, "work");
+        report(profiler, "main", work);
+        report(profiler, "noisy", work);
+        profiler.lock().new_frame();
+        accept_handshake(&mut client);
+
+        // Not sent at all, but the new scope must still reach the client:
+        let late = register_scope(profiler, "late");
+        report(profiler, "noisy", late);
+        profiler.lock().new_frame();
+
+        report_frame(profiler, late);
+        server.flush();
+
+        let first = crate::client::consume_message(&mut client).unwrap();
+        let first_threads: Vec<_> = first
+            .unpacked()
+            .unwrap()
+            .thread_streams
+            .keys()
+            .map(|info| info.name.clone())
+            .collect();
+        assert_eq!(first_threads, ["main"]);
+        assert_eq!(first.meta().num_scopes, 1);
+
+        let third = crate::client::consume_message(&mut client).unwrap();
+        assert_eq!(third.frame_index(), first.frame_index() + 2);
+        let scope_names: Vec<_> = third
+            .scope_delta
+            .iter()
+            .map(|scope| scope.name().to_string())
+            .collect();
+        assert_eq!(scope_names, ["late"]);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_stream() {
+        let (server, profiler) = test_server!("127.0.0.1:8595");
+        server.set_compression(true);
+        let mut client = TcpStream::connect("127.0.0.1:8595").unwrap();
+
+        report_frame(profiler, register_scope(profiler, "work"));
+        assert_eq!(accept_handshake(&mut client), crate::FLAG_ZSTD);
+        server.flush();
+
+        let mut client = zstd::stream::read::Decoder::new(client).unwrap();
+        let received = crate::client::consume_message(&mut client).unwrap();
+        assert_eq!(received.meta().num_scopes, 1);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compression_declined_by_client() {
+        let (server, profiler) = test_server!("127.0.0.1:8592");
+        server.set_compression(true);
+        let mut client = TcpStream::connect("127.0.0.1:8592").unwrap();
+
+        report_frame(profiler, register_scope(profiler, "work"));
+
+        // Like a client built without the `zstd` feature:
+        assert_eq!(
+            crate::read_handshake(&mut client).unwrap(),
+            crate::FLAG_ZSTD
+        );
+        crate::write_handshake(&mut client, 0).unwrap();
+        server.flush();
+
+        let received = crate::client::consume_message(&mut client).unwrap();
+        assert_eq!(received.meta().num_scopes, 1);
+    }
+
+    #[test]
+    fn paused_client() {
+        let (server, profiler) = test_server!("127.0.0.1:8594");
+        let mut paused = TcpStream::connect("127.0.0.1:8594").unwrap();
+        let mut running = TcpStream::connect("127.0.0.1:8594").unwrap();
+
+        let work = register_scope(profiler, "work");
+        report_frame(profiler, work);
+        accept_handshake(&mut paused);
+        accept_handshake(&mut running);
+        server.flush();
+        let first = crate::client::consume_message(&mut paused).unwrap();
+        assert_eq!(
+            crate::client::consume_message(&mut running)
+                .unwrap()
+                .frame_index(),
+            first.frame_index()
+        );
+
+        // Each flush waits for the server to handle the messages sent before it:
+        crate::ToServer::Pause.write_to(&mut paused).unwrap();
+        server.flush();
+        report_frame(profiler, register_scope(profiler, "late"));
+        server.flush();
+        let second = crate::client::consume_message(&mut running).unwrap();
+        assert_eq!(second.frame_index(), first.frame_index() + 1);
+
+        crate::ToServer::Resume.write_to(&mut paused).unwrap();
+        server.flush();
+        report_frame(profiler, work);
+        server.flush();
+
+        // The paused client skipped a frame, and with it the new scope:
+        let third = crate::client::consume_message(&mut paused).unwrap();
+        assert_eq!(third.frame_index(), first.frame_index() + 2);
+        assert_eq!(third.meta().num_scopes, 1);
+        assert_eq!(third.scope_delta.len(), 2);
+        let third = crate::client::consume_message(&mut running).unwrap();
+        assert_eq!(third.frame_index(), first.frame_index() + 2);
+        assert!(third.scope_delta.is_empty());
+    }
+
+    #[test]
+    fn max_send_fps() {
+        let (server, profiler) = test_server!("127.0.0.1:8593");
+        server.set_max_send_fps(Some(2.0));
+        let mut client = TcpStream::connect("127.0.0.1:8593").unwrap();
+
+        let work = register_scope(profiler, "work");
+        report_frame(profiler, work);
+        accept_handshake(&mut client);
+        server.flush();
+
+        // Too soon after the first frame, so not sent:
+        report_frame(profiler, register_scope(profiler, "late"));
+        report_frame(profiler, work);
+        server.flush();
+
+        std::thread::sleep(std::time::Duration::from_millis(600));
+        report_frame(profiler, work);
+        server.flush();
+
+        let first = crate::client::consume_message(&mut client).unwrap();
+        let fourth = crate::client::consume_message(&mut client).unwrap();
+        assert_eq!(fourth.frame_index(), first.frame_index() + 3);
+        let scope_names: Vec<_> = fourth
+            .scope_delta
+            .iter()
+            .map(|scope| scope.name().to_string())
+            .collect();
+        assert_eq!(scope_names, ["late"]);
+    }
+
+    #[test]
+    fn frame_format_matches_protocol_version() {
+        let profiler = parking_lot::Mutex::new(Profiler::default());
+        let scope_id = register_scope(&profiler, "scope");
+        let mut stream = Stream::default();
+        let (offset, _) = stream.begin_scope(|| 100, scope_id, "");
+        stream.end_scope(offset, 200);
+        let thread_streams = std::collections::BTreeMap::from([(
+            ThreadInfo::new(None, "main"),
+            StreamInfo::parse(stream).unwrap(),
+        )]);
+        let frame = puffin::FrameData::new(0, thread_streams, vec![], false).unwrap();
+        let mut bytes = vec![];
+        frame
+            .write_into(&Default::default(), false, &mut bytes)
+            .unwrap();
+
+        // A new frame format needs a new protocol version, see `crate::PROTOCOL_VERSION`:
+        assert_eq!(&bytes[..4], b"PFDD");
+        assert_eq!(crate::PROTOCOL_VERSION, 9);
+    }
+}
@@ -5,6 +5,11 @@
 #![allow(clippy::exit)]
 #![cfg_attr(target_arch = "wasm32", allow(clippy::unused_unit))]
 
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
+
 use eframe::egui;
 use puffin::FrameView;
 use puffin_egui::MaybeMutRef;
@@ -26,13 +31,24 @@ impl Source {
         }
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    fn with_frame_view<R>(&self, f: impl FnOnce(&FrameView) -> R) -> R {
+        match self {
+            Self::None => f(&FrameView::default()),
+            Self::Http(http_client) => f(&http_client.frame_view()),
+            Self::FilePath(_, frame_view) | Self::FileName(_, frame_view) => f(frame_view),
+        }
+    }
+
     fn ui(&self, ui: &mut egui::Ui) {
         match self {
             Self::None => {
                 ui.label("No file or stream open");
             }
             Self::Http(http_client) => {
-                if http_client.connected() {
+                if let Some(error) = http_client.error() {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                } else if http_client.connected() {
                     ui.label(format!("Connected to {}", http_client.addr()));
                 } else {
                     ui.label(format!("Connecting to {}…", http_client.addr()));
@@ -48,11 +64,209 @@ impl Source {
     }
 }
 
+/// The time window to export, in seconds since the start of the capture.
+#[cfg(not(target_arch = "wasm32"))]
+struct ExportTimeRange {
+    start_s: f64,
+    end_s: f64,
+}
+
+/// How many of the latest frames of a `.puffin` file to load.
+///
+/// Together with the slowest frames, see [`FrameView::add_frames_from_index`].
+/// The other frames of larger files are skipped, so those open quickly and fit in RAM.
+const MAX_RECENT_FRAMES: usize = 10_000;
+
+/// The frames of a `.puffin` file, read by [`Loading`].
+struct LoadedFile {
+    frame_view: FrameView,
+    /// Frames of the file that were skipped, see [`MAX_RECENT_FRAMES`].
+    num_skipped_frames: usize,
+}
+
+/// Read the frames of a `.puffin` file we keep, using a [`puffin::FileIndex`] to skip the others.
+///
+/// Files that can't be indexed, e.g. because they are gzip-compressed, are read in whole.
+fn load_file(
+    read: &mut (impl std::io::Read + std::io::Seek),
+    frames_to_read: &AtomicUsize,
+    progress: impl FnMut(usize) -> bool,
+) -> Result<LoadedFile, String> {
+    let Ok(index) = puffin::FileIndex::read(read) else {
+        read.rewind().map_err(|err| format!("{err:#}"))?;
+        return Ok(LoadedFile {
+            frame_view: FrameView::read_with_progress(read, progress)
+                .map_err(|err| format!("{err:#}"))?,
+            num_skipped_frames: 0,
+        });
+    };
+
+    let mut frame_view = FrameView::default();
+    frame_view.set_max_recent(MAX_RECENT_FRAMES);
+    let num_frames = index.entries().len();
+    frames_to_read.store(
+        num_frames.min(MAX_RECENT_FRAMES + frame_view.max_slow()),
+        Ordering::Relaxed,
+    );
+    frame_view
+        .add_frames_from_index(read, &index, progress)
+        .map_err(|err| format!("{err:#}"))?;
+    let num_skipped_frames = num_frames - frame_view.all_uniq().count();
+    Ok(LoadedFile {
+        frame_view,
+        num_skipped_frames,
+    })
+}
+
+/// A `.puffin` file being read on a background thread.
+struct Loading {
+    path: std::path::PathBuf,
+    file_size: Option<u64>,
+    /// At most how many frames will be read, once the file has been indexed. Zero until then.
+    frames_to_read: Arc<AtomicUsize>,
+    bytes_read: Arc<AtomicU64>,
+    frames_read: Arc<AtomicUsize>,
+    cancel: Arc<AtomicBool>,
+    result_rx: std::sync::mpsc::Receiver<Result<LoadedFile, String>>,
+    /// Add the frames to the file being viewed, instead of replacing it.
+    append: bool,
+}
+
+impl Loading {
+    fn start(path: std::path::PathBuf, ctx: egui::Context) -> std::io::Result<Self> {
+        let file = std::fs::File::open(&path)?;
+        let file_size = file.metadata().ok().map(|metadata| metadata.len());
+
+        let frames_to_read = Arc::new(AtomicUsize::new(0));
+        let bytes_read = Arc::new(AtomicU64::new(0));
+        let frames_read = Arc::new(AtomicUsize::new(0));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (result_tx, result_rx) = std::sync::mpsc::channel();
+
+        std::thread::Builder::new()
+            .name("puffin-loader".to_owned())
+            .spawn({
+                let frames_to_read = frames_to_read.clone();
+                let bytes_read = bytes_read.clone();
+                let frames_read = frames_read.clone();
+                let cancel = cancel.clone();
+                move || {
+                    let mut reader = CountingReader {
+                        inner: std::io::BufReader::new(file),
+                        bytes_read,
+                    };
+                    let result = load_file(&mut reader, &frames_to_read, |num_frames| {
+                        frames_read.store(num_frames, Ordering::Relaxed);
+                        ctx.request_repaint();
+                        !cancel.load(Ordering::Relaxed)
+                    });
+                    result_tx.send(result).ok();
+                    ctx.request_repaint();
+                }
+            })?;
+
+        Ok(Self {
+            path,
+            file_size,
+            frames_to_read,
+            bytes_read,
+            frames_read,
+            cancel,
+            result_rx,
+            append: false,
+        })
+    }
+
+    fn ui(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.spinner();
+            let frames_read = self.frames_read.load(Ordering::Relaxed);
+            let frames_to_read = self.frames_to_read.load(Ordering::Relaxed);
+            let progress = if 0 < frames_to_read {
+                ui.label(format!(
+                    "Loading {}… {frames_read} of up to {frames_to_read} frames",
+                    self.path.display(),
+                ));
+                Some(frames_read as f32 / frames_to_read as f32)
+            } else {
+                ui.label(format!(
+                    "Loading {}… {frames_read} frames",
+                    self.path.display(),
+                ));
+                let bytes_read = self.bytes_read.load(Ordering::Relaxed);
+                self.file_size
+                    .filter(|&size| size > 0)
+                    .map(|file_size| bytes_read as f32 / file_size as f32)
+            };
+            if let Some(progress) = progress {
+                ui.add(
+                    egui::ProgressBar::new(progress)
+                        .desired_width(200.0)
+                        .show_percentage(),
+                );
+            }
+            if ui.button("Cancel").clicked() {
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+}
+
+/// Keeps track of how far into the file we are, for the progress bar.
+struct CountingReader<R> {
+    inner: R,
+    bytes_read: Arc<AtomicU64>,
+}
+
+impl<R: std::io::Read> std::io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<R: std::io::Seek> std::io::Seek for CountingReader<R> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Where [`PuffinViewer::recent_files`] is stored in [`eframe::Storage`].
+#[cfg(not(target_arch = "wasm32"))]
+const RECENT_FILES_KEY: &str = "recent_files";
+
+/// How many files to list under "Open recent".
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_RECENT_FILES: usize = 10;
+
+/// Put `path` first in `recent_files`, see [`PuffinViewer::recent_files`].
+#[cfg(not(target_arch = "wasm32"))]
+fn add_recent_file(recent_files: &mut Vec<std::path::PathBuf>, path: std::path::PathBuf) {
+    let path = path.canonicalize().unwrap_or(path);
+    recent_files.retain(|recent| recent != &path);
+    recent_files.insert(0, path);
+    recent_files.truncate(MAX_RECENT_FILES);
+}
+
 pub struct PuffinViewer {
     profiler_ui: puffin_egui::ProfilerUi,
     source: Source,
+    /// A file being loaded in the background. [`Self::source`] is only replaced once it is done.
+    loading: Option<Loading>,
+    /// Frames of the viewed files that were skipped when loading them, see [`MAX_RECENT_FRAMES`].
+    num_skipped_frames: usize,
     error: Option<String>,
     profile_self: bool,
+    /// Set while the "Export time range" window is open.
+    #[cfg(not(target_arch = "wasm32"))]
+    export_time_range: Option<ExportTimeRange>,
+    /// The `.puffin` files opened most recently, newest first.
+    #[cfg(not(target_arch = "wasm32"))]
+    recent_files: Vec<std::path::PathBuf>,
+    /// The `?file=<url>` being downloaded on the web.
+    #[cfg(target_arch = "wasm32")]
+    download: Option<web::Download>,
     /// if [`Self::profile_self`] is checked, use this to introspect.
     global_profiler_ui: puffin_egui::GlobalProfilerUi,
 }
@@ -63,11 +277,30 @@ impl PuffinViewer {
             .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
             .unwrap_or_default();
 
+        #[cfg(not(target_arch = "wasm32"))]
+        let recent_files = {
+            let mut recent_files = storage
+                .and_then(|storage| eframe::get_value(storage, RECENT_FILES_KEY))
+                .unwrap_or_default();
+            if let Source::FilePath(path, _) = &source {
+                add_recent_file(&mut recent_files, path.clone());
+            }
+            recent_files
+        };
+
         Self {
             profiler_ui,
             source,
+            loading: None,
+            num_skipped_frames: 0,
             error: None,
             profile_self: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            export_time_range: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            recent_files,
+            #[cfg(target_arch = "wasm32")]
+            download: None,
             global_profiler_ui: Default::default(),
         }
     }
@@ -95,38 +328,274 @@ impl PuffinViewer {
     }
 
     #[cfg(not(target_arch = "wasm32"))]
-    fn open_dialog(&mut self) {
+    fn export_chrome_trace_dialog(&mut self) {
+        self.export_trace_dialog("Chrome trace", "json", |frame_view, file| {
+            frame_view.write_chrome_trace(file)
+        });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_perfetto_dialog(&mut self) {
+        self.export_trace_dialog("Perfetto trace", "perfetto-trace", |frame_view, file| {
+            frame_view.write_perfetto(file)
+        });
+    }
+
+    /// Ask for a file name, and export all frames to it with `export`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_trace_dialog<E: std::fmt::Display + From<std::io::Error>>(
+        &mut self,
+        filter_name: &str,
+        extension: &str,
+        export: impl FnOnce(&FrameView, &mut std::io::BufWriter<std::fs::File>) -> Result<(), E>,
+    ) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter(filter_name, &[extension])
+            .save_file()
+        {
+            let mut file = match std::fs::File::create(path) {
+                Ok(file) => std::io::BufWriter::new(file),
+                Err(error) => {
+                    self.error = Some(format!("Failed to create file: {error:#}"));
+                    return;
+                }
+            };
+
+            let result = self
+                .source
+                .with_frame_view(|frame_view| export(frame_view, &mut file))
+                .and_then(|()| std::io::Write::flush(&mut file).map_err(Into::into));
+            if let Err(error) = result {
+                self.error = Some(format!("Failed to export: {error:#}"));
+            } else {
+                self.error = None;
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_time_range_ui(&mut self, ctx: &egui::Context) {
+        let Some(export) = &mut self.export_time_range else {
+            return;
+        };
+
+        let mut open = true;
+        let mut export_to = None;
+        egui::Window::new("Export time range")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.source.with_frame_view(|frame_view| {
+                    let capture_start_ns = frame_view
+                        .all_uniq()
+                        .map(|frame| frame.range_ns().0)
+                        .min()
+                        .unwrap_or_default();
+                    let capture_end_ns = frame_view
+                        .all_uniq()
+                        .map(|frame| frame.range_ns().1)
+                        .max()
+                        .unwrap_or_default();
+                    let capture_s = (capture_end_ns - capture_start_ns) as f64 * 1e-9;
+                    export.start_s = export.start_s.clamp(0.0, capture_s);
+                    export.end_s = export.end_s.clamp(export.start_s, capture_s);
+
+                    ui.label(format!("The capture is {capture_s:.3} s long."));
+                    egui::Grid::new("export_time_range").show(ui, |ui| {
+                        ui.label("From:");
+                        ui.add(
+                            egui::DragValue::new(&mut export.start_s)
+                                .range(0.0..=capture_s)
+                                .speed(0.01)
+                                .suffix(" s"),
+                        );
+                        ui.end_row();
+                        ui.label("To:");
+                        ui.add(
+                            egui::DragValue::new(&mut export.end_s)
+                                .range(export.start_s..=capture_s)
+                                .speed(0.01)
+                                .suffix(" s"),
+                        );
+                        ui.end_row();
+                    });
+
+                    let range_ns = (
+                        capture_start_ns + (export.start_s * 1e9) as puffin::NanoSecond,
+                        capture_start_ns + (export.end_s * 1e9) as puffin::NanoSecond,
+                    );
+                    let num_frames = frame_view
+                        .all_uniq()
+                        .filter(|frame| {
+                            let (min_ns, max_ns) = frame.range_ns();
+                            min_ns <= range_ns.1 && range_ns.0 <= max_ns
+                        })
+                        .count();
+                    ui.label(format!("{num_frames} frames overlap this time range."));
+                    ui.weak(
+                        "Frames partially overlapping the edges of the range are included whole.",
+                    );
+
+                    if ui
+                        .add_enabled(num_frames > 0, egui::Button::new("Export…"))
+                        .clicked()
+                    {
+                        export_to = Some(range_ns);
+                    }
+                });
+            });
+
+        if !open {
+            self.export_time_range = None;
+        }
+
+        if let Some(range_ns) = export_to {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("puffin", &["puffin"])
+                .save_file()
+            {
+                let result = std::fs::File::create(path)
+                    .map_err(|error| format!("Failed to create file: {error:#}"))
+                    .and_then(|mut file| {
+                        self.source
+                            .with_frame_view(|frame_view| {
+                                frame_view.write_time_range(&mut file, range_ns)
+                            })
+                            .map_err(|error| format!("Failed to export: {error:#}"))
+                    });
+                match result {
+                    Ok(()) => {
+                        self.error = None;
+                        self.export_time_range = None;
+                    }
+                    Err(error) => {
+                        self.error = Some(error);
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("puffin", &["puffin"])
+            .pick_file()
+        {
+            self.open_puffin_path(path, ctx);
+        }
+    }
+
+    /// Load another `.puffin` file and add its frames after the ones being viewed,
+    /// e.g. to compare two runs of the same benchmark.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn append_dialog(&mut self, ctx: &egui::Context) {
         if let Some(path) = rfd::FileDialog::new()
             .add_filter("puffin", &["puffin"])
             .pick_file()
         {
-            self.open_puffin_path(path);
+            self.open_puffin_path(path, ctx);
+            if let Some(loading) = &mut self.loading {
+                loading.append = true;
+            }
         }
     }
 
-    fn open_puffin_path(&mut self, path: std::path::PathBuf) {
+    /// Start loading the `.puffin` file at `path` in the background.
+    ///
+    /// It replaces what is being viewed once it is loaded.
+    pub fn open_puffin_path(&mut self, path: std::path::PathBuf, ctx: &egui::Context) {
         puffin::profile_function!();
 
-        let mut file = match std::fs::File::open(&path) {
-            Ok(bytes) => bytes,
+        if let Some(loading) = self.loading.take() {
+            loading.cancel.store(true, Ordering::Relaxed);
+        }
+
+        match Loading::start(path.clone(), ctx.clone()) {
+            Ok(loading) => {
+                self.loading = Some(loading);
+            }
             Err(err) => {
                 self.error = Some(format!("Failed to open {}: {err:#}", path.display()));
-                return;
             }
+        }
+    }
+
+    /// Check if the background load has finished.
+    fn poll_loading(&mut self) {
+        let Some(loading) = &self.loading else {
+            return;
         };
 
-        match FrameView::read(&mut file) {
-            Ok(frame_view) => {
+        let result = match loading.result_rx.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => return,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                Err("The loading thread crashed".to_owned())
+            }
+        };
+
+        let Some(loading) = self.loading.take() else {
+            return;
+        };
+
+        match (result, &mut self.source) {
+            (Ok(loaded), Source::FilePath(_, current) | Source::FileName(_, current))
+                if loading.append =>
+            {
+                if let Err(err) = current.append(loaded.frame_view) {
+                    self.error = Some(format!(
+                        "Failed to append {}: {err:#}",
+                        loading.path.display()
+                    ));
+                } else {
+                    self.profiler_ui.reset();
+                    self.num_skipped_frames += loaded.num_skipped_frames;
+                    self.error = None;
+                }
+            }
+            (Ok(loaded), _) => {
                 self.profiler_ui.reset();
-                self.source = Source::FilePath(path, frame_view);
+                #[cfg(not(target_arch = "wasm32"))]
+                add_recent_file(&mut self.recent_files, loading.path.clone());
+                self.source = Source::FilePath(loading.path, loaded.frame_view);
+                self.num_skipped_frames = loaded.num_skipped_frames;
                 self.error = None;
             }
-            Err(err) => {
-                self.error = Some(format!("Failed to load {}: {err:#}", path.display()));
+            (Err(_), _) if loading.cancel.load(Ordering::Relaxed) => {
+                // Cancelled by the user: keep whatever we were viewing before.
+            }
+            (Err(err), _) => {
+                self.error = Some(format!("Failed to load {}: {err}", loading.path.display()));
             }
         }
     }
 
+    /// Check if the download of `?file=<url>` has finished.
+    #[cfg(target_arch = "wasm32")]
+    fn poll_download(&mut self) {
+        let Some(download) = &self.download else {
+            return;
+        };
+
+        let result = match download.result_rx.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => return,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                Err(format!("Failed to download {}", download.url))
+            }
+        };
+
+        let Some(download) = self.download.take() else {
+            return;
+        };
+
+        match result {
+            Ok(bytes) => self.open_puffin_bytes(download.url, &bytes),
+            Err(err) => self.error = Some(err),
+        }
+    }
+
     fn open_puffin_bytes(&mut self, name: String, bytes: &[u8]) {
         puffin::profile_function!();
         let mut reader = std::io::Cursor::new(bytes);
@@ -134,6 +603,7 @@ impl PuffinViewer {
             Ok(frame_view) => {
                 self.profiler_ui.reset();
                 self.source = Source::FileName(name, frame_view);
+                self.num_skipped_frames = 0;
                 self.error = None;
             }
             Err(err) => {
@@ -145,7 +615,7 @@ impl PuffinViewer {
     #[cfg(not(target_arch = "wasm32"))]
     fn ui_menu_bar(&mut self, ctx: &egui::Context) {
         if ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::O)) {
-            self.open_dialog();
+            self.open_dialog(ctx);
         }
 
         if ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::S)) {
@@ -158,13 +628,43 @@ impl PuffinViewer {
 
                 ui.menu_button("File", |ui| {
                     if ui.button("Open…").clicked() {
-                        self.open_dialog();
+                        self.open_dialog(ui.ctx());
+                    }
+
+                    ui.add_enabled_ui(!self.recent_files.is_empty(), |ui| {
+                        ui.menu_button("Open recent", |ui| self.recent_files_ui(ui));
+                    });
+
+                    let is_file =
+                        matches!(self.source, Source::FilePath(..) | Source::FileName(..));
+                    if ui
+                        .add_enabled(is_file, egui::Button::new("Append…"))
+                        .on_hover_text("Add the frames of another file, e.g. to compare two runs")
+                        .clicked()
+                    {
+                        self.append_dialog(ui.ctx());
                     }
 
                     if ui.button("Save as…").clicked() {
                         self.save_dialog();
                     }
 
+                    if ui.button("Export Chrome trace…").clicked() {
+                        self.export_chrome_trace_dialog();
+                    }
+
+                    if ui.button("Export Perfetto trace…").clicked() {
+                        self.export_perfetto_dialog();
+                    }
+
+                    if ui.button("Export time range…").clicked() {
+                        self.export_time_range = Some(ExportTimeRange {
+                            start_s: 0.0,
+                            end_s: f64::INFINITY,
+                        });
+                        ui.close_menu();
+                    }
+
                     if ui.button("Quit").clicked() {
                         ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
                     }
@@ -177,6 +677,35 @@ impl PuffinViewer {
         });
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    fn recent_files_ui(&mut self, ui: &mut egui::Ui) {
+        let mut clicked = None;
+        for path in &self.recent_files {
+            let name = path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+            if ui
+                .button(name)
+                .on_hover_text(path.display().to_string())
+                .clicked()
+            {
+                clicked = Some(path.clone());
+            }
+        }
+
+        ui.separator();
+        if ui.button("Clear list").clicked() {
+            self.recent_files.clear();
+            ui.close_menu();
+        }
+
+        if let Some(path) = clicked {
+            ui.close_menu();
+            self.open_puffin_path(path, ui.ctx());
+        }
+    }
+
     fn ui_file_drag_and_drop(&mut self, ctx: &egui::Context) {
         use egui::*;
 
@@ -197,33 +726,37 @@ impl PuffinViewer {
         }
 
         // Collect dropped files:
-        ctx.input(|i| {
-            if !i.raw.dropped_files.is_empty() {
-                for file in i.raw.dropped_files.iter() {
-                    if let Some(path) = &file.path {
-                        self.open_puffin_path(path.clone());
-                        break;
-                    } else if let Some(bytes) = &file.bytes {
-                        self.open_puffin_bytes(file.name.clone(), bytes);
-                        break;
-                    }
-                }
+        let dropped_files = ctx.input(|i| i.raw.dropped_files.clone());
+        for file in dropped_files {
+            if let Some(path) = file.path {
+                self.open_puffin_path(path, ctx);
+                break;
+            } else if let Some(bytes) = &file.bytes {
+                self.open_puffin_bytes(file.name.clone(), bytes);
+                break;
             }
-        });
+        }
     }
 }
 
 impl eframe::App for PuffinViewer {
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         eframe::set_value(storage, eframe::APP_KEY, &self.profiler_ui);
+        #[cfg(not(target_arch = "wasm32"))]
+        eframe::set_value(storage, RECENT_FILES_KEY, &self.recent_files);
     }
 
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         puffin::GlobalProfiler::lock().new_frame();
 
+        self.poll_loading();
+        #[cfg(target_arch = "wasm32")]
+        self.poll_download();
+
         #[cfg(not(target_arch = "wasm32"))]
         {
             self.ui_menu_bar(ctx);
+            self.export_time_range_ui(ctx);
         }
 
         #[cfg(target_arch = "wasm32")]
@@ -244,9 +777,29 @@ impl eframe::App for PuffinViewer {
                 ui.add_space(4.0);
             }
 
+            if let Some(loading) = &self.loading {
+                loading.ui(ui);
+                ui.add_space(4.0);
+            }
+
+            #[cfg(target_arch = "wasm32")]
+            if let Some(download) = &self.download {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("Downloading {}…", download.url));
+                });
+                ui.add_space(4.0);
+            }
+
             if self.profile_self {
                 ui.label("Profiling puffin_viewer");
             } else {
+                if 0 < self.num_skipped_frames {
+                    ui.label(format!(
+                        "Skipped {} frames of this large file: only the latest {MAX_RECENT_FRAMES} frames and the slowest frames are loaded.",
+                        self.num_skipped_frames
+                    ));
+                }
                 self.source.ui(ui);
             }
         });
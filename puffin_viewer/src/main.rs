@@ -17,9 +17,21 @@ fn main() -> Result<(), eframe::Error> {
         #[argh(option, default = "default_url()")]
         url: String,
 
-        /// what .puffin file to open, e.g. `my/recording.puffin`.
+        /// what .puffin file to open, e.g. `my/recording.puffin`, or `-` to read it from stdin.
         #[argh(positional)]
         file: Option<PathBuf>,
+
+        /// convert this .puffin file (or `-` for stdin) without opening a window, then exit.
+        #[argh(option)]
+        convert: Option<PathBuf>,
+
+        /// what to convert to with `--convert`: `chrome-json` (default), `perfetto` or `csv`.
+        #[argh(option, default = "ConvertFormat::ChromeJson")]
+        format: ConvertFormat,
+
+        /// where to write the output of `--convert`. Defaults to stdout.
+        #[argh(option)]
+        output: Option<PathBuf>,
     }
 
     fn default_url() -> String {
@@ -33,24 +45,33 @@ fn main() -> Result<(), eframe::Error> {
 
     let opt: Arguments = argh::from_env();
 
-    puffin::set_scopes_on(true); // so we can profile ourselves
+    if let Some(input) = &opt.convert {
+        if let Err(err) = convert(input, opt.format, opt.output.as_deref()) {
+            log::error!("Failed to convert {:?}: {err:#}", input.display());
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    let source = if let Some(path) = opt.file {
-        let mut file = match std::fs::File::open(&path) {
-            Ok(file) => file,
-            Err(err) => {
-                log::error!("Failed to open {:?}: {err:#}", path.display());
-                std::process::exit(1);
-            }
-        };
+    puffin::set_scopes_on(true); // so we can profile ourselves
 
-        match FrameView::read(&mut file) {
-            Ok(frame_view) => Source::FilePath(path, frame_view),
+    // Files are loaded in the background once the window is open, so large files don't block it:
+    let mut open_path = None;
+    let source = if opt.file.as_deref() == Some(std::path::Path::new("-")) {
+        match FrameView::read(&mut std::io::stdin().lock()) {
+            Ok(frame_view) => Source::FileName("stdin".to_owned(), frame_view),
             Err(err) => {
-                log::error!("Failed to load {:?}: {err:#}", path.display());
+                log::error!("Failed to load .puffin data from stdin: {err:#}");
                 std::process::exit(1);
             }
         }
+    } else if let Some(path) = opt.file {
+        if let Err(err) = std::fs::File::open(&path) {
+            log::error!("Failed to open {:?}: {err:#}", path.display());
+            std::process::exit(1);
+        }
+        open_path = Some(path);
+        Source::None
     } else {
         Source::Http(puffin_http::Client::new(opt.url))
     };
@@ -68,9 +89,85 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "puffin viewer",
         native_options,
-        Box::new(|cc| Ok(Box::new(PuffinViewer::new(source, cc.storage)))),
+        Box::new(|cc| {
+            let mut viewer = PuffinViewer::new(source, cc.storage);
+            if let Some(path) = open_path {
+                viewer.open_puffin_path(path, &cc.egui_ctx);
+            }
+            Ok(Box::new(viewer))
+        }),
     )
 }
 
+/// What `--convert` converts a .puffin file to.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy)]
+enum ConvertFormat {
+    /// See [`puffin::FrameView::write_chrome_trace`].
+    ChromeJson,
+
+    /// See [`puffin::FrameView::write_perfetto`].
+    Perfetto,
+
+    /// The stats table of all frames, see [`puffin_egui::stats::csv_table`].
+    Csv,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl std::str::FromStr for ConvertFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chrome-json" => Ok(Self::ChromeJson),
+            "perfetto" => Ok(Self::Perfetto),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!(
+                "Unknown format {s:?}, expected `chrome-json`, `perfetto` or `csv`"
+            )),
+        }
+    }
+}
+
+/// Convert the .puffin file at `input` (or stdin if it is `-`) to `format`,
+/// and write it to `output` (or stdout).
+///
+/// Doesn't need a window, so it works on CI and remote machines.
+#[cfg(not(target_arch = "wasm32"))]
+fn convert(
+    input: &std::path::Path,
+    format: ConvertFormat,
+    output: Option<&std::path::Path>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use std::io::Write as _;
+
+    let frame_view = if input == std::path::Path::new("-") {
+        puffin::FrameView::read(&mut std::io::stdin().lock())?
+    } else {
+        puffin::FrameView::read(&mut std::io::BufReader::new(std::fs::File::open(input)?))?
+    };
+
+    let mut write: Box<dyn std::io::Write> = match output {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    match format {
+        ConvertFormat::ChromeJson => frame_view.write_chrome_trace(&mut write)?,
+        ConvertFormat::Perfetto => frame_view.write_perfetto(&mut write)?,
+        ConvertFormat::Csv => {
+            let frames = frame_view
+                .all_uniq()
+                .map(|frame| frame.unpacked())
+                .collect::<Result<Vec<_>, _>>()?;
+            let rows = puffin_egui::stats::collect_rows(frame_view.scope_collection(), &frames);
+            write.write_all(puffin_egui::stats::csv_table(&rows).as_bytes())?;
+        }
+    }
+
+    write.flush()?;
+    Ok(())
+}
+
 #[cfg(target_arch = "wasm32")]
 fn main() {}
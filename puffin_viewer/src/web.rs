@@ -1,4 +1,7 @@
-use eframe::wasm_bindgen::{self, prelude::*};
+use eframe::{
+    egui,
+    wasm_bindgen::{self, prelude::*},
+};
 
 /// This is the entry-point for all the web-assembly.
 /// This is called once from the HTML.
@@ -19,13 +22,77 @@ pub async fn start(canvas_id: &str) -> Result<(), eframe::wasm_bindgen::JsValue>
             canvas_id,
             web_options,
             Box::new(|cc| {
-                Ok(Box::new(crate::PuffinViewer::new(
-                    crate::Source::None,
-                    cc.storage,
-                )))
+                let mut viewer = crate::PuffinViewer::new(crate::Source::None, cc.storage);
+                // Open `?file=<url>`, so recordings can be shared as links:
+                if let Some(url) = file_url_param() {
+                    viewer.download = Some(Download::start(url, cc.egui_ctx.clone()));
+                }
+                Ok(Box::new(viewer))
             }),
         )
         .await?;
 
     Ok(())
 }
+
+/// The `file` parameter of the query string of the page, if any.
+fn file_url_param() -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    web_sys::UrlSearchParams::new_with_str(&search)
+        .ok()?
+        .get("file")
+        .filter(|url| !url.is_empty())
+}
+
+/// A `.puffin` file being downloaded, see [`crate::PuffinViewer::poll_download`].
+pub(crate) struct Download {
+    pub url: String,
+    pub result_rx: std::sync::mpsc::Receiver<Result<Vec<u8>, String>>,
+}
+
+impl Download {
+    fn start(url: String, ctx: egui::Context) -> Self {
+        let (result_tx, result_rx) = std::sync::mpsc::channel();
+        wasm_bindgen_futures::spawn_local({
+            let url = url.clone();
+            async move {
+                result_tx.send(fetch_bytes(&url).await).ok();
+                ctx.request_repaint();
+            }
+        });
+        Self { url, result_rx }
+    }
+}
+
+async fn fetch_bytes(url: &str) -> Result<Vec<u8>, String> {
+    use wasm_bindgen_futures::JsFuture;
+
+    let window = web_sys::window().ok_or("No window")?;
+    // `fetch` doesn't tell us why it failed, but it is usually CORS:
+    let response = JsFuture::from(window.fetch_with_str(url))
+        .await
+        .map_err(|_| {
+            format!(
+                "Failed to download {url}. \
+                If it is on another server, that server must allow cross-origin requests (CORS)."
+            )
+        })?;
+    let response: web_sys::Response = response
+        .dyn_into()
+        .map_err(|_| "fetch did not return a Response".to_owned())?;
+    if !response.ok() {
+        return Err(format!(
+            "Failed to download {url}: {} {}",
+            response.status(),
+            response.status_text()
+        ));
+    }
+
+    let buffer = response
+        .array_buffer()
+        .map_err(|err| format!("Failed to download {url}: {err:?}"))?;
+    let buffer = JsFuture::from(buffer)
+        .await
+        .map_err(|err| format!("Failed to download {url}: {err:?}"))?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}